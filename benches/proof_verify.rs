@@ -0,0 +1,78 @@
+//! Cost of checking an encoded proof: decoding it into a `MerkleProof` and
+//! verifying with a hasher built for the call, as `verify` used to, against
+//! verifying the encoding in place through `MerkleProofRef`. Heap
+//! allocations per verification are counted and printed before timing.
+
+use std::alloc::{GlobalAlloc, Layout};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use SimpleSparseMerkle::{
+    proof::MerkleProofRef, tree_hasher::TreeHasher, DefaultHasher, Hash, InMemoryKVStore, MerkleProof, SparseMerkleTree,
+};
+
+#[cfg(feature = "jemalloc")]
+use tikv_jemallocator::Jemalloc as Inner;
+#[cfg(not(feature = "jemalloc"))]
+use std::alloc::System as Inner;
+
+/// Counts allocations on the way to the real allocator.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        Inner.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        Inner.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations_in(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn verify_owned(bytes: &[u8], root: &Hash, key: &Hash, value: &Hash) -> bool {
+    let proof = MerkleProof::from_bytes(bytes).unwrap();
+    proof.verify_with(&TreeHasher::<DefaultHasher>::new(), root, key, value)
+}
+
+fn verify_borrowed(hasher: &TreeHasher<DefaultHasher>, bytes: &[u8], root: &Hash, key: &Hash, value: &Hash, scratch: &mut Hash) -> bool {
+    MerkleProofRef::decode(bytes).unwrap().verify_into(hasher, root, key, value, scratch)
+}
+
+fn bench_verify(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+    let entries: Vec<(Hash, Hash)> = (0..1_000).map(|_| (rng.gen(), rng.gen())).collect();
+    smt.update_batch(&entries).unwrap();
+    let root = smt.root();
+    let (key, value) = entries[0];
+    let bytes = smt.get_proof(key).unwrap().to_bytes();
+
+    let hasher = TreeHasher::<DefaultHasher>::new();
+    let mut scratch = [0u8; 32];
+    let owned = allocations_in(|| assert!(verify_owned(&bytes, &root, &key, &value)));
+    let borrowed = allocations_in(|| assert!(verify_borrowed(&hasher, &bytes, &root, &key, &value, &mut scratch)));
+    println!("allocations per verification: owned {}, borrowed {}", owned, borrowed);
+
+    let mut group = c.benchmark_group("proof_verify");
+    group.bench_function("owned", |b| b.iter(|| black_box(verify_owned(&bytes, &root, &key, &value))));
+    group.bench_function("borrowed", |b| {
+        b.iter(|| black_box(verify_borrowed(&hasher, &bytes, &root, &key, &value, &mut scratch)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_verify);
+criterion_main!(benches);
@@ -0,0 +1,115 @@
+//! Latency of the core tree operations on trees of growing size, for every
+//! store backend compiled in. Sizes default to 10³ and 10⁴ leaves; set
+//! `SMT_BENCH_LEAVES` to a comma-separated list to go further, e.g.
+//!
+//!     SMT_BENCH_LEAVES=1000,10000,100000,1000000 cargo bench --features sled --bench tree_ops
+//!
+//! Each leaf costs about one node per level, so a 10⁶-leaf tree needs tens of
+//! gigabytes in memory or on disk.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use SimpleSparseMerkle::{kv_store::InMemoryKVStore, Hash, KVStore, SMTError, SparseMerkleTree};
+
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+const BATCH_SIZE: usize = 100;
+
+fn leaf_counts() -> Vec<usize> {
+    std::env::var("SMT_BENCH_LEAVES")
+        .ok()
+        .map(|sizes| sizes.split(',').filter_map(|size| size.trim().parse().ok()).collect())
+        .unwrap_or_else(|| vec![1_000, 10_000])
+}
+
+fn random_entries(rng: &mut StdRng, n: usize) -> Vec<(Hash, Hash)> {
+    (0..n).map(|_| (rng.gen(), rng.gen())).collect()
+}
+
+/// Benchmarks every operation on trees over stores from `make_store`, which
+/// is called once per tree size.
+fn bench_backend<S, F>(c: &mut Criterion, backend: &str, mut make_store: F)
+where
+    S: KVStore,
+    SMTError: From<S::Error>,
+    F: FnMut() -> S,
+{
+    let mut group = c.benchmark_group(format!("tree_ops/{}", backend));
+    group.sample_size(10);
+
+    for leaves in leaf_counts() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let entries = random_entries(&mut rng, leaves);
+        let mut smt = SparseMerkleTree::new(make_store());
+        for chunk in entries.chunks(10_000) {
+            smt.update_batch(chunk).unwrap();
+        }
+
+        group.throughput(Throughput::Elements(1));
+        group.bench_function(BenchmarkId::new("update", leaves), |b| {
+            b.iter(|| smt.update(rng.gen(), rng.gen()).unwrap())
+        });
+
+        group.throughput(Throughput::Elements(BATCH_SIZE as u64));
+        group.bench_function(BenchmarkId::new("update_batch", leaves), |b| {
+            b.iter(|| black_box(smt.update_batch(&random_entries(&mut rng, BATCH_SIZE)).unwrap()))
+        });
+
+        group.throughput(Throughput::Elements(1));
+        group.bench_function(BenchmarkId::new("get_proof", leaves), |b| {
+            b.iter(|| {
+                let (key, _) = entries[rng.gen_range(0..entries.len())];
+                black_box(smt.get_proof(key).unwrap())
+            })
+        });
+
+        // Proofs taken after the writes above, so they match the current root.
+        let root = smt.root();
+        let proofs: Vec<_> = entries.iter().take(100).map(|(key, value)| (*key, *value, smt.get_proof(*key).unwrap())).collect();
+        group.bench_function(BenchmarkId::new("verify", leaves), |b| {
+            b.iter(|| {
+                let (key, value, proof) = &proofs[rng.gen_range(0..proofs.len())];
+                assert!(proof.verify(&root, key, value));
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_in_memory(c: &mut Criterion) {
+    bench_backend(c, "in_memory", InMemoryKVStore::new);
+}
+
+#[cfg(feature = "sled")]
+fn bench_sled(c: &mut Criterion) {
+    let mut dirs = Vec::new();
+    bench_backend(c, "sled", || {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SimpleSparseMerkle::kv_store::SledStore::open(dir.path()).unwrap();
+        dirs.push(dir);
+        store
+    });
+}
+
+#[cfg(not(feature = "sled"))]
+fn bench_sled(_: &mut Criterion) {}
+
+#[cfg(feature = "rocksdb")]
+fn bench_rocksdb(c: &mut Criterion) {
+    let mut dirs = Vec::new();
+    bench_backend(c, "rocksdb", || {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SimpleSparseMerkle::kv_store::RocksDbStore::open(dir.path()).unwrap();
+        dirs.push(dir);
+        store
+    });
+}
+
+#[cfg(not(feature = "rocksdb"))]
+fn bench_rocksdb(_: &mut Criterion) {}
+
+criterion_group!(benches, bench_in_memory, bench_sled, bench_rocksdb);
+criterion_main!(benches);
@@ -0,0 +1,43 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use SimpleSparseMerkle::{kv_store::InMemoryKVStore, sparse_merkle_tree::SparseMerkleTree, Hash};
+
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+fn random_entries(n: usize) -> Vec<(Hash, Hash)> {
+    let mut rng = StdRng::seed_from_u64(42);
+    (0..n).map(|_| (rng.gen(), rng.gen())).collect()
+}
+
+fn bench_update_batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("update_batch");
+    group.sample_size(10);
+
+    for n in [10, 100, 1000] {
+        let entries = random_entries(n);
+
+        group.bench_with_input(BenchmarkId::new("sequential", n), &entries, |b, entries| {
+            b.iter(|| {
+                let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+                for (key, value) in entries {
+                    smt.update(*key, *value).unwrap();
+                }
+                black_box(smt.root())
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("batch", n), &entries, |b, entries| {
+            b.iter(|| {
+                let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+                black_box(smt.update_batch(entries).unwrap())
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_update_batch);
+criterion_main!(benches);
@@ -0,0 +1,11 @@
+fn main() {
+    // Only the gRPC server needs generated code
+    #[cfg(feature = "server")]
+    {
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().expect("vendored protoc"));
+        tonic_build::configure()
+            .build_client(false)
+            .compile_protos(&["proto/smt_service.proto"], &["proto"])
+            .expect("compile proto/smt_service.proto");
+    }
+}
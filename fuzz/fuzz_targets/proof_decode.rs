@@ -0,0 +1,34 @@
+//! Arbitrary bytes into every proof decoder. Decoding may fail but must not
+//! panic, and whatever decodes must encode back to the same bytes.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use SimpleSparseMerkle::{
+    proof::{CompressedMerkleProof, MerkleProofRef, MultiProof, NonMembershipProof},
+    range::RangeProof,
+    MerkleProof,
+};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(proof) = MerkleProof::from_bytes(data) {
+        assert_eq!(proof.to_bytes(), data);
+    }
+    if let Some(proof) = MerkleProofRef::decode(data) {
+        let _ = proof.to_proof();
+    }
+
+    let _ = serde_json::from_slice::<MerkleProof>(data);
+    if let Ok(proof) = serde_json::from_slice::<NonMembershipProof>(data) {
+        let _ = proof.verify(&[0u8; 32], &[0u8; 32]);
+    }
+    if let Ok(proof) = serde_json::from_slice::<RangeProof>(data) {
+        let _ = proof.verify(&[0u8; 32], &[0u8; 32], &[0xff; 32]);
+    }
+    if let Ok(proof) = serde_json::from_slice::<CompressedMerkleProof>(data) {
+        let _ = proof.decompress();
+    }
+    if let Ok(proof) = serde_json::from_slice::<MultiProof>(data) {
+        let _ = proof.verify(&[0u8; 32], &[([0u8; 32], [0u8; 32]), ([0xff; 32], [0u8; 32])]);
+    }
+});
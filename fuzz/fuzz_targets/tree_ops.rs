@@ -0,0 +1,77 @@
+//! Arbitrary sequences of updates, deletes and batches against a tree and a
+//! plain map. After each run the tree must hold what the map holds, prove
+//! it, and have the root a tree built from the map in one batch has.
+
+#![no_main]
+
+use std::collections::BTreeMap;
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use SimpleSparseMerkle::{Hash, InMemoryKVStore, SparseMerkleTree};
+
+/// Keys differing only in their first and last bytes, so paths share long
+/// prefixes and splits happen deep in the tree.
+#[derive(Arbitrary, Debug, Clone, Copy)]
+struct Key(u8, u8);
+
+impl Key {
+    fn hash(self) -> Hash {
+        let mut key = [0u8; 32];
+        key[0] = self.0;
+        key[31] = self.1;
+        key
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    Update(Key, u8),
+    Delete(Key),
+    Batch(Vec<(Key, u8)>),
+}
+
+fuzz_target!(|ops: Vec<Op>| {
+    let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+    let mut model = BTreeMap::new();
+    let mut touched = Vec::new();
+
+    for op in ops.into_iter().take(64) {
+        match op {
+            Op::Update(key, value) => {
+                smt.update(key.hash(), [value; 32]).unwrap();
+                model.insert(key.hash(), [value; 32]);
+                touched.push(key.hash());
+            }
+            Op::Delete(key) => {
+                smt.delete(key.hash()).unwrap();
+                model.remove(&key.hash());
+                touched.push(key.hash());
+            }
+            Op::Batch(entries) => {
+                let entries: Vec<(Hash, Hash)> = entries.iter().map(|(key, value)| (key.hash(), [*value; 32])).collect();
+                smt.update_batch(&entries).unwrap();
+                for (key, value) in entries {
+                    model.insert(key, value);
+                    touched.push(key);
+                }
+            }
+        }
+    }
+
+    let root = smt.root();
+    for key in touched {
+        let value = smt.get(key).unwrap();
+        assert_eq!(value, model.get(&key).copied());
+        let proof = smt.get_proof(key).unwrap();
+        match value {
+            Some(value) => assert!(proof.verify(&root, &key, &value)),
+            None => assert!(smt.get_non_membership_proof(key).unwrap().is_some_and(|proof| proof.verify(&root, &key))),
+        }
+    }
+
+    let mut rebuilt = SparseMerkleTree::new(InMemoryKVStore::new());
+    let entries: Vec<(Hash, Hash)> = model.into_iter().collect();
+    rebuilt.update_batch(&entries).unwrap();
+    assert_eq!(rebuilt.root(), root);
+});
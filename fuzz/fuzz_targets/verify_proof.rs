@@ -0,0 +1,45 @@
+//! A root, key and value followed by proof bytes into every way of checking
+//! a proof. Nothing may panic, and the owned and borrowed verifiers must
+//! agree.
+
+#![no_main]
+
+use std::sync::OnceLock;
+
+use libfuzzer_sys::fuzz_target;
+use SimpleSparseMerkle::{
+    proof::MerkleProofRef, spec::{verify_with_spec, TreeSpec}, Hash, InMemoryKVStore, MerkleProof, SparseMerkleTree,
+};
+
+fn tree() -> &'static SparseMerkleTree<InMemoryKVStore> {
+    static TREE: OnceLock<SparseMerkleTree<InMemoryKVStore>> = OnceLock::new();
+    TREE.get_or_init(|| {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        for i in 0..8u8 {
+            smt.update([i * 32; 32], [i; 32]).unwrap();
+        }
+        smt
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 96 {
+        return;
+    }
+    let (root, rest) = data.split_at(32);
+    let (key, rest) = rest.split_at(32);
+    let (value, bytes) = rest.split_at(32);
+    let root: Hash = root.try_into().unwrap();
+    let key: Hash = key.try_into().unwrap();
+    let value: Hash = value.try_into().unwrap();
+
+    let owned = MerkleProof::from_bytes(bytes).ok();
+    let borrowed = MerkleProofRef::decode(bytes);
+    if let (Some(owned), Some(borrowed)) = (&owned, borrowed) {
+        assert_eq!(owned.verify(&root, &key, &value), borrowed.verify(&root, &key, &value));
+    }
+    if let Some(proof) = owned {
+        let _ = verify_with_spec(&TreeSpec::default(), &root, &key, &value, &proof);
+        let _ = tree().verify_proof(key, value, &proof);
+    }
+});
@@ -0,0 +1,113 @@
+/* C declarations for src/ffi.rs. Regenerate with cbindgen, see cbindgen.toml. */
+
+#ifndef SMT_H
+#define SMT_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define SMT_OK 0
+
+#define SMT_ERR_NULL -1
+
+#define SMT_ERR_STORE -2
+
+#define SMT_ERR_INVALID_PROOF -3
+
+#define SMT_ERR_PANIC -4
+
+/**
+ * Opaque handle to a tree, created by `smt_new` and released by `smt_free`.
+ */
+typedef struct SmtTree SmtTree;
+
+/**
+ * Bytes owned by the library, released by `smt_buffer_free`.
+ */
+typedef struct SmtBuffer {
+  uint8_t *data;
+  size_t len;
+} SmtBuffer;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates an empty tree over an in-memory store.
+ */
+struct SmtTree *smt_new(void);
+
+/**
+ * Releases a tree. Null is ignored.
+ *
+ * # Safety
+ *
+ * `tree` must come from `smt_new` and not have been freed already.
+ */
+void smt_free(struct SmtTree *tree);
+
+/**
+ * Copies the current root into `root_out`.
+ *
+ * # Safety
+ *
+ * `tree` must be a live tree and `root_out` must point to 32 writable bytes.
+ */
+int32_t smt_root(const struct SmtTree *tree, uint8_t *root_out);
+
+/**
+ * Sets `key` to `value`.
+ *
+ * # Safety
+ *
+ * `tree` must be a live tree, and `key` and `value` must each point to 32
+ * readable bytes.
+ */
+int32_t smt_update(struct SmtTree *tree, const uint8_t *key, const uint8_t *value);
+
+/**
+ * Writes the proof for `key` under the current root to `proof_out`, which
+ * the caller releases with `smt_buffer_free`.
+ *
+ * # Safety
+ *
+ * `tree` must be a live tree, `key` must point to 32 readable bytes and
+ * `proof_out` to a writable `SmtBuffer`.
+ */
+int32_t smt_get_proof(const struct SmtTree *tree, const uint8_t *key, struct SmtBuffer *proof_out);
+
+/**
+ * Checks an encoded proof that `key` holds `value` under `root`. Returns
+ * `SMT_OK` if it does and `SMT_ERR_INVALID_PROOF` if it does not or the
+ * proof cannot be decoded. Needs no tree.
+ *
+ * # Safety
+ *
+ * `root`, `key` and `value` must each point to 32 readable bytes, and
+ * `proof` to `proof_len` readable bytes.
+ */
+int32_t smt_verify_proof(const uint8_t *root,
+                         const uint8_t *key,
+                         const uint8_t *value,
+                         const uint8_t *proof,
+                         size_t proof_len);
+
+/**
+ * Releases a buffer handed out by the library and resets it to empty.
+ *
+ * # Safety
+ *
+ * `buffer` must be null or point to a buffer filled in by this library that
+ * was not released yet.
+ */
+void smt_buffer_free(struct SmtBuffer *buffer);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SMT_H */
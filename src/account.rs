@@ -1,10 +1,24 @@
+use std::sync::OnceLock;
+
 use serde::{Serialize, Deserialize};
 
+use crate::{sparse_merkle_tree::DEFAULT_DEPTH, tree_hasher::TreeHasher, DefaultHasher, Hash};
+
+/// Root of an account's storage tree while it holds no slots: the empty
+/// root of a default tree.
+pub fn empty_storage_root() -> Hash {
+    static ROOT: OnceLock<Hash> = OnceLock::new();
+    *ROOT.get_or_init(|| TreeHasher::<DefaultHasher>::new().empty(DEFAULT_DEPTH))
+}
+
 #[derive(Debug, Serialize, Deserialize, Default, PartialEq, Clone)]
 pub struct Account {
+    #[cfg_attr(feature = "serde_hex", serde(with = "crate::hex::serde_hex"))]
     pub address: [u8; 32], // Unique address for the account
     pub balance: u64,      // Account balance
     pub nonce: u64,        // Nonce to prevent replay attacks
+    #[cfg_attr(feature = "serde_hex", serde(with = "crate::hex::serde_hex"))]
+    pub storage_root: Hash, // Root of the account's storage tree
 }
 
 impl Account {
@@ -14,6 +28,7 @@ impl Account {
             address,
             balance: initial_balance,
             nonce: 0, // Start nonce at 0
+            storage_root: empty_storage_root(),
         }
     }
 
@@ -46,6 +61,7 @@ mod tests {
         assert_eq!(account.address, address);
         assert_eq!(account.balance, 100);
         assert_eq!(account.nonce, 0);
+        assert_eq!(account.storage_root, empty_storage_root());
     }
 
     #[test]
@@ -0,0 +1,87 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ArithmeticError {
+    #[error("Arithmetic overflow")]
+    Overflow,
+
+    #[error("Division by zero")]
+    DivisionByZero,
+}
+
+/// Computes `a * b / denominator` with a 128-bit intermediate, rounding down.
+pub fn mul_div(a: u64, b: u64, denominator: u64) -> Result<u64, ArithmeticError> {
+    if denominator == 0 {
+        return Err(ArithmeticError::DivisionByZero);
+    }
+    let result = a as u128 * b as u128 / denominator as u128;
+    u64::try_from(result).map_err(|_| ArithmeticError::Overflow)
+}
+
+/// Computes `a * b / denominator` with a 128-bit intermediate, rounding up.
+pub fn mul_div_ceil(a: u64, b: u64, denominator: u64) -> Result<u64, ArithmeticError> {
+    if denominator == 0 {
+        return Err(ArithmeticError::DivisionByZero);
+    }
+    let result = (a as u128 * b as u128).div_ceil(denominator as u128);
+    u64::try_from(result).map_err(|_| ArithmeticError::Overflow)
+}
+
+/// Returns `percent`% of `amount`, rounding down.
+pub fn percentage(amount: u64, percent: u64) -> Result<u64, ArithmeticError> {
+    mul_div(amount, percent, 100)
+}
+
+/// Returns `bps` basis points (1/10_000) of `amount`, rounding down.
+pub fn basis_points(amount: u64, bps: u64) -> Result<u64, ArithmeticError> {
+    mul_div(amount, bps, 10_000)
+}
+
+/// Adds two amounts, failing instead of wrapping on overflow.
+pub fn checked_add(a: u64, b: u64) -> Result<u64, ArithmeticError> {
+    a.checked_add(b).ok_or(ArithmeticError::Overflow)
+}
+
+/// Subtracts `b` from `a`, failing instead of wrapping on underflow.
+pub fn checked_sub(a: u64, b: u64) -> Result<u64, ArithmeticError> {
+    a.checked_sub(b).ok_or(ArithmeticError::Overflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mul_div_rounding() {
+        assert_eq!(mul_div(10, 1, 3), Ok(3));
+        assert_eq!(mul_div_ceil(10, 1, 3), Ok(4));
+        assert_eq!(mul_div_ceil(9, 1, 3), Ok(3));
+    }
+
+    #[test]
+    fn test_mul_div_large_intermediate() {
+        // u64::MAX * 2 overflows u64 but the quotient fits
+        assert_eq!(mul_div(u64::MAX, 2, 4), Ok(u64::MAX / 2));
+        assert_eq!(mul_div(u64::MAX, 2, 1), Err(ArithmeticError::Overflow));
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        assert_eq!(mul_div(1, 1, 0), Err(ArithmeticError::DivisionByZero));
+        assert_eq!(mul_div_ceil(1, 1, 0), Err(ArithmeticError::DivisionByZero));
+    }
+
+    #[test]
+    fn test_percentage_and_basis_points() {
+        assert_eq!(percentage(250, 10), Ok(25));
+        assert_eq!(basis_points(1_000_000, 30), Ok(3_000)); // 0.30%
+        assert_eq!(basis_points(99, 1), Ok(0)); // Rounds down
+    }
+
+    #[test]
+    fn test_checked_add_sub() {
+        assert_eq!(checked_add(u64::MAX, 1), Err(ArithmeticError::Overflow));
+        assert_eq!(checked_sub(0, 1), Err(ArithmeticError::Overflow));
+        assert_eq!(checked_sub(5, 3), Ok(2));
+    }
+}
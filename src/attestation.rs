@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::SMTError,
+    signature::{SignatureScheme, Signer},
+    Hash,
+};
+
+const DOMAIN: &[u8] = b"SimpleSparseMerkle/root-attestation/v1";
+
+/// A published root co-signed by members of a committee. Light clients
+/// trust the root once enough committee members have signed it.
+#[derive(Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "S::PublicKey: Serialize, S::Signature: Serialize",
+    deserialize = "S::PublicKey: Deserialize<'de>, S::Signature: Deserialize<'de>"
+))]
+pub struct RootAttestation<S: SignatureScheme> {
+    pub root: Hash,
+    pub version: u64,
+    pub height: u64,
+    pub signatures: Vec<(S::PublicKey, S::Signature)>,
+}
+
+impl<S: SignatureScheme> RootAttestation<S> {
+    pub fn new(root: Hash, version: u64, height: u64) -> Self {
+        Self {
+            root,
+            version,
+            height,
+            signatures: Vec::new(),
+        }
+    }
+
+    /// Bytes every committee member signs: a domain tag, then the root,
+    /// version and height (big-endian).
+    pub fn message(&self) -> Vec<u8> {
+        let mut message = Vec::with_capacity(DOMAIN.len() + 32 + 16);
+        message.extend_from_slice(DOMAIN);
+        message.extend_from_slice(&self.root);
+        message.extend_from_slice(&self.version.to_be_bytes());
+        message.extend_from_slice(&self.height.to_be_bytes());
+        message
+    }
+
+    /// Adds `signer`'s signature, replacing any earlier one from the same key.
+    pub fn sign(&mut self, signer: &impl Signer<S>) {
+        let signature = signer.sign(&self.message());
+        self.add_signature(signer.public_key(), signature);
+    }
+
+    /// Adds a signature produced elsewhere. It is not checked until `verify`.
+    pub fn add_signature(&mut self, public_key: S::PublicKey, signature: S::Signature) {
+        self.signatures.retain(|(key, _)| *key != public_key);
+        self.signatures.push((public_key, signature));
+    }
+
+    /// Number of distinct `committee` members with a valid signature.
+    pub fn valid_signers(&self, committee: &[S::PublicKey]) -> usize {
+        let message = self.message();
+        committee
+            .iter()
+            .filter(|member| {
+                self.signatures
+                    .iter()
+                    .any(|(key, signature)| key == *member && S::verify(key, &message, signature))
+            })
+            .count()
+    }
+
+    /// Checks that at least `threshold` committee members signed this root.
+    pub fn verify(&self, committee: &[S::PublicKey], threshold: usize) -> Result<(), SMTError> {
+        let valid = self.valid_signers(committee);
+        if valid < threshold {
+            return Err(SMTError::InsufficientSignatures { valid, required: threshold });
+        }
+        Ok(())
+    }
+}
+
+impl<S: SignatureScheme> Clone for RootAttestation<S> {
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root,
+            version: self.version,
+            height: self.height,
+            signatures: self.signatures.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use digest::Digest;
+    use sha2::Sha256;
+
+    /// Insecure stand-in scheme: the "private" key is the public key.
+    struct TestScheme;
+
+    impl SignatureScheme for TestScheme {
+        type PublicKey = u8;
+        type Signature = Hash;
+
+        fn verify(public_key: &u8, message: &[u8], signature: &Hash) -> bool {
+            test_sign(*public_key, message) == *signature
+        }
+    }
+
+    struct TestSigner(u8);
+
+    impl Signer<TestScheme> for TestSigner {
+        fn public_key(&self) -> u8 {
+            self.0
+        }
+
+        fn sign(&self, message: &[u8]) -> Hash {
+            test_sign(self.0, message)
+        }
+    }
+
+    fn test_sign(key: u8, message: &[u8]) -> Hash {
+        let mut hasher = Sha256::new();
+        hasher.update([key]);
+        hasher.update(message);
+        hasher.finalize().into()
+    }
+
+    #[test]
+    fn test_threshold_of_committee_signatures() {
+        let committee = [1u8, 2, 3];
+        let mut attestation = RootAttestation::<TestScheme>::new([7u8; 32], 4, 100);
+        attestation.sign(&TestSigner(1));
+        attestation.sign(&TestSigner(9)); // Not on the committee
+        assert!(matches!(
+            attestation.verify(&committee, 2),
+            Err(SMTError::InsufficientSignatures { valid: 1, required: 2 })
+        ));
+
+        attestation.sign(&TestSigner(3));
+        attestation.sign(&TestSigner(3)); // Re-signing does not count twice
+        assert_eq!(attestation.signatures.len(), 3);
+        assert!(attestation.verify(&committee, 2).is_ok());
+    }
+
+    #[test]
+    fn test_signatures_bind_root_and_height() {
+        let committee = [1u8];
+        let mut attestation = RootAttestation::<TestScheme>::new([7u8; 32], 4, 100);
+        attestation.sign(&TestSigner(1));
+
+        let mut moved = attestation.clone();
+        moved.height = 101;
+        assert!(moved.verify(&committee, 1).is_err());
+
+        let mut forged = attestation.clone();
+        forged.add_signature(1, [0u8; 32]);
+        assert_eq!(forged.valid_signers(&committee), 0);
+        assert!(attestation.verify(&committee, 1).is_ok());
+    }
+}
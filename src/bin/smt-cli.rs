@@ -0,0 +1,116 @@
+//! Inspects and edits a tree kept in a sled store, for debugging state
+//! without writing Rust. Keys, values and roots are `0x`-prefixed hex.
+//!
+//!     smt-cli <store> init
+//!     smt-cli <store> put <key> <value>
+//!     smt-cli <store> get <key>
+//!     smt-cli <store> prove <key>                      Prints the proof as JSON
+//!     smt-cli <store> verify <root> <key> <value> <proof.json | ->
+//!     smt-cli <store> root
+//!     smt-cli <store> export                           One "key value" line per leaf
+//!
+//! `verify` only reads the proof, so its store argument is not opened.
+
+use std::io::Read;
+use std::process::ExitCode;
+
+use SimpleSparseMerkle::{
+    hex::serde_hex::{decode, encode},
+    kv_store::SledStore,
+    tree_hasher::{TreeHasher, DEFAULT_DEPTH},
+    DefaultHasher, Hash, KVStore, MerkleProof, SMTError, SparseMerkleTree,
+};
+
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+const USAGE: &str = "usage: smt-cli <store> <init | put <key> <value> | get <key> | prove <key> | verify <root> <key> <value> <proof.json | -> | root | export>";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args[..] {
+        [_, "verify", root, key, value, proof] => verify(hash(root)?, hash(key)?, hash(value)?, proof),
+        [path, "init"] => {
+            let mut store = SledStore::open(path).map_err(|error| error.to_string())?;
+            if store.get_root().map_err(|error| error.to_string())?.is_some() {
+                return Err(format!("{} already holds a tree", path));
+            }
+            let root = TreeHasher::<DefaultHasher>::new().empty(DEFAULT_DEPTH);
+            store.set_root(root).map_err(|error| error.to_string())?;
+            println!("{}", encode(&root));
+            Ok(())
+        }
+        [path, command, ref rest @ ..] => {
+            let mut tree = open(path)?;
+            match (command, rest) {
+                ("put", [key, value]) => {
+                    tree.update(hash(key)?, hash(value)?).map_err(describe)?;
+                    println!("{}", encode(&tree.root()));
+                }
+                ("get", [key]) => match tree.get(hash(key)?).map_err(describe)? {
+                    Some(value) => println!("{}", encode(&value)),
+                    None => return Err(format!("{} has no leaf", key)),
+                },
+                ("prove", [key]) => {
+                    let proof = tree.get_proof(hash(key)?).map_err(describe)?;
+                    println!("{}", serde_json::to_string_pretty(&proof).map_err(|error| error.to_string())?);
+                }
+                ("root", []) => println!("{}", encode(&tree.root())),
+                ("export", []) => {
+                    for leaf in tree.iter() {
+                        let (key, value) = leaf.map_err(describe)?;
+                        println!("{} {}", encode(&key), encode(&value));
+                    }
+                }
+                _ => return Err(USAGE.to_string()),
+            }
+            Ok(())
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn open(path: &str) -> Result<SparseMerkleTree<SledStore>, String> {
+    let store = SledStore::open(path).map_err(|error| error.to_string())?;
+    if store.get_root().map_err(|error| error.to_string())?.is_none() {
+        return Err(format!("{} holds no tree, run init first", path));
+    }
+    SparseMerkleTree::open(store).map_err(describe)
+}
+
+fn verify(root: Hash, key: Hash, value: Hash, proof: &str) -> Result<(), String> {
+    let mut json = String::new();
+    match proof {
+        "-" => std::io::stdin().read_to_string(&mut json).map(|_| ()),
+        path => std::fs::read_to_string(path).map(|read| json = read),
+    }
+    .map_err(|error| format!("cannot read {}: {}", proof, error))?;
+    let proof: MerkleProof = serde_json::from_str(&json).map_err(|error| format!("bad proof: {}", error))?;
+    match proof.verify(&root, &key, &value) {
+        true => {
+            println!("valid");
+            Ok(())
+        }
+        false => Err("invalid".to_string()),
+    }
+}
+
+fn hash(hex: &str) -> Result<Hash, String> {
+    decode(hex).ok_or_else(|| format!("{} is not 0x-prefixed hex of 32 bytes", hex))
+}
+
+fn describe(error: SMTError) -> String {
+    error.to_string()
+}
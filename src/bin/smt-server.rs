@@ -0,0 +1,20 @@
+//! Serves an in-memory tree over gRPC, see `proto/smt_service.proto`.
+//!
+//!     cargo run --features server --bin smt-server -- 127.0.0.1:50051
+
+use SimpleSparseMerkle::{server::TreeService, InMemoryKVStore, SparseMerkleTree};
+
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+const DEFAULT_ADDR: &str = "127.0.0.1:50051";
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = std::env::args().nth(1).unwrap_or_else(|| DEFAULT_ADDR.to_string()).parse()?;
+    let service = TreeService::new(SparseMerkleTree::new(InMemoryKVStore::new()));
+    println!("Serving on {}", addr);
+    tonic::transport::Server::builder().add_service(service.into_server()).serve(addr).await?;
+    Ok(())
+}
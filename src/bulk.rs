@@ -0,0 +1,196 @@
+//! Parallel bulk loading of an empty tree. Keys are sorted once, and since
+//! the two halves of a sorted run land in disjoint subtrees, each half is
+//! hashed on its own thread with `rayon::join` until the runs get small.
+//! Nothing is read from the store, and everything is written in one batch.
+
+use crate::{
+    error::SMTError,
+    hex::HexFmt,
+    kv_store::{KVStore, TreeWriteBatch},
+    node::{encode_internal, encode_leaf},
+    sparse_merkle_tree::{get_bit, SparseMerkleTree},
+    tree_hasher::{TreeDigest, TreeHasher},
+    Hash,
+};
+use rayon::prelude::*;
+use tracing::{field, instrument, Span};
+
+/// Runs shorter than this are hashed on the current thread; below it the
+/// cost of handing work to another thread outweighs the hashing.
+const PARALLEL_THRESHOLD: usize = 1024;
+
+impl<S: KVStore, D: TreeDigest + Sync> SparseMerkleTree<S, D> {
+    /// Writes `entries` into the tree and returns the new root, which is the
+    /// root `update_batch` would give. Later entries win when a key repeats.
+    ///
+    /// Only an empty tree is built in parallel. A tree that already holds
+    /// leaves falls back to `update_batch`, as its nodes would have to be
+    /// read back from a store that may not be shared across threads.
+    #[instrument(name = "smt.bulk_load", skip_all, fields(entries = entries.len(), root = field::Empty))]
+    pub fn bulk_load(&mut self, entries: &[(Hash, Hash)]) -> Result<Hash, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        for (key, _) in entries {
+            self.check_key(key)?;
+        }
+        if self.root != self.hasher.empty(self.depth) {
+            return self.update_batch(entries);
+        }
+        let mut sorted = entries.to_vec();
+        sorted.par_sort_by_key(|(key, _)| *key); // Stable, so repeats keep their order
+        let mut unique: Vec<(Hash, Hash)> = Vec::with_capacity(sorted.len());
+        for (key, value) in sorted {
+            match unique.last_mut() {
+                Some(last) if last.0 == key => last.1 = value,
+                _ => unique.push((key, value)),
+            }
+        }
+
+        let mut batches = Vec::new();
+        let root = build_subtree(&self.hasher, self.depth, 0, &unique, &mut batches);
+        let mut batch = TreeWriteBatch::new();
+        for built in batches {
+            batch.extend(built);
+        }
+        batch.set_root(root);
+
+        let old_root = self.root;
+        self.commit(batch)?;
+        self.root = root;
+        if !self.observers.is_empty() {
+            let writes: Vec<(Hash, Option<Hash>)> = unique.into_iter().map(|(key, value)| (key, Some(value))).collect();
+            self.notify_batch(old_root, &writes);
+        }
+        Span::current().record("root", field::display(HexFmt(&self.root)));
+        Ok(self.root)
+    }
+}
+
+/// Hashes the subtree at `depth` holding the sorted, unique `entries`, adding
+/// the batches its nodes were written to onto `batches`.
+fn build_subtree<D: TreeDigest + Sync>(
+    hasher: &TreeHasher<D>,
+    tree_depth: usize,
+    depth: usize,
+    entries: &[(Hash, Hash)],
+    batches: &mut Vec<TreeWriteBatch>,
+) -> Hash {
+    if entries.len() < PARALLEL_THRESHOLD || depth == tree_depth {
+        let mut batch = TreeWriteBatch::new();
+        let root = build_sequential(hasher, tree_depth, depth, entries, &mut batch);
+        batches.push(batch);
+        return root;
+    }
+
+    let split = entries.partition_point(|(key, _)| get_bit(key, depth) == 0);
+    let (entries_left, entries_right) = entries.split_at(split);
+    let ((left, left_batches), (right, right_batches)) = rayon::join(
+        || {
+            let mut built = Vec::new();
+            (build_subtree(hasher, tree_depth, depth + 1, entries_left, &mut built), built)
+        },
+        || {
+            let mut built = Vec::new();
+            (build_subtree(hasher, tree_depth, depth + 1, entries_right, &mut built), built)
+        },
+    );
+    batches.extend(left_batches);
+    batches.extend(right_batches);
+
+    let current = hasher.digest_node(&left, &right);
+    let mut batch = TreeWriteBatch::new();
+    batch.set_node(current, encode_internal(&left, &right));
+    batches.push(batch);
+    current
+}
+
+fn build_sequential<D: TreeDigest>(
+    hasher: &TreeHasher<D>,
+    tree_depth: usize,
+    depth: usize,
+    entries: &[(Hash, Hash)],
+    batch: &mut TreeWriteBatch,
+) -> Hash {
+    if entries.is_empty() {
+        return hasher.empty(tree_depth - depth);
+    }
+    if depth == tree_depth {
+        // Keys are zero past the tree's depth, so only one reaches each leaf
+        let (key, value) = entries[0];
+        let leaf_hash = hasher.digest_leaf(&key, &value);
+        batch.set_node(leaf_hash, encode_leaf(&key, &value));
+        return leaf_hash;
+    }
+
+    let split = entries.partition_point(|(key, _)| get_bit(key, depth) == 0);
+    let left = build_sequential(hasher, tree_depth, depth + 1, &entries[..split], batch);
+    let right = build_sequential(hasher, tree_depth, depth + 1, &entries[split..], batch);
+    let current = hasher.digest_node(&left, &right);
+    batch.set_node(current, encode_internal(&left, &right));
+    current
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_store::InMemoryKVStore;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn random_entries(n: usize) -> Vec<(Hash, Hash)> {
+        let mut rng = StdRng::seed_from_u64(7);
+        (0..n).map(|_| (rng.gen(), rng.gen())).collect()
+    }
+
+    #[test]
+    fn test_bulk_load_matches_update_batch() {
+        let mut entries = random_entries(3 * PARALLEL_THRESHOLD);
+        entries.push((entries[10].0, [9u8; 32])); // Repeated key, later value wins
+
+        let mut bulk = SparseMerkleTree::new(InMemoryKVStore::new());
+        let mut batched = SparseMerkleTree::new(InMemoryKVStore::new());
+        let root = bulk.bulk_load(&entries).unwrap();
+
+        assert_eq!(root, batched.update_batch(&entries).unwrap());
+        assert_eq!(bulk.get(entries[10].0).unwrap(), Some([9u8; 32]));
+        let proof = bulk.get_proof(entries[500].0).unwrap();
+        assert!(proof.verify(&root, &entries[500].0, &entries[500].1));
+    }
+
+    #[test]
+    fn test_bulk_load_shallow_tree() {
+        // 2048 two-byte keys, enough to be split across threads
+        let entries: Vec<(Hash, Hash)> = random_entries(2 * PARALLEL_THRESHOLD)
+            .into_iter()
+            .map(|(mut key, value)| {
+                key[2..].fill(0);
+                (key, value)
+            })
+            .collect();
+        let mut bulk = SparseMerkleTree::new(InMemoryKVStore::new()).with_depth(16);
+        let mut batched = SparseMerkleTree::new(InMemoryKVStore::new()).with_depth(16);
+        assert_eq!(bulk.bulk_load(&entries).unwrap(), batched.update_batch(&entries).unwrap());
+
+        let mut long = SparseMerkleTree::new(InMemoryKVStore::new()).with_depth(16);
+        assert!(matches!(long.bulk_load(&random_entries(1)), Err(SMTError::KeyBeyondDepth { depth: 16, .. })));
+    }
+
+    #[test]
+    fn test_bulk_load_into_populated_tree() {
+        let entries = random_entries(100);
+        let mut bulk = SparseMerkleTree::new(InMemoryKVStore::new());
+        let mut batched = SparseMerkleTree::new(InMemoryKVStore::new());
+        bulk.update([1u8; 32], [1u8; 32]).unwrap();
+        batched.update([1u8; 32], [1u8; 32]).unwrap();
+
+        assert_eq!(bulk.bulk_load(&entries).unwrap(), batched.update_batch(&entries).unwrap());
+        assert_eq!(bulk.get([1u8; 32]).unwrap(), Some([1u8; 32]));
+    }
+
+    #[test]
+    fn test_bulk_load_nothing() {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        let empty = smt.root();
+        assert_eq!(smt.bulk_load(&[]).unwrap(), empty);
+    }
+}
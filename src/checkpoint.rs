@@ -0,0 +1,223 @@
+use digest::Digest;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::SMTError,
+    clock::Clock,
+    signature::{SignatureScheme, Signer},
+    DefaultHasher, Hash,
+};
+
+const FORMAT_VERSION: u8 = 1;
+
+/// Length of `CheckpointDocument::to_bytes`.
+pub const CHECKPOINT_LEN: usize = 1 + 32 + 8 + 32 + 8;
+
+/// Compact record of a tree root meant for anchoring on an external chain or
+/// transparency log. Each checkpoint commits to the one before it, so a
+/// published sequence forms a hash chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckpointDocument {
+    pub root: Hash,
+    pub version: u64,
+    pub prev: Hash, // `hash()` of the previous checkpoint, zero for the first
+    pub timestamp: u64,
+}
+
+impl CheckpointDocument {
+    /// First checkpoint of a chain.
+    pub fn genesis(root: Hash, version: u64, timestamp: u64) -> Self {
+        Self {
+            root,
+            version,
+            prev: [0u8; 32],
+            timestamp,
+        }
+    }
+
+    /// Checkpoint following this one.
+    pub fn next(&self, root: Hash, version: u64, timestamp: u64) -> Self {
+        Self {
+            root,
+            version,
+            prev: self.hash(),
+            timestamp,
+        }
+    }
+
+    /// Checkpoint following this one, stamped by `clock`. A clock behind this
+    /// checkpoint's timestamp is clamped to it, keeping the chain valid.
+    pub fn next_now(&self, root: Hash, version: u64, clock: &impl Clock) -> Self {
+        self.next(root, version, clock.now().max(self.timestamp))
+    }
+
+    /// Canonical encoding: a format byte, then root, version, prev and
+    /// timestamp, integers big-endian.
+    pub fn to_bytes(&self) -> [u8; CHECKPOINT_LEN] {
+        let mut bytes = [0u8; CHECKPOINT_LEN];
+        bytes[0] = FORMAT_VERSION;
+        bytes[1..33].copy_from_slice(&self.root);
+        bytes[33..41].copy_from_slice(&self.version.to_be_bytes());
+        bytes[41..73].copy_from_slice(&self.prev);
+        bytes[73..81].copy_from_slice(&self.timestamp.to_be_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SMTError> {
+        if bytes.len() != CHECKPOINT_LEN || bytes[0] != FORMAT_VERSION {
+            return Err(SMTError::InvalidEncoding);
+        }
+        Ok(Self {
+            root: bytes[1..33].try_into().unwrap(),
+            version: u64::from_be_bytes(bytes[33..41].try_into().unwrap()),
+            prev: bytes[41..73].try_into().unwrap(),
+            timestamp: u64::from_be_bytes(bytes[73..81].try_into().unwrap()),
+        })
+    }
+
+    /// Hash of the canonical encoding, which the next checkpoint links to.
+    pub fn hash(&self) -> Hash {
+        DefaultHasher::digest(self.to_bytes()).into()
+    }
+
+    pub fn sign<S: SignatureScheme>(self, signer: &impl Signer<S>) -> SignedCheckpoint<S> {
+        SignedCheckpoint {
+            signer: signer.public_key(),
+            signature: signer.sign(&self.to_bytes()),
+            checkpoint: self,
+        }
+    }
+}
+
+/// Checks that each checkpoint links to the one before it, with versions
+/// strictly increasing and timestamps never going backwards. Fails with the
+/// index of the first checkpoint that breaks the chain.
+pub fn verify_chain(chain: &[CheckpointDocument]) -> Result<(), SMTError> {
+    for (index, pair) in chain.windows(2).enumerate() {
+        let (prev, next) = (&pair[0], &pair[1]);
+        if next.prev != prev.hash() || next.version <= prev.version || next.timestamp < prev.timestamp {
+            return Err(SMTError::BrokenChain(index + 1));
+        }
+    }
+    Ok(())
+}
+
+/// A checkpoint with its publisher's signature over the canonical encoding.
+#[derive(Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "S::PublicKey: Serialize, S::Signature: Serialize",
+    deserialize = "S::PublicKey: Deserialize<'de>, S::Signature: Deserialize<'de>"
+))]
+pub struct SignedCheckpoint<S: SignatureScheme> {
+    pub checkpoint: CheckpointDocument,
+    pub signer: S::PublicKey,
+    pub signature: S::Signature,
+}
+
+impl<S: SignatureScheme> SignedCheckpoint<S> {
+    /// Checks the signature and that it was made by `publisher`.
+    pub fn verify(&self, publisher: &S::PublicKey) -> bool {
+        self.signer == *publisher && S::verify(&self.signer, &self.checkpoint.to_bytes(), &self.signature)
+    }
+}
+
+impl<S: SignatureScheme> Clone for SignedCheckpoint<S> {
+    fn clone(&self) -> Self {
+        Self {
+            checkpoint: self.checkpoint,
+            signer: self.signer.clone(),
+            signature: self.signature.clone(),
+        }
+    }
+}
+
+/// Checks every signature against `publisher`, then the chain itself.
+pub fn verify_signed_chain<S: SignatureScheme>(chain: &[SignedCheckpoint<S>], publisher: &S::PublicKey) -> Result<(), SMTError> {
+    if let Some(index) = chain.iter().position(|signed| !signed.verify(publisher)) {
+        return Err(SMTError::BrokenChain(index));
+    }
+    let documents: Vec<CheckpointDocument> = chain.iter().map(|signed| signed.checkpoint).collect();
+    verify_chain(&documents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Insecure stand-in scheme: the signature is a hash of key and message.
+    struct TestScheme;
+
+    impl SignatureScheme for TestScheme {
+        type PublicKey = u8;
+        type Signature = Hash;
+
+        fn verify(public_key: &u8, message: &[u8], signature: &Hash) -> bool {
+            TestSigner(*public_key).sign(message) == *signature
+        }
+    }
+
+    struct TestSigner(u8);
+
+    impl Signer<TestScheme> for TestSigner {
+        fn public_key(&self) -> u8 {
+            self.0
+        }
+
+        fn sign(&self, message: &[u8]) -> Hash {
+            let mut hasher = DefaultHasher::new();
+            hasher.update([self.0]);
+            hasher.update(message);
+            hasher.finalize().into()
+        }
+    }
+
+    fn chain() -> Vec<CheckpointDocument> {
+        let first = CheckpointDocument::genesis([1u8; 32], 1, 1000);
+        let second = first.next([2u8; 32], 5, 1060);
+        let third = second.next([3u8; 32], 9, 1060);
+        vec![first, second, third]
+    }
+
+    #[test]
+    fn test_encoding_roundtrip() {
+        let checkpoint = chain()[1];
+        let bytes = checkpoint.to_bytes();
+        assert_eq!(CheckpointDocument::from_bytes(&bytes).unwrap(), checkpoint);
+        assert!(CheckpointDocument::from_bytes(&bytes[1..]).is_err());
+    }
+
+    #[test]
+    fn test_chain_verification() {
+        let mut chain = chain();
+        assert!(verify_chain(&chain).is_ok());
+
+        chain[1].root = [9u8; 32]; // Rewriting history breaks the next link
+        assert!(matches!(verify_chain(&chain), Err(SMTError::BrokenChain(2))));
+    }
+
+    #[test]
+    fn test_signed_chain() {
+        let publisher = TestSigner(7);
+        let mut signed: Vec<SignedCheckpoint<TestScheme>> =
+            chain().into_iter().map(|checkpoint| checkpoint.sign(&publisher)).collect();
+        assert!(verify_signed_chain(&signed, &7).is_ok());
+        assert!(verify_signed_chain(&signed, &8).is_err());
+
+        signed[2].checkpoint.timestamp += 1;
+        assert!(matches!(verify_signed_chain(&signed, &7), Err(SMTError::BrokenChain(2))));
+    }
+
+    #[cfg(feature = "test-clock")]
+    #[test]
+    fn test_next_now_never_goes_backwards() {
+        let clock = crate::clock::TestClock::new(2000);
+        let mut chain = chain();
+        chain.push(chain[2].next_now([4u8; 32], 10, &clock));
+        clock.set(500);
+        chain.push(chain[3].next_now([5u8; 32], 11, &clock));
+
+        assert_eq!(chain[3].timestamp, 2000);
+        assert_eq!(chain[4].timestamp, 2000);
+        assert!(verify_chain(&chain).is_ok());
+    }
+}
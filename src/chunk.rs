@@ -0,0 +1,204 @@
+use digest::Digest;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::SMTError,
+    kv_store::KVStore,
+    snapshot::TreeSnapshot,
+    sparse_merkle_tree::SparseMerkleTree,
+    tree_hasher::TreeDigest,
+    DefaultHasher, Hash,
+};
+
+const FORMAT_VERSION: u8 = 1;
+
+/// Largest `prefix_bits` accepted by `TreeSnapshot::export_chunks`.
+pub const MAX_PREFIX_BITS: u8 = 24;
+
+/// The leaves of one fixed slice of the key space: every key whose first
+/// `prefix_bits` bits equal `index`. Slices don't depend on what else is in
+/// the tree, so a chunk whose slice did not change between two snapshots is
+/// byte-for-byte identical and has the same `id`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotChunk {
+    pub prefix_bits: u8,
+    pub index: u32,
+    pub entries: Vec<(Hash, Hash)>, // In key order
+}
+
+/// Ids of the non-empty chunks of a snapshot, in index order, and the root
+/// they rebuild.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub root: Hash,
+    pub prefix_bits: u8,
+    pub chunks: Vec<(u32, Hash)>, // (index, id)
+}
+
+impl SnapshotChunk {
+    /// Canonical encoding: a format byte, `prefix_bits`, then the index and
+    /// entry count big-endian, then each key and value.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(10 + 64 * self.entries.len());
+        bytes.push(FORMAT_VERSION);
+        bytes.push(self.prefix_bits);
+        bytes.extend_from_slice(&self.index.to_be_bytes());
+        bytes.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        for (key, value) in &self.entries {
+            bytes.extend_from_slice(key);
+            bytes.extend_from_slice(value);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SMTError> {
+        if bytes.len() < 10 || bytes[0] != FORMAT_VERSION {
+            return Err(SMTError::InvalidEncoding);
+        }
+        let count = u32::from_be_bytes(bytes[6..10].try_into().unwrap()) as usize;
+        let body = &bytes[10..];
+        if count.checked_mul(64) != Some(body.len()) {
+            return Err(SMTError::InvalidEncoding);
+        }
+        let entries = body
+            .chunks_exact(64)
+            .map(|entry| (entry[..32].try_into().unwrap(), entry[32..].try_into().unwrap()))
+            .collect();
+        Ok(Self {
+            prefix_bits: bytes[1],
+            index: u32::from_be_bytes(bytes[2..6].try_into().unwrap()),
+            entries,
+        })
+    }
+
+    /// Content address of the chunk: the hash of its canonical encoding.
+    pub fn id(&self) -> Hash {
+        DefaultHasher::digest(self.to_bytes()).into()
+    }
+
+    /// Whether every entry belongs to this chunk's slice, in strict key order.
+    fn is_well_formed(&self) -> bool {
+        self.prefix_bits <= MAX_PREFIX_BITS
+            && self.entries.iter().all(|(key, _)| slice_index(key, self.prefix_bits) == self.index)
+            && self.entries.windows(2).all(|pair| pair[0].0 < pair[1].0)
+    }
+}
+
+/// Index of the slice holding `key` when the key space is split on its first
+/// `prefix_bits` bits.
+fn slice_index(key: &Hash, prefix_bits: u8) -> u32 {
+    let top = u32::from_be_bytes(key[..4].try_into().unwrap());
+    top.checked_shr(32 - prefix_bits as u32).unwrap_or(0)
+}
+
+impl<S: KVStore, D: TreeDigest> TreeSnapshot<S, D>
+where
+    SMTError: From<S::Error>,
+{
+    /// Splits the snapshot into `2^prefix_bits` equal slices of the key space
+    /// and returns the non-empty ones as chunks, with a manifest naming them.
+    pub fn export_chunks(&self, prefix_bits: u8) -> Result<(SnapshotManifest, Vec<SnapshotChunk>), SMTError> {
+        if prefix_bits > MAX_PREFIX_BITS {
+            return Err(SMTError::UnsupportedOperation);
+        }
+
+        let mut chunks: Vec<SnapshotChunk> = Vec::new();
+        for leaf in self.iter() {
+            let (key, value) = leaf?;
+            let index = slice_index(&key, prefix_bits);
+            match chunks.last_mut() {
+                Some(chunk) if chunk.index == index => chunk.entries.push((key, value)),
+                _ => chunks.push(SnapshotChunk { prefix_bits, index, entries: vec![(key, value)] }),
+            }
+        }
+
+        let manifest = SnapshotManifest {
+            root: self.root(),
+            prefix_bits,
+            chunks: chunks.iter().map(|chunk| (chunk.index, chunk.id())).collect(),
+        };
+        Ok((manifest, chunks))
+    }
+}
+
+/// Rebuilds a tree in `store` from the chunks named by `manifest`, given in
+/// manifest order. Fails if a chunk doesn't match its id or the rebuilt root
+/// differs from the manifest's.
+pub fn restore_from_chunks<S: KVStore>(store: S, manifest: &SnapshotManifest, chunks: &[SnapshotChunk]) -> Result<SparseMerkleTree<S>, SMTError>
+where
+    SMTError: From<S::Error>,
+{
+    if chunks.len() != manifest.chunks.len() {
+        return Err(SMTError::InvalidEncoding);
+    }
+    let mut entries = Vec::new();
+    for (chunk, (index, id)) in chunks.iter().zip(&manifest.chunks) {
+        if chunk.prefix_bits != manifest.prefix_bits || chunk.index != *index || !chunk.is_well_formed() || chunk.id() != *id {
+            return Err(SMTError::InvalidEncoding);
+        }
+        entries.extend_from_slice(&chunk.entries);
+    }
+
+    let mut tree = SparseMerkleTree::new(store);
+    if tree.update_batch(&entries)? != manifest.root {
+        return Err(SMTError::RootMismatch);
+    }
+    Ok(tree)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_store::InMemoryKVStore;
+
+    fn tree(entries: &[(u8, u8)]) -> SparseMerkleTree<InMemoryKVStore> {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        for (key, value) in entries {
+            smt.update([*key; 32], [*value; 32]).unwrap();
+        }
+        smt
+    }
+
+    #[test]
+    fn test_export_and_restore() {
+        let smt = tree(&[(0x01, 1), (0x02, 2), (0x81, 3), (0xf0, 4)]);
+        let (manifest, chunks) = smt.snapshot().export_chunks(1).unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1].entries.len(), 2);
+
+        let restored = restore_from_chunks(InMemoryKVStore::new(), &manifest, &chunks).unwrap();
+        assert_eq!(restored.root(), smt.root());
+    }
+
+    #[test]
+    fn test_unchanged_slices_keep_their_ids() {
+        let mut smt = tree(&[(0x01, 1), (0x41, 2), (0x81, 3)]);
+        let (before, _) = smt.snapshot().export_chunks(2).unwrap();
+        smt.update([0x42; 32], [5u8; 32]).unwrap();
+        let (after, _) = smt.snapshot().export_chunks(2).unwrap();
+
+        assert_eq!(before.chunks[0], after.chunks[0]);
+        assert_ne!(before.chunks[1], after.chunks[1]);
+        assert_eq!(before.chunks[2], after.chunks[2]);
+    }
+
+    #[test]
+    fn test_chunk_roundtrips_through_bytes() {
+        let smt = tree(&[(0x01, 1), (0x02, 2)]);
+        let (_, chunks) = smt.snapshot().export_chunks(8).unwrap();
+        for chunk in &chunks {
+            assert_eq!(&SnapshotChunk::from_bytes(&chunk.to_bytes()).unwrap(), chunk);
+        }
+        assert!(matches!(SnapshotChunk::from_bytes(&chunks[0].to_bytes()[..20]), Err(SMTError::InvalidEncoding)));
+    }
+
+    #[test]
+    fn test_restore_rejects_tampered_chunk() {
+        let smt = tree(&[(0x01, 1), (0x81, 3)]);
+        let (manifest, mut chunks) = smt.snapshot().export_chunks(1).unwrap();
+        chunks[0].entries[0].1 = [9u8; 32];
+
+        let result = restore_from_chunks(InMemoryKVStore::new(), &manifest, &chunks);
+        assert!(matches!(result, Err(SMTError::InvalidEncoding)));
+    }
+}
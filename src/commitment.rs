@@ -0,0 +1,118 @@
+//! Commitment to a tree's root together with how the tree is built. Two
+//! trees that differ in depth, hasher, prefixes or key domain may in
+//! principle share a root; they never share a commitment.
+
+use digest::Digest;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::SMTError,
+    hashers::HasherId,
+    kv_store::KVStore,
+    proof::MerkleProof,
+    sparse_merkle_tree::SparseMerkleTree,
+    spec::{verify_with_spec, TreeSpec},
+    DefaultHasher, Hash,
+};
+
+const COMMITMENT_DOMAIN: &[u8] = b"SimpleSparseMerkle/commitment/v1";
+
+/// Root, spec and leaf count of a tree, which `hash` commits to. Publish the
+/// hash and hand verifiers the opening, this struct, with each proof.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreeCommitment {
+    pub root: Hash,
+    pub spec: TreeSpec,
+    pub leaf_count: u64,
+}
+
+impl TreeCommitment {
+    /// Canonical encoding: the domain tag, the root, the depth, the hasher id
+    /// after its length byte, both prefixes, the empty root, the key domain
+    /// after a byte saying whether there is one, and the leaf count, integers
+    /// big-endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let id = self.spec.hasher_id.as_bytes();
+        let mut bytes = Vec::with_capacity(COMMITMENT_DOMAIN.len() + 32 + 2 + 1 + id.len() + 2 + 32 + 33 + 8);
+        bytes.extend_from_slice(COMMITMENT_DOMAIN);
+        bytes.extend_from_slice(&self.root);
+        bytes.extend_from_slice(&self.spec.depth.to_be_bytes());
+        bytes.push(id.len() as u8);
+        bytes.extend_from_slice(id);
+        bytes.extend_from_slice(&[self.spec.leaf_prefix, self.spec.node_prefix]);
+        bytes.extend_from_slice(&self.spec.empty_root);
+        match &self.spec.key_domain {
+            Some(domain) => {
+                bytes.push(1);
+                bytes.extend_from_slice(domain);
+            }
+            None => bytes.push(0),
+        }
+        bytes.extend_from_slice(&self.leaf_count.to_be_bytes());
+        bytes
+    }
+
+    /// The commitment, hashed with `DefaultHasher` whatever the tree's own
+    /// hasher.
+    pub fn hash(&self) -> Hash {
+        DefaultHasher::digest(self.to_bytes()).into()
+    }
+
+    /// Checks that this opens `commitment`, then the proof of `key` holding
+    /// `value` against the committed root and spec. Fails with
+    /// `RootMismatch` if it opens another commitment, and as
+    /// `verify_with_spec` does otherwise.
+    pub fn verify(&self, commitment: &Hash, key: &Hash, value: &Hash, proof: &MerkleProof) -> Result<bool, SMTError> {
+        if self.hash() != *commitment {
+            return Err(SMTError::RootMismatch);
+        }
+        verify_with_spec(&self.spec, &self.root, key, value, proof)
+    }
+}
+
+impl<S: KVStore, D: HasherId> SparseMerkleTree<S, D>
+where
+    SMTError: From<S::Error>,
+{
+    /// Opening of the tree's current commitment. This walks the whole tree
+    /// to count its leaves.
+    pub fn commitment(&self) -> Result<TreeCommitment, SMTError> {
+        Ok(TreeCommitment { root: self.root(), spec: self.spec(), leaf_count: self.len()? as u64 })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hashers::Keccak256, kv_store::InMemoryKVStore};
+
+    #[test]
+    fn test_proof_verifies_against_commitment() {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        smt.update_batch(&[([1u8; 32], [10u8; 32]), ([2u8; 32], [20u8; 32])]).unwrap();
+        let opening = smt.commitment().unwrap();
+        assert_eq!(opening.leaf_count, 2);
+        let commitment = opening.hash();
+
+        let proof = smt.get_proof([1u8; 32]).unwrap();
+        assert!(opening.verify(&commitment, &[1u8; 32], &[10u8; 32], &proof).unwrap());
+        assert!(!opening.verify(&commitment, &[1u8; 32], &[20u8; 32], &proof).unwrap());
+
+        let lying = TreeCommitment { leaf_count: 3, ..opening };
+        assert!(matches!(lying.verify(&commitment, &[1u8; 32], &[10u8; 32], &proof), Err(SMTError::RootMismatch)));
+    }
+
+    #[test]
+    fn test_parameters_change_the_commitment() {
+        let default = SparseMerkleTree::new(InMemoryKVStore::new()).commitment().unwrap();
+        let shallow = SparseMerkleTree::new(InMemoryKVStore::new()).with_depth(160).commitment().unwrap();
+        let keccak = SparseMerkleTree::<_, Keccak256>::with_hasher(InMemoryKVStore::new()).commitment().unwrap();
+        assert_ne!(default.hash(), shallow.hash());
+        assert_ne!(default.hash(), keccak.hash());
+
+        // Same root, different claimed metadata
+        let relabeled = TreeCommitment { spec: TreeSpec { depth: 255, ..default.spec.clone() }, ..default.clone() };
+        assert_eq!(relabeled.root, default.root);
+        assert_ne!(relabeled.hash(), default.hash());
+    }
+}
@@ -0,0 +1,169 @@
+//! A tree shared between threads: any number of readers serving gets and
+//! proofs, and writers applying blocks one at a time. Readers work on the
+//! last published `TreeSnapshot` and never wait on a writer, save for the
+//! moment it swaps in a new one.
+
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+
+use crate::{
+    error::SMTError,
+    kv_store::KVStore,
+    proof::MerkleProof,
+    snapshot::TreeSnapshot,
+    sparse_merkle_tree::SparseMerkleTree,
+    tree_hasher::TreeDigest,
+    DefaultHasher, Hash,
+};
+
+/// Tree behind `&self` methods, to share in an `Arc`. Snapshots clone the
+/// store, so use a store whose clones share data, such as `InMemoryKVStore`.
+pub struct ConcurrentSmt<S: KVStore + Clone, D: TreeDigest = DefaultHasher> {
+    writer: Mutex<SparseMerkleTree<S, D>>,
+    published: RwLock<Arc<TreeSnapshot<S, D>>>,
+}
+
+impl<S: KVStore + Clone, D: TreeDigest> ConcurrentSmt<S, D>
+where
+    SMTError: From<S::Error>,
+{
+    pub fn new(tree: SparseMerkleTree<S, D>) -> Self {
+        let published = RwLock::new(Arc::new(tree.snapshot()));
+        Self { writer: Mutex::new(tree), published }
+    }
+
+    /// The state as of the last write. It stays valid, and unchanged, for as
+    /// long as it is held; use one snapshot for reads that must agree.
+    pub fn snapshot(&self) -> Arc<TreeSnapshot<S, D>> {
+        self.published.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    pub fn root(&self) -> Hash {
+        self.snapshot().root()
+    }
+
+    pub fn get(&self, key: Hash) -> Result<Option<Hash>, SMTError> {
+        self.snapshot().get(key)
+    }
+
+    pub fn get_proof(&self, key: Hash) -> Result<MerkleProof, SMTError> {
+        self.snapshot().get_proof(key)
+    }
+
+    /// Runs `f` on the tree with other writers shut out, then publishes the
+    /// result to readers. If `f` fails, whatever it wrote is rolled back and
+    /// readers keep the last published state.
+    pub fn write<T>(&self, f: impl FnOnce(&mut SparseMerkleTree<S, D>) -> Result<T, SMTError>) -> Result<T, SMTError> {
+        let mut tree = self.lock_writer();
+        let checkpoint = tree.checkpoint();
+        let value = match f(&mut tree) {
+            Ok(value) => value,
+            Err(error) => {
+                tree.rollback(checkpoint)?;
+                return Err(error);
+            }
+        };
+        let snapshot = Arc::new(tree.snapshot());
+        *self.published.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = snapshot;
+        Ok(value)
+    }
+
+    pub fn update(&self, key: Hash, value: Hash) -> Result<(), SMTError> {
+        self.write(|tree| tree.update(key, value))
+    }
+
+    pub fn delete(&self, key: Hash) -> Result<(), SMTError> {
+        self.write(|tree| tree.delete(key))
+    }
+
+    pub fn update_batch(&self, entries: &[(Hash, Hash)]) -> Result<Hash, SMTError> {
+        self.write(|tree| tree.update_batch(entries))
+    }
+
+    pub fn into_inner(self) -> SparseMerkleTree<S, D> {
+        self.writer.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn lock_writer(&self) -> MutexGuard<'_, SparseMerkleTree<S, D>> {
+        self.writer.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_store::InMemoryKVStore;
+    use std::thread;
+
+    #[test]
+    fn test_readers_see_whole_blocks() {
+        let smt = Arc::new(ConcurrentSmt::new(SparseMerkleTree::new(InMemoryKVStore::new())));
+        let writer = {
+            let smt = smt.clone();
+            thread::spawn(move || {
+                for block in 1..=20u8 {
+                    let entries: Vec<(Hash, Hash)> = (0..4u8).map(|i| ([i; 32], [block; 32])).collect();
+                    smt.update_batch(&entries).unwrap();
+                }
+            })
+        };
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let smt = smt.clone();
+                thread::spawn(move || {
+                    for _ in 0..50 {
+                        // Every key holds the same block number in any one snapshot
+                        let snapshot = smt.snapshot();
+                        let values: Vec<_> = (0..4u8).map(|i| snapshot.get([i; 32]).unwrap()).collect();
+                        assert!(values.iter().all(|value| *value == values[0]));
+                        if let Some(value) = values[0] {
+                            assert!(snapshot.get_proof([0u8; 32]).unwrap().verify(&snapshot.root(), &[0u8; 32], &value));
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        writer.join().unwrap();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(smt.get([3u8; 32]).unwrap(), Some([20u8; 32]));
+    }
+
+    #[test]
+    fn test_held_snapshot_is_unchanged() {
+        let smt = ConcurrentSmt::new(SparseMerkleTree::new(InMemoryKVStore::new()));
+        smt.update([1u8; 32], [10u8; 32]).unwrap();
+        let before = smt.snapshot();
+        smt.write(|tree| {
+            tree.update([1u8; 32], [11u8; 32])?;
+            tree.delete([1u8; 32])
+        })
+        .unwrap();
+
+        assert_eq!(before.get([1u8; 32]).unwrap(), Some([10u8; 32]));
+        assert_eq!(smt.get([1u8; 32]).unwrap(), None);
+        assert_eq!(smt.into_inner().root(), SparseMerkleTree::new(InMemoryKVStore::new()).root());
+    }
+
+    #[test]
+    fn test_failed_write_is_rolled_back() {
+        let smt = ConcurrentSmt::new(SparseMerkleTree::new(InMemoryKVStore::new()));
+        smt.update([1u8; 32], [10u8; 32]).unwrap();
+        let (root, before) = (smt.root(), smt.snapshot());
+
+        let result: Result<(), SMTError> = smt.write(|tree| {
+            tree.update([1u8; 32], [11u8; 32])?;
+            tree.update([2u8; 32], [20u8; 32])?;
+            Err(SMTError::InvalidProof)
+        });
+
+        assert!(matches!(result, Err(SMTError::InvalidProof)));
+        assert_eq!(smt.root(), root);
+        assert!(Arc::ptr_eq(&smt.snapshot(), &before));
+        let tree = smt.into_inner();
+        assert_eq!(tree.root(), root);
+        assert_eq!(tree.get([1u8; 32]).unwrap(), Some([10u8; 32]));
+        assert_eq!(tree.get([2u8; 32]).unwrap(), None);
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::SMTError,
+    kv_store::KVStore,
+    proof::{MultiProof, MultiProofReader},
+    sparse_merkle_tree::{get_bit, SparseMerkleTree},
+    tree_hasher::{TreeDigest, TreeHasher},
+    DefaultHasher, Hash,
+};
+
+/// Proof that applying a batch of updates to one root yields another.
+///
+/// It holds the values the updated keys had under the old root (`None` for
+/// keys that were absent) and a multiproof for those keys. Siblings off the
+/// updated paths are untouched by the batch, so the same siblings rebuild
+/// the old root from the old values and the new root from the new ones.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DiffProof {
+    pub old_values: Vec<(Hash, Option<Hash>)>, // In key order
+    pub siblings: MultiProof,
+}
+
+impl DiffProof {
+    /// Checks that `update_batch(updates)` on the tree at `old_root` gives
+    /// `new_root`. As in `update_batch`, later entries win when a key repeats.
+    pub fn verify(&self, old_root: &Hash, new_root: &Hash, updates: &[(Hash, Hash)]) -> bool {
+        self.verify_with(&TreeHasher::<DefaultHasher>::new(), old_root, new_root, updates)
+    }
+
+    /// Like `verify`, but hashing with `hasher` instead of the default one.
+    pub fn verify_with<D: TreeDigest>(&self, hasher: &TreeHasher<D>, old_root: &Hash, new_root: &Hash, updates: &[(Hash, Hash)]) -> bool {
+        let siblings = &self.siblings;
+        if !siblings.is_well_formed() {
+            return false;
+        }
+        let new_values: Vec<(Hash, Option<Hash>)> = dedup(updates).into_iter().map(|(key, value)| (key, Some(value))).collect();
+        if new_values.is_empty() {
+            return self.old_values.is_empty() && siblings.len == 0 && old_root == new_root;
+        }
+        if self.old_values.len() != new_values.len()
+            || self.old_values.iter().zip(&new_values).any(|(old, new)| old.0 != new.0)
+        {
+            return false;
+        }
+
+        roots_to(hasher, siblings, &self.old_values) == Some(*old_root)
+            && roots_to(hasher, siblings, &new_values) == Some(*new_root)
+    }
+}
+
+/// Checks `proof` for the transition from `old_root` to `new_root`.
+pub fn verify_diff(old_root: &Hash, new_root: &Hash, updates: &[(Hash, Hash)], proof: &DiffProof) -> bool {
+    proof.verify(old_root, new_root, updates)
+}
+
+fn dedup(updates: &[(Hash, Hash)]) -> Vec<(Hash, Hash)> {
+    updates.iter().copied().collect::<BTreeMap<_, _>>().into_iter().collect()
+}
+
+/// Root of the tree holding `entries` on top of `siblings`, if the proof is
+/// shaped for exactly those keys.
+fn roots_to<D: TreeDigest>(hasher: &TreeHasher<D>, siblings: &MultiProof, entries: &[(Hash, Option<Hash>)]) -> Option<Hash> {
+    let mut reader = MultiProofReader::new(siblings);
+    let root = subtree_root(hasher, &mut reader, entries, 0)?;
+    reader.is_exhausted().then_some(root)
+}
+
+// Same walk as `MultiProof::verify_with`, except that absent leaves are empty.
+fn subtree_root<D: TreeDigest>(hasher: &TreeHasher<D>, reader: &mut MultiProofReader, entries: &[(Hash, Option<Hash>)], depth: usize) -> Option<Hash> {
+    if depth == reader.depth() {
+        // Writes reject keys set past the tree's depth (`KeyBeyondDepth`), so a
+        // leaf holds exactly one key and more entries here cannot be verified.
+        return match entries {
+            [(key, Some(value))] => Some(hasher.digest_leaf(key, value)),
+            [(_, None)] => Some(hasher.empty(0)),
+            _ => None,
+        };
+    }
+
+    let split = entries.partition_point(|(key, _)| get_bit(key, depth) == 0);
+    let (left_entries, right_entries) = entries.split_at(split);
+    let height = reader.depth() - depth - 1; // Of the children
+    let (left, right) = if right_entries.is_empty() {
+        let right = reader.next_sibling(hasher, height)?;
+        (subtree_root(hasher, reader, left_entries, depth + 1)?, right)
+    } else if left_entries.is_empty() {
+        let left = reader.next_sibling(hasher, height)?;
+        (left, subtree_root(hasher, reader, right_entries, depth + 1)?)
+    } else {
+        let left = subtree_root(hasher, reader, left_entries, depth + 1)?;
+        (left, subtree_root(hasher, reader, right_entries, depth + 1)?)
+    };
+    Some(hasher.digest_node(&left, &right))
+}
+
+impl<S: KVStore, D: TreeDigest> SparseMerkleTree<S, D>
+where
+    SMTError: From<S::Error>,
+{
+    /// Proves the effect of `update_batch(updates)` on the tree at `old_root`,
+    /// which may be the current root or any earlier one still in the store.
+    pub fn prove_update_batch(&self, old_root: Hash, updates: &[(Hash, Hash)]) -> Result<DiffProof, SMTError> {
+        let keys: Vec<Hash> = dedup(updates).into_iter().map(|(key, _)| key).collect();
+
+        let mut old_values = Vec::with_capacity(keys.len());
+        for key in &keys {
+            old_values.push((*key, self.leaf_at(old_root, key)?));
+        }
+        let mut siblings = MultiProof::new(self.depth);
+        if !keys.is_empty() {
+            self.collect_multiproof(old_root, &keys, 0, &mut siblings)?;
+        }
+        Ok(DiffProof { old_values, siblings })
+    }
+
+    /// Value of `key` under `root`, read from the leaf record at the end of
+    /// its path.
+    fn leaf_at(&self, root: Hash, key: &Hash) -> Result<Option<Hash>, SMTError> {
+        let mut current = root;
+        for depth in 0..self.depth {
+            if self.hasher.is_empty(&current) {
+                return Ok(None);
+            }
+            let (left, right) = self.read_node(&current)?;
+            current = if get_bit(key, depth) == 0 { left } else { right };
+        }
+        if self.hasher.is_empty(&current) {
+            return Ok(None);
+        }
+        let (leaf_key, value) = self.read_leaf(&current)?;
+        Ok((leaf_key == *key).then_some(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_store::InMemoryKVStore;
+
+    fn seeded() -> SparseMerkleTree<InMemoryKVStore> {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        smt.update_batch(&[([1u8; 32], [10u8; 32]), ([2u8; 32], [20u8; 32]), ([3u8; 32], [30u8; 32])]).unwrap();
+        smt
+    }
+
+    #[test]
+    fn test_diff_proof_verifies_transition() {
+        let mut smt = seeded();
+        let old_root = smt.root();
+        // One overwrite, one fresh key, and a repeat where the last entry wins.
+        let updates = [([2u8; 32], [21u8; 32]), ([9u8; 32], [90u8; 32]), ([2u8; 32], [22u8; 32])];
+
+        let proof = smt.prove_update_batch(old_root, &updates).unwrap();
+        let new_root = smt.update_batch(&updates).unwrap();
+
+        assert_eq!(proof.old_values, vec![([2u8; 32], Some([20u8; 32])), ([9u8; 32], None)]);
+        assert!(verify_diff(&old_root, &new_root, &updates, &proof));
+    }
+
+    #[test]
+    fn test_diff_proof_rejects_other_updates() {
+        let mut smt = seeded();
+        let old_root = smt.root();
+        let updates = [([2u8; 32], [21u8; 32])];
+
+        let proof = smt.prove_update_batch(old_root, &updates).unwrap();
+        let new_root = smt.update_batch(&updates).unwrap();
+
+        assert!(!proof.verify(&old_root, &new_root, &[([2u8; 32], [99u8; 32])]));
+        assert!(!proof.verify(&old_root, &new_root, &[([3u8; 32], [21u8; 32])]));
+        assert!(!proof.verify(&new_root, &new_root, &updates));
+    }
+
+    #[test]
+    fn test_diff_proof_from_earlier_root() {
+        let mut smt = seeded();
+        let old_root = smt.root();
+        let updates = [([1u8; 32], [11u8; 32])];
+        let new_root = smt.update_batch(&updates).unwrap();
+        smt.update([5u8; 32], [50u8; 32]).unwrap();
+
+        let proof = smt.prove_update_batch(old_root, &updates).unwrap();
+        assert!(proof.verify(&old_root, &new_root, &updates));
+    }
+
+    #[test]
+    fn test_diff_proof_from_empty_tree() {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        let updates = [([7u8; 32], [70u8; 32])];
+
+        let proof = smt.prove_update_batch(smt.root(), &updates).unwrap();
+        let new_root = smt.update_batch(&updates).unwrap();
+        assert!(proof.verify(&SparseMerkleTree::new(InMemoryKVStore::new()).root(), &new_root, &updates));
+
+        let empty = smt.prove_update_batch(new_root, &[]).unwrap();
+        assert!(empty.verify(&new_root, &new_root, &[]));
+    }
+}
@@ -1,5 +1,9 @@
+use std::fmt;
+
 use thiserror::Error;
 
+use crate::{arith::ArithmeticError, hex::HexFmt, Hash};
+
 #[derive(Error, Debug)]
 pub enum SMTError {
     #[error("Key-value store error: {0}")]
@@ -8,6 +12,176 @@ pub enum SMTError {
     #[error("Invalid proof")]
     InvalidProof,
 
+    #[error("Conflicting write for key {}", HexFmt(.0))]
+    KeyConflict(Hash),
+
+    #[error("Batches start from different roots")]
+    RootMismatch,
+
+    #[error("Unsupported tree spec: {0}")]
+    UnsupportedSpec(String),
+
+    #[error("Invalid encoding")]
+    InvalidEncoding,
+
     #[error("Unsupported operation")]
     UnsupportedOperation,
+
+    #[error("Unknown version {0}")]
+    UnknownVersion(u64),
+
+    #[error("Trees disagree on key {}", HexFmt(.0))]
+    LeafMismatch(Hash),
+
+    #[error("Node {} is missing from the store", HexFmt(.0))]
+    MissingNode(Hash),
+
+    #[error("Node {} is corrupt: cannot decode its {}-byte record", HexFmt(.hash), .len)]
+    CorruptNode { hash: Hash, len: usize },
+
+    #[error("Proof reaches depth {depth}, the tree is only {max} levels deep")]
+    DepthExceeded { depth: usize, max: usize },
+
+    #[error("Key {} has bits set past the tree's {depth} levels", HexFmt(.key))]
+    KeyBeyondDepth { key: Hash, depth: usize },
+
+    #[error("Checkpoint chain broken at index {0}")]
+    BrokenChain(usize),
+
+    #[error("Only {valid} valid signatures, {required} required")]
+    InsufficientSignatures { valid: usize, required: usize },
+
+    #[error("Transaction nonce {got}, account expects {expected}")]
+    BadNonce { expected: u64, got: u64 },
+
+    #[error("Transaction {} is already pooled", HexFmt(.0))]
+    DuplicateTransaction(Hash),
+
+    #[error("Balance {balance} cannot cover {amount}")]
+    InsufficientBalance { balance: u64, amount: u64 },
+
+    #[error(transparent)]
+    Arithmetic(#[from] ArithmeticError),
+
+    #[error("{context}: {source}")]
+    Context {
+        context: ErrorContext,
+        #[source]
+        source: Box<SMTError>,
+    },
+}
+
+#[cfg(feature = "rocksdb")]
+impl From<rocksdb::Error> for SMTError {
+    fn from(error: rocksdb::Error) -> Self {
+        SMTError::KVStoreError(std::io::Error::other(error))
+    }
+}
+
+#[cfg(feature = "sled")]
+impl From<sled::Error> for SMTError {
+    fn from(error: sled::Error) -> Self {
+        SMTError::KVStoreError(std::io::Error::other(error))
+    }
+}
+
+impl SMTError {
+    /// The error underneath any context wrappers.
+    pub fn root_cause(&self) -> &SMTError {
+        match self {
+            SMTError::Context { source, .. } => source.root_cause(),
+            other => other,
+        }
+    }
+
+    /// The outermost context attached to this error, if any.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            SMTError::Context { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+}
+
+/// Which operation failed, and where in the tree.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    pub operation: &'static str,
+    pub key: Option<Hash>,
+    pub depth: Option<usize>,
+    pub version: Option<u64>,
+}
+
+impl ErrorContext {
+    pub fn new(operation: &'static str) -> Self {
+        Self {
+            operation,
+            ..Self::default()
+        }
+    }
+
+    pub fn key(mut self, key: Hash) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+
+    pub fn version(mut self, version: u64) -> Self {
+        self.version = Some(version);
+        self
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.operation)?;
+        if let Some(key) = &self.key {
+            write!(f, " key={:#}", HexFmt(key))?;
+        }
+        if let Some(depth) = self.depth {
+            write!(f, " depth={}", depth)?;
+        }
+        if let Some(version) = self.version {
+            write!(f, " version={}", version)?;
+        }
+        Ok(())
+    }
+}
+
+/// Attaches an `ErrorContext` to any error convertible into `SMTError`.
+pub trait ResultExt<T> {
+    fn context(self, context: ErrorContext) -> Result<T, SMTError>;
+}
+
+impl<T, E: Into<SMTError>> ResultExt<T> for Result<T, E> {
+    fn context(self, context: ErrorContext) -> Result<T, SMTError> {
+        self.map_err(|error| SMTError::Context {
+            context,
+            source: Box::new(error.into()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_is_shown_and_unwrapped() {
+        let result: Result<(), SMTError> = Err(SMTError::InvalidProof);
+        let error = result
+            .context(ErrorContext::new("insert_proof").key([0xabu8; 32]).depth(7))
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            format!("insert_proof key={} depth=7: Invalid proof", "ab".repeat(32))
+        );
+        assert!(matches!(error.root_cause(), SMTError::InvalidProof));
+        assert_eq!(error.context().unwrap().key, Some([0xabu8; 32]));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,286 @@
+//! Full-tree backups as a single byte stream, for shipping a tree to another
+//! machine and rebuilding it to the identical root.
+//!
+//! The stream starts with `MAGIC`, a format byte, a flags byte, the depth as
+//! a big-endian `u16` and the root. Records follow, each a tag byte and its
+//! body, and the stream ends with `END_TAG` and the leaf count as a
+//! big-endian `u64`:
+//!
+//! - `LEAF_TAG`, key, value: one per leaf, in key order.
+//! - `NODE_TAG`, left, right: one per non-empty internal node, only when
+//!   `FLAG_NODES` is set. Records then come in pre-order (node, left subtree,
+//!   right subtree), so each one can be checked against the hash its parent
+//!   named and the store is filled without rehashing from scratch.
+//!
+//! A stream of the same tree always has the same bytes.
+
+use std::io::{ErrorKind, Read, Write};
+
+use crate::{
+    error::SMTError,
+    kv_store::{KVStore, TreeWriteBatch},
+    node::{encode_internal, encode_leaf},
+    sparse_merkle_tree::{get_bit, SparseMerkleTree, DEFAULT_DEPTH},
+    tree_hasher::TreeDigest,
+    Hash,
+};
+
+pub const MAGIC: [u8; 4] = *b"SMTX";
+const FORMAT_VERSION: u8 = 1;
+
+/// Set when the stream carries internal nodes as well as leaves.
+pub const FLAG_NODES: u8 = 1;
+
+const LEAF_TAG: u8 = b'L';
+const NODE_TAG: u8 = b'N';
+const END_TAG: u8 = b'E';
+
+impl<S: KVStore> SparseMerkleTree<S>
+where
+    SMTError: From<S::Error>,
+{
+    /// Rebuilds in `store` the tree exported to `reader`, replacing whatever
+    /// root the store recorded. Fails with `InvalidEncoding` on a malformed
+    /// or truncated stream and with `RootMismatch` if the leaves do not hash
+    /// to the root in its header. The store's recorded root only changes once
+    /// the whole stream has been checked.
+    pub fn import<R: Read>(store: S, reader: R) -> Result<Self, SMTError> {
+        Self::import_with_hasher(store, reader)
+    }
+}
+
+impl<S: KVStore, D: TreeDigest> SparseMerkleTree<S, D>
+where
+    SMTError: From<S::Error>,
+{
+    /// Writes every leaf to `writer` and returns how many there were.
+    pub fn export<W: Write>(&self, writer: W) -> Result<u64, SMTError> {
+        self.write_stream(writer, false)
+    }
+
+    /// Like `export`, with the internal nodes too, so `import` stores them
+    /// as they are instead of rebuilding them from the leaves.
+    pub fn export_with_nodes<W: Write>(&self, writer: W) -> Result<u64, SMTError> {
+        self.write_stream(writer, true)
+    }
+
+    fn write_stream<W: Write>(&self, mut writer: W, nodes: bool) -> Result<u64, SMTError> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&[FORMAT_VERSION, if nodes { FLAG_NODES } else { 0 }])?;
+        writer.write_all(&(self.depth as u16).to_be_bytes())?;
+        writer.write_all(&self.root)?;
+
+        let mut leaves = 0u64;
+        let mut stack = Vec::new();
+        if !self.is_empty() {
+            stack.push((self.root, 0));
+        }
+        while let Some((node, depth)) = stack.pop() {
+            if depth == self.depth {
+                let (key, value) = self.read_leaf(&node)?;
+                write_record(&mut writer, LEAF_TAG, &key, &value)?;
+                leaves += 1;
+                continue;
+            }
+            let (left, right) = self.read_node(&node)?;
+            if nodes {
+                write_record(&mut writer, NODE_TAG, &left, &right)?;
+            }
+            for child in [right, left] {
+                if !self.hasher.is_empty(&child) {
+                    stack.push((child, depth + 1));
+                }
+            }
+        }
+
+        writer.write_all(&[END_TAG])?;
+        writer.write_all(&leaves.to_be_bytes())?;
+        writer.flush()?;
+        Ok(leaves)
+    }
+
+    /// Like `import`, hashing with `D` instead of the default hasher.
+    pub fn import_with_hasher<R: Read>(store: S, mut reader: R) -> Result<Self, SMTError> {
+        let header: [u8; 40] = read_array(&mut reader)?;
+        let (flags, depth) = (header[5], u16::from_be_bytes([header[6], header[7]]) as usize);
+        if header[..4] != MAGIC || header[4] != FORMAT_VERSION || flags & !FLAG_NODES != 0 {
+            return Err(SMTError::InvalidEncoding);
+        }
+        if !(1..=DEFAULT_DEPTH).contains(&depth) {
+            return Err(SMTError::InvalidEncoding);
+        }
+        let root: Hash = header[8..].try_into().unwrap();
+
+        let mut tree = Self::with_hasher(store).with_depth(depth);
+        match flags & FLAG_NODES != 0 {
+            true => tree.import_nodes(&mut reader, root)?,
+            false => tree.import_leaves(&mut reader, root)?,
+        }
+        Ok(tree)
+    }
+
+    fn import_leaves<R: Read>(&mut self, reader: &mut R, root: Hash) -> Result<(), SMTError> {
+        let mut sorted: Vec<(Hash, Option<Hash>)> = Vec::new();
+        while let Some((key, value)) = read_record(reader, LEAF_TAG, sorted.len() as u64)? {
+            if sorted.last().is_some_and(|(last, _)| *last >= key) {
+                return Err(SMTError::InvalidEncoding);
+            }
+            sorted.push((key, Some(value)));
+        }
+        if self.apply_at(self.root, &sorted)? != root {
+            return Err(SMTError::RootMismatch);
+        }
+
+        let mut batch = TreeWriteBatch::new();
+        batch.set_root(root);
+        self.commit(batch)?;
+        self.root = root;
+        Ok(())
+    }
+
+    /// Walks the tree the stream describes in the order it was written,
+    /// checking each record against the hash its parent named and each leaf
+    /// against the path that led to it.
+    fn import_nodes<R: Read>(&mut self, reader: &mut R, root: Hash) -> Result<(), SMTError> {
+        let mut batch = TreeWriteBatch::new();
+        let mut leaves = 0u64;
+        let mut stack = Vec::new();
+        if !self.hasher.is_empty(&root) {
+            stack.push((root, 0, [0u8; 32]));
+        }
+        while let Some((node, depth, path)) = stack.pop() {
+            if depth == self.depth {
+                let (key, value) = read_record(reader, LEAF_TAG, leaves)?.ok_or(SMTError::InvalidEncoding)?;
+                let on_path = (0..depth).all(|bit| get_bit(&key, bit) == get_bit(&path, bit));
+                if self.hasher.digest_leaf(&key, &value) != node || !on_path {
+                    return Err(SMTError::RootMismatch);
+                }
+                batch.set_node(node, encode_leaf(&key, &value));
+                leaves += 1;
+                continue;
+            }
+            let (left, right) = read_record(reader, NODE_TAG, leaves)?.ok_or(SMTError::InvalidEncoding)?;
+            if self.hasher.digest_node(&left, &right) != node {
+                return Err(SMTError::RootMismatch);
+            }
+            batch.set_node(node, encode_internal(&left, &right));
+            if !self.hasher.is_empty(&right) {
+                let mut right_path = path;
+                right_path[depth / 8] |= 0x80 >> (depth % 8);
+                stack.push((right, depth + 1, right_path));
+            }
+            if !self.hasher.is_empty(&left) {
+                stack.push((left, depth + 1, path));
+            }
+        }
+        if read_record(reader, NODE_TAG, leaves)?.is_some() {
+            return Err(SMTError::InvalidEncoding);
+        }
+
+        batch.set_root(root);
+        self.commit(batch)?;
+        self.root = root;
+        Ok(())
+    }
+}
+
+fn write_record<W: Write>(writer: &mut W, tag: u8, first: &Hash, second: &Hash) -> Result<(), SMTError> {
+    writer.write_all(&[tag])?;
+    writer.write_all(first)?;
+    writer.write_all(second)?;
+    Ok(())
+}
+
+/// Next record, which must carry `tag`, or `None` at the end of the stream,
+/// whose count must be `leaves`.
+fn read_record<R: Read>(reader: &mut R, tag: u8, leaves: u64) -> Result<Option<(Hash, Hash)>, SMTError> {
+    let [next] = read_array(reader)?;
+    if next == END_TAG {
+        return match u64::from_be_bytes(read_array(reader)?) == leaves {
+            true => Ok(None),
+            false => Err(SMTError::InvalidEncoding),
+        };
+    }
+    if next != tag {
+        return Err(SMTError::InvalidEncoding);
+    }
+    Ok(Some((read_array(reader)?, read_array(reader)?)))
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N], SMTError> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes).map_err(|error| match error.kind() {
+        ErrorKind::UnexpectedEof => SMTError::InvalidEncoding,
+        _ => error.into(),
+    })?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_store::InMemoryKVStore;
+
+    fn tree() -> SparseMerkleTree<InMemoryKVStore> {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        smt.update_batch(&[([0x01; 32], [1u8; 32]), ([0x02; 32], [2u8; 32]), ([0x81; 32], [3u8; 32])]).unwrap();
+        smt
+    }
+
+    #[test]
+    fn test_roundtrip_rebuilds_the_same_root() {
+        let smt = tree();
+        for with_nodes in [false, true] {
+            let mut stream = Vec::new();
+            let leaves = match with_nodes {
+                true => smt.export_with_nodes(&mut stream),
+                false => smt.export(&mut stream),
+            };
+            assert_eq!(leaves.unwrap(), 3);
+
+            let restored = SparseMerkleTree::import(InMemoryKVStore::new(), stream.as_slice()).unwrap();
+            assert_eq!(restored.root(), smt.root());
+            assert_eq!(restored.get([0x81; 32]).unwrap(), Some([3u8; 32]));
+        }
+    }
+
+    #[test]
+    fn test_export_is_canonical() {
+        let mut reordered = SparseMerkleTree::new(InMemoryKVStore::new());
+        for (key, value) in [(0x81, 3), (0x02, 2), (0x01, 1)] {
+            reordered.update([key; 32], [value; 32]).unwrap();
+        }
+        let (mut first, mut second) = (Vec::new(), Vec::new());
+        tree().export_with_nodes(&mut first).unwrap();
+        reordered.export_with_nodes(&mut second).unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_empty_and_shallow_trees() {
+        let mut stream = Vec::new();
+        let shallow = SparseMerkleTree::new(InMemoryKVStore::new()).with_depth(8);
+        assert_eq!(shallow.export(&mut stream).unwrap(), 0);
+        let restored = SparseMerkleTree::import(InMemoryKVStore::new(), stream.as_slice()).unwrap();
+        assert_eq!((restored.depth(), restored.root()), (8, shallow.root()));
+    }
+
+    #[test]
+    fn test_rejects_tampered_streams() {
+        let smt = tree();
+        let (mut leaves, mut nodes) = (Vec::new(), Vec::new());
+        smt.export(&mut leaves).unwrap();
+        smt.export_with_nodes(&mut nodes).unwrap();
+
+        for stream in [&leaves, &nodes] {
+            let truncated = &stream[..stream.len() - 1];
+            let result = SparseMerkleTree::import(InMemoryKVStore::new(), truncated);
+            assert!(matches!(result, Err(SMTError::InvalidEncoding)));
+
+            let mut tampered = stream.clone();
+            tampered[stream.len() - 10] ^= 1; // Last byte of the last value
+            let result = SparseMerkleTree::import(InMemoryKVStore::new(), tampered.as_slice());
+            assert!(matches!(result, Err(SMTError::RootMismatch)));
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! C interface to an in-memory tree, for services in other languages. Keys,
+//! values and roots are 32-byte buffers; proofs cross the boundary in the
+//! `MerkleProof::to_bytes` encoding. `include/smt.h` declares everything
+//! here; see `cbindgen.toml` for regenerating it.
+//!
+//! Build a shared or static library with
+//!
+//! ```text
+//! cargo rustc --release --features capi --crate-type cdylib
+//! ```
+//!
+//! Every function returning `int32_t` returns `SMT_OK` or a negative
+//! `SMT_ERR_*` code. Panics never cross into C; they are reported as
+//! `SMT_ERR_PANIC`.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+use crate::{kv_store::InMemoryKVStore, proof::MerkleProof, sparse_merkle_tree::SparseMerkleTree, Hash};
+
+pub const SMT_OK: i32 = 0;
+pub const SMT_ERR_NULL: i32 = -1;
+pub const SMT_ERR_STORE: i32 = -2;
+pub const SMT_ERR_INVALID_PROOF: i32 = -3;
+pub const SMT_ERR_PANIC: i32 = -4;
+
+/// Opaque handle to a tree, created by `smt_new` and released by `smt_free`.
+pub struct SmtTree(SparseMerkleTree<InMemoryKVStore>);
+
+/// Bytes owned by the library, released by `smt_buffer_free`.
+#[repr(C)]
+pub struct SmtBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl SmtBuffer {
+    fn new(bytes: Vec<u8>) -> Self {
+        let mut bytes = bytes.into_boxed_slice();
+        let buffer = SmtBuffer { data: bytes.as_mut_ptr(), len: bytes.len() };
+        std::mem::forget(bytes);
+        buffer
+    }
+}
+
+unsafe fn read_hash(ptr: *const u8) -> Option<Hash> {
+    match ptr.is_null() {
+        true => None,
+        false => Some(ptr::read(ptr as *const Hash)),
+    }
+}
+
+fn guard(f: impl FnOnce() -> i32) -> i32 {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or(SMT_ERR_PANIC)
+}
+
+/// Creates an empty tree over an in-memory store.
+#[no_mangle]
+pub extern "C" fn smt_new() -> *mut SmtTree {
+    Box::into_raw(Box::new(SmtTree(SparseMerkleTree::new(InMemoryKVStore::new()))))
+}
+
+/// Releases a tree. Null is ignored.
+///
+/// # Safety
+///
+/// `tree` must come from `smt_new` and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn smt_free(tree: *mut SmtTree) {
+    if !tree.is_null() {
+        drop(Box::from_raw(tree));
+    }
+}
+
+/// Copies the current root into `root_out`.
+///
+/// # Safety
+///
+/// `tree` must be a live tree and `root_out` must point to 32 writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn smt_root(tree: *const SmtTree, root_out: *mut u8) -> i32 {
+    if tree.is_null() || root_out.is_null() {
+        return SMT_ERR_NULL;
+    }
+    ptr::write(root_out as *mut Hash, (*tree).0.root());
+    SMT_OK
+}
+
+/// Sets `key` to `value`.
+///
+/// # Safety
+///
+/// `tree` must be a live tree, and `key` and `value` must each point to 32
+/// readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn smt_update(tree: *mut SmtTree, key: *const u8, value: *const u8) -> i32 {
+    let (Some(key), Some(value)) = (read_hash(key), read_hash(value)) else {
+        return SMT_ERR_NULL;
+    };
+    let Some(tree) = tree.as_mut() else {
+        return SMT_ERR_NULL;
+    };
+    guard(|| match tree.0.update(key, value) {
+        Ok(()) => SMT_OK,
+        Err(_) => SMT_ERR_STORE,
+    })
+}
+
+/// Writes the proof for `key` under the current root to `proof_out`, which
+/// the caller releases with `smt_buffer_free`.
+///
+/// # Safety
+///
+/// `tree` must be a live tree, `key` must point to 32 readable bytes and
+/// `proof_out` to a writable `SmtBuffer`.
+#[no_mangle]
+pub unsafe extern "C" fn smt_get_proof(tree: *const SmtTree, key: *const u8, proof_out: *mut SmtBuffer) -> i32 {
+    let Some(key) = read_hash(key) else {
+        return SMT_ERR_NULL;
+    };
+    let Some(tree) = tree.as_ref() else {
+        return SMT_ERR_NULL;
+    };
+    if proof_out.is_null() {
+        return SMT_ERR_NULL;
+    }
+    guard(|| match tree.0.get_proof(key) {
+        Ok(proof) => {
+            ptr::write(proof_out, SmtBuffer::new(proof.to_bytes()));
+            SMT_OK
+        }
+        Err(_) => SMT_ERR_STORE,
+    })
+}
+
+/// Checks an encoded proof that `key` holds `value` under `root`. Returns
+/// `SMT_OK` if it does and `SMT_ERR_INVALID_PROOF` if it does not or the
+/// proof cannot be decoded. Needs no tree.
+///
+/// # Safety
+///
+/// `root`, `key` and `value` must each point to 32 readable bytes, and
+/// `proof` to `proof_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn smt_verify_proof(
+    root: *const u8,
+    key: *const u8,
+    value: *const u8,
+    proof: *const u8,
+    proof_len: usize,
+) -> i32 {
+    let (Some(root), Some(key), Some(value)) = (read_hash(root), read_hash(key), read_hash(value)) else {
+        return SMT_ERR_NULL;
+    };
+    if proof.is_null() {
+        return SMT_ERR_NULL;
+    }
+    let bytes = std::slice::from_raw_parts(proof, proof_len);
+    guard(|| match MerkleProof::from_bytes(bytes) {
+        Ok(proof) if proof.verify(&root, &key, &value) => SMT_OK,
+        _ => SMT_ERR_INVALID_PROOF,
+    })
+}
+
+/// Releases a buffer handed out by the library and resets it to empty.
+///
+/// # Safety
+///
+/// `buffer` must be null or point to a buffer filled in by this library that
+/// was not released yet.
+#[no_mangle]
+pub unsafe extern "C" fn smt_buffer_free(buffer: *mut SmtBuffer) {
+    let Some(buffer) = buffer.as_mut() else {
+        return;
+    };
+    if !buffer.data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(buffer.data, buffer.len)));
+    }
+    *buffer = SmtBuffer { data: ptr::null_mut(), len: 0 };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_prove_verify() {
+        let key = [1u8; 32];
+        let value = [2u8; 32];
+        let mut root = [0u8; 32];
+        let mut proof = SmtBuffer { data: ptr::null_mut(), len: 0 };
+        unsafe {
+            let tree = smt_new();
+            assert_eq!(smt_update(tree, key.as_ptr(), value.as_ptr()), SMT_OK);
+            assert_eq!(smt_root(tree, root.as_mut_ptr()), SMT_OK);
+            assert_eq!(smt_get_proof(tree, key.as_ptr(), &mut proof), SMT_OK);
+
+            assert_eq!(smt_verify_proof(root.as_ptr(), key.as_ptr(), value.as_ptr(), proof.data, proof.len), SMT_OK);
+            let wrong = [3u8; 32];
+            assert_eq!(
+                smt_verify_proof(root.as_ptr(), key.as_ptr(), wrong.as_ptr(), proof.data, proof.len),
+                SMT_ERR_INVALID_PROOF
+            );
+            assert_eq!(smt_verify_proof(root.as_ptr(), key.as_ptr(), value.as_ptr(), proof.data, 1), SMT_ERR_INVALID_PROOF);
+
+            smt_buffer_free(&mut proof);
+            assert!(proof.data.is_null());
+            smt_free(tree);
+        }
+    }
+
+    #[test]
+    fn test_null_arguments() {
+        let key = [1u8; 32];
+        unsafe {
+            assert_eq!(smt_update(ptr::null_mut(), key.as_ptr(), key.as_ptr()), SMT_ERR_NULL);
+            let tree = smt_new();
+            assert_eq!(smt_update(tree, ptr::null(), key.as_ptr()), SMT_ERR_NULL);
+            assert_eq!(smt_get_proof(tree, key.as_ptr(), ptr::null_mut()), SMT_ERR_NULL);
+            assert_eq!(smt_root(tree, ptr::null_mut()), SMT_ERR_NULL);
+            smt_free(tree);
+            smt_free(ptr::null_mut());
+            smt_buffer_free(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use crate::{error::SMTError, kv_store::KVStore, proof::MerkleProof, sparse_merkle_tree::SparseMerkleTree, tree_hasher::TreeDigest, Hash};
+
+/// The operations an application needs from a tree, independent of where the
+/// tree lives. Code written against `SmtHandle` runs unchanged on an embedded
+/// `SparseMerkleTree` or on any other implementation that passes the parity
+/// scenarios in this module's tests.
+pub trait SmtHandle {
+    type Error;
+
+    fn get(&self, key: Hash) -> Result<Option<Hash>, Self::Error>;
+
+    fn update(&mut self, key: Hash, value: Hash) -> Result<(), Self::Error>;
+
+    fn delete(&mut self, key: Hash) -> Result<(), Self::Error>;
+
+    fn root(&self) -> Result<Hash, Self::Error>;
+
+    fn get_proof(&self, key: Hash) -> Result<MerkleProof, Self::Error>;
+}
+
+impl<S: KVStore, D: TreeDigest> SmtHandle for SparseMerkleTree<S, D>
+where
+    SMTError: From<S::Error>,
+{
+    type Error = SMTError;
+
+    fn get(&self, key: Hash) -> Result<Option<Hash>, Self::Error> {
+        SparseMerkleTree::get(self, key)
+    }
+
+    fn update(&mut self, key: Hash, value: Hash) -> Result<(), Self::Error> {
+        SparseMerkleTree::update(self, key, value)
+    }
+
+    fn delete(&mut self, key: Hash) -> Result<(), Self::Error> {
+        SparseMerkleTree::delete(self, key)
+    }
+
+    fn root(&self) -> Result<Hash, Self::Error> {
+        Ok(SparseMerkleTree::root(self))
+    }
+
+    fn get_proof(&self, key: Hash) -> Result<MerkleProof, Self::Error> {
+        SparseMerkleTree::get_proof(self, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{kv_store::InMemoryKVStore, overlay::OverlayStore};
+    use std::fmt::Debug;
+
+    // Each scenario runs against every handle below and must leave them all
+    // in the same state, down to the root.
+    fn scenario_insert_and_prove<H: SmtHandle>(handle: &mut H) -> Hash
+    where
+        H::Error: Debug,
+    {
+        for i in 1..=8u8 {
+            handle.update([i; 32], [i * 2; 32]).unwrap();
+        }
+        let root = handle.root().unwrap();
+        for i in 1..=8u8 {
+            assert_eq!(handle.get([i; 32]).unwrap(), Some([i * 2; 32]));
+            assert!(handle.get_proof([i; 32]).unwrap().verify(&root, &[i; 32], &[i * 2; 32]));
+        }
+        root
+    }
+
+    fn scenario_overwrite_and_delete<H: SmtHandle>(handle: &mut H) -> Hash
+    where
+        H::Error: Debug,
+    {
+        handle.update([1u8; 32], [10u8; 32]).unwrap();
+        handle.update([2u8; 32], [20u8; 32]).unwrap();
+        handle.update([1u8; 32], [11u8; 32]).unwrap();
+        handle.delete([2u8; 32]).unwrap();
+        handle.delete([3u8; 32]).unwrap();
+
+        assert_eq!(handle.get([1u8; 32]).unwrap(), Some([11u8; 32]));
+        assert_eq!(handle.get([2u8; 32]).unwrap(), None);
+        handle.root().unwrap()
+    }
+
+    fn scenario_delete_everything<H: SmtHandle>(handle: &mut H) -> Hash
+    where
+        H::Error: Debug,
+    {
+        handle.update([4u8; 32], [40u8; 32]).unwrap();
+        handle.delete([4u8; 32]).unwrap();
+
+        assert_eq!(handle.get([4u8; 32]).unwrap(), None);
+        handle.root().unwrap()
+    }
+
+    fn run_all<H: SmtHandle>(mut make: impl FnMut() -> H) -> Vec<Hash>
+    where
+        H::Error: Debug,
+    {
+        vec![
+            scenario_insert_and_prove(&mut make()),
+            scenario_overwrite_and_delete(&mut make()),
+            scenario_delete_everything(&mut make()),
+        ]
+    }
+
+    #[test]
+    fn test_handles_agree_on_every_scenario() {
+        let direct = run_all(|| SparseMerkleTree::new(InMemoryKVStore::new()));
+        let buffered = run_all(|| SparseMerkleTree::new(OverlayStore::new(InMemoryKVStore::new())));
+
+        assert_eq!(direct, buffered);
+        assert_eq!(direct[2], SparseMerkleTree::new(InMemoryKVStore::new()).root());
+    }
+}
@@ -0,0 +1,122 @@
+//! Hash functions a tree can be built with, for `SparseMerkleTree::with_hasher`
+//! and friends. `Keccak256` commits to the same roots as Ethereum tooling
+//! using the same scheme; `Blake3`, behind the `blake3` feature, is the
+//! fastest. `Poseidon`, behind the `poseidon` feature, is cheap to check in
+//! SNARK circuits. Each has the identifier `TreeSpec` and `verify_with_spec` use.
+
+pub use sha2::Sha256;
+pub use sha3::{Keccak256, Sha3_256};
+
+#[cfg(feature = "blake3")]
+pub type Blake3 = blake3::Hasher;
+#[cfg(feature = "poseidon")]
+pub use crate::poseidon::Poseidon;
+
+use crate::tree_hasher::TreeDigest;
+
+/// A `TreeDigest` with a name in `TreeSpec::hasher_id`.
+pub trait HasherId: TreeDigest {
+    const HASHER_ID: &'static str;
+}
+
+impl HasherId for Sha256 {
+    const HASHER_ID: &'static str = "sha256";
+}
+
+impl HasherId for Sha3_256 {
+    const HASHER_ID: &'static str = "sha3-256";
+}
+
+impl HasherId for Keccak256 {
+    const HASHER_ID: &'static str = "keccak256";
+}
+
+#[cfg(feature = "blake3")]
+impl HasherId for Blake3 {
+    const HASHER_ID: &'static str = "blake3";
+}
+
+#[cfg(feature = "poseidon")]
+impl HasherId for Poseidon {
+    const HASHER_ID: &'static str = "poseidon-bn254";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{kv_store::InMemoryKVStore, sparse_merkle_tree::SparseMerkleTree, spec::verify_with_spec, Hash};
+
+    // Vectors from an independent implementation of the scheme: the empty
+    // root of depth 256, the leaf hash of ([1; 32], [2; 32]), and the roots
+    // with that leaf alone and together with ([0x80; 32], [3; 32]) and
+    // ([0x81; 32], [4; 32]).
+    struct Vectors {
+        empty: &'static str,
+        leaf: &'static str,
+        one: &'static str,
+        three: &'static str,
+    }
+
+    fn hash(hex: &str) -> Hash {
+        let mut hash = [0u8; 32];
+        for (byte, pair) in hash.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            *byte = u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap();
+        }
+        hash
+    }
+
+    fn check<D: HasherId>(vectors: Vectors) {
+        let mut smt = SparseMerkleTree::<_, D>::with_hasher(InMemoryKVStore::new());
+        assert_eq!(smt.root(), hash(vectors.empty), "{} empty root", D::HASHER_ID);
+        assert_eq!(smt.hasher.digest_leaf(&[1u8; 32], &[2u8; 32]), hash(vectors.leaf), "{} leaf", D::HASHER_ID);
+
+        smt.update([1u8; 32], [2u8; 32]).unwrap();
+        assert_eq!(smt.root(), hash(vectors.one), "{} one leaf", D::HASHER_ID);
+        let proof = smt.get_proof([1u8; 32]).unwrap();
+        assert!(verify_with_spec(&smt.spec(), &smt.root(), &[1u8; 32], &[2u8; 32], &proof).unwrap());
+
+        smt.update_batch(&[([0x80u8; 32], [3u8; 32]), ([0x81u8; 32], [4u8; 32])]).unwrap();
+        assert_eq!(smt.root(), hash(vectors.three), "{} three leaves", D::HASHER_ID);
+    }
+
+    #[test]
+    fn test_sha256_vectors() {
+        check::<Sha256>(Vectors {
+            empty: "6155289130893872355eac98042d22aefa2c2e708bea169402760e3b55f9a2dc",
+            leaf: "32fb2d4416067c5bff06423e18714ad3884365d7b816d34a77b33527d8438624",
+            one: "b9e5387757b69766a0e517159512afb3ee1effd9c945a68a99d47771f5d3d5c9",
+            three: "1680ea84115b5488a9d58bd536555018b2e2ceed0f8dd5b661bf632401db3571",
+        });
+    }
+
+    #[test]
+    fn test_sha3_256_vectors() {
+        check::<Sha3_256>(Vectors {
+            empty: "7b1098aba4ae8eda74f87d3da2af5105bd35d14032b1a45ef7629e1549f8025c",
+            leaf: "80e6e555ec6f6e3db93c28b68fcc6838a8e49764eb1aa08ff806fde3c98ce9b3",
+            one: "b8c838ec5887f427ef35d34255b5f7a6289ad8eeada564ca6c738fa16dceb2b8",
+            three: "dc89899e203a84b6eb2465ebc5edf55f3414677bfca397b5ea77303bb5ae8d38",
+        });
+    }
+
+    #[test]
+    fn test_keccak256_vectors() {
+        check::<Keccak256>(Vectors {
+            empty: "ca35b60c4cbb11bc17b902989f14c51d764dfb865e9ade93d57df3def62a1e05",
+            leaf: "cc00fb1023d284c63b89ba218c121e83d59206130ec78854fc0eb77e6b89e76b",
+            one: "6dcf926b385439c31be97d506547fa03038081ddc3a786b063d2818c77146e0b",
+            three: "ac285f4548b46356af6074b9f24a79529f333df4ef2bcfa81dd299953f0b9c7b",
+        });
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn test_blake3_vectors() {
+        check::<Blake3>(Vectors {
+            empty: "53852c7404ac6cb402b385ffeec50fa4fe8f59ed34c0a851357ced5dac6ce6aa",
+            leaf: "d1c3fa7c9752dd1e91c8238138884fa0f902dc73e398b911318b072526b495c6",
+            one: "63ae8f55499b84947d37f4d09d5d6ec5e2db9e67a26b7cb4ebd8ce39af3875e7",
+            three: "8ae511bbc3d4146a3392632e92192fd32f8fc744ff12b6f499c37e261929b4a1",
+        });
+    }
+}
@@ -0,0 +1,163 @@
+use core::fmt;
+
+use crate::Hash;
+
+const SHORT_LEN: usize = 8;
+
+/// Displays a hash as lowercase hex without allocating. Only the first 8
+/// bytes are shown, followed by `…`; use the alternate flag (`{:#}`) for the
+/// full 64 characters.
+#[derive(Clone, Copy)]
+pub struct HexFmt<'a>(pub &'a Hash);
+
+impl fmt::Display for HexFmt<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = match f.alternate() {
+            true => &self.0[..],
+            false => &self.0[..SHORT_LEN],
+        };
+        for byte in bytes {
+            write!(f, "{:02x}", byte)?;
+        }
+        if !f.alternate() {
+            f.write_str("…")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for HexFmt<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Serde representation of byte arrays as `0x`-prefixed lowercase hex, used
+/// for hashes and signatures when the `serde_hex` feature is on. Use it with
+/// `#[serde(with = "crate::hex::serde_hex")]`, or wrap values in `Hex` in
+/// hand-written impls. Uppercase digits are accepted when reading.
+#[cfg(feature = "serde_hex")]
+pub mod serde_hex {
+    use alloc::string::String;
+    use core::fmt::Write;
+
+    use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+    /// `bytes` as a `0x`-prefixed hex string.
+    pub fn encode(bytes: &[u8]) -> String {
+        let mut hex = String::with_capacity(2 + 2 * bytes.len());
+        hex.push_str("0x");
+        for byte in bytes {
+            let _ = write!(hex, "{:02x}", byte);
+        }
+        hex
+    }
+
+    /// Reads exactly `N` bytes from a `0x`-prefixed hex string.
+    pub fn decode<const N: usize>(hex: &str) -> Option<[u8; N]> {
+        let digits = hex.strip_prefix("0x")?.as_bytes();
+        if digits.len() != 2 * N || !digits.iter().all(u8::is_ascii_hexdigit) {
+            return None;
+        }
+        let mut bytes = [0u8; N];
+        for (byte, pair) in bytes.iter_mut().zip(digits.chunks_exact(2)) {
+            *byte = u8::from_str_radix(core::str::from_utf8(pair).ok()?, 16).ok()?;
+        }
+        Some(bytes)
+    }
+
+    pub fn serialize<S: Serializer, const N: usize>(bytes: &[u8; N], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(deserializer: D) -> Result<[u8; N], D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        decode(&hex).ok_or_else(|| de::Error::invalid_value(de::Unexpected::Str(&hex), &"0x-prefixed hex"))
+    }
+
+    /// Byte array that serializes as hex.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Hex<const N: usize>(pub [u8; N]);
+
+    impl<const N: usize> Serialize for Hex<N> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serialize(&self.0, serializer)
+        }
+    }
+
+    impl<'de, const N: usize> Deserialize<'de> for Hex<N> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserialize(deserializer).map(Hex)
+        }
+    }
+
+    /// Like the parent module, for a list of arrays.
+    pub mod vec {
+        use super::Hex;
+        use alloc::vec::Vec;
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer, const N: usize>(list: &[[u8; N]], serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(list.iter().map(|bytes| Hex(*bytes)))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(deserializer: D) -> Result<Vec<[u8; N]>, D::Error> {
+            Ok(Vec::<Hex<N>>::deserialize(deserializer)?.into_iter().map(|hex| hex.0).collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncates_by_default() {
+        let mut hash = [0u8; 32];
+        hash[0] = 0xab;
+        hash[31] = 0xff;
+        assert_eq!(HexFmt(&hash).to_string(), "ab00000000000000…");
+    }
+
+    #[test]
+    fn test_alternate_prints_full_hash() {
+        let hash = [0x1fu8; 32];
+        assert_eq!(format!("{:#}", HexFmt(&hash)), "1f".repeat(32));
+    }
+
+    #[cfg(feature = "serde_hex")]
+    #[test]
+    fn test_serde_hex_decoding() {
+        use super::serde_hex::{decode, encode};
+        assert_eq!(encode(&[0xab, 0x01]), "0xab01");
+        assert_eq!(decode::<2>("0xAB01"), Some([0xab, 0x01]));
+        assert_eq!(decode::<2>("ab01"), None);
+        assert_eq!(decode::<2>("0xab0"), None);
+        assert_eq!(decode::<2>("0xab0102"), None);
+        assert_eq!(decode::<2>("0x+b01"), None);
+        assert_eq!(decode::<2>("0xé1"), None);
+    }
+
+    #[cfg(feature = "serde_hex")]
+    #[test]
+    fn test_serde_hex_round_trips() {
+        use crate::{account::Account, proof::MerkleProof, transaction::Transaction};
+
+        let account = Account::new([0xaa; 32], 7);
+        let json = serde_json::to_string(&account).unwrap();
+        assert!(json.contains(&format!("\"address\":\"0x{}\"", "aa".repeat(32))));
+        assert_eq!(serde_json::from_str::<Account>(&json).unwrap(), account);
+
+        let tx = Transaction { from: [1u8; 32], to: [2u8; 32], amount: 5, nonce: 1, signature: [0xff; 64] };
+        let json = serde_json::to_string(&tx).unwrap();
+        assert!(json.contains(&format!("\"signature\":\"0x{}\"", "ff".repeat(64))));
+        assert_eq!(serde_json::from_str::<Transaction>(&json).unwrap(), tx);
+
+        let proof = MerkleProof { side_nodes: vec![[3u8; 32], [4u8; 32]] };
+        let json = serde_json::to_string(&proof).unwrap();
+        assert_eq!(json, format!("{{\"side_nodes\":[\"0x{}\",\"0x{}\"]}}", "03".repeat(32), "04".repeat(32)));
+        assert_eq!(serde_json::from_str::<MerkleProof>(&json).unwrap().side_nodes, proof.side_nodes);
+
+        assert!(serde_json::from_str::<MerkleProof>("{\"side_nodes\":[\"0x03\"]}").is_err());
+    }
+}
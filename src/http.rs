@@ -0,0 +1,140 @@
+//! JSON over HTTP for a single tree, a lighter alternative to the gRPC
+//! service in `server.rs`. Hashes are `0x`-prefixed hex, as with `serde_hex`.
+//!
+//! - `GET /root` returns `{"root"}`.
+//! - `GET /get/{key}` returns `{"key", "value"}`, with a null value for a key
+//!   that has no leaf.
+//! - `GET /proof/{key}` returns `{"root", "key", "value", "proof"}`, the proof
+//!   being a `MerkleProof` taken under that root.
+//! - `POST /update` takes `{"entries": [{"key", "value"}, ...]}`, applies them
+//!   as one batch and returns `{"root"}`.
+//!
+//! Malformed keys get a 400 status and store failures a 500, both with an
+//! `{"error"}` body.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::{
+    error::SMTError,
+    hex::serde_hex::{decode, Hex},
+    kv_store::KVStore,
+    proof::MerkleProof,
+    sparse_merkle_tree::SparseMerkleTree,
+};
+
+type SharedTree<S> = Arc<RwLock<SparseMerkleTree<S>>>;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RootResponse {
+    pub root: Hex<32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetResponse {
+    pub key: Hex<32>,
+    pub value: Option<Hex<32>>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ProofResponse {
+    pub root: Hex<32>,
+    pub key: Hex<32>,
+    pub value: Option<Hex<32>>,
+    pub proof: MerkleProof,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateEntry {
+    pub key: Hex<32>,
+    pub value: Hex<32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateRequest {
+    pub entries: Vec<UpdateEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+/// Failure of a request, turned into a status and an `ErrorResponse`.
+pub enum ApiError {
+    BadRequest(String),
+    Tree(SMTError),
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, error) = match self {
+            ApiError::BadRequest(error) => (StatusCode::BAD_REQUEST, error),
+            ApiError::Tree(error) => (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+        };
+        (status, Json(ErrorResponse { error })).into_response()
+    }
+}
+
+/// Routes serving `tree`, ready for `axum::serve`.
+pub fn router<S>(tree: SparseMerkleTree<S>) -> Router
+where
+    S: KVStore + Send + Sync + 'static,
+    SMTError: From<S::Error>,
+{
+    Router::new()
+        .route("/root", get(root::<S>))
+        .route("/get/{key}", get(get_value::<S>))
+        .route("/proof/{key}", get(proof::<S>))
+        .route("/update", post(update::<S>))
+        .with_state(Arc::new(RwLock::new(tree)))
+}
+
+fn parse_key(key: &str) -> Result<Hex<32>, ApiError> {
+    decode(key).map(Hex).ok_or_else(|| ApiError::BadRequest(format!("Key {} is not 0x-prefixed hex of 32 bytes", key)))
+}
+
+async fn root<S: KVStore>(State(tree): State<SharedTree<S>>) -> Json<RootResponse> {
+    Json(RootResponse { root: Hex(tree.read().await.root()) })
+}
+
+async fn get_value<S>(State(tree): State<SharedTree<S>>, Path(key): Path<String>) -> Result<Json<GetResponse>, ApiError>
+where
+    S: KVStore,
+    SMTError: From<S::Error>,
+{
+    let key = parse_key(&key)?;
+    let value = tree.read().await.get(key.0).map_err(ApiError::Tree)?;
+    Ok(Json(GetResponse { key, value: value.map(Hex) }))
+}
+
+async fn proof<S>(State(tree): State<SharedTree<S>>, Path(key): Path<String>) -> Result<Json<ProofResponse>, ApiError>
+where
+    S: KVStore,
+    SMTError: From<S::Error>,
+{
+    let key = parse_key(&key)?;
+    let tree = tree.read().await;
+    let value = tree.get(key.0).map_err(ApiError::Tree)?;
+    let proof = tree.get_proof(key.0).map_err(ApiError::Tree)?;
+    Ok(Json(ProofResponse { root: Hex(tree.root()), key, value: value.map(Hex), proof }))
+}
+
+async fn update<S>(State(tree): State<SharedTree<S>>, Json(request): Json<UpdateRequest>) -> Result<Json<RootResponse>, ApiError>
+where
+    S: KVStore,
+    SMTError: From<S::Error>,
+{
+    let entries: Vec<_> = request.entries.iter().map(|entry| (entry.key.0, entry.value.0)).collect();
+    let root = tree.write().await.update_batch(&entries).map_err(ApiError::Tree)?;
+    Ok(Json(RootResponse { root: Hex(root) }))
+}
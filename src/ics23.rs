@@ -0,0 +1,341 @@
+//! Conversion between this crate's proofs and the ICS-23 proof format used by
+//! IBC, so counter-party chains can check them with a stock ICS-23 verifier.
+//!
+//! The structs mirror the ICS-23 protobuf messages field for field. The tree
+//! maps onto ICS-23 as a leaf op of `sha256(0x00 || key || value)` with no
+//! prehashing or length prefix, and one inner op per depth hashing
+//! `0x01 || left || right`. Empty subtrees are the per-level default hashes of
+//! `TreeHasher::default_hashes`.
+
+use digest::Digest;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::SMTError,
+    kv_store::KVStore,
+    proof::MerkleProof,
+    sparse_merkle_tree::{get_bit, SparseMerkleTree, DEFAULT_DEPTH},
+    tree_hasher::{TreeHasher, LEAF_PREFIX, NODE_PREFIX},
+    DefaultHasher, Hash,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HashOp {
+    NoHash,
+    Sha256,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LengthOp {
+    NoPrefix,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LeafOp {
+    pub hash: HashOp,
+    pub prehash_key: HashOp,
+    pub prehash_value: HashOp,
+    pub length: LengthOp,
+    pub prefix: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InnerOp {
+    pub hash: HashOp,
+    pub prefix: Vec<u8>,
+    pub suffix: Vec<u8>,
+}
+
+/// Membership proof. `path` runs from the leaf up to the root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExistenceProof {
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+    pub leaf: LeafOp,
+    pub path: Vec<InnerOp>,
+}
+
+/// Absence proof: the keys immediately before and after `key`. A side is
+/// `None` when `key` is beyond the last (or before the first) leaf.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NonExistenceProof {
+    pub key: Vec<u8>,
+    pub left: Option<ExistenceProof>,
+    pub right: Option<ExistenceProof>,
+}
+
+/// The leaf op every proof from this tree uses.
+pub fn leaf_op() -> LeafOp {
+    LeafOp {
+        hash: HashOp::Sha256,
+        prehash_key: HashOp::NoHash,
+        prehash_value: HashOp::NoHash,
+        length: LengthOp::NoPrefix,
+        prefix: vec![LEAF_PREFIX],
+    }
+}
+
+impl MerkleProof {
+    /// Expresses a membership proof for `key` and `value` in ICS-23 form.
+    pub fn to_ics23(&self, key: &Hash, value: &Hash) -> ExistenceProof {
+        let path = self
+            .side_nodes
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(depth, sibling)| {
+                let (prefix, suffix) = match get_bit(key, depth) {
+                    0 => (vec![NODE_PREFIX], sibling.to_vec()),
+                    _ => ([&[NODE_PREFIX][..], sibling].concat(), Vec::new()),
+                };
+                InnerOp { hash: HashOp::Sha256, prefix, suffix }
+            })
+            .collect();
+        ExistenceProof { key: key.to_vec(), value: value.to_vec(), leaf: leaf_op(), path }
+    }
+
+    /// Reads an ICS-23 membership proof back into the key, value and proof.
+    /// Fails unless every op has the shape `to_ics23` produces and each step
+    /// puts the child on the side the key's bits say it is.
+    pub fn from_ics23(proof: &ExistenceProof) -> Result<(Hash, Hash, MerkleProof), SMTError> {
+        let key: Hash = proof.key.as_slice().try_into().map_err(|_| SMTError::InvalidEncoding)?;
+        let value: Hash = proof.value.as_slice().try_into().map_err(|_| SMTError::InvalidEncoding)?;
+        let depth = proof.path.len();
+        if proof.leaf != leaf_op() || !(1..=DEFAULT_DEPTH).contains(&depth) {
+            return Err(SMTError::InvalidEncoding);
+        }
+
+        let mut side_nodes = vec![[0u8; 32]; depth];
+        for (op, depth) in proof.path.iter().zip((0..depth).rev()) {
+            if op.hash != HashOp::Sha256 || op.prefix.first() != Some(&NODE_PREFIX) {
+                return Err(SMTError::InvalidEncoding);
+            }
+            let sibling = match get_bit(&key, depth) {
+                0 if op.prefix.len() == 1 => op.suffix.as_slice(),
+                1 if op.suffix.is_empty() => &op.prefix[1..],
+                _ => return Err(SMTError::InvalidEncoding),
+            };
+            side_nodes[depth] = sibling.try_into().map_err(|_| SMTError::InvalidEncoding)?;
+        }
+        Ok((key, value, MerkleProof { side_nodes }))
+    }
+}
+
+impl ExistenceProof {
+    /// Root the proof commits to, computed the way an ICS-23 verifier does.
+    pub fn calculate(&self) -> Hash {
+        let mut current: Hash = DefaultHasher::new()
+            .chain_update(&self.leaf.prefix)
+            .chain_update(&self.key)
+            .chain_update(&self.value)
+            .finalize()
+            .into();
+        for op in &self.path {
+            current = DefaultHasher::new()
+                .chain_update(&op.prefix)
+                .chain_update(current)
+                .chain_update(&op.suffix)
+                .finalize()
+                .into();
+        }
+        current
+    }
+
+    pub fn verify(&self, root: &Hash) -> bool {
+        MerkleProof::from_ics23(self).is_ok() && self.calculate() == *root
+    }
+}
+
+impl NonExistenceProof {
+    /// Checks that both neighbours are in the tree under `root`, bracket
+    /// `key`, and have no leaves between them. With neither neighbour, this
+    /// only holds for the empty tree.
+    pub fn verify(&self, root: &Hash) -> bool {
+        let Ok(key) = Hash::try_from(self.key.as_slice()) else {
+            return false;
+        };
+        let side = |proof: &Option<ExistenceProof>| match proof {
+            Some(proof) if proof.verify(root) => MerkleProof::from_ics23(proof).ok().map(Some),
+            Some(_) => None,
+            None => Some(None),
+        };
+        let (Some(left), Some(right)) = (side(&self.left), side(&self.right)) else {
+            return false;
+        };
+
+        let hasher = TreeHasher::<DefaultHasher>::new();
+        let is_edge = |key: &Hash, proof: &MerkleProof, from: usize, bit: u8| is_edge(&hasher, key, proof, from, bit);
+        match (left, right) {
+            (None, None) => *root == hasher.empty(DEFAULT_DEPTH),
+            (Some((left, _, proof)), None) => left < key && is_edge(&left, &proof, 0, 0),
+            (None, Some((right, _, proof))) => key < right && is_edge(&right, &proof, 0, 1),
+            (Some((left, _, left_proof)), Some((right, _, right_proof))) => {
+                let depth = left_proof.side_nodes.len();
+                if !(left < key && key < right) || right_proof.side_nodes.len() != depth {
+                    return false;
+                }
+                // Past the depth where the neighbours split, nothing may hang
+                // to the right of the left one or to the left of the right one.
+                let split = (0..depth).find(|depth| get_bit(&left, *depth) != get_bit(&right, *depth)).unwrap_or(depth);
+                is_edge(&left, &left_proof, split + 1, 0) && is_edge(&right, &right_proof, split + 1, 1)
+            }
+        }
+    }
+}
+
+/// Whether every sibling from `from` down, on the side where the key's bit is
+/// `bit`, is an empty subtree.
+fn is_edge(hasher: &TreeHasher<DefaultHasher>, key: &Hash, proof: &MerkleProof, from: usize, bit: u8) -> bool {
+    let len = proof.side_nodes.len();
+    proof
+        .side_nodes
+        .iter()
+        .enumerate()
+        .skip(from)
+        .all(|(depth, sibling)| get_bit(key, depth) != bit || *sibling == hasher.empty(len - depth - 1))
+}
+
+impl<S: KVStore> SparseMerkleTree<S>
+where
+    SMTError: From<S::Error>,
+{
+    pub fn get_ics23_proof(&self, key: Hash) -> Result<Option<ExistenceProof>, SMTError> {
+        match self.get(key)? {
+            Some(value) => Ok(Some(self.get_proof(key)?.to_ics23(&key, &value))),
+            None => Ok(None),
+        }
+    }
+
+    /// Proves `key` absent in ICS-23 form. Returns `None` if the key is present.
+    pub fn get_ics23_non_existence_proof(&self, key: Hash) -> Result<Option<NonExistenceProof>, SMTError> {
+        if self.get(key)?.is_some() {
+            return Ok(None);
+        }
+        let left = match self.neighbour(&key, 1)? {
+            Some(left) => self.get_ics23_proof(left)?,
+            None => None,
+        };
+        let right = match self.neighbour(&key, 0)? {
+            Some(right) => self.get_ics23_proof(right)?,
+            None => None,
+        };
+        Ok(Some(NonExistenceProof { key: key.to_vec(), left, right }))
+    }
+
+    /// Closest key before (`towards` = 1) or after (`towards` = 0) the absent
+    /// `key`: the outermost leaf of the deepest subtree branching off `key`'s
+    /// path on that side.
+    fn neighbour(&self, key: &Hash, towards: u8) -> Result<Option<Hash>, SMTError> {
+        let hasher = &self.hasher;
+        let mut branch = None;
+        let mut current = self.root();
+        for depth in 0..self.depth {
+            if hasher.is_empty(&current) {
+                break;
+            }
+            let (left, right) = self.read_node(&current)?;
+            let (next, other) = if get_bit(key, depth) == 0 { (left, right) } else { (right, left) };
+            if get_bit(key, depth) == towards && !hasher.is_empty(&other) {
+                branch = Some((other, depth + 1));
+            }
+            current = next;
+        }
+
+        let Some((mut node, depth)) = branch else {
+            return Ok(None);
+        };
+        // Before the key, take the rightmost leaf; after it, the leftmost.
+        for _ in depth..self.depth {
+            let (left, right) = self.read_node(&node)?;
+            node = match towards {
+                1 if !hasher.is_empty(&right) => right,
+                1 => left,
+                _ if !hasher.is_empty(&left) => left,
+                _ => right,
+            };
+        }
+        Ok(Some(self.read_leaf(&node)?.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_store::InMemoryKVStore;
+
+    fn key(first: u8) -> Hash {
+        let mut key = [0u8; 32];
+        key[0] = first;
+        key
+    }
+
+    fn tree(firsts: &[u8]) -> SparseMerkleTree<InMemoryKVStore> {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        for first in firsts {
+            smt.update(key(*first), [*first; 32]).unwrap();
+        }
+        smt
+    }
+
+    #[test]
+    fn test_existence_proof_roundtrip() {
+        let smt = tree(&[10, 20, 30]);
+        let proof = smt.get_proof(key(20)).unwrap();
+
+        let ics = proof.to_ics23(&key(20), &[20u8; 32]);
+        assert_eq!(ics.calculate(), smt.root());
+        assert!(ics.verify(&smt.root()));
+
+        let (k, v, back) = MerkleProof::from_ics23(&ics).unwrap();
+        assert_eq!((k, v), (key(20), [20u8; 32]));
+        assert!(back.verify(&smt.root(), &k, &v));
+    }
+
+    #[test]
+    fn test_from_ics23_rejects_foreign_shapes() {
+        let smt = tree(&[10]);
+        let mut ics = smt.get_ics23_proof(key(10)).unwrap().unwrap();
+        ics.leaf.prehash_key = HashOp::Sha256;
+        assert!(matches!(MerkleProof::from_ics23(&ics), Err(SMTError::InvalidEncoding)));
+
+        let mut ics = smt.get_ics23_proof(key(10)).unwrap().unwrap();
+        ics.path.pop();
+        assert!(!ics.verify(&smt.root()));
+    }
+
+    #[test]
+    fn test_non_existence_between_neighbours() {
+        let smt = tree(&[10, 20, 30]);
+        let proof = smt.get_ics23_non_existence_proof(key(25)).unwrap().unwrap();
+
+        assert_eq!(proof.left.as_ref().unwrap().key, key(20).to_vec());
+        assert_eq!(proof.right.as_ref().unwrap().key, key(30).to_vec());
+        assert!(proof.verify(&smt.root()));
+        assert!(smt.get_ics23_non_existence_proof(key(20)).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_non_existence_rejects_non_adjacent_neighbours() {
+        let smt = tree(&[10, 20, 30]);
+        let mut proof = smt.get_ics23_non_existence_proof(key(15)).unwrap().unwrap();
+        // 20 sits between 10 and 30, so they do not bracket an empty gap.
+        proof.right = smt.get_ics23_proof(key(30)).unwrap();
+        assert!(!proof.verify(&smt.root()));
+    }
+
+    #[test]
+    fn test_non_existence_at_the_edges() {
+        let smt = tree(&[10, 20]);
+
+        let before = smt.get_ics23_non_existence_proof(key(5)).unwrap().unwrap();
+        assert!(before.left.is_none());
+        assert!(before.verify(&smt.root()));
+
+        let after = smt.get_ics23_non_existence_proof(key(200)).unwrap().unwrap();
+        assert!(after.right.is_none());
+        assert!(after.verify(&smt.root()));
+
+        let empty = tree(&[]);
+        assert!(empty.get_ics23_non_existence_proof(key(1)).unwrap().unwrap().verify(&empty.root()));
+    }
+}
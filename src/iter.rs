@@ -0,0 +1,86 @@
+use crate::{error::SMTError, kv_store::KVStore, proof::MultiProof, sparse_merkle_tree::SparseMerkleTree, tree_hasher::TreeDigest, Hash};
+
+/// Iterator over the leaves of a tree in key order, see `SparseMerkleTree::iter`.
+pub struct Leaves<'a, S: KVStore, D: TreeDigest> {
+    tree: &'a SparseMerkleTree<S, D>,
+    stack: Vec<(Hash, usize)>, // Subtrees still to visit, leftmost on top
+}
+
+impl<S: KVStore, D: TreeDigest> Iterator for Leaves<'_, S, D>
+where
+    SMTError: From<S::Error>,
+{
+    type Item = Result<(Hash, Hash), SMTError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((node, depth)) = self.stack.pop() {
+            if depth == self.tree.depth {
+                let leaf = self.tree.read_leaf(&node);
+                if leaf.is_err() {
+                    self.stack.clear();
+                }
+                return Some(leaf);
+            }
+            let (left, right) = match self.tree.read_node(&node) {
+                Ok(children) => children,
+                Err(error) => {
+                    self.stack.clear();
+                    return Some(Err(error));
+                }
+            };
+
+            let hasher = &self.tree.hasher;
+            if !hasher.is_empty(&right) {
+                self.stack.push((right, depth + 1));
+            }
+            if !hasher.is_empty(&left) {
+                self.stack.push((left, depth + 1));
+            }
+        }
+        None
+    }
+}
+
+impl<S: KVStore, D: TreeDigest> SparseMerkleTree<S, D>
+where
+    SMTError: From<S::Error>,
+{
+    /// Walks every `(key, value)` leaf in key order. Only non-empty subtrees
+    /// are visited. Stops after the first error.
+    pub fn iter(&self) -> Leaves<'_, S, D> {
+        let mut stack = Vec::new();
+        if !self.is_empty() {
+            stack.push((self.root(), 0));
+        }
+        Leaves { tree: self, stack }
+    }
+
+    /// Number of leaves. This walks the whole tree.
+    pub fn len(&self) -> Result<usize, SMTError> {
+        self.iter().try_fold(0, |count, leaf| leaf.map(|_| count + 1))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root() == self.hasher.empty(self.depth)
+    }
+
+    /// Collects the leaves for which `predicate(key, value)` holds, in key
+    /// order, with one multiproof covering all of them, e.g. every account
+    /// above a stake threshold for a provable validator set. Walks the whole
+    /// tree. The proof is empty, and verifies nothing, when no leaf matches.
+    pub fn select_with_proof<F>(&self, mut predicate: F) -> Result<(Vec<(Hash, Hash)>, MultiProof), SMTError>
+    where
+        F: FnMut(&Hash, &Hash) -> bool,
+    {
+        let mut selected = Vec::new();
+        for leaf in self.iter() {
+            let (key, value) = leaf?;
+            if predicate(&key, &value) {
+                selected.push((key, value));
+            }
+        }
+        let keys: Vec<Hash> = selected.iter().map(|(key, _)| *key).collect();
+        let proof = self.get_multiproof(&keys)?;
+        Ok((selected, proof))
+    }
+}
@@ -0,0 +1,232 @@
+use digest::Digest;
+
+use crate::{error::SMTError, kv_store::KVStore, proof::MerkleProof, sparse_merkle_tree::SparseMerkleTree, tree_hasher::TreeHasher, DefaultHasher, Hash};
+
+/// Longest namespace `NamespacedKeys` accepts, leaving at least 24 bytes of
+/// the key hash after it.
+pub const MAX_NAMESPACE_LEN: usize = 8;
+
+/// How application keys map to the 32-byte keys the tree stores them under.
+pub trait KeyHasher {
+    fn hash_key(&self, key: &[u8]) -> Result<Hash, SMTError>;
+
+    /// Mixed into every leaf hash so trees whose keys are derived differently
+    /// never share a root. `None` leaves leaf hashes as a plain tree has them.
+    fn domain(&self) -> Option<Hash>;
+}
+
+/// Keys are used as they are and must be exactly 32 bytes. Trees keyed this
+/// way have the same roots as a plain `SparseMerkleTree`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RawKeys;
+
+impl KeyHasher for RawKeys {
+    fn hash_key(&self, key: &[u8]) -> Result<Hash, SMTError> {
+        key.try_into().map_err(|_| SMTError::InvalidEncoding)
+    }
+
+    fn domain(&self) -> Option<Hash> {
+        None
+    }
+}
+
+/// Keys of any length are stored under their SHA-256 hash, which spreads
+/// them evenly over the tree.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sha256Keys;
+
+impl KeyHasher for Sha256Keys {
+    fn hash_key(&self, key: &[u8]) -> Result<Hash, SMTError> {
+        Ok(DefaultHasher::digest(key).into())
+    }
+
+    fn domain(&self) -> Option<Hash> {
+        Some(DefaultHasher::digest(b"SimpleSparseMerkle/keys/sha256").into())
+    }
+}
+
+/// Keys are stored as `prefix || sha256(key)`, cut to 32 bytes, so every key
+/// in a namespace lands in one subtree and `range::prefix_range(prefix)`
+/// covers exactly that namespace.
+#[derive(Debug, Clone)]
+pub struct NamespacedKeys {
+    prefix: Vec<u8>,
+}
+
+impl NamespacedKeys {
+    /// Panics if `prefix` is longer than `MAX_NAMESPACE_LEN`.
+    pub fn new(prefix: &[u8]) -> Self {
+        assert!(prefix.len() <= MAX_NAMESPACE_LEN, "namespace must be at most 8 bytes");
+        Self { prefix: prefix.to_vec() }
+    }
+
+    pub fn prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+}
+
+impl KeyHasher for NamespacedKeys {
+    fn hash_key(&self, key: &[u8]) -> Result<Hash, SMTError> {
+        let digest: Hash = DefaultHasher::digest(key).into();
+        let mut hashed = [0u8; 32];
+        hashed[..self.prefix.len()].copy_from_slice(&self.prefix);
+        hashed[self.prefix.len()..].copy_from_slice(&digest[..32 - self.prefix.len()]);
+        Ok(hashed)
+    }
+
+    fn domain(&self) -> Option<Hash> {
+        let mut hasher = DefaultHasher::new();
+        hasher.update(b"SimpleSparseMerkle/keys/namespaced");
+        hasher.update([self.prefix.len() as u8]);
+        hasher.update(&self.prefix);
+        Some(hasher.finalize().into())
+    }
+}
+
+/// Tree addressed by application keys, which `K` turns into tree keys. Range
+/// proofs, iteration and the other tools on `tree()` see the hashed keys.
+pub struct KeyedTree<S: KVStore, K: KeyHasher> {
+    tree: SparseMerkleTree<S>,
+    keys: K,
+}
+
+impl<S: KVStore, K: KeyHasher> KeyedTree<S, K>
+where
+    SMTError: From<S::Error>,
+{
+    /// Wraps `tree`, which must be empty or have been written through a
+    /// `KeyedTree` with the same strategy: the strategy's domain changes how
+    /// leaves hash.
+    pub fn new(mut tree: SparseMerkleTree<S>, keys: K) -> Self {
+        if let Some(domain) = keys.domain() {
+            tree.hasher = tree.hasher.with_key_domain(domain);
+        }
+        Self { tree, keys }
+    }
+
+    pub fn update(&mut self, key: &[u8], value: Hash) -> Result<(), SMTError> {
+        let key = self.keys.hash_key(key)?;
+        self.tree.update(key, value)
+    }
+
+    pub fn get(&self, key: &[u8]) -> Result<Option<Hash>, SMTError> {
+        let key = self.keys.hash_key(key)?;
+        self.tree.get(key)
+    }
+
+    pub fn delete(&mut self, key: &[u8]) -> Result<(), SMTError> {
+        let key = self.keys.hash_key(key)?;
+        self.tree.delete(key)
+    }
+
+    pub fn get_proof(&self, key: &[u8]) -> Result<MerkleProof, SMTError> {
+        let key = self.keys.hash_key(key)?;
+        self.tree.get_proof(key)
+    }
+
+    pub fn verify_proof(&self, key: &[u8], value: Hash, proof: &MerkleProof) -> Result<(), SMTError> {
+        let key = self.keys.hash_key(key)?;
+        self.tree.verify_proof(key, value, proof)
+    }
+
+    pub fn root(&self) -> Hash {
+        self.tree.root()
+    }
+
+    pub fn key_hasher(&self) -> &K {
+        &self.keys
+    }
+
+    pub fn tree(&self) -> &SparseMerkleTree<S> {
+        &self.tree
+    }
+
+    pub fn into_inner(self) -> SparseMerkleTree<S> {
+        self.tree
+    }
+}
+
+/// Checks a proof from a `KeyedTree` using `keys` without needing its store.
+pub fn verify_keyed<K: KeyHasher>(keys: &K, root: &Hash, key: &[u8], value: &Hash, proof: &MerkleProof) -> bool {
+    let Ok(key) = keys.hash_key(key) else {
+        return false;
+    };
+    let hasher = match keys.domain() {
+        Some(domain) => TreeHasher::<DefaultHasher>::new().with_key_domain(domain),
+        None => TreeHasher::new(),
+    };
+    proof.verify_with(&hasher, root, &key, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{kv_store::InMemoryKVStore, range::prefix_range, spec::verify_with_spec};
+
+    fn keyed<K: KeyHasher>(keys: K) -> KeyedTree<InMemoryKVStore, K> {
+        let mut tree = KeyedTree::new(SparseMerkleTree::new(InMemoryKVStore::new()), keys);
+        tree.update(&[1u8; 32], [10u8; 32]).unwrap();
+        tree.update(&[2u8; 32], [20u8; 32]).unwrap();
+        tree
+    }
+
+    #[test]
+    fn test_raw_keys_match_plain_tree() {
+        let tree = keyed(RawKeys);
+        let mut plain = SparseMerkleTree::new(InMemoryKVStore::new());
+        plain.update([1u8; 32], [10u8; 32]).unwrap();
+        plain.update([2u8; 32], [20u8; 32]).unwrap();
+
+        assert_eq!(tree.root(), plain.root());
+        assert!(matches!(tree.get(b"short"), Err(SMTError::InvalidEncoding)));
+    }
+
+    #[test]
+    fn test_strategies_never_share_roots() {
+        let roots = [
+            keyed(RawKeys).root(),
+            keyed(Sha256Keys).root(),
+            keyed(NamespacedKeys::new(b"a")).root(),
+            keyed(NamespacedKeys::new(b"b")).root(),
+        ];
+        for (i, root) in roots.iter().enumerate() {
+            assert!(roots[i + 1..].iter().all(|other| other != root));
+        }
+
+        // Storing the hashed keys directly still gives a different root.
+        let mut raw = KeyedTree::new(SparseMerkleTree::new(InMemoryKVStore::new()), RawKeys);
+        for (key, value) in [([1u8; 32], [10u8; 32]), ([2u8; 32], [20u8; 32])] {
+            raw.update(&Sha256Keys.hash_key(&key).unwrap(), value).unwrap();
+        }
+        assert_ne!(raw.root(), roots[1]);
+    }
+
+    #[test]
+    fn test_keyed_proofs() {
+        let mut tree = keyed(Sha256Keys);
+        tree.update(b"alice", [30u8; 32]).unwrap();
+        assert_eq!(tree.get(b"alice").unwrap(), Some([30u8; 32]));
+
+        let proof = tree.get_proof(b"alice").unwrap();
+        assert!(tree.verify_proof(b"alice", [30u8; 32], &proof).is_ok());
+        assert!(!verify_keyed(&RawKeys, &tree.root(), b"alice", &[30u8; 32], &proof));
+
+        let key = Sha256Keys.hash_key(b"alice").unwrap();
+        let spec = tree.tree().spec();
+        assert!(verify_with_spec(&spec, &tree.root(), &key, &[30u8; 32], &proof).unwrap());
+
+        tree.delete(b"alice").unwrap();
+        assert_eq!(tree.get(b"alice").unwrap(), None);
+    }
+
+    #[test]
+    fn test_namespace_is_one_subtree() {
+        let mut tree = KeyedTree::new(SparseMerkleTree::new(InMemoryKVStore::new()), NamespacedKeys::new(b"acct"));
+        tree.update(b"alice", [1u8; 32]).unwrap();
+        tree.update(b"bob", [2u8; 32]).unwrap();
+
+        let (start, end) = prefix_range(b"acct");
+        let proof = tree.tree().prove_range(start, end).unwrap();
+        assert_eq!(proof.entries.len(), 2);
+    }
+}
@@ -1,32 +1,1254 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
 use crate::Hash;
 
+/// Entries yielded by `KVStore::scan_prefix`, as key and value.
+pub type Scan<'a, E> = Box<dyn Iterator<Item = Result<(Hash, Vec<u8>), E>> + 'a>;
+
 pub trait KVStore {
     type Error;
 
     fn get(&self, key: &Hash) -> Result<Option<Vec<u8>>, Self::Error>;
     fn set(&mut self, key: Hash, value: Vec<u8>) -> Result<(), Self::Error>;
+    fn remove(&mut self, key: &Hash) -> Result<(), Self::Error>;
+
+    /// Internal nodes go through these so backends can keep them apart from
+    /// leaf values. By default both share the same key space.
+    fn get_node(&self, hash: &Hash) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.get(hash)
+    }
+
+    fn set_node(&mut self, hash: Hash, node: Vec<u8>) -> Result<(), Self::Error> {
+        self.set(hash, node)
+    }
+
+    fn remove_node(&mut self, hash: &Hash) -> Result<(), Self::Error> {
+        self.remove(hash)
+    }
+
+    /// Last root recorded with `set_root`, for backends that outlive the process.
+    fn get_root(&self) -> Result<Option<Hash>, Self::Error> {
+        Ok(None)
+    }
+
+    /// Called once at the end of every tree update with the new root.
+    fn set_root(&mut self, _root: Hash) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Applies every write in `batch`. The default replays them in order,
+    /// root last, which is atomic for backends that buffer until `set_root`;
+    /// others should override it if they can do better.
+    fn commit_batch(&mut self, batch: TreeWriteBatch) -> Result<(), Self::Error> {
+        for (key, value) in batch.values {
+            match value {
+                Some(value) => self.set(key, value)?,
+                None => self.remove(&key)?,
+            }
+        }
+        for (hash, node) in batch.nodes {
+            match node {
+                Some(node) => self.set_node(hash, node)?,
+                None => self.remove_node(&hash)?,
+            }
+        }
+        if let Some(root) = batch.root {
+            self.set_root(root)?;
+        }
+        Ok(())
+    }
+
+    /// Applies value writes, `None` removing the key, together. The default
+    /// hands them to `commit_batch` without a root, so on backends that
+    /// buffer until `set_root` they land with the next root.
+    fn write_batch(&mut self, writes: Vec<(Hash, Option<Vec<u8>>)>) -> Result<(), Self::Error> {
+        let mut batch = TreeWriteBatch::new();
+        batch.values.extend(writes);
+        self.commit_batch(batch)
+    }
+
+    /// Every value whose key starts with `prefix`, in no particular order,
+    /// or `None` if the backend cannot list its keys, as by default.
+    fn scan_prefix(&self, _prefix: &[u8]) -> Option<Scan<'_, Self::Error>> {
+        None
+    }
+
+    /// Like `scan_prefix`, over nodes. By default nodes share the value key
+    /// space, as with `get_node`.
+    fn scan_node_prefix(&self, prefix: &[u8]) -> Option<Scan<'_, Self::Error>> {
+        self.scan_prefix(prefix)
+    }
 }
 
+/// `scan` with the writes in `pending`, `None` for a removal, applied on
+/// top, for stores that buffer writes in front of a backend.
+pub(crate) fn scan_with_pending<'a, E: 'a>(
+    scan: Scan<'a, E>,
+    pending: impl Iterator<Item = (&'a Hash, Option<&'a Vec<u8>>)>,
+    prefix: &[u8],
+) -> Scan<'a, E> {
+    let mut merged = BTreeMap::new();
+    for entry in scan {
+        match entry {
+            Ok((key, value)) => merged.insert(key, Some(value)),
+            Err(error) => return Box::new(std::iter::once(Err(error))),
+        };
+    }
+    for (key, value) in pending.filter(|(key, _)| key.starts_with(prefix)) {
+        merged.insert(*key, value.cloned());
+    }
+    Box::new(merged.into_iter().filter_map(|(key, value)| value.map(|value| Ok((key, value)))))
+}
+
+/// Writes staged in memory and handed to a store in one `commit`, so an error
+/// part way through a tree operation leaves the store untouched.
+#[derive(Default)]
+pub struct TreeWriteBatch {
+    values: BTreeMap<Hash, Option<Vec<u8>>>, // `None` removes the key
+    nodes: BTreeMap<Hash, Option<Vec<u8>>>,
+    root: Option<Hash>,
+}
+
+impl TreeWriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, key: Hash, value: Vec<u8>) {
+        self.values.insert(key, Some(value));
+    }
+
+    pub fn remove(&mut self, key: Hash) {
+        self.values.insert(key, None);
+    }
+
+    pub fn set_node(&mut self, hash: Hash, node: Vec<u8>) {
+        self.nodes.insert(hash, Some(node));
+    }
+
+    pub fn remove_node(&mut self, hash: Hash) {
+        self.nodes.insert(hash, None);
+    }
+
+    pub fn set_root(&mut self, root: Hash) {
+        self.root = Some(root);
+    }
+
+    /// Root the batch records, if any.
+    pub(crate) fn root(&self) -> Option<Hash> {
+        self.root
+    }
+
+    /// Number of staged value and node writes.
+    pub fn len(&self) -> usize {
+        self.values.len() + self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0 && self.root.is_none()
+    }
+
+    pub fn commit<S: KVStore + ?Sized>(self, store: &mut S) -> Result<(), S::Error> {
+        store.commit_batch(self)
+    }
+
+    /// Adds every write staged in `other`, which win over those already here.
+    #[cfg(feature = "parallel")]
+    pub(crate) fn extend(&mut self, other: TreeWriteBatch) {
+        self.values.extend(other.values);
+        self.nodes.extend(other.nodes);
+        if other.root.is_some() {
+            self.root = other.root;
+        }
+    }
+
+    /// Number of node records the batch writes, not counting removals.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn written_nodes(&self) -> usize {
+        self.nodes.values().filter(|node| node.is_some()).count()
+    }
+
+    /// Hashes of the nodes the batch deletes.
+    #[cfg(feature = "lru")]
+    pub(crate) fn removed_nodes(&self) -> impl Iterator<Item = &Hash> {
+        self.nodes.iter().filter(|(_, node)| node.is_none()).map(|(hash, _)| hash)
+    }
+
+    /// Drops every staged write.
+    pub fn abort(self) {}
+}
+
+/// Cloning is O(1), and so is writing after a clone. The store is a stack of
+/// maps, and clones share every map. A write never copies a map a clone still
+/// holds; it starts a new one on top. The two newest maps are merged
+/// whenever the newer is at least half the size of the one below, so the
+/// stack stays logarithmic in the number of entries and each entry is
+/// copied a logarithmic number of times.
+///
+/// An optional byte budget caps what the store may hold. Each entry counts
+/// as its 32-byte key plus its value; a write that would go over fails with
+/// `ErrorKind::OutOfMemory` and changes nothing. Use `TieredStore` to spill
+/// to a slower store instead of failing.
+#[derive(Clone)]
 pub struct InMemoryKVStore {
-    store: HashMap<Hash, Vec<u8>>,
+    layers: Vec<Arc<Layer>>, // Oldest first, never empty
+    used: usize,
+    budget: Option<usize>,
+}
+
+/// Entries of one map in `InMemoryKVStore`'s stack. `None` hides a key that
+/// an older map holds.
+type Layer = HashMap<Hash, Option<Vec<u8>>>;
+
+impl Default for InMemoryKVStore {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl InMemoryKVStore {
     pub fn new() -> Self {
-        Self { store: HashMap::new() }
+        Self { layers: vec![Arc::new(Layer::new())], used: 0, budget: None }
+    }
+
+    /// Empty store that refuses writes past `bytes`.
+    pub fn with_budget(bytes: usize) -> Self {
+        Self { budget: Some(bytes), ..Self::new() }
+    }
+
+    /// Bytes held, counted as the budget counts them.
+    pub fn bytes_used(&self) -> usize {
+        self.used
+    }
+
+    pub fn budget(&self) -> Option<usize> {
+        self.budget
+    }
+
+    /// Usage after replacing whatever `key` holds with `len` bytes, or `None`
+    /// for a removal.
+    fn usage_after(&self, used: usize, key: &Hash, len: Option<usize>) -> usize {
+        let old = self.lookup(key).map_or(0, |value| entry_size(value.len()));
+        used - old + len.map_or(0, entry_size)
+    }
+
+    fn lookup(&self, key: &Hash) -> Option<&Vec<u8>> {
+        self.layers.iter().rev().find_map(|layer| layer.get(key)).and_then(Option::as_ref)
+    }
+
+    /// The newest map, after starting a new one if a clone shares it.
+    fn top(&mut self) -> &mut Layer {
+        if Arc::strong_count(self.layers.last().expect("never empty")) > 1 {
+            self.merge_full_layers();
+            self.layers.push(Arc::new(Layer::new()));
+        }
+        Arc::make_mut(self.layers.last_mut().expect("never empty"))
+    }
+
+    /// Merges the two newest maps into a new one while the newer is at least
+    /// half the size of the older. Maps clones hold are left as they are.
+    fn merge_full_layers(&mut self) {
+        while let [.., older, newer] = &self.layers[..] {
+            if newer.len() * 2 < older.len() {
+                break;
+            }
+            let bottom = self.layers.len() == 2;
+            let mut merged = Layer::clone(older);
+            for (key, value) in newer.iter() {
+                match value {
+                    None if bottom => merged.remove(key), // Nothing older left to hide
+                    _ => merged.insert(*key, value.clone()),
+                };
+            }
+            self.layers.truncate(self.layers.len() - 2);
+            self.layers.push(Arc::new(merged));
+        }
+    }
+
+    fn check_budget(&self, used: usize) -> Result<(), std::io::Error> {
+        match self.budget {
+            Some(budget) if used > budget => Err(std::io::Error::new(
+                std::io::ErrorKind::OutOfMemory,
+                format!("memory budget of {} bytes exceeded ({} needed)", budget, used),
+            )),
+            _ => Ok(()),
+        }
     }
 }
 
+fn entry_size(value_len: usize) -> usize {
+    std::mem::size_of::<Hash>() + value_len
+}
+
 impl KVStore for InMemoryKVStore {
     type Error = std::io::Error;
 
     fn get(&self, key: &Hash) -> Result<Option<Vec<u8>>, Self::Error> {
-        Ok(self.store.get(key).cloned())
+        Ok(self.lookup(key).cloned())
     }
 
     fn set(&mut self, key: Hash, value: Vec<u8>) -> Result<(), Self::Error> {
-        self.store.insert(key, value);
+        let used = self.usage_after(self.used, &key, Some(value.len()));
+        self.check_budget(used)?;
+        self.top().insert(key, Some(value));
+        self.used = used;
         Ok(())
     }
-}
\ No newline at end of file
+
+    fn remove(&mut self, key: &Hash) -> Result<(), Self::Error> {
+        if self.lookup(key).is_none() {
+            return Ok(());
+        }
+        self.used = self.usage_after(self.used, key, None);
+        // Only a lone, unshared map can drop the key instead of hiding it
+        let bottom = self.layers.len() == 1 && Arc::strong_count(&self.layers[0]) == 1;
+        let top = self.top();
+        match bottom {
+            true => top.remove(key),
+            false => top.insert(*key, None),
+        };
+        Ok(())
+    }
+
+    /// Checks the whole batch against the budget before writing any of it.
+    fn commit_batch(&mut self, batch: TreeWriteBatch) -> Result<(), Self::Error> {
+        if self.budget.is_some() {
+            // Values and nodes share one key space here, and nodes are
+            // written second, so they win when both name the same key.
+            let mut writes: HashMap<&Hash, Option<usize>> = HashMap::new();
+            for (key, value) in batch.values.iter().chain(&batch.nodes) {
+                writes.insert(key, value.as_ref().map(Vec::len));
+            }
+            let used = writes.into_iter().fold(self.used, |used, (key, len)| self.usage_after(used, key, len));
+            self.check_budget(used)?;
+        }
+
+        for (key, value) in batch.values.into_iter().chain(batch.nodes) {
+            match value {
+                Some(value) => self.set(key, value)?,
+                None => self.remove(&key)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Option<Scan<'_, Self::Error>> {
+        let mut merged = BTreeMap::new();
+        for layer in &self.layers {
+            merged.extend(layer.iter().filter(|(key, _)| key.starts_with(prefix)));
+        }
+        Some(Box::new(merged.into_iter().filter_map(|(key, value)| value.as_ref().map(|value| Ok((*key, value.clone()))))))
+    }
+}
+
+/// In-memory store that keeps at most `budget` bytes and moves the entries
+/// written longest ago to `cold` to make room, so a tree can run in bounded
+/// memory on top of a disk-backed store. Reads check memory first.
+///
+/// The root is kept in memory too, as the nodes under it may not have
+/// reached `cold` yet: nothing is durable until `flush` or `into_cold`,
+/// which write every entry to `cold` before the root.
+pub struct TieredStore<S: KVStore> {
+    hot: HashMap<Hash, (Vec<u8>, u64)>, // Value and the tick it was written at
+    by_write: BTreeMap<u64, Hash>,
+    tick: u64,
+    root: Option<Hash>,
+    used: usize,
+    budget: usize,
+    cold: S,
+}
+
+impl<S: KVStore> TieredStore<S> {
+    pub fn new(cold: S, budget: usize) -> Self {
+        Self { hot: HashMap::new(), by_write: BTreeMap::new(), tick: 0, root: None, used: 0, budget, cold }
+    }
+
+    /// Bytes held in memory, counted like `InMemoryKVStore::bytes_used`.
+    pub fn bytes_used(&self) -> usize {
+        self.used
+    }
+
+    pub fn cold(&self) -> &S {
+        &self.cold
+    }
+
+    /// Moves everything still in memory to the cold store, then records the
+    /// root there.
+    pub fn flush(&mut self) -> Result<(), S::Error> {
+        self.by_write.clear();
+        self.used = 0;
+        for (key, (value, _)) in self.hot.drain() {
+            self.cold.set(key, value)?;
+        }
+        match self.root.take() {
+            Some(root) => self.cold.set_root(root),
+            None => Ok(()),
+        }
+    }
+
+    /// Flushes, and returns the cold store.
+    pub fn into_cold(mut self) -> Result<S, S::Error> {
+        self.flush()?;
+        Ok(self.cold)
+    }
+
+    fn take_hot(&mut self, key: &Hash) -> Option<Vec<u8>> {
+        let (value, tick) = self.hot.remove(key)?;
+        self.by_write.remove(&tick);
+        self.used -= entry_size(value.len());
+        Some(value)
+    }
+
+    fn evict_until_fits(&mut self, incoming: usize) -> Result<(), S::Error> {
+        while self.used + incoming > self.budget {
+            let Some((_, key)) = self.by_write.pop_first() else {
+                break; // Nothing left to evict; the entry alone exceeds the budget
+            };
+            if let Some((value, _)) = self.hot.remove(&key) {
+                self.used -= entry_size(value.len());
+                self.cold.set(key, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: KVStore> KVStore for TieredStore<S> {
+    type Error = S::Error;
+
+    fn get(&self, key: &Hash) -> Result<Option<Vec<u8>>, Self::Error> {
+        match self.hot.get(key) {
+            Some((value, _)) => Ok(Some(value.clone())),
+            None => self.cold.get(key),
+        }
+    }
+
+    fn set(&mut self, key: Hash, value: Vec<u8>) -> Result<(), Self::Error> {
+        self.take_hot(&key);
+        let size = entry_size(value.len());
+        self.evict_until_fits(size)?;
+        if size > self.budget {
+            return self.cold.set(key, value);
+        }
+        self.tick += 1;
+        self.hot.insert(key, (value, self.tick));
+        self.by_write.insert(self.tick, key);
+        self.used += size;
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &Hash) -> Result<(), Self::Error> {
+        self.take_hot(key);
+        self.cold.remove(key)
+    }
+
+    fn get_root(&self) -> Result<Option<Hash>, Self::Error> {
+        match self.root {
+            Some(root) => Ok(Some(root)),
+            None => self.cold.get_root(),
+        }
+    }
+
+    fn set_root(&mut self, root: Hash) -> Result<(), Self::Error> {
+        self.root = Some(root);
+        Ok(())
+    }
+
+    /// Needs the cold store to scan.
+    fn scan_prefix(&self, prefix: &[u8]) -> Option<Scan<'_, Self::Error>> {
+        let cold = self.cold.scan_prefix(prefix)?;
+        Some(scan_with_pending(cold, self.hot.iter().map(|(key, (value, _))| (key, Some(value))), prefix))
+    }
+}
+
+/// Spreads a tree over several stores, routing every key (leaf key or node
+/// hash) by its first byte. Node hashes are uniformly distributed, so the
+/// shards see roughly equal I/O. The root is recorded on the first shard.
+pub struct CompositeStore<S: KVStore> {
+    shards: Vec<S>,
+}
+
+impl<S: KVStore> CompositeStore<S> {
+    /// Splits the key space into equal first-byte ranges, one per shard.
+    /// Panics if `shards` is empty or has more than 256 entries.
+    pub fn new(shards: Vec<S>) -> Self {
+        assert!(!shards.is_empty() && shards.len() <= 256, "CompositeStore needs 1 to 256 shards");
+        Self { shards }
+    }
+
+    /// Index of the shard responsible for `key`.
+    pub fn shard_for(&self, key: &Hash) -> usize {
+        key[0] as usize * self.shards.len() / 256
+    }
+
+    pub fn shards(&self) -> &[S] {
+        &self.shards
+    }
+
+    pub fn into_shards(self) -> Vec<S> {
+        self.shards
+    }
+}
+
+impl<S: KVStore> KVStore for CompositeStore<S> {
+    type Error = S::Error;
+
+    fn get(&self, key: &Hash) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.shards[self.shard_for(key)].get(key)
+    }
+
+    fn set(&mut self, key: Hash, value: Vec<u8>) -> Result<(), Self::Error> {
+        let shard = self.shard_for(&key);
+        self.shards[shard].set(key, value)
+    }
+
+    fn remove(&mut self, key: &Hash) -> Result<(), Self::Error> {
+        let shard = self.shard_for(key);
+        self.shards[shard].remove(key)
+    }
+
+    fn get_node(&self, hash: &Hash) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.shards[self.shard_for(hash)].get_node(hash)
+    }
+
+    fn set_node(&mut self, hash: Hash, node: Vec<u8>) -> Result<(), Self::Error> {
+        let shard = self.shard_for(&hash);
+        self.shards[shard].set_node(hash, node)
+    }
+
+    fn remove_node(&mut self, hash: &Hash) -> Result<(), Self::Error> {
+        let shard = self.shard_for(hash);
+        self.shards[shard].remove_node(hash)
+    }
+
+    fn get_root(&self) -> Result<Option<Hash>, Self::Error> {
+        self.shards[0].get_root()
+    }
+
+    fn set_root(&mut self, root: Hash) -> Result<(), Self::Error> {
+        // Every shard sees the root so buffering backends flush their part of the update.
+        for shard in &mut self.shards {
+            shard.set_root(root)?;
+        }
+        Ok(())
+    }
+
+    /// Needs every shard to scan.
+    fn scan_prefix(&self, prefix: &[u8]) -> Option<Scan<'_, Self::Error>> {
+        let scans = self.shards.iter().map(|shard| shard.scan_prefix(prefix)).collect::<Option<Vec<_>>>()?;
+        Some(Box::new(scans.into_iter().flatten()))
+    }
+
+    fn scan_node_prefix(&self, prefix: &[u8]) -> Option<Scan<'_, Self::Error>> {
+        let scans = self.shards.iter().map(|shard| shard.scan_node_prefix(prefix)).collect::<Option<Vec<_>>>()?;
+        Some(Box::new(scans.into_iter().flatten()))
+    }
+}
+
+#[cfg(feature = "rocksdb")]
+pub use self::rocks::RocksDbStore;
+
+#[cfg(feature = "rocksdb")]
+mod rocks {
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    use rocksdb::{ColumnFamily, Direction, IteratorMode, Options, WriteBatch, DB};
+
+    use super::{scan_with_pending, KVStore, Scan};
+    use crate::Hash;
+
+    const NODES_CF: &str = "nodes";
+    const VALUES_CF: &str = "values";
+    const META_CF: &str = "meta";
+    const ROOT_KEY: &[u8] = b"root";
+
+    /// RocksDB-backed store. Internal nodes and leaf values live in separate
+    /// column families. Writes are buffered and flushed as one atomic batch
+    /// when the tree records its new root, so each update lands all-or-nothing.
+    pub struct RocksDbStore {
+        db: DB,
+        batch: WriteBatch,
+        pending: HashMap<(&'static str, Hash), Option<Vec<u8>>>,
+    }
+
+    impl RocksDbStore {
+        /// Opens (or creates) a store at `path`.
+        pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, rocksdb::Error> {
+            let mut opts = Options::default();
+            opts.create_if_missing(true);
+            opts.create_missing_column_families(true);
+            let db = DB::open_cf(&opts, path, [NODES_CF, VALUES_CF, META_CF])?;
+            Ok(Self {
+                db,
+                batch: WriteBatch::default(),
+                pending: HashMap::new(),
+            })
+        }
+
+        fn cf(&self, name: &str) -> &ColumnFamily {
+            self.db
+                .cf_handle(name)
+                .expect("column families are created on open")
+        }
+
+        fn read(&self, cf: &'static str, key: &Hash) -> Result<Option<Vec<u8>>, rocksdb::Error> {
+            if let Some(value) = self.pending.get(&(cf, *key)) {
+                return Ok(value.clone());
+            }
+            self.db.get_cf(self.cf(cf), key)
+        }
+
+        fn stage(&mut self, cf: &'static str, key: Hash, value: Vec<u8>) {
+            let handle = self.db.cf_handle(cf).expect("column families are created on open");
+            self.batch.put_cf(handle, key, &value);
+            self.pending.insert((cf, key), Some(value));
+        }
+
+        fn stage_removal(&mut self, cf: &'static str, key: Hash) {
+            let handle = self.db.cf_handle(cf).expect("column families are created on open");
+            self.batch.delete_cf(handle, key);
+            self.pending.insert((cf, key), None);
+        }
+
+        fn scan(&self, cf: &'static str, prefix: &[u8]) -> Scan<'_, rocksdb::Error> {
+            let within = prefix.to_vec();
+            let stored = self
+                .db
+                .iterator_cf(self.cf(cf), IteratorMode::From(prefix, Direction::Forward))
+                .take_while(move |entry| entry.as_ref().map_or(true, |(key, _)| key.starts_with(&within)))
+                .filter_map(|entry| match entry {
+                    Ok((key, value)) => Some(Ok((key[..].try_into().ok()?, value.to_vec()))),
+                    Err(error) => Some(Err(error)),
+                });
+            let pending = self.pending.iter().filter(move |((name, _), _)| *name == cf).map(|((_, key), value)| (key, value.as_ref()));
+            scan_with_pending(Box::new(stored), pending, prefix)
+        }
+    }
+
+    impl KVStore for RocksDbStore {
+        type Error = rocksdb::Error;
+
+        fn get(&self, key: &Hash) -> Result<Option<Vec<u8>>, Self::Error> {
+            self.read(VALUES_CF, key)
+        }
+
+        fn set(&mut self, key: Hash, value: Vec<u8>) -> Result<(), Self::Error> {
+            self.stage(VALUES_CF, key, value);
+            Ok(())
+        }
+
+        fn remove(&mut self, key: &Hash) -> Result<(), Self::Error> {
+            self.stage_removal(VALUES_CF, *key);
+            Ok(())
+        }
+
+        fn get_node(&self, hash: &Hash) -> Result<Option<Vec<u8>>, Self::Error> {
+            self.read(NODES_CF, hash)
+        }
+
+        fn set_node(&mut self, hash: Hash, node: Vec<u8>) -> Result<(), Self::Error> {
+            self.stage(NODES_CF, hash, node);
+            Ok(())
+        }
+
+        fn remove_node(&mut self, hash: &Hash) -> Result<(), Self::Error> {
+            self.stage_removal(NODES_CF, *hash);
+            Ok(())
+        }
+
+        fn get_root(&self) -> Result<Option<Hash>, Self::Error> {
+            let root = self.db.get_cf(self.cf(META_CF), ROOT_KEY)?;
+            Ok(root.and_then(|bytes| bytes.as_slice().try_into().ok()))
+        }
+
+        fn set_root(&mut self, root: Hash) -> Result<(), Self::Error> {
+            let mut batch = std::mem::take(&mut self.batch);
+            batch.put_cf(self.cf(META_CF), ROOT_KEY, root);
+            self.db.write(batch)?;
+            self.pending.clear();
+            Ok(())
+        }
+
+        /// Applies the writes atomically right away, independently of any
+        /// tree update in progress. Writes staged for the same keys are
+        /// replaced, so they neither shadow these nor replay over them when
+        /// the next root is recorded.
+        fn write_batch(&mut self, writes: Vec<(Hash, Option<Vec<u8>>)>) -> Result<(), Self::Error> {
+            let mut batch = WriteBatch::default();
+            let mut staged = Vec::new();
+            for (key, value) in writes {
+                match &value {
+                    Some(value) => batch.put_cf(self.cf(VALUES_CF), key, value),
+                    None => batch.delete_cf(self.cf(VALUES_CF), key),
+                }
+                if self.pending.contains_key(&(VALUES_CF, key)) {
+                    staged.push((key, value));
+                }
+            }
+            self.db.write(batch)?;
+            for (key, value) in staged {
+                match value {
+                    Some(value) => self.stage(VALUES_CF, key, value),
+                    None => self.stage_removal(VALUES_CF, key),
+                }
+            }
+            Ok(())
+        }
+
+        fn scan_prefix(&self, prefix: &[u8]) -> Option<Scan<'_, Self::Error>> {
+            Some(self.scan(VALUES_CF, prefix))
+        }
+
+        fn scan_node_prefix(&self, prefix: &[u8]) -> Option<Scan<'_, Self::Error>> {
+            Some(self.scan(NODES_CF, prefix))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::sparse_merkle_tree::SparseMerkleTree;
+
+        #[test]
+        fn test_root_survives_reopen() {
+            let dir = tempfile::tempdir().unwrap();
+            let key: Hash = [1u8; 32];
+            let value: Hash = [2u8; 32];
+
+            let root = {
+                let mut smt = SparseMerkleTree::new(RocksDbStore::open(dir.path()).unwrap());
+                smt.update(key, value).unwrap();
+                smt.update([3u8; 32], [4u8; 32]).unwrap();
+                smt.root()
+            };
+
+            let smt = SparseMerkleTree::open(RocksDbStore::open(dir.path()).unwrap()).unwrap();
+            assert_eq!(smt.root(), root);
+            assert_eq!(smt.get(key).unwrap(), Some(value));
+            let proof = smt.get_proof(key).unwrap();
+            assert!(smt.verify_proof(key, value, &proof).is_ok());
+        }
+
+        #[test]
+        fn test_fresh_store_has_no_root() {
+            let dir = tempfile::tempdir().unwrap();
+            let store = RocksDbStore::open(dir.path()).unwrap();
+            assert_eq!(store.get_root().unwrap(), None);
+        }
+
+        #[test]
+        fn test_write_batch_replaces_staged_writes() {
+            let dir = tempfile::tempdir().unwrap();
+            let mut store = RocksDbStore::open(dir.path()).unwrap();
+            store.set([1u8; 32], vec![1]).unwrap();
+            store.set([2u8; 32], vec![2]).unwrap();
+            store.write_batch(vec![([1u8; 32], Some(vec![10])), ([2u8; 32], None)]).unwrap();
+            assert_eq!(store.get(&[1u8; 32]).unwrap(), Some(vec![10]));
+            assert_eq!(store.get(&[2u8; 32]).unwrap(), None);
+
+            store.set_root([9u8; 32]).unwrap();
+            assert_eq!(store.get(&[1u8; 32]).unwrap(), Some(vec![10]));
+            assert_eq!(store.get(&[2u8; 32]).unwrap(), None);
+        }
+    }
+}
+
+#[cfg(feature = "sled")]
+pub use self::sled_store::SledStore;
+
+#[cfg(feature = "sled")]
+mod sled_store {
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    use super::{scan_with_pending, KVStore, Scan};
+    use crate::Hash;
+
+    const VALUE_PREFIX: u8 = b'v';
+    const NODE_PREFIX: u8 = b'n';
+    const ROOT_KEY: &[u8] = b"root";
+
+    fn prefixed(prefix: u8, key: &Hash) -> [u8; 33] {
+        let mut out = [0u8; 33];
+        out[0] = prefix;
+        out[1..].copy_from_slice(key);
+        out
+    }
+
+    /// Pure-Rust persistent store on top of sled. Values and nodes share one
+    /// sled tree under different key prefixes so a whole tree update can be
+    /// applied as a single atomic batch when the new root is recorded.
+    pub struct SledStore {
+        db: sled::Db,
+        batch: sled::Batch,
+        pending: HashMap<[u8; 33], Option<Vec<u8>>>,
+    }
+
+    impl SledStore {
+        /// Opens (or creates) a store at `path`.
+        pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, sled::Error> {
+            Ok(Self::from_db(sled::open(path)?))
+        }
+
+        /// Store over an already open database. Writes staged in another store
+        /// over the same database stay invisible until that store commits.
+        pub fn from_db(db: sled::Db) -> Self {
+            Self {
+                db,
+                batch: sled::Batch::default(),
+                pending: HashMap::new(),
+            }
+        }
+
+        fn read(&self, key: [u8; 33]) -> Result<Option<Vec<u8>>, sled::Error> {
+            if let Some(value) = self.pending.get(&key) {
+                return Ok(value.clone());
+            }
+            Ok(self.db.get(key)?.map(|value| value.to_vec()))
+        }
+
+        fn stage(&mut self, key: [u8; 33], value: Vec<u8>) {
+            self.batch.insert(&key[..], value.clone());
+            self.pending.insert(key, Some(value));
+        }
+
+        fn stage_removal(&mut self, key: [u8; 33]) {
+            self.batch.remove(&key[..]);
+            self.pending.insert(key, None);
+        }
+
+        fn scan(&self, space: u8, prefix: &[u8]) -> Scan<'_, sled::Error> {
+            let stored = self.db.scan_prefix([&[space][..], prefix].concat()).filter_map(|entry| match entry {
+                Ok((key, value)) => Some(Ok((key[1..].try_into().ok()?, value.to_vec()))),
+                Err(error) => Some(Err(error)),
+            });
+            let pending = self
+                .pending
+                .iter()
+                .filter(move |(key, _)| key[0] == space)
+                .filter_map(|(key, value)| Some((<&Hash>::try_from(&key[1..]).ok()?, value.as_ref())));
+            scan_with_pending(Box::new(stored), pending, prefix)
+        }
+    }
+
+    impl KVStore for SledStore {
+        type Error = sled::Error;
+
+        fn get(&self, key: &Hash) -> Result<Option<Vec<u8>>, Self::Error> {
+            self.read(prefixed(VALUE_PREFIX, key))
+        }
+
+        fn set(&mut self, key: Hash, value: Vec<u8>) -> Result<(), Self::Error> {
+            self.stage(prefixed(VALUE_PREFIX, &key), value);
+            Ok(())
+        }
+
+        /// Like other writes, a removal becomes durable with the next root.
+        fn remove(&mut self, key: &Hash) -> Result<(), Self::Error> {
+            self.stage_removal(prefixed(VALUE_PREFIX, key));
+            Ok(())
+        }
+
+        fn get_node(&self, hash: &Hash) -> Result<Option<Vec<u8>>, Self::Error> {
+            self.read(prefixed(NODE_PREFIX, hash))
+        }
+
+        fn set_node(&mut self, hash: Hash, node: Vec<u8>) -> Result<(), Self::Error> {
+            self.stage(prefixed(NODE_PREFIX, &hash), node);
+            Ok(())
+        }
+
+        fn remove_node(&mut self, hash: &Hash) -> Result<(), Self::Error> {
+            self.stage_removal(prefixed(NODE_PREFIX, hash));
+            Ok(())
+        }
+
+        fn get_root(&self) -> Result<Option<Hash>, Self::Error> {
+            let root = self.db.get(ROOT_KEY)?;
+            Ok(root.and_then(|bytes| bytes.as_ref().try_into().ok()))
+        }
+
+        fn set_root(&mut self, root: Hash) -> Result<(), Self::Error> {
+            let mut batch = std::mem::take(&mut self.batch);
+            batch.insert(ROOT_KEY, &root[..]);
+            self.db.apply_batch(batch)?;
+            self.db.flush()?;
+            self.pending.clear();
+            Ok(())
+        }
+
+        /// Applies the writes atomically and durably right away, independently
+        /// of any tree update in progress. Writes staged for the same keys are
+        /// replaced, so they neither shadow these nor replay over them when
+        /// the next root is recorded.
+        fn write_batch(&mut self, writes: Vec<(Hash, Option<Vec<u8>>)>) -> Result<(), Self::Error> {
+            let mut batch = sled::Batch::default();
+            let mut staged = Vec::new();
+            for (key, value) in writes {
+                let key = prefixed(VALUE_PREFIX, &key);
+                match &value {
+                    Some(value) => batch.insert(&key[..], &value[..]),
+                    None => batch.remove(&key[..]),
+                }
+                if self.pending.contains_key(&key) {
+                    staged.push((key, value));
+                }
+            }
+            self.db.apply_batch(batch)?;
+            self.db.flush()?;
+            for (key, value) in staged {
+                match value {
+                    Some(value) => self.stage(key, value),
+                    None => self.stage_removal(key),
+                }
+            }
+            Ok(())
+        }
+
+        fn scan_prefix(&self, prefix: &[u8]) -> Option<Scan<'_, Self::Error>> {
+            Some(self.scan(VALUE_PREFIX, prefix))
+        }
+
+        fn scan_node_prefix(&self, prefix: &[u8]) -> Option<Scan<'_, Self::Error>> {
+            Some(self.scan(NODE_PREFIX, prefix))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::sparse_merkle_tree::SparseMerkleTree;
+
+        // sled keeps its lock until background threads let go of a dropped
+        // database, so reopening the path straight away can fail. Reopen from
+        // a handle to the same database instead, which only shares what was
+        // committed.
+        fn open_db() -> (tempfile::TempDir, sled::Db) {
+            let dir = tempfile::tempdir().unwrap();
+            let db = sled::open(dir.path()).unwrap();
+            (dir, db)
+        }
+
+        #[test]
+        fn test_root_survives_reopen() {
+            let (_dir, db) = open_db();
+            let key: Hash = [1u8; 32];
+            let value: Hash = [2u8; 32];
+
+            let root = {
+                let mut smt = SparseMerkleTree::new(SledStore::from_db(db.clone()));
+                smt.update(key, value).unwrap();
+                smt.update([3u8; 32], [4u8; 32]).unwrap();
+                smt.root()
+            };
+
+            let smt = SparseMerkleTree::open(SledStore::from_db(db)).unwrap();
+            assert_eq!(smt.root(), root);
+            assert_eq!(smt.get(key).unwrap(), Some(value));
+            let proof = smt.get_proof(key).unwrap();
+            assert!(smt.verify_proof(key, value, &proof).is_ok());
+        }
+
+        #[test]
+        fn test_interrupted_update_is_not_persisted() {
+            let (_dir, db) = open_db();
+            let root = {
+                let mut smt = SparseMerkleTree::new(SledStore::from_db(db.clone()));
+                smt.update([1u8; 32], [2u8; 32]).unwrap();
+                smt.root()
+            };
+
+            {
+                // Writes staged without a new root, as if the process died mid-update
+                let mut store = SledStore::from_db(db.clone());
+                store.set([5u8; 32], vec![6u8; 32]).unwrap();
+                store.set_node([7u8; 32], vec![0u8; 64]).unwrap();
+            }
+
+            let store = SledStore::from_db(db);
+            assert_eq!(store.get(&[5u8; 32]).unwrap(), None);
+            let smt = SparseMerkleTree::open(store).unwrap();
+            assert_eq!(smt.root(), root);
+        }
+
+        #[test]
+        fn test_write_batch_and_remove() {
+            let (_dir, db) = open_db();
+            let mut store = SledStore::from_db(db.clone());
+            store
+                .write_batch(vec![([1u8; 32], Some(vec![1u8; 32])), ([2u8; 32], Some(vec![2u8; 32]))])
+                .unwrap();
+            assert_eq!(store.get(&[1u8; 32]).unwrap(), Some(vec![1u8; 32]));
+
+            store.remove(&[1u8; 32]).unwrap();
+            assert_eq!(store.get(&[1u8; 32]).unwrap(), None);
+
+            store.write_batch(vec![([2u8; 32], None)]).unwrap();
+            drop(store);
+
+            let store = SledStore::from_db(db);
+            assert_eq!(store.get(&[2u8; 32]).unwrap(), None);
+        }
+
+        #[test]
+        fn test_write_batch_replaces_staged_writes() {
+            let (_dir, db) = open_db();
+            let mut store = SledStore::from_db(db.clone());
+            store.set([1u8; 32], vec![1]).unwrap();
+            store.set([2u8; 32], vec![2]).unwrap();
+            store.write_batch(vec![([1u8; 32], Some(vec![10])), ([2u8; 32], None)]).unwrap();
+            assert_eq!(store.get(&[1u8; 32]).unwrap(), Some(vec![10]));
+            assert_eq!(store.get(&[2u8; 32]).unwrap(), None);
+
+            store.set_root([9u8; 32]).unwrap();
+            let store = SledStore::from_db(db);
+            assert_eq!(store.get(&[1u8; 32]).unwrap(), Some(vec![10]));
+            assert_eq!(store.get(&[2u8; 32]).unwrap(), None);
+        }
+
+        #[test]
+        fn test_scan_prefix_merges_staged_writes() {
+            let dir = tempfile::tempdir().unwrap();
+            let mut store = SledStore::open(dir.path()).unwrap();
+            store.write_batch(vec![([1u8; 32], Some(vec![1])), ([2u8; 32], Some(vec![2]))]).unwrap();
+            store.remove(&[1u8; 32]).unwrap();
+            store.set([3u8; 32], vec![3]).unwrap();
+            store.set_node([2u8; 32], vec![4]).unwrap();
+
+            let values: Vec<_> = store.scan_prefix(&[]).unwrap().collect::<Result<_, _>>().unwrap();
+            assert_eq!(values, vec![([2u8; 32], vec![2]), ([3u8; 32], vec![3])]);
+            let nodes: Vec<_> = store.scan_node_prefix(&[2]).unwrap().collect::<Result<_, _>>().unwrap();
+            assert_eq!(nodes, vec![([2u8; 32], vec![4])]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sparse_merkle_tree::SparseMerkleTree;
+    use digest::Digest;
+
+    #[test]
+    fn test_composite_store_routes_by_first_byte() {
+        let store = CompositeStore::new(vec![InMemoryKVStore::new(), InMemoryKVStore::new()]);
+        assert_eq!(store.shard_for(&[0x00; 32]), 0);
+        assert_eq!(store.shard_for(&[0x7f; 32]), 0);
+        assert_eq!(store.shard_for(&[0x80; 32]), 1);
+        assert_eq!(store.shard_for(&[0xff; 32]), 1);
+    }
+
+    #[test]
+    fn test_composite_store_matches_single_store() {
+        let mut single = SparseMerkleTree::new(InMemoryKVStore::new());
+        let shards = (0..4).map(|_| InMemoryKVStore::new()).collect();
+        let mut sharded = SparseMerkleTree::new(CompositeStore::new(shards));
+
+        for i in 0..32u8 {
+            let key: Hash = [i.wrapping_mul(8); 32];
+            single.update(key, [i; 32]).unwrap();
+            sharded.update(key, [i; 32]).unwrap();
+        }
+
+        assert_eq!(sharded.root(), single.root());
+        let key: Hash = [0xf8; 32];
+        assert_eq!(sharded.get(key).unwrap(), Some([31u8; 32]));
+        assert!(sharded.verify_proof(key, [31u8; 32], &sharded.get_proof(key).unwrap()).is_ok());
+    }
+
+    fn scanned<E: std::fmt::Debug>(scan: Option<Scan<'_, E>>) -> Vec<(Hash, Vec<u8>)> {
+        let mut entries: Vec<_> = scan.unwrap().collect::<Result<_, _>>().unwrap();
+        entries.sort();
+        entries
+    }
+
+    #[test]
+    fn test_write_batch_and_scan_prefix() {
+        let mut store = InMemoryKVStore::new();
+        let mut key = [0xabu8; 32];
+        key[1] = 0;
+        store.write_batch(vec![([0xab; 32], Some(vec![1])), (key, Some(vec![2])), ([0xcd; 32], Some(vec![3]))]).unwrap();
+        store.write_batch(vec![([0xcd; 32], None)]).unwrap();
+
+        assert_eq!(scanned(store.scan_prefix(&[0xab])), vec![(key, vec![2]), ([0xab; 32], vec![1])]);
+        assert_eq!(scanned(store.scan_prefix(&[0xab, 0xab])), vec![([0xab; 32], vec![1])]);
+        assert_eq!(scanned(store.scan_prefix(&[])).len(), 2);
+
+        let mut budgeted = InMemoryKVStore::with_budget(100);
+        assert!(budgeted.write_batch(vec![([1; 32], Some(vec![0; 32])), ([2; 32], Some(vec![0; 32]))]).is_err());
+        assert_eq!(budgeted.bytes_used(), 0);
+    }
+
+    #[test]
+    fn test_scan_sees_buffered_writes() {
+        let mut base = InMemoryKVStore::new();
+        base.set([1u8; 32], vec![1]).unwrap();
+        base.set([2u8; 32], vec![2]).unwrap();
+        let mut overlay = crate::overlay::OverlayStore::new(base);
+        overlay.remove(&[1u8; 32]).unwrap();
+        overlay.set([2u8; 32], vec![20]).unwrap();
+        overlay.set([3u8; 32], vec![3]).unwrap();
+        assert_eq!(scanned(overlay.scan_prefix(&[])), vec![([2u8; 32], vec![20]), ([3u8; 32], vec![3])]);
+
+        let mut tiered = TieredStore::new(InMemoryKVStore::new(), 100);
+        tiered.set([1u8; 32], vec![1; 40]).unwrap();
+        tiered.set([2u8; 32], vec![2; 40]).unwrap();
+        tiered.set([1u8; 32], vec![10]).unwrap();
+        assert_eq!(scanned(tiered.scan_prefix(&[])), vec![([1u8; 32], vec![10]), ([2u8; 32], vec![2; 40])]);
+    }
+
+    #[test]
+    fn test_clones_keep_their_contents() {
+        let mut store = InMemoryKVStore::new();
+        store.set([1u8; 32], vec![1]).unwrap();
+        store.set([2u8; 32], vec![2]).unwrap();
+        let before = store.clone();
+        store.remove(&[1u8; 32]).unwrap();
+        store.set([2u8; 32], vec![20]).unwrap();
+        let middle = store.clone();
+        store.set([1u8; 32], vec![10]).unwrap();
+
+        assert_eq!(scanned(before.scan_prefix(&[])), vec![([1u8; 32], vec![1]), ([2u8; 32], vec![2])]);
+        assert_eq!(scanned(middle.scan_prefix(&[])), vec![([2u8; 32], vec![20])]);
+        assert_eq!(middle.get(&[1u8; 32]).unwrap(), None);
+        assert_eq!(store.get(&[1u8; 32]).unwrap(), Some(vec![10]));
+        assert_eq!(store.bytes_used(), 66);
+    }
+
+    #[test]
+    fn test_writes_after_clone_copy_only_what_changed() {
+        // What ConcurrentSmt does: clone the store for readers after every write
+        let entries: Vec<(Hash, Hash)> = (0..4096u32)
+            .map(|i| {
+                let mut key: Hash = crate::DefaultHasher::digest(i.to_be_bytes()).into();
+                key[4..].fill(0);
+                (key, [1u8; 32])
+            })
+            .collect();
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new()).with_depth(32);
+        smt.update_batch(&entries).unwrap();
+        let size = smt.store.layers.iter().map(|layer| layer.len()).sum::<usize>();
+
+        let mut copied = 0;
+        for (key, _) in entries.iter().take(64) {
+            let published = smt.store.clone();
+            smt.update(*key, [2u8; 32]).unwrap();
+            let fresh = smt.store.layers.iter().filter(|layer| !published.layers.iter().any(|old| Arc::ptr_eq(layer, old)));
+            copied += fresh.map(|layer| layer.len()).sum::<usize>();
+        }
+        // Copying the whole store on each write would be 64 times its size
+        assert!(copied < size / 2, "copied {} entries of {}", copied, size);
+        assert!(smt.store.layers.len() <= 2 * usize::BITS as usize);
+        assert_eq!(smt.get(entries[0].0).unwrap(), Some([2u8; 32]));
+    }
+
+    #[test]
+    fn test_budget_rejects_writes_past_limit() {
+        let mut store = InMemoryKVStore::with_budget(100);
+        store.set([1u8; 32], vec![0u8; 32]).unwrap();
+        assert_eq!(store.bytes_used(), 64);
+
+        let error = store.set([2u8; 32], vec![0u8; 32]).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::OutOfMemory);
+        assert_eq!(store.get(&[2u8; 32]).unwrap(), None);
+
+        store.set([1u8; 32], vec![0u8; 60]).unwrap();
+        store.remove(&[1u8; 32]).unwrap();
+        assert_eq!(store.bytes_used(), 0);
+    }
+
+    #[test]
+    fn test_budget_failure_leaves_tree_untouched() {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::with_budget(64 * 1024));
+        smt.update([1u8; 32], [1u8; 32]).unwrap();
+        let (root, used) = (smt.root(), smt.store.bytes_used());
+
+        let entries: Vec<(Hash, Hash)> = (0..200u8).map(|i| ([i; 32], [i; 32])).collect();
+        assert!(smt.update_batch(&entries).is_err());
+        assert_eq!(smt.root(), root);
+        assert_eq!(smt.store.bytes_used(), used);
+        assert_eq!(smt.get([1u8; 32]).unwrap(), Some([1u8; 32]));
+    }
+
+    #[test]
+    fn test_tiered_store_spills_to_cold() {
+        let mut smt = SparseMerkleTree::new(TieredStore::new(InMemoryKVStore::new(), 16 * 1024));
+        let mut reference = SparseMerkleTree::new(InMemoryKVStore::new());
+        for i in 0..64u8 {
+            smt.update([i; 32], [i; 32]).unwrap();
+            reference.update([i; 32], [i; 32]).unwrap();
+        }
+
+        assert!(smt.store.bytes_used() <= 16 * 1024);
+        assert!(smt.store.cold().bytes_used() > 0);
+        assert_eq!(smt.root(), reference.root());
+        assert_eq!(smt.get([3u8; 32]).unwrap(), Some([3u8; 32]));
+        assert!(smt.verify_proof([3u8; 32], [3u8; 32], &smt.get_proof([3u8; 32]).unwrap()).is_ok());
+
+        let leaf = smt.hasher.digest_leaf(&[63u8; 32], &[63u8; 32]);
+        let cold = smt.store.into_cold().unwrap();
+        assert_eq!(cold.get_node(&leaf).unwrap(), Some(crate::node::encode_leaf(&[63u8; 32], &[63u8; 32])));
+    }
+
+    /// In-memory store that records its root, like a durable backend.
+    #[derive(Clone)]
+    struct RootedStore {
+        inner: InMemoryKVStore,
+        root: Option<Hash>,
+    }
+
+    impl KVStore for RootedStore {
+        type Error = std::io::Error;
+
+        fn get(&self, key: &Hash) -> Result<Option<Vec<u8>>, Self::Error> {
+            self.inner.get(key)
+        }
+
+        fn set(&mut self, key: Hash, value: Vec<u8>) -> Result<(), Self::Error> {
+            self.inner.set(key, value)
+        }
+
+        fn remove(&mut self, key: &Hash) -> Result<(), Self::Error> {
+            self.inner.remove(key)
+        }
+
+        fn get_root(&self) -> Result<Option<Hash>, Self::Error> {
+            Ok(self.root)
+        }
+
+        fn set_root(&mut self, root: Hash) -> Result<(), Self::Error> {
+            self.root = Some(root);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_tiered_store_root_waits_for_flush() {
+        let mut smt = SparseMerkleTree::new(TieredStore::new(RootedStore { inner: InMemoryKVStore::new(), root: None }, 64 * 1024));
+        smt.update([1u8; 32], [1u8; 32]).unwrap();
+
+        // The new root's nodes are still in memory, so cold must not name it
+        assert_eq!(smt.store.get_root().unwrap(), Some(smt.root()));
+        assert_eq!(smt.store.cold().get_root().unwrap(), None);
+
+        smt.store.flush().unwrap();
+        assert_eq!(smt.store.bytes_used(), 0);
+        let reopened = SparseMerkleTree::open(smt.store.cold().clone()).unwrap();
+        assert_eq!(reopened.root(), smt.root());
+        assert_eq!(reopened.get([1u8; 32]).unwrap(), Some([1u8; 32]));
+    }
+
+    #[test]
+    fn test_tiered_store_rewrites_do_not_pile_up() {
+        let mut tiered = TieredStore::new(InMemoryKVStore::new(), 1000);
+        for i in 0..100u8 {
+            tiered.set([1u8; 32], vec![i; 8]).unwrap();
+        }
+        tiered.remove(&[1u8; 32]).unwrap();
+        tiered.set([2u8; 32], vec![2; 8]).unwrap();
+        assert_eq!(tiered.by_write.len(), 1);
+        assert_eq!(tiered.bytes_used(), 40);
+        assert_eq!(tiered.cold().get(&[1u8; 32]).unwrap(), None);
+    }
+}
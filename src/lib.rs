@@ -1,14 +1,127 @@
+//! Sparse Merkle trees. With the default `std` feature off, only `TreeHasher`
+//! and proof verification are built, on `core` and `alloc`, for embedded and
+//! zkVM verifiers.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+// The crate keeps its published CamelCase name.
+#![allow(non_snake_case)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
 pub mod kv_store;
 pub mod proof;
+#[cfg(feature = "std")]
 pub mod sparse_merkle_tree;
 pub mod tree_hasher;
+#[cfg(feature = "std")]
 pub mod error;
+#[cfg(feature = "std")]
 pub mod account;
+#[cfg(feature = "std")]
 pub mod transaction;
+#[cfg(feature = "std")]
+pub mod partial_tree;
+#[cfg(feature = "std")]
+pub mod op;
+#[cfg(feature = "std")]
+pub mod overlay;
+#[cfg(feature = "std")]
+pub mod arith;
+#[cfg(feature = "std")]
+pub mod spec;
+#[cfg(feature = "std")]
+pub mod hashers;
+pub mod hex;
+#[cfg(feature = "std")]
+pub mod observer;
+#[cfg(feature = "std")]
+pub mod versioned;
+#[cfg(feature = "std")]
+pub mod snapshot;
+#[cfg(feature = "std")]
+pub mod read_only;
+#[cfg(feature = "std")]
+pub mod concurrent;
+#[cfg(feature = "std")]
+pub mod migration;
+#[cfg(feature = "std")]
+pub mod ttl;
+#[cfg(feature = "std")]
+pub mod signature;
+#[cfg(feature = "std")]
+pub mod attestation;
+#[cfg(feature = "std")]
+pub mod checkpoint;
+#[cfg(feature = "std")]
+pub mod commitment;
+#[cfg(feature = "std")]
+pub mod iter;
+#[cfg(feature = "std")]
+pub mod root_history;
+#[cfg(feature = "std")]
+pub mod root_log;
+#[cfg(feature = "std")]
+pub mod range;
+#[cfg(feature = "std")]
+pub mod handle;
+#[cfg(feature = "std")]
+pub mod diff;
+#[cfg(feature = "std")]
+pub mod chunk;
+#[cfg(feature = "std")]
+pub mod key_hasher;
+#[cfg(feature = "std")]
+pub mod clock;
+#[cfg(feature = "std")]
+pub mod node;
+#[cfg(feature = "std")]
+pub mod verifier_kit;
+#[cfg(feature = "std")]
+pub mod state;
+#[cfg(feature = "std")]
+pub mod mempool;
+#[cfg(feature = "std")]
+pub mod export;
+#[cfg(feature = "std")]
+pub mod sync;
+#[cfg(feature = "std")]
+pub mod namespace;
+#[cfg(feature = "std")]
+pub mod value;
+#[cfg(feature = "ics23")]
+pub mod ics23;
+#[cfg(feature = "proto")]
+pub mod proto;
+#[cfg(feature = "lru")]
+pub mod node_cache;
+#[cfg(feature = "parallel")]
+pub mod bulk;
+#[cfg(feature = "poseidon")]
+pub mod poseidon;
+#[cfg(feature = "test-utils")]
+pub mod testing;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "capi")]
+pub mod ffi;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "http")]
+pub mod http;
 
+#[cfg(feature = "std")]
 pub mod tree_sparse_merkle;
 
-#[cfg(test)]
+#[cfg(feature = "std")]
+pub use error::{ErrorContext, ResultExt, SMTError};
+#[cfg(feature = "std")]
+pub use kv_store::{InMemoryKVStore, KVStore};
+pub use proof::MerkleProof;
+#[cfg(feature = "std")]
+pub use sparse_merkle_tree::SparseMerkleTree;
+
+#[cfg(all(test, feature = "std"))]
 mod tests;
 
 use sha2::Sha256;
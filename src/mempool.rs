@@ -0,0 +1,170 @@
+use std::collections::{BTreeMap, HashSet};
+
+use tracing::debug;
+
+use crate::{error::SMTError, hex::HexFmt, kv_store::KVStore, state::StateMachine, transaction::Transaction, Hash};
+
+/// Transactions waiting to be put in a block. Each sender's transactions are
+/// kept as a gapless run of nonces starting at the sender's nonce in the
+/// state, so every batch taken from the pool applies in order.
+///
+/// Signatures are not checked on admission.
+#[derive(Default)]
+pub struct Mempool {
+    by_sender: BTreeMap<Hash, BTreeMap<u64, Transaction>>,
+    hashes: HashSet<Hash>,
+}
+
+impl Mempool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Admits `tx` if it is new and its nonce is the next one for its sender:
+    /// the sender's nonce in `state` plus the sender's transactions already
+    /// pooled.
+    pub fn insert<S: KVStore>(&mut self, state: &StateMachine<S>, tx: Transaction) -> Result<(), SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let hash = tx.compute_hash();
+        if self.hashes.contains(&hash) {
+            return Err(SMTError::DuplicateTransaction(hash));
+        }
+        let expected = self.next_nonce(state, &tx.from)?;
+        if tx.nonce != expected {
+            return Err(SMTError::BadNonce { expected, got: tx.nonce });
+        }
+
+        debug!("Pooling transaction {} from {} with nonce {}", HexFmt(&hash), HexFmt(&tx.from), tx.nonce);
+        self.hashes.insert(hash);
+        self.by_sender.entry(tx.from).or_default().insert(tx.nonce, tx);
+        Ok(())
+    }
+
+    /// Nonce the next pooled transaction from `sender` must carry.
+    pub fn next_nonce<S: KVStore>(&self, state: &StateMachine<S>, sender: &Hash) -> Result<u64, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        if let Some(last) = self.by_sender.get(sender).and_then(|pooled| pooled.last_key_value()) {
+            return Ok(last.0 + 1);
+        }
+        Ok(state.account(sender)?.map_or(0, |account| account.nonce))
+    }
+
+    /// Removes and returns up to `n` transactions for a block. Senders take
+    /// turns in address order, one transaction each per round, so a busy
+    /// sender cannot crowd the others out. Each sender's transactions come out
+    /// in nonce order.
+    pub fn take_batch(&mut self, n: usize) -> Vec<Transaction> {
+        let mut batch = Vec::with_capacity(n.min(self.len()));
+        while batch.len() < n && !self.by_sender.is_empty() {
+            for pooled in self.by_sender.values_mut() {
+                if batch.len() == n {
+                    break;
+                }
+                if let Some((_, tx)) = pooled.pop_first() {
+                    self.hashes.remove(&tx.compute_hash());
+                    batch.push(tx);
+                }
+            }
+            self.by_sender.retain(|_, pooled| !pooled.is_empty());
+        }
+        batch
+    }
+
+    /// Drops transactions whose nonces `state` has already moved past, such
+    /// as those included in a block built elsewhere.
+    pub fn prune<S: KVStore>(&mut self, state: &StateMachine<S>) -> Result<(), SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        for (sender, pooled) in &mut self.by_sender {
+            let nonce = state.account(sender)?.map_or(0, |account| account.nonce);
+            let current = pooled.split_off(&nonce);
+            for tx in std::mem::replace(pooled, current).into_values() {
+                self.hashes.remove(&tx.compute_hash());
+            }
+        }
+        self.by_sender.retain(|_, pooled| !pooled.is_empty());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{account::Account, kv_store::InMemoryKVStore, sparse_merkle_tree::SparseMerkleTree};
+
+    const ALICE: Hash = [1u8; 32];
+    const BOB: Hash = [2u8; 32];
+
+    fn transfer(from: Hash, to: Hash, amount: u64, nonce: u64) -> Transaction {
+        Transaction { from, to, amount, nonce, ..Default::default() }
+    }
+
+    fn funded() -> StateMachine<InMemoryKVStore> {
+        let mut state = StateMachine::new(SparseMerkleTree::new(InMemoryKVStore::new()));
+        state.set_account(&Account::new(ALICE, 100)).unwrap();
+        state.set_account(&Account { nonce: 5, ..Account::new(BOB, 100) }).unwrap();
+        state
+    }
+
+    #[test]
+    fn test_rejects_duplicates_and_gaps() {
+        let state = funded();
+        let mut pool = Mempool::new();
+        pool.insert(&state, transfer(ALICE, BOB, 1, 0)).unwrap();
+
+        assert!(matches!(pool.insert(&state, transfer(ALICE, BOB, 1, 0)), Err(SMTError::DuplicateTransaction(_))));
+        assert!(matches!(pool.insert(&state, transfer(ALICE, BOB, 2, 0)), Err(SMTError::BadNonce { expected: 1, got: 0 })));
+        assert!(matches!(pool.insert(&state, transfer(ALICE, BOB, 1, 2)), Err(SMTError::BadNonce { expected: 1, got: 2 })));
+        assert!(matches!(pool.insert(&state, transfer(BOB, ALICE, 1, 0)), Err(SMTError::BadNonce { expected: 5, got: 0 })));
+        pool.insert(&state, transfer(ALICE, BOB, 1, 1)).unwrap();
+        pool.insert(&state, transfer(BOB, ALICE, 1, 5)).unwrap();
+        assert_eq!(pool.len(), 3);
+    }
+
+    #[test]
+    fn test_batches_apply_in_order() {
+        let mut state = funded();
+        let mut pool = Mempool::new();
+        for nonce in 0..3 {
+            pool.insert(&state, transfer(ALICE, BOB, 1, nonce)).unwrap();
+        }
+        pool.insert(&state, transfer(BOB, ALICE, 1, 5)).unwrap();
+
+        let batch = pool.take_batch(2);
+        assert_eq!(batch.iter().map(|tx| (tx.from, tx.nonce)).collect::<Vec<_>>(), vec![(ALICE, 0), (BOB, 5)]);
+        state.apply_all(&batch).unwrap();
+
+        let batch = pool.take_batch(10);
+        assert_eq!(batch.len(), 2);
+        state.apply_all(&batch).unwrap();
+        assert!(pool.is_empty());
+        assert_eq!(state.account(&ALICE).unwrap().unwrap().nonce, 3);
+        assert_eq!(pool.next_nonce(&state, &ALICE).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_prune_drops_included_transactions() {
+        let mut state = funded();
+        let mut pool = Mempool::new();
+        pool.insert(&state, transfer(ALICE, BOB, 1, 0)).unwrap();
+        pool.insert(&state, transfer(ALICE, BOB, 2, 1)).unwrap();
+
+        state.apply(&transfer(ALICE, BOB, 1, 0)).unwrap();
+        pool.prune(&state).unwrap();
+        assert_eq!(pool.len(), 1);
+        assert_eq!(pool.take_batch(1)[0].nonce, 1);
+    }
+}
@@ -0,0 +1,102 @@
+//! Process-wide hooks for exporting tree metrics, in the manner of the `log`
+//! facade: install one `MetricsRecorder` at startup and every tree reports
+//! to it. Until one is installed, or without the `metrics` feature, nothing
+//! is recorded and the hooks cost a single atomic load.
+
+use std::sync::OnceLock;
+
+/// Receives measurements from every tree in the process. Forward them to
+/// whichever metrics library the application uses. Every method defaults to
+/// doing nothing, and all run on the calling thread, so they should be cheap.
+pub trait MetricsRecorder: Send + Sync {
+    /// Node records read from a store. Reads served by a node cache are not
+    /// counted.
+    fn node_reads(&self, _count: u64) {}
+
+    /// Node records written to a store by one commit.
+    fn node_writes(&self, _count: u64) {}
+
+    /// Side nodes in a proof the tree just built, a sample for a histogram
+    /// of proof sizes.
+    fn proof_size(&self, _side_nodes: usize) {}
+}
+
+static RECORDER: OnceLock<Box<dyn MetricsRecorder>> = OnceLock::new();
+
+/// Installs the process-wide recorder. Only the first call succeeds; later
+/// ones hand their recorder back.
+pub fn set_recorder(recorder: Box<dyn MetricsRecorder>) -> Result<(), Box<dyn MetricsRecorder>> {
+    RECORDER.set(recorder)
+}
+
+/// The installed recorder, if any.
+pub fn recorder() -> Option<&'static dyn MetricsRecorder> {
+    RECORDER.get().map(|recorder| recorder.as_ref())
+}
+
+/// Runs `f` on the installed recorder, if any.
+pub(crate) fn record(f: impl FnOnce(&dyn MetricsRecorder)) {
+    if let Some(recorder) = recorder() {
+        f(recorder);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{kv_store::InMemoryKVStore, sparse_merkle_tree::SparseMerkleTree};
+    use std::cell::RefCell;
+
+    // Tests share the one recorder, so it counts per thread and each test
+    // sees only its own tree
+    thread_local! {
+        static SEEN: RefCell<(u64, u64, Vec<usize>)> = const { RefCell::new((0, 0, Vec::new())) };
+    }
+
+    struct ThreadRecorder;
+
+    impl MetricsRecorder for ThreadRecorder {
+        fn node_reads(&self, count: u64) {
+            SEEN.with(|seen| seen.borrow_mut().0 += count);
+        }
+
+        fn node_writes(&self, count: u64) {
+            SEEN.with(|seen| seen.borrow_mut().1 += count);
+        }
+
+        fn proof_size(&self, side_nodes: usize) {
+            SEEN.with(|seen| seen.borrow_mut().2.push(side_nodes));
+        }
+    }
+
+    fn take() -> (u64, u64, Vec<usize>) {
+        SEEN.with(|seen| seen.take())
+    }
+
+    #[test]
+    fn test_tree_reports_reads_writes_and_proofs() {
+        let _ = set_recorder(Box::new(ThreadRecorder));
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new()).with_depth(8);
+        take();
+
+        smt.update([0u8; 32], [1u8; 32]).unwrap();
+        let (reads, writes, _) = take();
+        assert_eq!(reads, 0, "an empty tree has nothing to read");
+        assert_eq!(writes, 9, "the leaf and one node per level");
+
+        let mut other = [0u8; 32];
+        other[0] = 0xff;
+        smt.update(other, [2u8; 32]).unwrap();
+        let proof = smt.get_proof([0u8; 32]).unwrap();
+        let (reads, _, sizes) = take();
+        assert!(reads > 0);
+        assert_eq!(sizes, vec![proof.side_nodes.len()]);
+    }
+
+    #[test]
+    fn test_second_recorder_is_refused() {
+        let _ = set_recorder(Box::new(ThreadRecorder));
+        assert!(set_recorder(Box::new(ThreadRecorder)).is_err());
+        assert!(recorder().is_some());
+    }
+}
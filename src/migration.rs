@@ -0,0 +1,146 @@
+use std::collections::BTreeSet;
+
+use crate::{
+    error::SMTError,
+    kv_store::KVStore,
+    sparse_merkle_tree::SparseMerkleTree,
+    tree_hasher::TreeDigest,
+    DefaultHasher, Hash,
+};
+
+/// Keeps an old and a new tree in lockstep while moving between hashers or
+/// store formats. Every write goes to both trees, and leaves they both hold
+/// can be cross-checked, so operators can publish both roots for a while and
+/// only cut over once the new tree is known to match.
+///
+/// Leaves that exist before the migration starts are copied into the new tree
+/// with `backfill`.
+pub struct MigrationTree<S1: KVStore, S2: KVStore, D1: TreeDigest = DefaultHasher, D2: TreeDigest = DefaultHasher> {
+    old: SparseMerkleTree<S1, D1>,
+    new: SparseMerkleTree<S2, D2>,
+    window: u64,
+    writes: u64,
+    keys: BTreeSet<Hash>,
+}
+
+impl<S1: KVStore, S2: KVStore, D1: TreeDigest, D2: TreeDigest> MigrationTree<S1, S2, D1, D2>
+where
+    SMTError: From<S1::Error> + From<S2::Error>,
+{
+    /// Runs both trees side by side for at least `window` writes.
+    pub fn new(old: SparseMerkleTree<S1, D1>, new: SparseMerkleTree<S2, D2>, window: u64) -> Self {
+        Self {
+            old,
+            new,
+            window,
+            writes: 0,
+            keys: BTreeSet::new(),
+        }
+    }
+
+    pub fn update(&mut self, key: Hash, value: Hash) -> Result<(), SMTError> {
+        self.old.update(key, value)?;
+        self.new.update(key, value)?;
+        self.keys.insert(key);
+        self.writes += 1;
+        Ok(())
+    }
+
+    pub fn delete(&mut self, key: Hash) -> Result<(), SMTError> {
+        self.old.delete(key)?;
+        self.new.delete(key)?;
+        self.keys.insert(key);
+        self.writes += 1;
+        Ok(())
+    }
+
+    /// Copies a leaf the old tree already holds into the new one.
+    pub fn backfill(&mut self, key: Hash) -> Result<(), SMTError> {
+        if let Some(value) = self.old.get(key)? {
+            self.new.update(key, value)?;
+            self.keys.insert(key);
+        }
+        Ok(())
+    }
+
+    /// Roots of the old and new tree, in that order.
+    pub fn roots(&self) -> (Hash, Hash) {
+        (self.old.root(), self.new.root())
+    }
+
+    /// Writes left before the window is over.
+    pub fn remaining_window(&self) -> u64 {
+        self.window.saturating_sub(self.writes)
+    }
+
+    /// Checks that both trees agree on every leaf written or backfilled so far.
+    pub fn cross_check(&self) -> Result<(), SMTError> {
+        for key in &self.keys {
+            if self.old.get(*key)? != self.new.get(*key)? {
+                return Err(SMTError::LeafMismatch(*key));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the window is over and the trees still agree.
+    pub fn ready_to_cut_over(&self) -> Result<bool, SMTError> {
+        if self.remaining_window() > 0 {
+            return Ok(false);
+        }
+        self.cross_check()?;
+        Ok(true)
+    }
+
+    /// Ends the migration, keeping only the new tree. Check
+    /// `ready_to_cut_over` first.
+    pub fn cut_over(self) -> SparseMerkleTree<S2, D2> {
+        self.new
+    }
+
+    /// Ends the migration, keeping only the old tree.
+    pub fn abandon(self) -> SparseMerkleTree<S1, D1> {
+        self.old
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_store::InMemoryKVStore;
+    use sha3::Sha3_256;
+
+    fn migration() -> MigrationTree<InMemoryKVStore, InMemoryKVStore, DefaultHasher, Sha3_256> {
+        let mut old = SparseMerkleTree::new(InMemoryKVStore::new());
+        old.update([1u8; 32], [10u8; 32]).unwrap();
+        let new = SparseMerkleTree::<_, Sha3_256>::with_hasher(InMemoryKVStore::new());
+        MigrationTree::new(old, new, 2)
+    }
+
+    #[test]
+    fn test_lockstep_writes_and_cut_over() {
+        let mut migration = migration();
+        migration.backfill([1u8; 32]).unwrap();
+        migration.update([2u8; 32], [20u8; 32]).unwrap();
+        assert!(!migration.ready_to_cut_over().unwrap());
+
+        migration.delete([1u8; 32]).unwrap();
+        assert_eq!(migration.remaining_window(), 0);
+        assert!(migration.ready_to_cut_over().unwrap());
+
+        let (old_root, new_root) = migration.roots();
+        assert_ne!(old_root, new_root);
+        let tree = migration.cut_over();
+        assert_eq!(tree.root(), new_root);
+        assert_eq!(tree.get([2u8; 32]).unwrap(), Some([20u8; 32]));
+    }
+
+    #[test]
+    fn test_cross_check_catches_divergence() {
+        let mut migration = migration();
+        migration.update([2u8; 32], [20u8; 32]).unwrap();
+        migration.new.update([2u8; 32], [21u8; 32]).unwrap();
+
+        assert!(matches!(migration.cross_check(), Err(SMTError::LeafMismatch(key)) if key == [2u8; 32]));
+    }
+}
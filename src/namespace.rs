@@ -0,0 +1,228 @@
+//! Several logical trees (accounts, storage, receipts, ...) kept in one
+//! physical store. Each namespace gets its own key space and recorded root,
+//! and the store as a whole records the root of roots, one hash committing
+//! to the root of every namespace.
+
+use digest::Digest;
+
+use crate::{error::SMTError, kv_store::KVStore, DefaultHasher, Hash};
+
+const KEY_DOMAIN: &[u8] = b"SimpleSparseMerkle/namespace-key/v1";
+const ROOTS_DOMAIN: &[u8] = b"SimpleSparseMerkle/root-of-roots/v1";
+
+/// Longest namespace name, so its length fits the byte before it.
+pub const MAX_NAME_LEN: usize = 255;
+
+const VALUE_KIND: u8 = b'v';
+const NODE_KIND: u8 = b'n';
+
+/// Key the registry of namespace roots is stored under.
+fn registry_key() -> Hash {
+    DefaultHasher::new().chain_update(KEY_DOMAIN).chain_update(b"registry").finalize().into()
+}
+
+/// Commitment to the root of every namespace: the hash of each name and
+/// root, in name order. Names must be unique.
+pub fn root_of_roots(roots: &[(Vec<u8>, Hash)]) -> Hash {
+    let mut sorted: Vec<&(Vec<u8>, Hash)> = roots.iter().collect();
+    sorted.sort();
+    let mut hasher = DefaultHasher::new().chain_update(ROOTS_DOMAIN);
+    for (name, root) in sorted {
+        hasher.update([name.len() as u8]);
+        hasher.update(name);
+        hasher.update(root);
+    }
+    hasher.finalize().into()
+}
+
+fn encode_registry(roots: &[(Vec<u8>, Hash)]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for (name, root) in roots {
+        bytes.push(name.len() as u8);
+        bytes.extend_from_slice(name);
+        bytes.extend_from_slice(root);
+    }
+    bytes
+}
+
+fn decode_registry(mut bytes: &[u8]) -> Option<Vec<(Vec<u8>, Hash)>> {
+    let mut roots = Vec::new();
+    while let [len, rest @ ..] = bytes {
+        let len = *len as usize;
+        if rest.len() < len + 32 {
+            return None;
+        }
+        roots.push((rest[..len].to_vec(), rest[len..len + 32].try_into().ok()?));
+        bytes = &rest[len + 32..];
+    }
+    Some(roots)
+}
+
+/// Store holding any number of namespaced trees. Open a tree over one with
+/// `SparseMerkleTree::open(store.namespace(b"accounts"))`; it picks up that
+/// namespace's root and records its commits there.
+pub struct NamespacedStore<S: KVStore> {
+    inner: S,
+}
+
+impl<S: KVStore> NamespacedStore<S>
+where
+    SMTError: From<S::Error>,
+{
+    pub fn new(store: S) -> Self {
+        Self { inner: store }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// The store of the namespace `name`, which exists once a tree over it
+    /// commits.
+    ///
+    /// Panics if `name` is longer than `MAX_NAME_LEN`.
+    pub fn namespace(&mut self, name: &[u8]) -> Namespace<'_, S> {
+        assert!(name.len() <= MAX_NAME_LEN, "namespace name must be at most 255 bytes");
+        Namespace { inner: &mut self.inner, name: name.to_vec() }
+    }
+
+    /// Name and root of every namespace, in name order.
+    pub fn roots(&self) -> Result<Vec<(Vec<u8>, Hash)>, SMTError> {
+        read_registry(&self.inner)
+    }
+
+    pub fn root_of(&self, name: &[u8]) -> Result<Option<Hash>, SMTError> {
+        Ok(self.roots()?.into_iter().find(|(other, _)| other == name).map(|(_, root)| root))
+    }
+
+    /// `root_of_roots` over every namespace. This is also the root the
+    /// wrapped store records.
+    pub fn root_of_roots(&self) -> Result<Hash, SMTError> {
+        Ok(root_of_roots(&self.roots()?))
+    }
+}
+
+fn read_registry<S: KVStore>(store: &S) -> Result<Vec<(Vec<u8>, Hash)>, SMTError>
+where
+    SMTError: From<S::Error>,
+{
+    match store.get(&registry_key())? {
+        Some(bytes) => decode_registry(&bytes).ok_or(SMTError::InvalidEncoding),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// One namespace of a `NamespacedStore`, see `NamespacedStore::namespace`.
+pub struct Namespace<'a, S: KVStore> {
+    inner: &'a mut S,
+    name: Vec<u8>,
+}
+
+impl<S: KVStore> Namespace<'_, S> {
+    /// Where `key` of the given kind lives in the wrapped store.
+    fn key(&self, kind: u8, key: &Hash) -> Hash {
+        DefaultHasher::new()
+            .chain_update(KEY_DOMAIN)
+            .chain_update([self.name.len() as u8])
+            .chain_update(&self.name)
+            .chain_update([kind])
+            .chain_update(key)
+            .finalize()
+            .into()
+    }
+}
+
+impl<S: KVStore> KVStore for Namespace<'_, S>
+where
+    SMTError: From<S::Error>,
+{
+    type Error = SMTError;
+
+    fn get(&self, key: &Hash) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(self.inner.get(&self.key(VALUE_KIND, key))?)
+    }
+
+    fn set(&mut self, key: Hash, value: Vec<u8>) -> Result<(), Self::Error> {
+        Ok(self.inner.set(self.key(VALUE_KIND, &key), value)?)
+    }
+
+    fn remove(&mut self, key: &Hash) -> Result<(), Self::Error> {
+        Ok(self.inner.remove(&self.key(VALUE_KIND, key))?)
+    }
+
+    fn get_node(&self, hash: &Hash) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(self.inner.get(&self.key(NODE_KIND, hash))?)
+    }
+
+    fn set_node(&mut self, hash: Hash, node: Vec<u8>) -> Result<(), Self::Error> {
+        Ok(self.inner.set(self.key(NODE_KIND, &hash), node)?)
+    }
+
+    fn remove_node(&mut self, hash: &Hash) -> Result<(), Self::Error> {
+        Ok(self.inner.remove(&self.key(NODE_KIND, hash))?)
+    }
+
+    fn get_root(&self) -> Result<Option<Hash>, Self::Error> {
+        Ok(read_registry(self.inner)?.into_iter().find(|(name, _)| *name == self.name).map(|(_, root)| root))
+    }
+
+    /// Updates this namespace's entry in the registry, then records the new
+    /// root of roots with the wrapped store.
+    fn set_root(&mut self, root: Hash) -> Result<(), Self::Error> {
+        let mut roots = read_registry(self.inner)?;
+        match roots.binary_search_by(|(name, _)| name.as_slice().cmp(&self.name)) {
+            Ok(index) => roots[index].1 = root,
+            Err(index) => roots.insert(index, (self.name.clone(), root)),
+        }
+        self.inner.set(registry_key(), encode_registry(&roots))?;
+        Ok(self.inner.set_root(root_of_roots(&roots))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{kv_store::InMemoryKVStore, sparse_merkle_tree::SparseMerkleTree};
+
+    #[test]
+    fn test_namespaces_do_not_collide() {
+        let mut store = NamespacedStore::new(InMemoryKVStore::new());
+        let accounts_root = {
+            let mut accounts = SparseMerkleTree::open(store.namespace(b"accounts")).unwrap();
+            accounts.update([1u8; 32], [10u8; 32]).unwrap();
+            accounts.root()
+        };
+        let receipts_root = {
+            let mut receipts = SparseMerkleTree::open(store.namespace(b"receipts")).unwrap();
+            receipts.update([1u8; 32], [20u8; 32]).unwrap();
+            receipts.delete([1u8; 32]).unwrap();
+            receipts.root()
+        };
+
+        let accounts = SparseMerkleTree::open(store.namespace(b"accounts")).unwrap();
+        assert_eq!(accounts.root(), accounts_root);
+        assert_eq!(accounts.get([1u8; 32]).unwrap(), Some([10u8; 32]));
+        assert_eq!(store.root_of(b"receipts").unwrap(), Some(receipts_root));
+        assert_eq!(store.root_of(b"storage").unwrap(), None);
+    }
+
+    #[test]
+    fn test_root_of_roots_commits_to_every_namespace() {
+        let mut store = NamespacedStore::new(InMemoryKVStore::new());
+        SparseMerkleTree::open(store.namespace(b"b")).unwrap().update([1u8; 32], [1u8; 32]).unwrap();
+        SparseMerkleTree::open(store.namespace(b"a")).unwrap().update([2u8; 32], [2u8; 32]).unwrap();
+        let before = store.root_of_roots().unwrap();
+
+        let roots = store.roots().unwrap();
+        assert_eq!(roots.iter().map(|(name, _)| name.as_slice()).collect::<Vec<_>>(), vec![&b"a"[..], &b"b"[..]]);
+        let reversed: Vec<_> = roots.iter().rev().cloned().collect();
+        assert_eq!(root_of_roots(&reversed), before);
+
+        SparseMerkleTree::open(store.namespace(b"a")).unwrap().update([3u8; 32], [3u8; 32]).unwrap();
+        assert_ne!(store.root_of_roots().unwrap(), before);
+    }
+}
@@ -0,0 +1,163 @@
+//! How nodes are laid out in the store. Every record the tree writes is
+//! `NODE_FORMAT_VERSION || tag || body`, where the tag says whether the body
+//! is an internal node's two children, a leaf's key and value, or nothing at
+//! all for an empty subtree. Whoever reads a record back can tell what it is
+//! without knowing where in the tree it came from, and a later format can
+//! bump the version without being mistaken for this one.
+
+use crate::{tree_hasher::{LEAF_PREFIX, NODE_PREFIX}, Hash};
+
+/// Version byte leading every record `NodeCodec` writes.
+pub const NODE_FORMAT_VERSION: u8 = 1;
+
+/// Tag of a leaf record, the same byte that prefixes a leaf's hash.
+pub const LEAF_TAG: u8 = LEAF_PREFIX;
+
+/// Tag of an internal node record, the same byte that prefixes its hash.
+pub const INTERNAL_TAG: u8 = NODE_PREFIX;
+
+/// Tag of an empty subtree record.
+pub const EMPTY_TAG: u8 = 2;
+
+/// Length of a leaf or internal node record.
+pub const NODE_LEN: usize = 2 + 32 + 32;
+
+/// A node as the store holds it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Node {
+    Empty,
+    Internal { left: Hash, right: Hash },
+    Leaf { key: Hash, value: Hash },
+}
+
+/// Turns `Node`s into store records and back.
+pub struct NodeCodec;
+
+impl NodeCodec {
+    pub fn encode(node: &Node) -> Vec<u8> {
+        let (tag, body) = match node {
+            Node::Empty => return vec![NODE_FORMAT_VERSION, EMPTY_TAG],
+            Node::Internal { left, right } => (INTERNAL_TAG, [left, right]),
+            Node::Leaf { key, value } => (LEAF_TAG, [key, value]),
+        };
+        let mut record = Vec::with_capacity(NODE_LEN);
+        record.extend_from_slice(&[NODE_FORMAT_VERSION, tag]);
+        record.extend_from_slice(body[0]);
+        record.extend_from_slice(body[1]);
+        record
+    }
+
+    /// Decodes a record. `None` for an unknown version or tag, or a body of
+    /// the wrong length.
+    pub fn decode(record: &[u8]) -> Option<Node> {
+        match record {
+            [NODE_FORMAT_VERSION, EMPTY_TAG] => Some(Node::Empty),
+            [NODE_FORMAT_VERSION, INTERNAL_TAG, body @ ..] => {
+                let (left, right) = split_pair(body)?;
+                Some(Node::Internal { left, right })
+            }
+            [NODE_FORMAT_VERSION, LEAF_TAG, body @ ..] => {
+                let (key, value) = split_pair(body)?;
+                Some(Node::Leaf { key, value })
+            }
+            _ => None,
+        }
+    }
+
+    /// Children of the internal node in `record`. `None` for anything else.
+    pub fn decode_internal(record: &[u8]) -> Option<(Hash, Hash)> {
+        match Self::decode(record)? {
+            Node::Internal { left, right } => Some((left, right)),
+            _ => None,
+        }
+    }
+
+    /// Key and value of the leaf in `record`. `None` for anything else.
+    pub fn decode_leaf(record: &[u8]) -> Option<(Hash, Hash)> {
+        match Self::decode(record)? {
+            Node::Leaf { key, value } => Some((key, value)),
+            _ => None,
+        }
+    }
+}
+
+pub fn encode_internal(left: &Hash, right: &Hash) -> Vec<u8> {
+    NodeCodec::encode(&Node::Internal { left: *left, right: *right })
+}
+
+pub fn encode_leaf(key: &Hash, value: &Hash) -> Vec<u8> {
+    NodeCodec::encode(&Node::Leaf { key: *key, value: *value })
+}
+
+/// Shorthand for `NodeCodec::decode_internal`.
+pub fn decode_internal(record: &[u8]) -> Option<(Hash, Hash)> {
+    NodeCodec::decode_internal(record)
+}
+
+/// Shorthand for `NodeCodec::decode_leaf`.
+pub fn decode_leaf(record: &[u8]) -> Option<(Hash, Hash)> {
+    NodeCodec::decode_leaf(record)
+}
+
+fn split_pair(body: &[u8]) -> Option<(Hash, Hash)> {
+    if body.len() != 64 {
+        return None;
+    }
+    let (first, second) = body.split_at(32);
+    Some((first.try_into().ok()?, second.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_roundtrip() {
+        let nodes = [
+            Node::Empty,
+            Node::Internal { left: [1u8; 32], right: [2u8; 32] },
+            Node::Leaf { key: [3u8; 32], value: [4u8; 32] },
+        ];
+        for node in nodes {
+            let record = NodeCodec::encode(&node);
+            assert_eq!(record[0], NODE_FORMAT_VERSION);
+            assert_eq!(NodeCodec::decode(&record), Some(node));
+        }
+        assert_eq!(encode_leaf(&[3u8; 32], &[4u8; 32]).len(), NODE_LEN);
+    }
+
+    #[test]
+    fn test_leaf_roundtrip() {
+        let record = encode_leaf(&[1u8; 32], &[2u8; 32]);
+        assert_eq!(decode_leaf(&record), Some(([1u8; 32], [2u8; 32])));
+        assert_eq!(decode_internal(&record), None);
+    }
+
+    #[test]
+    fn test_internal_roundtrip() {
+        let record = encode_internal(&[1u8; 32], &[2u8; 32]);
+        assert_eq!(decode_internal(&record), Some(([1u8; 32], [2u8; 32])));
+        assert_eq!(decode_leaf(&record), None);
+    }
+
+    #[test]
+    fn test_decode_rejects_other_records() {
+        let mut record = encode_leaf(&[1u8; 32], &[2u8; 32]);
+        record[1] = EMPTY_TAG + 1;
+        assert_eq!(NodeCodec::decode(&record), None);
+        record[1] = LEAF_TAG;
+        record[0] = NODE_FORMAT_VERSION + 1;
+        assert_eq!(NodeCodec::decode(&record), None);
+        assert_eq!(decode_leaf(&encode_leaf(&[1u8; 32], &[2u8; 32])[..40]), None);
+        assert_eq!(NodeCodec::decode(&[NODE_FORMAT_VERSION, EMPTY_TAG, 0]), None);
+        assert_eq!(decode_leaf(&[]), None);
+
+        // Untagged or unversioned records are not nodes of any kind
+        let bare = [[1u8; 32], [2u8; 32]].concat();
+        assert_eq!(NodeCodec::decode(&bare), None);
+        assert_eq!(decode_leaf(&bare), None);
+        assert_eq!(decode_internal(&bare), None);
+        let unversioned = [[LEAF_TAG].as_slice(), &[1u8; 32], &[2u8; 32]].concat();
+        assert_eq!(decode_leaf(&unversioned), None);
+    }
+}
@@ -0,0 +1,240 @@
+//! Least-recently-used cache of node records, kept by a tree in front of its
+//! store so the nodes near the root, which every proof and update walks
+//! through, are read from the store once instead of on every call.
+//!
+//! Records are keyed by their hash, so a cached record is never stale for the
+//! hash it is stored under. The only way to go wrong is to keep serving a
+//! node after it was deleted from the store, which the tree prevents by
+//! evicting the nodes it removes.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use crate::Hash;
+
+/// Limits on what a `NodeCache` holds. Each record counts as its 32-byte hash
+/// plus its length, like entries of `InMemoryKVStore`. The least recently
+/// used records are dropped once either limit is passed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheConfig {
+    pub max_nodes: usize,
+    pub max_bytes: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self { max_nodes: 4096, max_bytes: 4096 * (32 + crate::node::NODE_LEN) }
+    }
+}
+
+/// Hits and misses since the cache was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    records: HashMap<Hash, (Vec<u8>, u64)>, // Record and the tick it was last used at
+    by_use: BTreeMap<u64, Hash>,
+    tick: u64,
+    bytes: usize,
+    stats: CacheStats,
+}
+
+pub struct NodeCache {
+    config: CacheConfig,
+    entries: Mutex<Entries>,
+}
+
+impl NodeCache {
+    pub fn new(config: CacheConfig) -> Self {
+        Self { config, entries: Mutex::new(Entries::default()) }
+    }
+
+    pub fn config(&self) -> CacheConfig {
+        self.config
+    }
+
+    /// The record cached for `hash`, marking it as just used.
+    pub fn get(&self, hash: &Hash) -> Option<Vec<u8>> {
+        let mut entries = self.lock();
+        let tick = entries.tick + 1;
+        let Some((record, used)) = entries.records.get_mut(hash) else {
+            entries.stats.misses += 1;
+            return None;
+        };
+        let (record, last_used) = (record.clone(), std::mem::replace(used, tick));
+        entries.tick = tick;
+        entries.by_use.remove(&last_used);
+        entries.by_use.insert(tick, *hash);
+        entries.stats.hits += 1;
+        Some(record)
+    }
+
+    /// Caches `record` under `hash`, evicting the least recently used records
+    /// to stay within the limits. A record bigger than `max_bytes` on its own
+    /// is not cached.
+    pub fn insert(&self, hash: Hash, record: Vec<u8>) {
+        let size = 32 + record.len();
+        if size > self.config.max_bytes || self.config.max_nodes == 0 {
+            return;
+        }
+        let mut entries = self.lock();
+        entries.remove(&hash);
+        while entries.records.len() >= self.config.max_nodes || entries.bytes + size > self.config.max_bytes {
+            let Some((_, oldest)) = entries.by_use.pop_first() else { break };
+            entries.remove(&oldest);
+        }
+        entries.tick += 1;
+        let tick = entries.tick;
+        entries.by_use.insert(tick, hash);
+        entries.records.insert(hash, (record, tick));
+        entries.bytes += size;
+    }
+
+    pub fn remove(&self, hash: &Hash) {
+        self.lock().remove(hash);
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn bytes_used(&self) -> usize {
+        self.lock().bytes
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.lock().stats
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        // Nothing panics while the lock is held, so a poisoned lock still
+        // guards consistent entries.
+        self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Entries {
+    fn remove(&mut self, hash: &Hash) {
+        if let Some((record, used)) = self.records.remove(hash) {
+            self.by_use.remove(&used);
+            self.bytes -= 32 + record.len();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{kv_store::{InMemoryKVStore, KVStore}, sparse_merkle_tree::SparseMerkleTree};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Store counting how many nodes are read from it.
+    struct CountingStore {
+        inner: InMemoryKVStore,
+        node_reads: AtomicUsize,
+    }
+
+    impl KVStore for CountingStore {
+        type Error = std::io::Error;
+
+        fn get(&self, key: &Hash) -> Result<Option<Vec<u8>>, Self::Error> {
+            self.inner.get(key)
+        }
+
+        fn set(&mut self, key: Hash, value: Vec<u8>) -> Result<(), Self::Error> {
+            self.inner.set(key, value)
+        }
+
+        fn remove(&mut self, key: &Hash) -> Result<(), Self::Error> {
+            self.inner.remove(key)
+        }
+
+        fn get_node(&self, hash: &Hash) -> Result<Option<Vec<u8>>, Self::Error> {
+            self.node_reads.fetch_add(1, Ordering::Relaxed);
+            self.inner.get_node(hash)
+        }
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let cache = NodeCache::new(CacheConfig { max_nodes: 2, max_bytes: 1024 });
+        cache.insert([1u8; 32], vec![1]);
+        cache.insert([2u8; 32], vec![2]);
+        assert_eq!(cache.get(&[1u8; 32]), Some(vec![1]));
+        cache.insert([3u8; 32], vec![3]);
+
+        assert_eq!(cache.get(&[2u8; 32]), None);
+        assert_eq!(cache.get(&[1u8; 32]), Some(vec![1]));
+        assert_eq!(cache.get(&[3u8; 32]), Some(vec![3]));
+        assert_eq!(cache.stats(), CacheStats { hits: 3, misses: 1 });
+    }
+
+    #[test]
+    fn test_byte_limit() {
+        let cache = NodeCache::new(CacheConfig { max_nodes: 100, max_bytes: 2 * (32 + 10) });
+        cache.insert([1u8; 32], vec![0; 10]);
+        cache.insert([2u8; 32], vec![0; 10]);
+        cache.insert([3u8; 32], vec![0; 10]);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.bytes_used(), 2 * 42);
+        assert_eq!(cache.get(&[1u8; 32]), None);
+
+        cache.insert([4u8; 32], vec![0; 100]); // Too big to cache at all
+        assert_eq!(cache.get(&[4u8; 32]), None);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_reinsert_and_remove() {
+        let cache = NodeCache::new(CacheConfig::default());
+        cache.insert([1u8; 32], vec![1; 4]);
+        cache.insert([1u8; 32], vec![1; 8]);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.bytes_used(), 40);
+        cache.remove(&[1u8; 32]);
+        assert!(cache.is_empty());
+        assert_eq!(cache.bytes_used(), 0);
+    }
+
+    #[test]
+    fn test_repeated_proofs_skip_the_store() {
+        let mut smt = SparseMerkleTree::new(CountingStore { inner: InMemoryKVStore::new(), node_reads: AtomicUsize::new(0) }).with_cache(CacheConfig::default());
+        smt.update_batch(&[([1u8; 32], [10u8; 32]), ([0x80; 32], [20u8; 32])]).unwrap();
+
+        let proof = smt.get_proof([1u8; 32]).unwrap();
+        assert_eq!(smt.get([1u8; 32]).unwrap(), Some([10u8; 32]));
+        let reads = smt.store.node_reads.load(Ordering::Relaxed);
+        assert!(reads > 0);
+        assert_eq!(smt.get_proof([1u8; 32]).unwrap().side_nodes, proof.side_nodes);
+        assert_eq!(smt.get([1u8; 32]).unwrap(), Some([10u8; 32]));
+        assert_eq!(smt.store.node_reads.load(Ordering::Relaxed), reads);
+        assert!(smt.cache().unwrap().stats().hits > 0);
+    }
+
+    #[test]
+    fn test_cached_tree_matches_uncached() {
+        let mut cached = SparseMerkleTree::new(InMemoryKVStore::new()).with_cache(CacheConfig { max_nodes: 16, max_bytes: 4096 });
+        let mut plain = SparseMerkleTree::new(InMemoryKVStore::new());
+        for i in 0..32u8 {
+            cached.update([i; 32], [i; 32]).unwrap();
+            plain.update([i; 32], [i; 32]).unwrap();
+            if i % 3 == 0 {
+                cached.delete([i / 2; 32]).unwrap();
+                plain.delete([i / 2; 32]).unwrap();
+            }
+        }
+        assert_eq!(cached.root(), plain.root());
+        assert!(cached.cache().unwrap().len() <= 16);
+        for i in 0..32u8 {
+            assert_eq!(cached.get([i; 32]).unwrap(), plain.get([i; 32]).unwrap());
+        }
+    }
+}
@@ -0,0 +1,17 @@
+use crate::Hash;
+
+/// Callbacks invoked synchronously by the tree as its contents change, for
+/// metrics, secondary indexes or cache invalidation kept outside the tree.
+///
+/// Every method defaults to doing nothing. Callbacks run on the writer's
+/// thread before the write returns, so they should be cheap.
+pub trait TreeObserver: Send + Sync {
+    /// A leaf was written. Batched writes report each key that was applied.
+    fn on_update(&self, _key: &Hash, _value: &Hash) {}
+
+    /// A leaf was removed.
+    fn on_delete(&self, _key: &Hash) {}
+
+    /// The tree moved to a new root, after the leaf events that caused it.
+    fn on_commit(&self, _old_root: &Hash, _new_root: &Hash) {}
+}
@@ -0,0 +1,72 @@
+use serde::{Serialize, Deserialize};
+
+use crate::{error::SMTError, Hash};
+
+const PUT_TAG: u8 = 0;
+const DELETE_TAG: u8 = 1;
+
+/// A single mutation of the tree, shared by every API that records or replays writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Op {
+    Put(Hash, Hash),
+    Delete(Hash),
+}
+
+impl Op {
+    /// Returns the key this operation touches.
+    pub fn key(&self) -> &Hash {
+        match self {
+            Op::Put(key, _) | Op::Delete(key) => key,
+        }
+    }
+
+    /// Canonical encoding: a tag byte followed by the key and, for puts, the value.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Op::Put(key, value) => [&[PUT_TAG][..], key, value].concat(),
+            Op::Delete(key) => [&[DELETE_TAG][..], key].concat(),
+        }
+    }
+
+    /// Decodes an operation produced by `to_bytes`, rejecting trailing or missing bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SMTError> {
+        match bytes {
+            [PUT_TAG, rest @ ..] if rest.len() == 64 => {
+                let (key, value) = rest.split_at(32);
+                Ok(Op::Put(to_hash(key)?, to_hash(value)?))
+            }
+            [DELETE_TAG, rest @ ..] if rest.len() == 32 => Ok(Op::Delete(to_hash(rest)?)),
+            _ => Err(SMTError::InvalidEncoding),
+        }
+    }
+}
+
+fn to_hash(bytes: &[u8]) -> Result<Hash, SMTError> {
+    bytes.try_into().map_err(|_| SMTError::InvalidEncoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_op_roundtrip() {
+        let put = Op::Put([1u8; 32], [2u8; 32]);
+        let delete = Op::Delete([3u8; 32]);
+
+        assert_eq!(Op::from_bytes(&put.to_bytes()).unwrap(), put);
+        assert_eq!(Op::from_bytes(&delete.to_bytes()).unwrap(), delete);
+        assert_eq!(put.to_bytes().len(), 65);
+        assert_eq!(delete.to_bytes().len(), 33);
+    }
+
+    #[test]
+    fn test_op_rejects_malformed_bytes() {
+        assert!(Op::from_bytes(&[]).is_err());
+        assert!(Op::from_bytes(&[2u8; 33]).is_err());
+
+        let mut bytes = Op::Delete([3u8; 32]).to_bytes();
+        bytes.push(0);
+        assert!(Op::from_bytes(&bytes).is_err());
+    }
+}
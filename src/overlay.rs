@@ -0,0 +1,360 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use crate::{error::{ErrorContext, ResultExt, SMTError}, kv_store::{scan_with_pending, KVStore, Scan, TreeWriteBatch}, observer::TreeObserver, proof::MerkleProof, sparse_merkle_tree::SparseMerkleTree, Hash};
+
+/// Store wrapper that buffers writes in memory and serves reads from the
+/// buffer first, falling back to the wrapped store.
+pub struct OverlayStore<S: KVStore> {
+    inner: S,
+    pending: HashMap<Hash, Option<Vec<u8>>>, // `None` marks a removed key
+    pending_nodes: HashMap<Hash, Option<Vec<u8>>>,
+    pending_root: Option<Hash>,
+}
+
+impl<S: KVStore> OverlayStore<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            pending: HashMap::new(),
+            pending_nodes: HashMap::new(),
+            pending_root: None,
+        }
+    }
+
+    /// Number of buffered writes not yet flushed to the wrapped store.
+    pub fn pending_len(&self) -> usize {
+        self.pending.len() + self.pending_nodes.len()
+    }
+
+    /// Flushes every buffered write into the wrapped store and returns it.
+    pub fn commit(mut self) -> Result<S, S::Error> {
+        let mut batch = TreeWriteBatch::new();
+        for (key, value) in self.pending.drain() {
+            match value {
+                Some(value) => batch.set(key, value),
+                None => batch.remove(key),
+            }
+        }
+        for (hash, node) in self.pending_nodes.drain() {
+            match node {
+                Some(node) => batch.set_node(hash, node),
+                None => batch.remove_node(hash),
+            }
+        }
+        if let Some(root) = self.pending_root {
+            batch.set_root(root);
+        }
+        batch.commit(&mut self.inner)?;
+        Ok(self.inner)
+    }
+
+    /// Drops every buffered write and returns the wrapped store untouched.
+    pub fn discard(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: KVStore> KVStore for OverlayStore<S> {
+    type Error = S::Error;
+
+    fn get(&self, key: &Hash) -> Result<Option<Vec<u8>>, Self::Error> {
+        match self.pending.get(key) {
+            Some(value) => Ok(value.clone()),
+            None => self.inner.get(key),
+        }
+    }
+
+    fn set(&mut self, key: Hash, value: Vec<u8>) -> Result<(), Self::Error> {
+        self.pending.insert(key, Some(value));
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &Hash) -> Result<(), Self::Error> {
+        self.pending.insert(*key, None);
+        Ok(())
+    }
+
+    fn get_node(&self, hash: &Hash) -> Result<Option<Vec<u8>>, Self::Error> {
+        match self.pending_nodes.get(hash) {
+            Some(node) => Ok(node.clone()),
+            None => self.inner.get_node(hash),
+        }
+    }
+
+    fn set_node(&mut self, hash: Hash, node: Vec<u8>) -> Result<(), Self::Error> {
+        self.pending_nodes.insert(hash, Some(node));
+        Ok(())
+    }
+
+    fn remove_node(&mut self, hash: &Hash) -> Result<(), Self::Error> {
+        self.pending_nodes.insert(*hash, None);
+        Ok(())
+    }
+
+    fn get_root(&self) -> Result<Option<Hash>, Self::Error> {
+        match self.pending_root {
+            Some(root) => Ok(Some(root)),
+            None => self.inner.get_root(),
+        }
+    }
+
+    fn set_root(&mut self, root: Hash) -> Result<(), Self::Error> {
+        self.pending_root = Some(root);
+        Ok(())
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Option<Scan<'_, Self::Error>> {
+        let pending = self.pending.iter().map(|(key, value)| (key, value.as_ref()));
+        Some(scan_with_pending(self.inner.scan_prefix(prefix)?, pending, prefix))
+    }
+
+    fn scan_node_prefix(&self, prefix: &[u8]) -> Option<Scan<'_, Self::Error>> {
+        let pending = self.pending_nodes.iter().map(|(hash, node)| (hash, node.as_ref()));
+        Some(scan_with_pending(self.inner.scan_node_prefix(prefix)?, pending, prefix))
+    }
+}
+
+/// A batch of updates staged on top of a tree. Reads and proofs inside the
+/// batch observe its own pending writes; nothing reaches the underlying store
+/// until `commit`.
+pub struct StagedBatch<S: KVStore> {
+    tree: SparseMerkleTree<OverlayStore<S>>,
+    base_root: Hash,
+    observers: Vec<Arc<dyn TreeObserver>>, // Only told about the batch on commit
+    writes: BTreeMap<Hash, Hash>,
+}
+
+impl<S: KVStore> StagedBatch<S> {
+    pub fn update(&mut self, key: Hash, value: Hash) -> Result<(), SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        self.tree.update(key, value)?;
+        self.writes.insert(key, value);
+        Ok(())
+    }
+
+    pub fn get(&self, key: Hash) -> Result<Option<Hash>, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        self.tree.get(key)
+    }
+
+    pub fn get_proof(&self, key: Hash) -> Result<MerkleProof, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        self.tree.get_proof(key)
+    }
+
+    /// Root including the pending writes.
+    pub fn root(&self) -> Hash {
+        self.tree.root()
+    }
+
+    /// Root of the tree the batch was started from.
+    pub fn base_root(&self) -> Hash {
+        self.base_root
+    }
+
+    /// Leaf writes staged so far, in key order.
+    pub fn writes(&self) -> impl Iterator<Item = (&Hash, &Hash)> {
+        self.writes.iter()
+    }
+
+    /// Folds another batch's leaf writes into this one. Both batches must start
+    /// from the same root and touch disjoint keys, so workers can each stage
+    /// part of a block on a fork of the tree and combine the results.
+    pub fn merge<T: KVStore>(mut self, other: StagedBatch<T>) -> Result<Self, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        if self.base_root != other.base_root {
+            return Err(SMTError::RootMismatch);
+        }
+        if let Some(key) = other.writes.keys().find(|key| self.writes.contains_key(*key)) {
+            return Err(SMTError::KeyConflict(*key));
+        }
+
+        for (key, value) in other.writes {
+            self.update(key, value).context(ErrorContext::new("merge").key(key))?;
+        }
+        Ok(self)
+    }
+
+    /// Applies the batch to the underlying store and returns the updated tree.
+    pub fn commit(self) -> Result<SparseMerkleTree<S>, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let tree = SparseMerkleTree {
+            hasher: self.tree.hasher,
+            store: self.tree.store.commit()?,
+            root: self.tree.root,
+            depth: self.tree.depth,
+            observers: self.observers,
+            #[cfg(feature = "lru")]
+            cache: self.tree.cache,
+        };
+        let writes: Vec<(Hash, Option<Hash>)> = self.writes.into_iter().map(|(key, value)| (key, Some(value))).collect();
+        tree.notify_batch(self.base_root, &writes);
+        Ok(tree)
+    }
+
+    /// Discards the batch and returns the tree as it was before it started.
+    pub fn abort(self) -> SparseMerkleTree<S> {
+        SparseMerkleTree {
+            hasher: self.tree.hasher,
+            depth: self.tree.depth,
+            store: self.tree.store.discard(),
+            root: self.base_root,
+            observers: self.observers,
+            #[cfg(feature = "lru")]
+            cache: self.tree.cache,
+        }
+    }
+}
+
+impl<S: KVStore> SparseMerkleTree<S> {
+    /// Starts a staged batch over this tree.
+    pub fn begin_batch(self) -> StagedBatch<S> {
+        let base_root = self.root;
+        StagedBatch {
+            tree: SparseMerkleTree {
+                hasher: self.hasher,
+                store: OverlayStore::new(self.store),
+                root: base_root,
+                depth: self.depth,
+                observers: Vec::new(),
+                #[cfg(feature = "lru")]
+                cache: self.cache,
+            },
+            base_root,
+            observers: self.observers,
+            writes: BTreeMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_store::InMemoryKVStore;
+
+    #[test]
+    fn test_batch_reads_its_own_writes() {
+        let smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        let mut batch = smt.begin_batch();
+        let key: Hash = [1u8; 32];
+        let value: Hash = [2u8; 32];
+
+        batch.update(key, value).unwrap();
+        assert_eq!(batch.get(key).unwrap(), Some(value));
+        let proof = batch.get_proof(key).unwrap();
+        assert!(proof.verify(&batch.root(), &key, &value));
+    }
+
+    #[test]
+    fn test_commit_applies_batch() {
+        let smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        let mut batch = smt.begin_batch();
+        let key: Hash = [1u8; 32];
+        let value: Hash = [2u8; 32];
+        batch.update(key, value).unwrap();
+        let staged_root = batch.root();
+
+        let smt = batch.commit().unwrap();
+        assert_eq!(smt.root(), staged_root);
+        assert_eq!(smt.get(key).unwrap(), Some(value));
+    }
+
+    #[test]
+    fn test_observers_notified_only_on_commit() {
+        struct CountingObserver(std::sync::atomic::AtomicUsize);
+        impl TreeObserver for CountingObserver {
+            fn on_update(&self, _key: &Hash, _value: &Hash) {
+                self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            }
+        }
+
+        let observer = Arc::new(CountingObserver(Default::default()));
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        smt.add_observer(observer.clone());
+
+        let mut batch = smt.begin_batch();
+        batch.update([1u8; 32], [2u8; 32]).unwrap();
+        batch.update([3u8; 32], [4u8; 32]).unwrap();
+        assert_eq!(observer.0.load(std::sync::atomic::Ordering::SeqCst), 0);
+
+        let smt = batch.commit().unwrap().begin_batch().abort();
+        assert_eq!(observer.0.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert_eq!(smt.observers.len(), 1);
+    }
+
+    #[test]
+    fn test_merge_disjoint_batches() {
+        let smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        let mut left = smt.clone().begin_batch();
+        let mut right = smt.begin_batch();
+        left.update([1u8; 32], [10u8; 32]).unwrap();
+        right.update([2u8; 32], [20u8; 32]).unwrap();
+
+        let merged = left.merge(right).unwrap();
+        assert_eq!(merged.get([1u8; 32]).unwrap(), Some([10u8; 32]));
+        assert_eq!(merged.get([2u8; 32]).unwrap(), Some([20u8; 32]));
+
+        let smt = merged.commit().unwrap();
+        assert_eq!(smt.get([2u8; 32]).unwrap(), Some([20u8; 32]));
+    }
+
+    #[test]
+    fn test_merge_rejects_overlapping_keys() {
+        let smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        let mut left = smt.clone().begin_batch();
+        let mut right = smt.begin_batch();
+        left.update([1u8; 32], [10u8; 32]).unwrap();
+        right.update([1u8; 32], [11u8; 32]).unwrap();
+
+        assert!(matches!(left.merge(right), Err(SMTError::KeyConflict(key)) if key == [1u8; 32]));
+    }
+
+    #[test]
+    fn test_merge_rejects_different_base_roots() {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        let left = smt.clone().begin_batch();
+        smt.update([3u8; 32], [30u8; 32]).unwrap();
+        let right = smt.begin_batch();
+
+        assert!(matches!(left.merge(right), Err(SMTError::RootMismatch)));
+    }
+
+    #[test]
+    fn test_removal_masks_inner_store_until_commit() {
+        let mut inner = InMemoryKVStore::new();
+        inner.set([1u8; 32], vec![2u8; 32]).unwrap();
+        let mut overlay = OverlayStore::new(inner);
+
+        overlay.remove(&[1u8; 32]).unwrap();
+        assert_eq!(overlay.get(&[1u8; 32]).unwrap(), None);
+
+        let inner = overlay.commit().unwrap();
+        assert_eq!(inner.get(&[1u8; 32]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_abort_restores_tree() {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        let key: Hash = [1u8; 32];
+        smt.update(key, [2u8; 32]).unwrap();
+        let root = smt.root();
+
+        let mut batch = smt.begin_batch();
+        batch.update(key, [3u8; 32]).unwrap();
+        let smt = batch.abort();
+
+        assert_eq!(smt.root(), root);
+        assert_eq!(smt.get(key).unwrap(), Some([2u8; 32]));
+    }
+}
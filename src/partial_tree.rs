@@ -0,0 +1,164 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{error::{ErrorContext, ResultExt, SMTError}, proof::MerkleProof, tree_hasher::TreeHasher, DefaultHasher, Hash};
+
+/// Client-side cache of the tree nodes learned from verified proofs.
+///
+/// Nodes are content-addressed, so anything learned under an old root stays
+/// usable under a new one as long as the new root still reaches it. Only the
+/// paths that actually changed need to be fetched again.
+pub struct PartialTree {
+    hasher: TreeHasher<DefaultHasher>,
+    root: Hash,
+    nodes: HashMap<Hash, (Hash, Hash)>,
+    leaves: HashMap<Hash, (Hash, Hash)>, // leaf hash -> (key, value)
+}
+
+impl PartialTree {
+    /// Creates an empty cache trusting `root`.
+    pub fn new(root: Hash) -> Self {
+        Self {
+            hasher: TreeHasher::<DefaultHasher>::new(),
+            root,
+            nodes: HashMap::new(),
+            leaves: HashMap::new(),
+        }
+    }
+
+    pub fn root(&self) -> Hash {
+        self.root
+    }
+
+    /// Verifies `proof` against the current root and caches the nodes on its path.
+    pub fn insert_proof(&mut self, key: Hash, value: Hash, proof: &MerkleProof) -> Result<(), SMTError> {
+        if !proof.verify(&self.root, &key, &value) {
+            return Err(SMTError::InvalidProof).context(ErrorContext::new("insert_proof").key(key));
+        }
+
+        let leaf_hash = self.hasher.digest_leaf(&key, &value);
+        self.leaves.insert(leaf_hash, (key, value));
+
+        let mut current = leaf_hash;
+        for (i, sibling) in proof.side_nodes.iter().enumerate().rev() {
+            let bit = (key[i / 8] >> (7 - (i % 8))) & 1;
+            let (left, right) = if bit == 0 {
+                (current, *sibling)
+            } else {
+                (*sibling, current)
+            };
+            current = self.hasher.digest_node(&left, &right);
+            self.nodes.insert(current, (left, right));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the cached value for `key`, or `None` if its path under the
+    /// current root has not been verified.
+    pub fn get(&self, key: &Hash) -> Option<Hash> {
+        let mut current = self.root;
+
+        for i in 0..256 {
+            if let Some((leaf_key, value)) = self.leaves.get(&current) {
+                return (leaf_key == key).then_some(*value);
+            }
+            let (left, right) = self.nodes.get(&current)?;
+            let bit = (key[i / 8] >> (7 - (i % 8))) & 1;
+            current = if bit == 0 { *left } else { *right };
+        }
+
+        self.leaves
+            .get(&current)
+            .and_then(|(leaf_key, value)| (leaf_key == key).then_some(*value))
+    }
+
+    pub fn contains(&self, key: &Hash) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Moves the cache to a new trusted root, dropping every node the new root
+    /// no longer reaches. Returns the keys whose proofs must be fetched again.
+    pub fn set_root(&mut self, root: Hash) -> Vec<Hash> {
+        self.root = root;
+
+        let mut reachable = HashSet::new();
+        let mut stack = vec![root];
+        while let Some(hash) = stack.pop() {
+            if !reachable.insert(hash) {
+                continue;
+            }
+            if let Some((left, right)) = self.nodes.get(&hash) {
+                stack.push(*left);
+                stack.push(*right);
+            }
+        }
+
+        self.nodes.retain(|hash, _| reachable.contains(hash));
+
+        let mut invalidated = Vec::new();
+        self.leaves.retain(|hash, (key, _)| {
+            let keep = reachable.contains(hash);
+            if !keep {
+                invalidated.push(*key);
+            }
+            keep
+        });
+        invalidated.sort();
+        invalidated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{kv_store::InMemoryKVStore, sparse_merkle_tree::SparseMerkleTree};
+
+    #[test]
+    fn test_get_cached_key() {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        let key: Hash = [1u8; 32];
+        let value: Hash = [2u8; 32];
+        smt.update(key, value).unwrap();
+
+        let mut partial = PartialTree::new(smt.root());
+        partial.insert_proof(key, value, &smt.get_proof(key).unwrap()).unwrap();
+
+        assert_eq!(partial.get(&key), Some(value));
+        assert!(!partial.contains(&[3u8; 32]));
+    }
+
+    #[test]
+    fn test_rejects_invalid_proof() {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        let key: Hash = [1u8; 32];
+        smt.update(key, [2u8; 32]).unwrap();
+
+        let mut partial = PartialTree::new(smt.root());
+        let proof = smt.get_proof(key).unwrap();
+        let error = partial.insert_proof(key, [3u8; 32], &proof).unwrap_err();
+        assert!(matches!(error.root_cause(), SMTError::InvalidProof));
+        assert_eq!(error.context().unwrap().key, Some(key));
+        assert!(!partial.contains(&key));
+    }
+
+    #[test]
+    fn test_set_root_invalidates_changed_paths() {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        let key: Hash = [1u8; 32];
+        smt.update(key, [2u8; 32]).unwrap();
+
+        let mut partial = PartialTree::new(smt.root());
+        partial.insert_proof(key, [2u8; 32], &smt.get_proof(key).unwrap()).unwrap();
+
+        // Unchanged root keeps everything.
+        assert!(partial.set_root(smt.root()).is_empty());
+        assert!(partial.contains(&key));
+
+        smt.update(key, [4u8; 32]).unwrap();
+        assert_eq!(partial.set_root(smt.root()), vec![key]);
+        assert_eq!(partial.get(&key), None);
+
+        partial.insert_proof(key, [4u8; 32], &smt.get_proof(key).unwrap()).unwrap();
+        assert_eq!(partial.get(&key), Some([4u8; 32]));
+    }
+}
@@ -0,0 +1,153 @@
+//! Poseidon over the BN254 scalar field, with the circomlib parameters, as a
+//! `TreeDigest`, so `TreeHasher<Poseidon>` gives roots and proofs a SNARK
+//! circuit can check cheaply.
+//!
+//! Poseidon hashes field elements, not bytes. The input is read as its first
+//! byte, the `TreeHasher` prefix, as one element, then the rest in 16-byte
+//! big-endian limbs, the last one possibly shorter. A node is thus
+//! `Poseidon(prefix, left_hi, left_lo, right_hi, right_lo)` and a leaf the
+//! same over key and value, with the key domain's two limbs after the prefix
+//! when there is one. Digests are the output element in 32 big-endian bytes.
+//! Inputs of more than 12 elements, which `TreeHasher` never writes, are
+//! chained: the hash of the first 12, then the hash of it and the next 11,
+//! and so on.
+
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use digest::{consts::U32, FixedOutput, FixedOutputReset, HashMarker, Output, OutputSizeUser, Reset, Update};
+use light_poseidon::{Poseidon as Permutation, PoseidonHasher};
+
+/// Most inputs one Poseidon call takes in the circomlib parameters.
+const MAX_INPUTS: usize = 12;
+
+/// Bytes per field element after the prefix, so each limb is below the
+/// field modulus.
+const LIMB_LEN: usize = 16;
+
+/// Poseidon hasher, see the module docs for how bytes become field elements.
+#[derive(Debug, Clone, Default)]
+pub struct Poseidon {
+    buffer: Vec<u8>,
+}
+
+/// The field elements `bytes` is read as.
+fn elements(bytes: &[u8]) -> Vec<Fr> {
+    let Some((prefix, rest)) = bytes.split_first() else {
+        return vec![Fr::from(0u8)];
+    };
+    let mut elements = vec![Fr::from(*prefix)];
+    elements.extend(rest.chunks(LIMB_LEN).map(Fr::from_be_bytes_mod_order));
+    elements
+}
+
+fn hash(inputs: &[Fr]) -> Fr {
+    let mut permutation = Permutation::<Fr>::new_circom(inputs.len()).expect("1 to 12 inputs");
+    permutation.hash(inputs).expect("input count matches the parameters")
+}
+
+/// Poseidon of `bytes`, as 32 big-endian bytes.
+fn digest(bytes: &[u8]) -> [u8; 32] {
+    let elements = elements(bytes);
+    let (first, mut rest) = elements.split_at(elements.len().min(MAX_INPUTS));
+    let mut state = hash(first);
+    while !rest.is_empty() {
+        let (next, remaining) = rest.split_at(rest.len().min(MAX_INPUTS - 1));
+        state = hash(&[&[state], next].concat());
+        rest = remaining;
+    }
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&state.into_bigint().to_bytes_be());
+    out
+}
+
+impl HashMarker for Poseidon {}
+
+impl OutputSizeUser for Poseidon {
+    type OutputSize = U32;
+}
+
+impl Update for Poseidon {
+    fn update(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+}
+
+impl FixedOutput for Poseidon {
+    fn finalize_into(self, out: &mut Output<Self>) {
+        out.copy_from_slice(&digest(&self.buffer));
+    }
+}
+
+impl Reset for Poseidon {
+    fn reset(&mut self) {
+        self.buffer.clear();
+    }
+}
+
+impl FixedOutputReset for Poseidon {
+    fn finalize_into_reset(&mut self, out: &mut Output<Self>) {
+        out.copy_from_slice(&digest(&self.buffer));
+        self.buffer.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        kv_store::InMemoryKVStore, sparse_merkle_tree::SparseMerkleTree, spec::verify_with_spec, tree_hasher::TreeHasher, Hash,
+    };
+
+    fn hex(hex: &str) -> Hash {
+        let mut hash = [0u8; 32];
+        for (byte, pair) in hash.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            *byte = u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap();
+        }
+        hash
+    }
+
+    #[test]
+    fn test_matches_circomlib() {
+        // circomlibjs: poseidon([1, 2]) and poseidon([1, 2, 3, 4])
+        let two = hash(&[Fr::from(1u8), Fr::from(2u8)]);
+        assert_eq!(two.into_bigint().to_bytes_be(), hex("115cc0f5e7d690413df64c6b9662e9cf2a3617f2743245519e19607a4417189a"));
+        let four = hash(&[Fr::from(1u8), Fr::from(2u8), Fr::from(3u8), Fr::from(4u8)]);
+        assert_eq!(four.into_bigint().to_bytes_be(), hex("299c867db6c1fdd79dcefa40e4510b9837e60ebb1ce0663dbaa525df65250465"));
+    }
+
+    #[test]
+    fn test_node_is_poseidon_over_limbs() {
+        // What a circuit computes for a node: the prefix, then each child in
+        // a high and a low limb.
+        let hasher = TreeHasher::<Poseidon>::new();
+        let (left, right) = ([0xabu8; 32], [0x01u8; 32]);
+        let limbs = |hash: &Hash| [Fr::from_be_bytes_mod_order(&hash[..16]), Fr::from_be_bytes_mod_order(&hash[16..])];
+        let inputs = [&[Fr::from(1u8)][..], &limbs(&left), &limbs(&right)].concat();
+        assert_eq!(hasher.digest_node(&left, &right).to_vec(), hash(&inputs).into_bigint().to_bytes_be());
+    }
+
+    // Roots of a depth-32 tree, empty and with ([1, 1, 1, 1, 0, ..], [2; 32]),
+    // for circuit test suites to check against.
+    #[test]
+    fn test_tree_vectors() {
+        let mut smt = SparseMerkleTree::<_, Poseidon>::with_hasher(InMemoryKVStore::new()).with_depth(32);
+        assert_eq!(smt.spec().hasher_id, "poseidon-bn254");
+        assert_eq!(smt.root(), hex("0d6c90e86d08868a73da8abfb4c42319f40515c6102821b3575c826d627c8ebf"));
+
+        let mut key = [0u8; 32];
+        key[..4].fill(1);
+        smt.update(key, [2u8; 32]).unwrap();
+        assert_eq!(smt.root(), hex("0d8d4523817da33cad3abc2076d47c40f806dc6cc97ed145f6cc21208e3aa9a2"));
+        let proof = smt.get_proof(key).unwrap();
+        assert!(verify_with_spec(&smt.spec(), &smt.root(), &key, &[2u8; 32], &proof).unwrap());
+        assert!(!verify_with_spec(&smt.spec(), &smt.root(), &key, &[3u8; 32], &proof).unwrap());
+    }
+
+    #[test]
+    fn test_long_inputs_are_chained() {
+        let bytes = [7u8; 1 + 12 * LIMB_LEN];
+        let elements = elements(&bytes);
+        let expected = hash(&[&[hash(&elements[..12])], &elements[12..]].concat());
+        assert_eq!(digest(&bytes).to_vec(), expected.into_bigint().to_bytes_be());
+    }
+}
@@ -1,7 +1,659 @@
 use serde::{Serialize, Deserialize};
-use crate::Hash;
 
-#[derive(Clone, Serialize, Deserialize)]
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+use crate::error::SMTError;
+use crate::{
+    tree_hasher::{hash_pair_into, TreeDigest, TreeHasher, DEFAULT_DEPTH, LEAF_PREFIX, NODE_PREFIX},
+    DefaultHasher, Hash,
+};
+
+/// Version byte leading `MerkleProof::to_bytes`.
+pub const PROOF_FORMAT_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MerkleProof {
+    #[cfg_attr(feature = "serde_hex", serde(with = "crate::hex::serde_hex::vec"))]
+    pub side_nodes: Vec<Hash>,
+}
+
+/// Proof that a key has no leaf: the side nodes from the root down to the
+/// first empty subtree on the key's path, or down to the leaf of another key
+/// sharing the whole path, which `leaf` then holds as `(key, value)`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct NonMembershipProof {
     pub side_nodes: Vec<Hash>,
-}
\ No newline at end of file
+    #[serde(default)]
+    pub leaf: Option<(Hash, Hash)>,
+}
+
+/// Everything `SparseMerkleTree::prove` can say about a key.
+#[derive(Clone, Serialize, Deserialize)]
+pub enum KeyProof {
+    Membership { value: Hash, proof: MerkleProof },
+    NonMembership(NonMembershipProof),
+}
+
+impl KeyProof {
+    /// The key's value, `None` if the proof shows it absent.
+    pub fn value(&self) -> Option<Hash> {
+        match self {
+            KeyProof::Membership { value, .. } => Some(*value),
+            KeyProof::NonMembership(_) => None,
+        }
+    }
+
+    /// Checks the proof for `key` against `root` of a full-depth tree.
+    pub fn verify(&self, root: &Hash, key: &Hash) -> bool {
+        match self {
+            KeyProof::Membership { value, proof } => proof.verify(root, key, value),
+            KeyProof::NonMembership(proof) => proof.verify(root, key),
+        }
+    }
+}
+
+impl NonMembershipProof {
+    /// Checks that `key` is absent from the tree committed to by `root`.
+    pub fn verify(&self, root: &Hash, key: &Hash) -> bool {
+        self.verify_with(&TreeHasher::<DefaultHasher>::new(), root, key)
+    }
+
+    /// Like `verify`, but hashing with `hasher` instead of the default one.
+    pub fn verify_with<D: TreeDigest>(&self, hasher: &TreeHasher<D>, root: &Hash, key: &Hash) -> bool {
+        self.verify_at_depth(hasher, root, key, DEFAULT_DEPTH)
+    }
+
+    /// Like `verify_with`, for a tree `depth` levels deep.
+    pub(crate) fn verify_at_depth<D: TreeDigest>(&self, hasher: &TreeHasher<D>, root: &Hash, key: &Hash, depth: usize) -> bool {
+        let bit = |key: &Hash, i: usize| (key[i / 8] >> (7 - (i % 8))) & 1;
+        if self.side_nodes.len() > depth {
+            return false;
+        }
+
+        // The path ends in an empty subtree, or in a leaf of another key with
+        // the same first `depth` bits.
+        let mut current = match &self.leaf {
+            None => hasher.empty(depth - self.side_nodes.len()),
+            Some((other, value)) => {
+                if other == key || self.side_nodes.len() != depth || (0..depth).any(|i| bit(other, i) != bit(key, i)) {
+                    return false;
+                }
+                hasher.digest_leaf(other, value)
+            }
+        };
+        for (i, sibling) in self.side_nodes.iter().enumerate().rev() {
+            let bit = bit(key, i);
+            let (left, right) = if bit == 0 {
+                (current, *sibling)
+            } else {
+                (*sibling, current)
+            };
+            current = hasher.digest_node(&left, &right);
+        }
+
+        current == *root
+    }
+}
+
+/// `MerkleProof` with the empty-subtree siblings left out. Bit `i` of
+/// `bitmap` (MSB first, like keys) is set when the sibling at depth `i` is
+/// not the default hash for its height; `side_nodes` holds only those
+/// siblings, in depth order.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CompressedMerkleProof {
+    pub depth: u16,
+    pub bitmap: [u8; 32],
+    pub side_nodes: Vec<Hash>,
+}
+
+impl CompressedMerkleProof {
+    /// Expands back into a full proof. Fails if the bitmap and the stored
+    /// side nodes disagree.
+    #[cfg(feature = "std")]
+    pub fn decompress(&self) -> Result<MerkleProof, SMTError> {
+        self.decompress_with(&TreeHasher::<DefaultHasher>::new())
+    }
+
+    /// Like `decompress`, filling in the default hashes of `hasher`.
+    #[cfg(feature = "std")]
+    pub fn decompress_with<D: TreeDigest>(&self, hasher: &TreeHasher<D>) -> Result<MerkleProof, SMTError> {
+        if !self.is_consistent() {
+            return Err(SMTError::InvalidProof);
+        }
+
+        let depth = self.depth as usize;
+        let mut present = self.side_nodes.iter();
+        let side_nodes = (0..depth)
+            .map(|i| match self.has_sibling(i) {
+                true => *present.next().unwrap(),
+                false => hasher.empty(depth - i - 1),
+            })
+            .collect();
+        Ok(MerkleProof { side_nodes })
+    }
+
+    /// Checks the proof against `root` without expanding it first.
+    pub fn verify(&self, root: &Hash, key: &Hash, value: &Hash) -> bool {
+        self.verify_with(&TreeHasher::<DefaultHasher>::new(), root, key, value)
+    }
+
+    /// Estimates the cost of verifying this proof, like
+    /// `MerkleProof::verification_cost`. Every level is still hashed, but
+    /// only the depth, the bitmap and the stored side nodes are decoded.
+    pub fn verification_cost(&self) -> VerificationCost {
+        self.cost(0)
+    }
+
+    /// Like `verification_cost`, for a tree built with `hasher`.
+    pub fn verification_cost_with<D: TreeDigest>(&self, hasher: &TreeHasher<D>) -> VerificationCost {
+        self.cost(domain_len(hasher))
+    }
+
+    fn cost(&self, domain_len: usize) -> VerificationCost {
+        VerificationCost::new(self.depth as usize, domain_len, 2 + 32 + 32 * self.side_nodes.len())
+    }
+
+    /// Like `verify`, but hashing with `hasher` instead of the default one.
+    pub fn verify_with<D: TreeDigest>(&self, hasher: &TreeHasher<D>, root: &Hash, key: &Hash, value: &Hash) -> bool {
+        if !self.is_consistent() {
+            return false;
+        }
+
+        let depth = self.depth as usize;
+        let mut current = hasher.digest_leaf(key, value);
+        let mut present = self.side_nodes.iter().rev();
+        for i in (0..depth).rev() {
+            let sibling = match self.has_sibling(i) {
+                true => *present.next().unwrap(),
+                false => hasher.empty(depth - i - 1),
+            };
+            let bit = (key[i / 8] >> (7 - (i % 8))) & 1;
+            let (left, right) = if bit == 0 {
+                (current, sibling)
+            } else {
+                (sibling, current)
+            };
+            current = hasher.digest_node(&left, &right);
+        }
+
+        current == *root
+    }
+
+    fn has_sibling(&self, depth: usize) -> bool {
+        (self.bitmap[depth / 8] >> (7 - (depth % 8))) & 1 == 1
+    }
+
+    // Depth within the tree, no bits set past it, one stored node per set bit.
+    fn is_consistent(&self) -> bool {
+        let depth = self.depth as usize;
+        depth <= 256
+            && (depth..256).all(|i| !self.has_sibling(i))
+            && (0..depth).filter(|i| self.has_sibling(*i)).count() == self.side_nodes.len()
+    }
+}
+
+/// Proof for several keys at once. Siblings shared by more than one key's path
+/// are included once, and as in `CompressedMerkleProof` empty-subtree
+/// siblings are only recorded in `bitmap`.
+///
+/// Siblings are listed in the order a depth-first, left-to-right walk from the
+/// root meets them; bit `i` of `bitmap` says whether the `i`th of the `len`
+/// siblings is non-zero and stored in `side_nodes`. `depth` is the depth of
+/// the tree the proof came from.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MultiProof {
+    pub len: u32,
+    pub bitmap: Vec<u8>,
+    pub side_nodes: Vec<Hash>,
+    #[serde(default = "default_depth")]
+    pub depth: u16,
+}
+
+fn default_depth() -> u16 {
+    DEFAULT_DEPTH as u16
+}
+
+impl Default for MultiProof {
+    fn default() -> Self {
+        Self::new(DEFAULT_DEPTH)
+    }
+}
+
+impl MultiProof {
+    pub(crate) fn new(depth: usize) -> Self {
+        Self {
+            len: 0,
+            bitmap: Vec::new(),
+            side_nodes: Vec::new(),
+            depth: depth as u16,
+        }
+    }
+
+    /// Appends the next sibling, `None` for an empty subtree.
+    #[cfg(feature = "std")]
+    pub(crate) fn push(&mut self, sibling: Option<Hash>) {
+        let i = self.len as usize;
+        if i.is_multiple_of(8) {
+            self.bitmap.push(0);
+        }
+        if let Some(sibling) = sibling {
+            self.bitmap[i / 8] |= 1 << (7 - (i % 8));
+            self.side_nodes.push(sibling);
+        }
+        self.len += 1;
+    }
+
+    /// Whether the bitmap has one bit per sibling and the depth is one a tree
+    /// can have.
+    pub(crate) fn is_well_formed(&self) -> bool {
+        self.bitmap.len() == (self.len as usize).div_ceil(8) && (1..=DEFAULT_DEPTH).contains(&(self.depth as usize))
+    }
+
+    /// Checks that every `(key, value)` in `entries` is in the tree committed
+    /// to by `root`. Entries may come in any order but keys must be distinct.
+    pub fn verify(&self, root: &Hash, entries: &[(Hash, Hash)]) -> bool {
+        self.verify_with(&TreeHasher::<DefaultHasher>::new(), root, entries)
+    }
+
+    /// Like `verify`, but hashing with `hasher` instead of the default one.
+    pub fn verify_with<D: TreeDigest>(&self, hasher: &TreeHasher<D>, root: &Hash, entries: &[(Hash, Hash)]) -> bool {
+        if entries.is_empty() || !self.is_well_formed() {
+            return false;
+        }
+        let mut entries = entries.to_vec();
+        entries.sort_by_key(|(key, _)| *key);
+        if entries.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            return false;
+        }
+
+        let mut reader = MultiProofReader::new(self);
+        match reader.subtree_root(hasher, &entries, 0) {
+            Some(computed) => reader.is_exhausted() && computed == *root,
+            None => false,
+        }
+    }
+}
+
+/// Checks `proof` for `entries` against `root`.
+pub fn verify_multiproof(root: &Hash, entries: &[(Hash, Hash)], proof: &MultiProof) -> bool {
+    proof.verify(root, entries)
+}
+
+pub(crate) struct MultiProofReader<'a> {
+    proof: &'a MultiProof,
+    next_slot: usize,
+    next_node: usize,
+}
+
+impl<'a> MultiProofReader<'a> {
+    pub(crate) fn new(proof: &'a MultiProof) -> Self {
+        Self { proof, next_slot: 0, next_node: 0 }
+    }
+
+    /// Depth of the tree the proof came from.
+    pub(crate) fn depth(&self) -> usize {
+        self.proof.depth as usize
+    }
+
+    /// Whether every sibling in the proof has been consumed.
+    pub(crate) fn is_exhausted(&self) -> bool {
+        self.next_slot == self.proof.len as usize && self.next_node == self.proof.side_nodes.len()
+    }
+
+    /// Consumes the next sibling, the root of a subtree `height` levels
+    /// above the leaves.
+    pub(crate) fn next_sibling<D: TreeDigest>(&mut self, hasher: &TreeHasher<D>, height: usize) -> Option<Hash> {
+        let i = self.next_slot;
+        if i >= self.proof.len as usize {
+            return None;
+        }
+        self.next_slot += 1;
+        if (self.proof.bitmap[i / 8] >> (7 - (i % 8))) & 1 == 0 {
+            return Some(hasher.empty(height));
+        }
+        let sibling = *self.proof.side_nodes.get(self.next_node)?;
+        self.next_node += 1;
+        Some(sibling)
+    }
+
+    // Mirrors the walk in `SparseMerkleTree::collect_multiproof`: a subtree
+    // holding keys on only one side takes its other child from the proof.
+    fn subtree_root<D: TreeDigest>(&mut self, hasher: &TreeHasher<D>, entries: &[(Hash, Hash)], depth: usize) -> Option<Hash> {
+        if depth == self.proof.depth as usize {
+            // Writes reject keys set past the tree's depth (`KeyBeyondDepth`), so a
+            // leaf holds exactly one key and more entries here cannot be verified.
+            let [(key, value)] = entries else {
+                return None;
+            };
+            return Some(hasher.digest_leaf(key, value));
+        }
+
+        let split = entries.partition_point(|(key, _)| (key[depth / 8] >> (7 - (depth % 8))) & 1 == 0);
+        let (left_entries, right_entries) = entries.split_at(split);
+        let height = self.depth() - depth - 1; // Of the children
+        let (left, right) = if right_entries.is_empty() {
+            let right = self.next_sibling(hasher, height)?;
+            (self.subtree_root(hasher, left_entries, depth + 1)?, right)
+        } else if left_entries.is_empty() {
+            let left = self.next_sibling(hasher, height)?;
+            (left, self.subtree_root(hasher, right_entries, depth + 1)?)
+        } else {
+            let left = self.subtree_root(hasher, left_entries, depth + 1)?;
+            (left, self.subtree_root(hasher, right_entries, depth + 1)?)
+        };
+        Some(hasher.digest_node(&left, &right))
+    }
+}
+
+/// A `MerkleProof` whose side nodes are borrowed, e.g. straight out of the
+/// buffer holding its `to_bytes` encoding. Verifying one allocates nothing
+/// and copies no side node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MerkleProofRef<'a> {
+    pub side_nodes: &'a [Hash],
+}
+
+impl<'a> MerkleProofRef<'a> {
+    /// Reads a proof encoded by `MerkleProof::to_bytes` in place. `None` for
+    /// anything `MerkleProof::from_bytes` rejects.
+    pub fn decode(bytes: &'a [u8]) -> Option<Self> {
+        let [PROOF_FORMAT_VERSION, high, low, body @ ..] = bytes else {
+            return None;
+        };
+        let (side_nodes, rest) = body.as_chunks::<32>();
+        let count = u16::from_be_bytes([*high, *low]) as usize;
+        if count > DEFAULT_DEPTH || side_nodes.len() != count || !rest.is_empty() {
+            return None;
+        }
+        Some(Self { side_nodes })
+    }
+
+    pub fn to_proof(&self) -> MerkleProof {
+        MerkleProof { side_nodes: self.side_nodes.to_vec() }
+    }
+
+    /// Checks the proof against `root` under the default hasher. Unlike
+    /// `verify_with`, this needs no `TreeHasher` and its table of default
+    /// hashes.
+    pub fn verify(&self, root: &Hash, key: &Hash, value: &Hash) -> bool {
+        let mut scratch = [0u8; 32];
+        self.fold::<DefaultHasher>(LEAF_PREFIX, NODE_PREFIX, None, key, value, &mut scratch) && scratch == *root
+    }
+
+    /// Like `verify`, but hashing with `hasher` instead of the default one.
+    pub fn verify_with<D: TreeDigest>(&self, hasher: &TreeHasher<D>, root: &Hash, key: &Hash, value: &Hash) -> bool {
+        let mut scratch = [0u8; 32];
+        self.verify_into(hasher, root, key, value, &mut scratch)
+    }
+
+    /// Like `verify_with`, hashing in the caller's `scratch`, which is left
+    /// holding the root the proof leads to. Verifying many proofs in a loop
+    /// then reuses one buffer throughout.
+    #[inline]
+    pub fn verify_into<D: TreeDigest>(&self, hasher: &TreeHasher<D>, root: &Hash, key: &Hash, value: &Hash, scratch: &mut Hash) -> bool {
+        let (leaf_prefix, node_prefix) = hasher.prefixes();
+        self.fold::<D>(leaf_prefix, node_prefix, hasher.key_domain().as_ref(), key, value, scratch) && *scratch == *root
+    }
+
+    /// Hashes from the leaf up to the root in `scratch`. False if the proof
+    /// is too long to walk.
+    #[inline]
+    fn fold<D: TreeDigest>(&self, leaf_prefix: u8, node_prefix: u8, domain: Option<&Hash>, key: &Hash, value: &Hash, scratch: &mut Hash) -> bool {
+        if self.side_nodes.len() > DEFAULT_DEPTH {
+            return false;
+        }
+        hash_pair_into::<D>(leaf_prefix, domain, key, value, scratch);
+        for (i, sibling) in self.side_nodes.iter().enumerate().rev() {
+            let current = *scratch;
+            match (key[i / 8] >> (7 - (i % 8))) & 1 {
+                0 => hash_pair_into::<D>(node_prefix, None, &current, sibling, scratch),
+                _ => hash_pair_into::<D>(node_prefix, None, sibling, &current, scratch),
+            }
+        }
+        true
+    }
+}
+
+/// Estimated work needed to verify a proof, computed without hashing anything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerificationCost {
+    pub hash_invocations: usize, // One leaf hash plus one node hash per level
+    pub bytes_hashed: usize,     // Total input fed to the hasher, prefixes included
+    pub bytes_decoded: usize,    // Size of the proof's encoding, read before hashing
+}
+
+impl VerificationCost {
+    /// Cost of a proof `depth` levels deep whose encoding is `bytes_decoded`
+    /// long, under a hasher whose key domain adds `domain_len` bytes to the
+    /// leaf.
+    fn new(depth: usize, domain_len: usize, bytes_decoded: usize) -> Self {
+        // Leaves hash `prefix || domain || key || value`, nodes hash `prefix || left || right`.
+        let node_len = 1 + 2 * core::mem::size_of::<Hash>();
+        Self {
+            hash_invocations: 1 + depth,
+            bytes_hashed: node_len + domain_len + depth * node_len,
+            bytes_decoded,
+        }
+    }
+}
+
+fn domain_len<D: TreeDigest>(hasher: &TreeHasher<D>) -> usize {
+    hasher.key_domain().map_or(0, |domain| domain.len())
+}
+
+impl MerkleProof {
+    /// Checks the proof against `root` without needing access to a store.
+    pub fn verify(&self, root: &Hash, key: &Hash, value: &Hash) -> bool {
+        self.view().verify(root, key, value)
+    }
+
+    /// Like `verify`, but hashing with `hasher` instead of the default one.
+    pub fn verify_with<D: TreeDigest>(&self, hasher: &TreeHasher<D>, root: &Hash, key: &Hash, value: &Hash) -> bool {
+        self.view().verify_with(hasher, root, key, value)
+    }
+
+    /// Like `verify_with`, hashing in `scratch`, see `MerkleProofRef::verify_into`.
+    #[inline]
+    pub fn verify_into<D: TreeDigest>(&self, hasher: &TreeHasher<D>, root: &Hash, key: &Hash, value: &Hash, scratch: &mut Hash) -> bool {
+        self.view().verify_into(hasher, root, key, value, scratch)
+    }
+
+    /// The proof as a `MerkleProofRef` over its side nodes.
+    pub fn view(&self) -> MerkleProofRef<'_> {
+        MerkleProofRef { side_nodes: &self.side_nodes }
+    }
+
+    /// Canonical encoding: `PROOF_FORMAT_VERSION`, the number of side nodes as
+    /// a big-endian `u16`, then the side nodes from the root down. Every proof
+    /// has exactly one encoding.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(3 + 32 * self.side_nodes.len());
+        bytes.push(PROOF_FORMAT_VERSION);
+        bytes.extend_from_slice(&(self.side_nodes.len() as u16).to_be_bytes());
+        for side_node in &self.side_nodes {
+            bytes.extend_from_slice(side_node);
+        }
+        bytes
+    }
+
+    /// Decodes a proof produced by `to_bytes`. Rejects unknown versions, more
+    /// than 256 side nodes, and missing or trailing bytes.
+    #[cfg(feature = "std")]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SMTError> {
+        if bytes.len() < 3 || bytes[0] != PROOF_FORMAT_VERSION {
+            return Err(SMTError::InvalidEncoding);
+        }
+        let count = u16::from_be_bytes([bytes[1], bytes[2]]) as usize;
+        let body = &bytes[3..];
+        if count > 256 || body.len() != count * 32 {
+            return Err(SMTError::InvalidEncoding);
+        }
+        let side_nodes = body.chunks_exact(32).map(|node| node.try_into().unwrap()).collect();
+        Ok(Self { side_nodes })
+    }
+
+    /// Drops the empty-subtree siblings, which make up most of a proof in any
+    /// tree far from full.
+    pub fn compress(&self) -> CompressedMerkleProof {
+        self.compress_with(&TreeHasher::<DefaultHasher>::new())
+    }
+
+    /// Like `compress`, recognising the default hashes of `hasher`.
+    pub fn compress_with<D: TreeDigest>(&self, hasher: &TreeHasher<D>) -> CompressedMerkleProof {
+        let depth = self.side_nodes.len().min(256);
+        let mut bitmap = [0u8; 32];
+        let mut side_nodes = Vec::new();
+        for (i, sibling) in self.side_nodes.iter().enumerate().take(depth) {
+            if *sibling != hasher.empty(depth - i - 1) {
+                bitmap[i / 8] |= 1 << (7 - (i % 8));
+                side_nodes.push(*sibling);
+            }
+        }
+        CompressedMerkleProof {
+            depth: depth as u16,
+            bitmap,
+            side_nodes,
+        }
+    }
+
+    /// Estimates the cost of verifying this proof from its `to_bytes`
+    /// encoding, so callers can reject unexpectedly expensive proofs before
+    /// doing any hashing.
+    pub fn verification_cost(&self) -> VerificationCost {
+        self.cost(0)
+    }
+
+    /// Like `verification_cost`, for a tree built with `hasher`. A key domain
+    /// makes every leaf hash longer.
+    pub fn verification_cost_with<D: TreeDigest>(&self, hasher: &TreeHasher<D>) -> VerificationCost {
+        self.cost(domain_len(hasher))
+    }
+
+    fn cost(&self, domain_len: usize) -> VerificationCost {
+        VerificationCost::new(self.side_nodes.len(), domain_len, 3 + 32 * self.side_nodes.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proof_bytes_roundtrip() {
+        let proof = MerkleProof { side_nodes: vec![[1u8; 32], [2u8; 32]] };
+        let bytes = proof.to_bytes();
+
+        assert_eq!(&bytes[..3], &[PROOF_FORMAT_VERSION, 0, 2]);
+        assert_eq!(bytes.len(), 3 + 64);
+        assert_eq!(MerkleProof::from_bytes(&bytes).unwrap().side_nodes, proof.side_nodes);
+    }
+
+    #[test]
+    fn test_proof_from_bytes_rejects_malformed_input() {
+        let bytes = MerkleProof { side_nodes: vec![[1u8; 32]] }.to_bytes();
+
+        assert!(MerkleProof::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(MerkleProof::from_bytes(&[bytes.as_slice(), &[0]].concat()).is_err());
+        assert!(MerkleProof::from_bytes(&[[2u8].as_slice(), &bytes[1..]].concat()).is_err());
+
+        let mut too_long = vec![PROOF_FORMAT_VERSION, 1, 1];
+        too_long.extend(vec![0u8; 257 * 32]);
+        assert!(MerkleProof::from_bytes(&too_long).is_err());
+    }
+
+    #[test]
+    fn test_borrowed_proof_verifies_in_place() {
+        let hasher = TreeHasher::<DefaultHasher>::new();
+        let (key, value) = ([0b1010_0000u8; 32], [7u8; 32]);
+        let side_nodes = vec![[1u8; 32], [2u8; 32], [3u8; 32]];
+        let mut root = hasher.digest_leaf(&key, &value);
+        for (i, sibling) in side_nodes.iter().enumerate().rev() {
+            root = match (key[0] >> (7 - i)) & 1 {
+                0 => hasher.digest_node(&root, sibling),
+                _ => hasher.digest_node(sibling, &root),
+            };
+        }
+
+        let bytes = MerkleProof { side_nodes }.to_bytes();
+        let proof = MerkleProofRef::decode(&bytes).unwrap();
+        assert!(proof.verify(&root, &key, &value));
+        assert!(proof.to_proof().verify(&root, &key, &value));
+        let mut scratch = [0u8; 32];
+        assert!(proof.verify_into(&hasher, &root, &key, &value, &mut scratch));
+        assert_eq!(scratch, root);
+        assert!(!proof.verify_into(&hasher, &root, &key, &[8u8; 32], &mut scratch));
+
+        assert!(MerkleProofRef::decode(&bytes[..bytes.len() - 1]).is_none());
+        assert!(MerkleProofRef::decode(&[bytes.as_slice(), &[0]].concat()).is_none());
+    }
+
+    #[test]
+    fn test_verification_cost() {
+        let proof = MerkleProof { side_nodes: vec![[0u8; 32]; 256] };
+        let cost = proof.verification_cost();
+        assert_eq!(cost.hash_invocations, 257);
+        assert_eq!(cost.bytes_hashed, 257 * 65);
+        assert_eq!(cost.bytes_decoded, proof.to_bytes().len());
+        assert_eq!(proof.verification_cost_with(&TreeHasher::<DefaultHasher>::new()), cost);
+    }
+
+    #[test]
+    fn test_compressed_verification_cost() {
+        let hasher = TreeHasher::<DefaultHasher>::new();
+        let mut side_nodes: Vec<Hash> = (0..256).map(|i| hasher.empty(255 - i)).collect();
+        side_nodes[3] = [7u8; 32];
+        side_nodes[255] = [9u8; 32];
+        let proof = MerkleProof { side_nodes };
+
+        let cost = proof.compress().verification_cost();
+        assert_eq!(cost.hash_invocations, 257);
+        assert_eq!(cost.bytes_hashed, proof.verification_cost().bytes_hashed);
+        assert_eq!(cost.bytes_decoded, 2 + 32 + 2 * 32);
+        let hasher = hasher.with_key_domain([7u8; 32]);
+        assert_eq!(proof.compress().verification_cost_with(&hasher).bytes_hashed, cost.bytes_hashed + 32);
+    }
+
+    #[test]
+    fn test_verification_cost_counts_key_domain() {
+        let proof = MerkleProof { side_nodes: vec![[0u8; 32]; 256] };
+        let hasher = TreeHasher::<DefaultHasher>::new().with_key_domain([7u8; 32]);
+        let cost = proof.verification_cost_with(&hasher);
+        assert_eq!(cost.hash_invocations, 257);
+        assert_eq!(cost.bytes_hashed, (1 + 32 + 64) + 256 * 65);
+    }
+
+    #[test]
+    fn test_non_membership_proof_for_empty_root() {
+        let empty_root = TreeHasher::<DefaultHasher>::new().empty(DEFAULT_DEPTH);
+        let proof = NonMembershipProof { side_nodes: Vec::new(), leaf: None };
+        assert!(proof.verify(&empty_root, &[1u8; 32]));
+        assert!(!proof.verify(&[0u8; 32], &[1u8; 32]));
+        assert!(!proof.verify(&[1u8; 32], &[1u8; 32]));
+    }
+
+    #[test]
+    fn test_compress_roundtrip() {
+        let hasher = TreeHasher::<DefaultHasher>::new();
+        let mut side_nodes: Vec<Hash> = (0..256).map(|i| hasher.empty(255 - i)).collect();
+        side_nodes[3] = [7u8; 32];
+        side_nodes[255] = [9u8; 32];
+        let proof = MerkleProof { side_nodes };
+
+        let compressed = proof.compress();
+        assert_eq!(compressed.side_nodes, vec![[7u8; 32], [9u8; 32]]);
+        assert_eq!(compressed.decompress().unwrap().side_nodes, proof.side_nodes);
+    }
+
+    #[test]
+    fn test_decompress_rejects_inconsistent_bitmap() {
+        let mut compressed = MerkleProof { side_nodes: vec![[1u8; 32]; 4] }.compress();
+        compressed.side_nodes.pop();
+        assert!(compressed.decompress().is_err());
+        assert!(!compressed.verify(&[0u8; 32], &[0u8; 32], &[0u8; 32]));
+
+        let mut compressed = MerkleProof { side_nodes: vec![[0u8; 32]; 4] }.compress();
+        compressed.bitmap[31] = 1; // Bit past the proof depth
+        assert!(compressed.decompress().is_err());
+    }
+}
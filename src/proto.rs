@@ -0,0 +1,228 @@
+//! Protobuf encoding of proofs and roots following `proto/smt.proto`, for
+//! verifiers written against generated code in other languages.
+//!
+//! The messages are small enough that the wire format is written by hand
+//! here rather than generated. Decoding skips unknown fields, as proto3
+//! requires, so newer schemas stay readable.
+
+use crate::{
+    error::SMTError,
+    proof::{MerkleProof, MultiProof},
+    sparse_merkle_tree::DEFAULT_DEPTH,
+    Hash,
+};
+
+const WIRE_VARINT: u8 = 0;
+const WIRE_FIXED64: u8 = 1;
+const WIRE_LEN: u8 = 2;
+const WIRE_FIXED32: u8 = 5;
+
+impl MerkleProof {
+    pub fn to_proto(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(34 * self.side_nodes.len());
+        for side_node in &self.side_nodes {
+            put_bytes(&mut out, 1, side_node);
+        }
+        out
+    }
+
+    pub fn from_proto(bytes: &[u8]) -> Result<Self, SMTError> {
+        let mut side_nodes = Vec::new();
+        for field in Fields(bytes) {
+            if let (1, Value::Bytes(node)) = field? {
+                side_nodes.push(to_hash(node)?);
+            }
+        }
+        Ok(Self { side_nodes })
+    }
+}
+
+impl MultiProof {
+    pub fn to_proto(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        if self.len != 0 {
+            put_key(&mut out, 1, WIRE_VARINT);
+            put_varint(&mut out, self.len as u64);
+        }
+        if !self.bitmap.is_empty() {
+            put_bytes(&mut out, 2, &self.bitmap);
+        }
+        for side_node in &self.side_nodes {
+            put_bytes(&mut out, 3, side_node);
+        }
+        if self.depth as usize != DEFAULT_DEPTH {
+            put_key(&mut out, 4, WIRE_VARINT);
+            put_varint(&mut out, self.depth as u64);
+        }
+        out
+    }
+
+    pub fn from_proto(bytes: &[u8]) -> Result<Self, SMTError> {
+        let mut proof = Self::default(); // Depth 256 unless the message says otherwise
+        for field in Fields(bytes) {
+            match field? {
+                (1, Value::Varint(len)) => proof.len = u32::try_from(len).map_err(|_| SMTError::InvalidEncoding)?,
+                (2, Value::Bytes(bitmap)) => proof.bitmap = bitmap.to_vec(),
+                (3, Value::Bytes(node)) => proof.side_nodes.push(to_hash(node)?),
+                (4, Value::Varint(depth)) => proof.depth = u16::try_from(depth).map_err(|_| SMTError::InvalidEncoding)?,
+                _ => {}
+            }
+        }
+        Ok(proof)
+    }
+}
+
+pub fn root_to_proto(root: &Hash) -> Vec<u8> {
+    let mut out = Vec::with_capacity(34);
+    put_bytes(&mut out, 1, root);
+    out
+}
+
+pub fn root_from_proto(bytes: &[u8]) -> Result<Hash, SMTError> {
+    let mut root = None;
+    for field in Fields(bytes) {
+        if let (1, Value::Bytes(hash)) = field? {
+            root = Some(to_hash(hash)?);
+        }
+    }
+    root.ok_or(SMTError::InvalidEncoding)
+}
+
+fn to_hash(bytes: &[u8]) -> Result<Hash, SMTError> {
+    bytes.try_into().map_err(|_| SMTError::InvalidEncoding)
+}
+
+fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn put_key(out: &mut Vec<u8>, field: u32, wire: u8) {
+    put_varint(out, ((field as u64) << 3) | wire as u64);
+}
+
+fn put_bytes(out: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    put_key(out, field, WIRE_LEN);
+    put_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// Walks the `(field number, value)` pairs of an encoded message.
+struct Fields<'a>(&'a [u8]);
+
+impl<'a> Fields<'a> {
+    fn varint(&mut self) -> Result<u64, SMTError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self.0.split_first().ok_or(SMTError::InvalidEncoding)?;
+            self.0 = rest;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(SMTError::InvalidEncoding)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SMTError> {
+        if len > self.0.len() {
+            return Err(SMTError::InvalidEncoding);
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn field(&mut self) -> Result<(u32, Value<'a>), SMTError> {
+        let key = self.varint()?;
+        let field = u32::try_from(key >> 3).map_err(|_| SMTError::InvalidEncoding)?;
+        let value = match (key & 7) as u8 {
+            WIRE_VARINT => Value::Varint(self.varint()?),
+            WIRE_LEN => {
+                let len = usize::try_from(self.varint()?).map_err(|_| SMTError::InvalidEncoding)?;
+                Value::Bytes(self.take(len)?)
+            }
+            WIRE_FIXED64 => self.take(8).map(|_| Value::Fixed)?,
+            WIRE_FIXED32 => self.take(4).map(|_| Value::Fixed)?,
+            _ => return Err(SMTError::InvalidEncoding),
+        };
+        Ok((field, value))
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Result<(u32, Value<'a>), SMTError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let field = self.field();
+        if field.is_err() {
+            self.0 = &[];
+        }
+        Some(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Golden vectors: any protobuf library decoding `proto/smt.proto` must
+    // produce and accept exactly these bytes.
+    #[test]
+    fn test_merkle_proof_golden_vector() {
+        let proof = MerkleProof { side_nodes: vec![[1u8; 32], [0u8; 32]] };
+        let expected = [[0x0a, 0x20].as_slice(), &[1u8; 32], &[0x0a, 0x20], &[0u8; 32]].concat();
+
+        assert_eq!(proof.to_proto(), expected);
+        assert_eq!(MerkleProof::from_proto(&expected).unwrap().side_nodes, proof.side_nodes);
+    }
+
+    #[test]
+    fn test_multiproof_golden_vector() {
+        let proof = MultiProof { len: 3, bitmap: vec![0xa0], side_nodes: vec![[2u8; 32], [3u8; 32]], depth: 256 };
+        let expected = [[0x08, 0x03, 0x12, 0x01, 0xa0, 0x1a, 0x20].as_slice(), &[2u8; 32], &[0x1a, 0x20], &[3u8; 32]].concat();
+
+        assert_eq!(proof.to_proto(), expected);
+        let decoded = MultiProof::from_proto(&expected).unwrap();
+        assert_eq!((decoded.len, decoded.bitmap, decoded.side_nodes, decoded.depth), (proof.len, proof.bitmap, proof.side_nodes, 256));
+
+        let shallow = MultiProof { depth: 160, ..MultiProof::default() };
+        assert_eq!(shallow.to_proto(), vec![0x20, 0xa0, 0x01]);
+        assert_eq!(MultiProof::from_proto(&shallow.to_proto()).unwrap().depth, 160);
+    }
+
+    #[test]
+    fn test_root_golden_vector() {
+        let expected = [[0x0a, 0x20].as_slice(), &[7u8; 32]].concat();
+        assert_eq!(root_to_proto(&[7u8; 32]), expected);
+        assert_eq!(root_from_proto(&expected).unwrap(), [7u8; 32]);
+        assert!(matches!(root_from_proto(&[]), Err(SMTError::InvalidEncoding)));
+    }
+
+    #[test]
+    fn test_unknown_fields_are_skipped() {
+        // Field 9 as a varint and field 10 as fixed32 ahead of the real data.
+        let mut bytes = vec![0x48, 0x96, 0x01, 0x55, 1, 2, 3, 4];
+        bytes.extend(root_to_proto(&[5u8; 32]));
+        assert_eq!(root_from_proto(&bytes).unwrap(), [5u8; 32]);
+    }
+
+    #[test]
+    fn test_rejects_truncated_and_wrong_width_input() {
+        let bytes = MerkleProof { side_nodes: vec![[1u8; 32]] }.to_proto();
+        assert!(matches!(MerkleProof::from_proto(&bytes[..20]), Err(SMTError::InvalidEncoding)));
+        assert!(matches!(MerkleProof::from_proto(&[0x0a, 0x01, 0xff]), Err(SMTError::InvalidEncoding)));
+    }
+}
@@ -0,0 +1,300 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::SMTError,
+    kv_store::KVStore,
+    proof::{MultiProof, MultiProofReader},
+    sparse_merkle_tree::{get_bit, SparseMerkleTree},
+    tree_hasher::{TreeDigest, TreeHasher},
+    DefaultHasher, Hash,
+};
+
+/// Every leaf with a key in `start..=end`, plus the hashes of the subtrees
+/// lying wholly outside the interval. Subtrees inside the interval are
+/// rebuilt from `entries` alone, so a verified proof also shows that no other
+/// key in the interval has a leaf.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RangeProof {
+    pub entries: Vec<(Hash, Hash)>, // In key order
+    pub siblings: MultiProof,
+}
+
+impl RangeProof {
+    /// Checks that `entries` are exactly the leaves of `root` in `start..=end`.
+    pub fn verify(&self, root: &Hash, start: &Hash, end: &Hash) -> bool {
+        self.verify_with(&TreeHasher::<DefaultHasher>::new(), root, start, end)
+    }
+
+    /// Like `verify`, but hashing with `hasher` instead of the default one.
+    pub fn verify_with<D: TreeDigest>(&self, hasher: &TreeHasher<D>, root: &Hash, start: &Hash, end: &Hash) -> bool {
+        verify_entries(hasher, &self.siblings, &self.entries, root, start, end)
+    }
+}
+
+/// Proof that no key in `start..=end` has a leaf: a `RangeProof` with no
+/// entries, which needs only the subtrees outside the interval.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EmptyRangeProof {
+    pub siblings: MultiProof,
+}
+
+impl EmptyRangeProof {
+    /// Checks that `root` has no leaf in `start..=end`.
+    pub fn verify(&self, root: &Hash, start: &Hash, end: &Hash) -> bool {
+        self.verify_with(&TreeHasher::<DefaultHasher>::new(), root, start, end)
+    }
+
+    /// Like `verify`, but hashing with `hasher` instead of the default one.
+    pub fn verify_with<D: TreeDigest>(&self, hasher: &TreeHasher<D>, root: &Hash, start: &Hash, end: &Hash) -> bool {
+        verify_entries(hasher, &self.siblings, &[], root, start, end)
+    }
+}
+
+/// First and last key starting with `prefix`, for proving over a namespace.
+/// Prefixes longer than a key are cut to 32 bytes.
+pub fn prefix_range(prefix: &[u8]) -> (Hash, Hash) {
+    let prefix = &prefix[..prefix.len().min(32)];
+    let mut start = [0u8; 32];
+    let mut end = [0xffu8; 32];
+    start[..prefix.len()].copy_from_slice(prefix);
+    end[..prefix.len()].copy_from_slice(prefix);
+    (start, end)
+}
+
+fn verify_entries<D: TreeDigest>(hasher: &TreeHasher<D>, siblings: &MultiProof, entries: &[(Hash, Hash)], root: &Hash, start: &Hash, end: &Hash) -> bool {
+    if !siblings.is_well_formed() {
+        return false;
+    }
+    if entries.windows(2).any(|pair| pair[0].0 >= pair[1].0) || entries.iter().any(|(key, _)| key < start || key > end) {
+        return false;
+    }
+
+    let mut reader = MultiProofReader::new(siblings);
+    let interval = Interval { start, end, depth: siblings.depth as usize };
+    match interval.rebuild(hasher, &mut reader, entries, [0u8; 32], 0) {
+        Some(computed) => reader.is_exhausted() && computed == *root,
+        None => false,
+    }
+}
+
+/// Checks `proof` for the interval `start..=end` against `root`.
+pub fn verify_range(root: &Hash, start: &Hash, end: &Hash, proof: &RangeProof) -> bool {
+    proof.verify(root, start, end)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Overlap {
+    Disjoint,
+    Partial,
+    Contained,
+}
+
+struct Interval<'a> {
+    start: &'a Hash,
+    end: &'a Hash,
+    depth: usize, // Of the tree, where the leaves are
+}
+
+impl Interval<'_> {
+    /// How the subtree at `depth` whose keys all start with the first `depth`
+    /// bits of `path` relates to the interval. `path` is zero past `depth`,
+    /// and so are keys past the tree's depth.
+    fn overlap(&self, path: &Hash, depth: usize) -> Overlap {
+        let min = *path;
+        let mut max = *path;
+        let mut bit = depth;
+        while bit < self.depth {
+            if bit.is_multiple_of(8) && bit + 8 <= self.depth {
+                max[bit / 8] = 0xff;
+                bit += 8;
+            } else {
+                max[bit / 8] |= 1 << (7 - bit % 8);
+                bit += 1;
+            }
+        }
+
+        if self.start > self.end || max < *self.start || min > *self.end {
+            Overlap::Disjoint
+        } else if min >= *self.start && max <= *self.end {
+            Overlap::Contained
+        } else {
+            Overlap::Partial
+        }
+    }
+
+    // Mirrors the walk in `SparseMerkleTree::collect_range`: subtrees outside
+    // the interval come from the proof, and subtrees inside it with no
+    // entries are empty.
+    fn rebuild<D: TreeDigest>(
+        &self,
+        hasher: &TreeHasher<D>,
+        reader: &mut MultiProofReader,
+        entries: &[(Hash, Hash)],
+        path: Hash,
+        depth: usize,
+    ) -> Option<Hash> {
+        let overlap = self.overlap(&path, depth);
+        if overlap == Overlap::Disjoint {
+            return reader.next_sibling(hasher, self.depth - depth);
+        }
+        if overlap == Overlap::Contained && entries.is_empty() {
+            return Some(hasher.empty(self.depth - depth));
+        }
+        if depth == self.depth {
+            // Writes reject keys set past the tree's depth (`KeyBeyondDepth`), so a
+            // leaf holds exactly one key and more entries here cannot be verified.
+            let [(key, value)] = entries else {
+                return None;
+            };
+            return Some(hasher.digest_leaf(key, value));
+        }
+
+        let split = entries.partition_point(|(key, _)| get_bit(key, depth) == 0);
+        let left = self.rebuild(hasher, reader, &entries[..split], path, depth + 1)?;
+        let right = self.rebuild(hasher, reader, &entries[split..], with_bit(path, depth), depth + 1)?;
+        Some(hasher.digest_node(&left, &right))
+    }
+}
+
+impl<S: KVStore, D: TreeDigest> SparseMerkleTree<S, D>
+where
+    SMTError: From<S::Error>,
+{
+    /// Proves every leaf with a key in `start..=end`, including that there
+    /// are no others. An empty interval (`start > end`) is proven by the root
+    /// alone.
+    pub fn prove_range(&self, start: Hash, end: Hash) -> Result<RangeProof, SMTError> {
+        let mut proof = RangeProof { entries: Vec::new(), siblings: MultiProof::new(self.depth) };
+        let interval = Interval { start: &start, end: &end, depth: self.depth };
+        self.collect_range(&interval, self.root(), [0u8; 32], 0, &mut proof)?;
+        Ok(proof)
+    }
+
+    /// Proves that no key in `start..=end` has a leaf. Returns `None` if the
+    /// interval holds any.
+    pub fn prove_range_empty(&self, start: Hash, end: Hash) -> Result<Option<EmptyRangeProof>, SMTError> {
+        let proof = self.prove_range(start, end)?;
+        Ok(proof.entries.is_empty().then_some(EmptyRangeProof { siblings: proof.siblings }))
+    }
+
+    fn collect_range(&self, interval: &Interval, node: Hash, path: Hash, depth: usize, proof: &mut RangeProof) -> Result<(), SMTError> {
+        match interval.overlap(&path, depth) {
+            Overlap::Disjoint => {
+                proof.siblings.push(self.sibling(node));
+                return Ok(());
+            }
+            Overlap::Contained if self.hasher.is_empty(&node) => return Ok(()),
+            _ => {}
+        }
+
+        if depth == self.depth {
+            proof.entries.push(self.read_leaf(&node)?);
+            return Ok(());
+        }
+        let (left, right) = self.read_node(&node)?;
+        self.collect_range(interval, left, path, depth + 1, proof)?;
+        self.collect_range(interval, right, with_bit(path, depth), depth + 1, proof)
+    }
+}
+
+fn with_bit(mut path: Hash, depth: usize) -> Hash {
+    path[depth / 8] |= 1 << (7 - (depth % 8));
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_store::InMemoryKVStore;
+
+    fn key(first: u8) -> Hash {
+        let mut key = [0u8; 32];
+        key[0] = first;
+        key
+    }
+
+    fn tree(firsts: &[u8]) -> SparseMerkleTree<InMemoryKVStore> {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        for first in firsts {
+            smt.update(key(*first), [*first; 32]).unwrap();
+        }
+        smt
+    }
+
+    #[test]
+    fn test_range_proof_covers_interval() {
+        let smt = tree(&[10, 20, 30, 40, 50]);
+        let (start, end) = (key(15), key(40));
+
+        let proof = smt.prove_range(start, end).unwrap();
+        let keys: Vec<Hash> = proof.entries.iter().map(|(key, _)| *key).collect();
+        assert_eq!(keys, vec![key(20), key(30), key(40)]);
+        assert!(verify_range(&smt.root(), &start, &end, &proof));
+    }
+
+    #[test]
+    fn test_range_proof_rejects_omitted_leaf() {
+        let smt = tree(&[10, 20, 30, 40, 50]);
+        let (start, end) = (key(15), key(40));
+
+        let mut proof = smt.prove_range(start, end).unwrap();
+        proof.entries.remove(1);
+        assert!(!proof.verify(&smt.root(), &start, &end));
+    }
+
+    #[test]
+    fn test_range_proof_rejects_other_interval() {
+        let smt = tree(&[10, 20, 30]);
+        let proof = smt.prove_range(key(15), key(25)).unwrap();
+
+        // 30 has a leaf, so the proof must not stretch to cover it.
+        assert!(!proof.verify(&smt.root(), &key(15), &key(35)));
+    }
+
+    #[test]
+    fn test_empty_ranges() {
+        let smt = tree(&[10, 50]);
+
+        let gap = smt.prove_range(key(20), key(40)).unwrap();
+        assert!(gap.entries.is_empty());
+        assert!(gap.verify(&smt.root(), &key(20), &key(40)));
+
+        let inverted = smt.prove_range(key(40), key(20)).unwrap();
+        assert!(inverted.verify(&smt.root(), &key(40), &key(20)));
+
+        let empty = tree(&[]);
+        let proof = empty.prove_range([0u8; 32], [0xffu8; 32]).unwrap();
+        assert!(proof.verify(&empty.root(), &[0u8; 32], &[0xffu8; 32]));
+    }
+
+    #[test]
+    fn test_full_range_returns_every_leaf() {
+        let smt = tree(&[0, 1, 128, 255]);
+        let proof = smt.prove_range([0u8; 32], [0xffu8; 32]).unwrap();
+
+        assert_eq!(proof.entries, smt.iter().collect::<Result<Vec<_>, _>>().unwrap());
+        assert_eq!(proof.siblings.len, 0);
+        assert!(proof.verify(&smt.root(), &[0u8; 32], &[0xffu8; 32]));
+    }
+
+    #[test]
+    fn test_empty_range_proof() {
+        let smt = tree(&[0x10, 0x30]);
+        let (start, end) = prefix_range(&[0x20]);
+
+        let proof = smt.prove_range_empty(start, end).unwrap().unwrap();
+        assert!(proof.verify(&smt.root(), &start, &end));
+        assert!(!proof.verify(&smt.root(), &key(0x10), &end));
+
+        let (start, end) = prefix_range(&[0x30]);
+        assert!(smt.prove_range_empty(start, end).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_prefix_range() {
+        let (start, end) = prefix_range(&[0xab, 0xcd]);
+        assert_eq!(&start[..3], &[0xab, 0xcd, 0x00]);
+        assert_eq!(&end[..3], &[0xab, 0xcd, 0xff]);
+        assert_eq!(prefix_range(&[]), ([0u8; 32], [0xffu8; 32]));
+    }
+}
@@ -0,0 +1,197 @@
+//! Read access to a tree without the means to change it, for request
+//! handlers serving the canonical state. Nothing that could write is
+//! reachable from a `ReadOnlyTree`, and the store it reads through refuses
+//! writes anyway.
+
+use crate::{
+    error::SMTError,
+    kv_store::{KVStore, Scan, TreeWriteBatch},
+    proof::MerkleProof,
+    sparse_merkle_tree::{SparseMerkleTree, DEFAULT_DEPTH},
+    tree_hasher::{TreeDigest, TreeHasher},
+    DefaultHasher, Hash,
+};
+
+/// Store reading through to a borrowed one. Every write fails with
+/// `UnsupportedOperation`.
+pub struct ReadOnlyStore<'a, S: KVStore> {
+    inner: &'a S,
+}
+
+impl<'a, S: KVStore> ReadOnlyStore<'a, S> {
+    pub fn new(inner: &'a S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S: KVStore> KVStore for ReadOnlyStore<'_, S>
+where
+    SMTError: From<S::Error>,
+{
+    type Error = SMTError;
+
+    fn get(&self, key: &Hash) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(self.inner.get(key)?)
+    }
+
+    fn set(&mut self, _key: Hash, _value: Vec<u8>) -> Result<(), Self::Error> {
+        Err(SMTError::UnsupportedOperation)
+    }
+
+    fn remove(&mut self, _key: &Hash) -> Result<(), Self::Error> {
+        Err(SMTError::UnsupportedOperation)
+    }
+
+    fn get_node(&self, hash: &Hash) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(self.inner.get_node(hash)?)
+    }
+
+    fn set_node(&mut self, _hash: Hash, _node: Vec<u8>) -> Result<(), Self::Error> {
+        Err(SMTError::UnsupportedOperation)
+    }
+
+    fn remove_node(&mut self, _hash: &Hash) -> Result<(), Self::Error> {
+        Err(SMTError::UnsupportedOperation)
+    }
+
+    fn get_root(&self) -> Result<Option<Hash>, Self::Error> {
+        Ok(self.inner.get_root()?)
+    }
+
+    fn set_root(&mut self, _root: Hash) -> Result<(), Self::Error> {
+        Err(SMTError::UnsupportedOperation)
+    }
+
+    fn commit_batch(&mut self, _batch: TreeWriteBatch) -> Result<(), Self::Error> {
+        Err(SMTError::UnsupportedOperation)
+    }
+
+    fn write_batch(&mut self, _writes: Vec<(Hash, Option<Vec<u8>>)>) -> Result<(), Self::Error> {
+        Err(SMTError::UnsupportedOperation)
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Option<Scan<'_, Self::Error>> {
+        Some(Box::new(self.inner.scan_prefix(prefix)?.map(|entry| Ok(entry?))))
+    }
+
+    fn scan_node_prefix(&self, prefix: &[u8]) -> Option<Scan<'_, Self::Error>> {
+        Some(Box::new(self.inner.scan_node_prefix(prefix)?.map(|entry| Ok(entry?))))
+    }
+}
+
+/// The tree under one root of a borrowed store, offering reads and proofs
+/// only. Cheap to make per request: it holds a reference, the root and a
+/// hasher whose empty-subtree table is shared with every other tree.
+pub struct ReadOnlyTree<'a, S: KVStore, D: TreeDigest = DefaultHasher>
+where
+    SMTError: From<S::Error>,
+{
+    tree: SparseMerkleTree<ReadOnlyStore<'a, S>, D>,
+}
+
+impl<'a, S: KVStore> ReadOnlyTree<'a, S>
+where
+    SMTError: From<S::Error>,
+{
+    /// The tree of depth 256 under `root` in `store`. Hashes nothing: the
+    /// default hasher's table is built once per process.
+    pub fn new(store: &'a S, root: Hash) -> Self {
+        Self::with_hasher(store, root, TreeHasher::new(), DEFAULT_DEPTH)
+    }
+}
+
+impl<'a, S: KVStore, D: TreeDigest> ReadOnlyTree<'a, S, D>
+where
+    SMTError: From<S::Error>,
+{
+    /// Like `new`, for a tree built with `hasher` and `depth`.
+    pub fn with_hasher(store: &'a S, root: Hash, hasher: TreeHasher<D>, depth: usize) -> Self {
+        Self {
+            tree: SparseMerkleTree {
+                hasher,
+                store: ReadOnlyStore::new(store),
+                root,
+                depth,
+                observers: Vec::new(),
+                #[cfg(feature = "lru")]
+                cache: None,
+            },
+        }
+    }
+
+    pub fn root(&self) -> Hash {
+        self.tree.root()
+    }
+
+    pub fn get(&self, key: Hash) -> Result<Option<Hash>, SMTError> {
+        self.tree.get(key)
+    }
+
+    pub fn get_proof(&self, key: Hash) -> Result<MerkleProof, SMTError> {
+        self.tree.get_proof(key)
+    }
+}
+
+impl<S: KVStore, D: TreeDigest> SparseMerkleTree<S, D>
+where
+    SMTError: From<S::Error>,
+{
+    /// Read-only view of the current root, borrowing the store. The tree
+    /// cannot be written while the view lives.
+    pub fn read_only(&self) -> ReadOnlyTree<'_, S, D> {
+        ReadOnlyTree {
+            tree: SparseMerkleTree {
+                hasher: self.hasher.clone(),
+                store: ReadOnlyStore::new(&self.store),
+                root: self.root,
+                depth: self.depth,
+                observers: Vec::new(),
+                #[cfg(feature = "lru")]
+                cache: self.cache.clone(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_store::InMemoryKVStore;
+
+    #[test]
+    fn test_view_reads_and_proves() {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        smt.update([1u8; 32], [10u8; 32]).unwrap();
+        let old_root = smt.root();
+        smt.update([1u8; 32], [11u8; 32]).unwrap();
+
+        let view = smt.read_only();
+        assert_eq!(view.root(), smt.root());
+        assert_eq!(view.get([1u8; 32]).unwrap(), Some([11u8; 32]));
+        assert!(view.get_proof([1u8; 32]).unwrap().verify(&smt.root(), &[1u8; 32], &[11u8; 32]));
+
+        // Any root whose nodes are in the store can be served
+        let old = ReadOnlyTree::new(&smt.store, old_root);
+        assert_eq!(old.get([1u8; 32]).unwrap(), Some([10u8; 32]));
+        assert_eq!(old.get([2u8; 32]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_views_share_the_hasher_table() {
+        let smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        let first = ReadOnlyTree::new(&smt.store, smt.root());
+        let second = ReadOnlyTree::new(&smt.store, smt.root());
+        assert!(std::ptr::eq(first.tree.hasher.default_hashes(), smt.hasher.default_hashes()));
+        assert!(std::ptr::eq(first.tree.hasher.default_hashes(), second.tree.hasher.default_hashes()));
+    }
+
+    #[test]
+    fn test_store_refuses_writes() {
+        let inner = InMemoryKVStore::new();
+        let mut store = ReadOnlyStore::new(&inner);
+        assert!(matches!(store.set([1u8; 32], vec![1]), Err(SMTError::UnsupportedOperation)));
+        assert!(matches!(store.set_root([1u8; 32]), Err(SMTError::UnsupportedOperation)));
+        assert!(matches!(store.write_batch(Vec::new()), Err(SMTError::UnsupportedOperation)));
+        assert_eq!(inner.get(&[1u8; 32]).unwrap(), None);
+    }
+}
@@ -0,0 +1,310 @@
+use digest::Digest;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::SMTError,
+    signature::{SignatureScheme, Signer},
+    DefaultHasher, Hash,
+};
+
+const STH_DOMAIN: &[u8] = b"SimpleSparseMerkle/signed-tree-head/v1";
+
+/// Append-only Merkle log of published tree roots, hashed as in RFC 6962.
+/// Tree heads over it commit to the entire root history, and consistency
+/// proofs between two heads show the later one only appended to the earlier,
+/// so a rewritten history is caught by anyone holding an old head.
+#[derive(Default)]
+pub struct RootHistoryLog {
+    leaves: Vec<Hash>, // Leaf hashes of the logged roots
+}
+
+/// Size and Merkle root of the log at some point, plus when it was taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreeHead {
+    pub size: u64,
+    pub log_root: Hash,
+    pub timestamp: u64,
+}
+
+impl TreeHead {
+    /// Bytes the log operator signs.
+    pub fn message(&self) -> Vec<u8> {
+        [STH_DOMAIN, &self.size.to_be_bytes(), &self.log_root, &self.timestamp.to_be_bytes()].concat()
+    }
+
+    pub fn sign<S: SignatureScheme>(self, signer: &impl Signer<S>) -> SignedTreeHead<S> {
+        SignedTreeHead {
+            signature: signer.sign(&self.message()),
+            head: self,
+        }
+    }
+}
+
+/// A tree head signed by the log operator.
+#[derive(Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "S::Signature: Serialize",
+    deserialize = "S::Signature: Deserialize<'de>"
+))]
+pub struct SignedTreeHead<S: SignatureScheme> {
+    pub head: TreeHead,
+    pub signature: S::Signature,
+}
+
+impl<S: SignatureScheme> SignedTreeHead<S> {
+    pub fn verify(&self, operator: &S::PublicKey) -> bool {
+        S::verify(operator, &self.head.message(), &self.signature)
+    }
+}
+
+impl<S: SignatureScheme> Clone for SignedTreeHead<S> {
+    fn clone(&self) -> Self {
+        Self {
+            head: self.head,
+            signature: self.signature.clone(),
+        }
+    }
+}
+
+impl RootHistoryLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a published tree root and returns its index in the log.
+    pub fn append(&mut self, root: Hash) -> u64 {
+        self.leaves.push(leaf_hash(&root));
+        self.leaves.len() as u64 - 1
+    }
+
+    pub fn size(&self) -> u64 {
+        self.leaves.len() as u64
+    }
+
+    /// Head over the first `size` entries, or `None` past the end of the log.
+    pub fn tree_head(&self, size: u64, timestamp: u64) -> Option<TreeHead> {
+        let leaves = self.leaves.get(..size as usize)?;
+        Some(TreeHead {
+            size,
+            log_root: subtree_root(leaves),
+            timestamp,
+        })
+    }
+
+    /// Proof that the root at `index` is in the log as of `size` entries.
+    pub fn prove_inclusion(&self, index: u64, size: u64) -> Result<Vec<Hash>, SMTError> {
+        if index >= size || size > self.size() {
+            return Err(SMTError::UnknownVersion(index));
+        }
+        let mut proof = Vec::new();
+        inclusion_path(index as usize, &self.leaves[..size as usize], &mut proof);
+        Ok(proof)
+    }
+
+    /// Consistency proof showing that `new` extends `old` without rewriting
+    /// any of the roots `old` covers.
+    pub fn prove_root_included(&self, old: &TreeHead, new: &TreeHead) -> Result<Vec<Hash>, SMTError> {
+        if old.size > new.size || new.size > self.size() {
+            return Err(SMTError::UnknownVersion(new.size));
+        }
+        let mut proof = Vec::new();
+        if old.size > 0 {
+            consistency_path(old.size as usize, &self.leaves[..new.size as usize], true, &mut proof);
+        }
+        Ok(proof)
+    }
+}
+
+/// Checks that `root` is entry `index` of the log under `head`.
+pub fn verify_root_inclusion(head: &TreeHead, index: u64, root: &Hash, proof: &[Hash]) -> bool {
+    if index >= head.size {
+        return false;
+    }
+    let (mut fn_, mut sn) = (index, head.size - 1);
+    let mut r = leaf_hash(root);
+    for p in proof {
+        if sn == 0 {
+            return false;
+        }
+        if fn_ & 1 == 1 || fn_ == sn {
+            r = node_hash(p, &r);
+            while fn_ & 1 == 0 && fn_ != 0 {
+                fn_ >>= 1;
+                sn >>= 1;
+            }
+        } else {
+            r = node_hash(&r, p);
+        }
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    sn == 0 && r == head.log_root
+}
+
+/// Checks a consistency proof between two heads of the same log (RFC 9162,
+/// section 2.1.4.2).
+pub fn verify_root_included(old: &TreeHead, new: &TreeHead, proof: &[Hash]) -> bool {
+    if old.size > new.size {
+        return false;
+    }
+    if old.size == new.size {
+        return proof.is_empty() && old.log_root == new.log_root;
+    }
+    if old.size == 0 {
+        return proof.is_empty();
+    }
+    if proof.is_empty() {
+        return false;
+    }
+
+    let mut path = Vec::with_capacity(proof.len() + 1);
+    if old.size.is_power_of_two() {
+        path.push(old.log_root);
+    }
+    path.extend_from_slice(proof);
+
+    let (mut fn_, mut sn) = (old.size - 1, new.size - 1);
+    while fn_ & 1 == 1 {
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    let (mut fr, mut sr) = (path[0], path[0]);
+    for c in &path[1..] {
+        if sn == 0 {
+            return false;
+        }
+        if fn_ & 1 == 1 || fn_ == sn {
+            fr = node_hash(c, &fr);
+            sr = node_hash(c, &sr);
+            while fn_ & 1 == 0 && fn_ != 0 {
+                fn_ >>= 1;
+                sn >>= 1;
+            }
+        } else {
+            sr = node_hash(&sr, c);
+        }
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    fr == old.log_root && sr == new.log_root && sn == 0
+}
+
+fn leaf_hash(root: &Hash) -> Hash {
+    DefaultHasher::new().chain_update([0u8]).chain_update(root).finalize().into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    DefaultHasher::new()
+        .chain_update([1u8])
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+/// Largest power of two strictly below `n`, for `n > 1`.
+fn split_point(n: usize) -> usize {
+    (n - 1).next_power_of_two() / if (n - 1).is_power_of_two() { 1 } else { 2 }
+}
+
+fn subtree_root(leaves: &[Hash]) -> Hash {
+    match leaves.len() {
+        0 => DefaultHasher::digest([]).into(),
+        1 => leaves[0],
+        n => {
+            let k = split_point(n);
+            node_hash(&subtree_root(&leaves[..k]), &subtree_root(&leaves[k..]))
+        }
+    }
+}
+
+fn inclusion_path(index: usize, leaves: &[Hash], proof: &mut Vec<Hash>) {
+    if leaves.len() <= 1 {
+        return;
+    }
+    let k = split_point(leaves.len());
+    if index < k {
+        inclusion_path(index, &leaves[..k], proof);
+        proof.push(subtree_root(&leaves[k..]));
+    } else {
+        inclusion_path(index - k, &leaves[k..], proof);
+        proof.push(subtree_root(&leaves[..k]));
+    }
+}
+
+fn consistency_path(old_size: usize, leaves: &[Hash], complete: bool, proof: &mut Vec<Hash>) {
+    if old_size == leaves.len() {
+        if !complete {
+            proof.push(subtree_root(leaves));
+        }
+        return;
+    }
+    let k = split_point(leaves.len());
+    if old_size <= k {
+        consistency_path(old_size, &leaves[..k], complete, proof);
+        proof.push(subtree_root(&leaves[k..]));
+    } else {
+        consistency_path(old_size - k, &leaves[k..], false, proof);
+        proof.push(subtree_root(&leaves[..k]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(size: u8) -> RootHistoryLog {
+        let mut log = RootHistoryLog::new();
+        for i in 0..size {
+            log.append([i; 32]);
+        }
+        log
+    }
+
+    #[test]
+    fn test_split_point() {
+        assert_eq!(split_point(2), 1);
+        assert_eq!(split_point(3), 2);
+        assert_eq!(split_point(4), 2);
+        assert_eq!(split_point(5), 4);
+        assert_eq!(split_point(9), 8);
+    }
+
+    #[test]
+    fn test_every_head_is_consistent_with_every_later_one() {
+        let log = log(9);
+        for new_size in 0..=9 {
+            let new = log.tree_head(new_size, 0).unwrap();
+            for old_size in 0..=new_size {
+                let old = log.tree_head(old_size, 0).unwrap();
+                let proof = log.prove_root_included(&old, &new).unwrap();
+                assert!(verify_root_included(&old, &new, &proof), "{} -> {}", old_size, new_size);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rewritten_history_is_detected() {
+        let honest = log(7);
+        let old = honest.tree_head(4, 0).unwrap();
+
+        let mut rewritten = RootHistoryLog::new();
+        for i in [0u8, 1, 9, 3, 4, 5, 6] {
+            rewritten.append([i; 32]);
+        }
+        let new = rewritten.tree_head(7, 0).unwrap();
+        let proof = rewritten.prove_root_included(&old, &new).unwrap();
+        assert!(!verify_root_included(&old, &new, &proof));
+    }
+
+    #[test]
+    fn test_root_inclusion() {
+        let log = log(6);
+        let head = log.tree_head(6, 0).unwrap();
+        for index in 0..6u8 {
+            let proof = log.prove_inclusion(index as u64, 6).unwrap();
+            assert!(verify_root_inclusion(&head, index as u64, &[index; 32], &proof));
+            assert!(!verify_root_inclusion(&head, index as u64, &[99u8; 32], &proof));
+        }
+    }
+}
@@ -0,0 +1,288 @@
+//! Persistent record of every root a tree committed, for auditors listing
+//! historical commitments. Unlike `RootHistoryLog`, which proves a published
+//! history append-only, this only keeps it, in the tree's own store.
+
+use digest::Digest;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::{Clock, SystemClock},
+    error::SMTError,
+    kv_store::{KVStore, Scan, TreeWriteBatch},
+    DefaultHasher, Hash,
+};
+
+const LOG_DOMAIN: &[u8] = b"SimpleSparseMerkle/root-log/v1";
+
+/// Length of an encoded `RootEntry`.
+const ENTRY_LEN: usize = 8 + 32 + 8;
+
+/// One committed root. Versions count commits from 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RootEntry {
+    pub version: u64,
+    pub root: Hash,
+    pub timestamp: u64,
+}
+
+impl RootEntry {
+    fn to_bytes(self) -> Vec<u8> {
+        [&self.version.to_be_bytes()[..], &self.root, &self.timestamp.to_be_bytes()].concat()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != ENTRY_LEN {
+            return None;
+        }
+        Some(Self {
+            version: u64::from_be_bytes(bytes[..8].try_into().ok()?),
+            root: bytes[8..40].try_into().ok()?,
+            timestamp: u64::from_be_bytes(bytes[40..].try_into().ok()?),
+        })
+    }
+}
+
+/// Key of a log record, hashed under its own domain so it cannot meet a leaf
+/// key or an account record.
+fn log_key(kind: &[u8], id: &[u8]) -> Hash {
+    DefaultHasher::new().chain_update(LOG_DOMAIN).chain_update(kind).chain_update(id).finalize().into()
+}
+
+fn entry_key(version: u64) -> Hash {
+    log_key(b"entry", &version.to_be_bytes())
+}
+
+fn index_key(root: &Hash) -> Hash {
+    log_key(b"root", root)
+}
+
+fn head_key() -> Hash {
+    log_key(b"head", &[])
+}
+
+/// Store wrapper that appends each root recorded through it to a log kept in
+/// the wrapped store, in the same batch as the nodes behind the root. Build
+/// the tree over it and read the log back through `SparseMerkleTree::store`.
+///
+/// Every commit is logged, including ones that leave the root as it was.
+pub struct RootLog<S: KVStore, C: Clock = SystemClock> {
+    inner: S,
+    clock: C,
+    len: u64,
+}
+
+impl<S: KVStore> RootLog<S> {
+    /// Continues the log already in `store`, if any.
+    pub fn open(store: S) -> Result<Self, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        Self::open_with_clock(store, SystemClock)
+    }
+}
+
+impl<S: KVStore, C: Clock> RootLog<S, C> {
+    /// Like `open`, timestamping entries with `clock`.
+    pub fn open_with_clock(store: S, clock: C) -> Result<Self, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let len = match store.get(&head_key())? {
+            Some(bytes) => u64::from_be_bytes(bytes.as_slice().try_into().map_err(|_| SMTError::InvalidEncoding)?),
+            None => 0,
+        };
+        Ok(Self { inner: store, clock, len })
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Number of logged roots.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn entry(&self, version: u64) -> Result<Option<RootEntry>, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        if version >= self.len {
+            return Ok(None);
+        }
+        let bytes = self.inner.get(&entry_key(version))?.ok_or(SMTError::UnknownVersion(version))?;
+        RootEntry::from_bytes(&bytes).map(Some).ok_or(SMTError::InvalidEncoding)
+    }
+
+    /// Every logged root, oldest first.
+    pub fn roots(&self) -> Result<Vec<RootEntry>, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        (0..self.len).map(|version| self.entry(version)?.ok_or(SMTError::UnknownVersion(version))).collect()
+    }
+
+    pub fn latest(&self) -> Result<Option<RootEntry>, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        match self.len {
+            0 => Ok(None),
+            len => self.entry(len - 1),
+        }
+    }
+
+    /// The first commit of `root`.
+    pub fn find(&self, root: &Hash) -> Result<Option<RootEntry>, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        match self.inner.get(&index_key(root))? {
+            Some(bytes) => self.entry(u64::from_be_bytes(bytes.as_slice().try_into().map_err(|_| SMTError::InvalidEncoding)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Records logging `root` as the next version.
+    fn log_writes(&self, root: &Hash) -> Result<Vec<(Hash, Vec<u8>)>, S::Error> {
+        let entry = RootEntry { version: self.len, root: *root, timestamp: self.clock.now() };
+        let mut writes = vec![
+            (entry_key(entry.version), entry.to_bytes()),
+            (head_key(), (self.len + 1).to_be_bytes().to_vec()),
+        ];
+        if self.inner.get(&index_key(root))?.is_none() {
+            writes.push((index_key(root), entry.version.to_be_bytes().to_vec()));
+        }
+        Ok(writes)
+    }
+}
+
+impl<S: KVStore, C: Clock> KVStore for RootLog<S, C> {
+    type Error = S::Error;
+
+    fn get(&self, key: &Hash) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.inner.get(key)
+    }
+
+    fn set(&mut self, key: Hash, value: Vec<u8>) -> Result<(), Self::Error> {
+        self.inner.set(key, value)
+    }
+
+    fn remove(&mut self, key: &Hash) -> Result<(), Self::Error> {
+        self.inner.remove(key)
+    }
+
+    fn get_node(&self, hash: &Hash) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.inner.get_node(hash)
+    }
+
+    fn set_node(&mut self, hash: Hash, node: Vec<u8>) -> Result<(), Self::Error> {
+        self.inner.set_node(hash, node)
+    }
+
+    fn remove_node(&mut self, hash: &Hash) -> Result<(), Self::Error> {
+        self.inner.remove_node(hash)
+    }
+
+    fn get_root(&self) -> Result<Option<Hash>, Self::Error> {
+        self.inner.get_root()
+    }
+
+    fn set_root(&mut self, root: Hash) -> Result<(), Self::Error> {
+        for (key, value) in self.log_writes(&root)? {
+            self.inner.set(key, value)?;
+        }
+        self.inner.set_root(root)?;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn commit_batch(&mut self, mut batch: TreeWriteBatch) -> Result<(), Self::Error> {
+        let Some(root) = batch.root() else {
+            return self.inner.commit_batch(batch);
+        };
+        for (key, value) in self.log_writes(&root)? {
+            batch.set(key, value);
+        }
+        self.inner.commit_batch(batch)?;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn write_batch(&mut self, writes: Vec<(Hash, Option<Vec<u8>>)>) -> Result<(), Self::Error> {
+        self.inner.write_batch(writes)
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Option<Scan<'_, Self::Error>> {
+        self.inner.scan_prefix(prefix)
+    }
+
+    fn scan_node_prefix(&self, prefix: &[u8]) -> Option<Scan<'_, Self::Error>> {
+        self.inner.scan_node_prefix(prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{kv_store::InMemoryKVStore, sparse_merkle_tree::SparseMerkleTree};
+
+    fn tree() -> SparseMerkleTree<RootLog<InMemoryKVStore>> {
+        SparseMerkleTree::new(RootLog::open(InMemoryKVStore::new()).unwrap())
+    }
+
+    #[test]
+    fn test_every_commit_is_logged() {
+        let mut smt = tree();
+        let empty = smt.root();
+        smt.update([1u8; 32], [10u8; 32]).unwrap();
+        let first = smt.root();
+        smt.update_batch(&[([2u8; 32], [20u8; 32])]).unwrap();
+        smt.delete([2u8; 32]).unwrap();
+
+        let log = smt.store();
+        let roots: Vec<Hash> = log.roots().unwrap().iter().map(|entry| entry.root).collect();
+        assert_eq!(roots.len(), 3);
+        assert_eq!(roots[0], first);
+        assert_eq!(roots[2], first);
+        assert_eq!(log.latest().unwrap().unwrap().version, 2);
+        assert_eq!(log.find(&first).unwrap().unwrap().version, 0);
+        assert_eq!(log.find(&empty).unwrap(), None);
+    }
+
+    #[test]
+    fn test_log_survives_reopen() {
+        let mut smt = tree();
+        smt.update([1u8; 32], [10u8; 32]).unwrap();
+        let store = smt.store().inner().clone();
+
+        let mut reopened = SparseMerkleTree::new(RootLog::open(store).unwrap());
+        assert_eq!(reopened.store().len(), 1);
+        reopened.update([2u8; 32], [20u8; 32]).unwrap();
+        let log = reopened.store();
+        assert_eq!(log.len(), 2);
+        assert_eq!(log.latest().unwrap().unwrap().root, reopened.root());
+    }
+
+    #[cfg(feature = "test-clock")]
+    #[test]
+    fn test_entries_are_timestamped() {
+        let clock = crate::clock::TestClock::new(1_000);
+        let store = RootLog::open_with_clock(InMemoryKVStore::new(), clock.clone()).unwrap();
+        let mut smt = SparseMerkleTree::new(store);
+        smt.update([1u8; 32], [10u8; 32]).unwrap();
+        clock.advance(5);
+        smt.update([2u8; 32], [20u8; 32]).unwrap();
+
+        let timestamps: Vec<u64> = smt.store().roots().unwrap().iter().map(|entry| entry.timestamp).collect();
+        assert_eq!(timestamps, vec![1_000, 1_005]);
+    }
+}
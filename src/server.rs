@@ -0,0 +1,171 @@
+//! gRPC service over a single tree, following `proto/smt_service.proto`, so
+//! the crate can run as a standalone verifiable key-value store. Proofs are
+//! returned as the `MerkleProof` message of `proto/smt.proto`; clients check
+//! them against the root returned alongside.
+//!
+//! Reads share the tree, writes take it exclusively, so every response is
+//! consistent with the root it carries.
+
+#![allow(clippy::result_large_err)] // Handlers have to return the large tonic::Status
+
+use tokio::sync::RwLock;
+use tonic::{Request, Response, Status};
+
+use crate::{error::SMTError, kv_store::KVStore, proof::MerkleProof, sparse_merkle_tree::SparseMerkleTree, Hash};
+
+/// Code generated from `proto/smt_service.proto`.
+pub mod pb {
+    tonic::include_proto!("simple_sparse_merkle.v1");
+}
+
+use pb::sparse_merkle_tree_server::{SparseMerkleTree as SparseMerkleTreeRpc, SparseMerkleTreeServer};
+
+pub struct TreeService<S: KVStore> {
+    tree: RwLock<SparseMerkleTree<S>>,
+}
+
+impl<S: KVStore> TreeService<S> {
+    pub fn new(tree: SparseMerkleTree<S>) -> Self {
+        Self { tree: RwLock::new(tree) }
+    }
+
+    pub fn into_tree(self) -> SparseMerkleTree<S> {
+        self.tree.into_inner()
+    }
+}
+
+impl<S> TreeService<S>
+where
+    S: KVStore + Send + Sync + 'static,
+    SMTError: From<S::Error>,
+{
+    /// The service ready to add to a `tonic::transport::Server`.
+    pub fn into_server(self) -> SparseMerkleTreeServer<Self> {
+        SparseMerkleTreeServer::new(self)
+    }
+}
+
+impl From<MerkleProof> for pb::MerkleProof {
+    fn from(proof: MerkleProof) -> Self {
+        Self { side_nodes: proof.side_nodes.iter().map(|node| node.to_vec()).collect() }
+    }
+}
+
+impl TryFrom<pb::MerkleProof> for MerkleProof {
+    type Error = SMTError;
+
+    fn try_from(proof: pb::MerkleProof) -> Result<Self, SMTError> {
+        let side_nodes = proof.side_nodes.iter().map(|node| node.as_slice().try_into()).collect::<Result<_, _>>();
+        Ok(Self { side_nodes: side_nodes.map_err(|_| SMTError::InvalidEncoding)? })
+    }
+}
+
+fn root_message(root: Hash) -> pb::Root {
+    pb::Root { hash: root.to_vec() }
+}
+
+fn hash_field(bytes: &[u8], field: &str) -> Result<Hash, Status> {
+    bytes.try_into().map_err(|_| Status::invalid_argument(format!("{} must be 32 bytes, got {}", field, bytes.len())))
+}
+
+fn entry_fields(entry: &pb::Entry) -> Result<(Hash, Hash), Status> {
+    Ok((hash_field(&entry.key, "key")?, hash_field(&entry.value, "value")?))
+}
+
+fn internal(error: SMTError) -> Status {
+    Status::internal(error.to_string())
+}
+
+#[tonic::async_trait]
+impl<S> SparseMerkleTreeRpc for TreeService<S>
+where
+    S: KVStore + Send + Sync + 'static,
+    SMTError: From<S::Error>,
+{
+    async fn get(&self, request: Request<pb::GetRequest>) -> Result<Response<pb::GetResponse>, Status> {
+        let key = hash_field(&request.get_ref().key, "key")?;
+        let value = self.tree.read().await.get(key).map_err(internal)?;
+        Ok(Response::new(pb::GetResponse { value: value.map(|value| value.to_vec()) }))
+    }
+
+    async fn put(&self, request: Request<pb::PutRequest>) -> Result<Response<pb::Root>, Status> {
+        let entry = request.get_ref().entry.as_ref().ok_or_else(|| Status::invalid_argument("entry is missing"))?;
+        let (key, value) = entry_fields(entry)?;
+        let mut tree = self.tree.write().await;
+        tree.update(key, value).map_err(internal)?;
+        Ok(Response::new(root_message(tree.root())))
+    }
+
+    async fn get_proof(&self, request: Request<pb::GetProofRequest>) -> Result<Response<pb::GetProofResponse>, Status> {
+        let key = hash_field(&request.get_ref().key, "key")?;
+        let tree = self.tree.read().await;
+        let value = tree.get(key).map_err(internal)?;
+        let proof = tree.get_proof(key).map_err(internal)?;
+        Ok(Response::new(pb::GetProofResponse {
+            root: Some(root_message(tree.root())),
+            value: value.map(|value| value.to_vec()),
+            proof: Some(proof.into()),
+        }))
+    }
+
+    async fn get_root(&self, _: Request<pb::GetRootRequest>) -> Result<Response<pb::Root>, Status> {
+        Ok(Response::new(root_message(self.tree.read().await.root())))
+    }
+
+    async fn batch_update(&self, request: Request<pb::BatchUpdateRequest>) -> Result<Response<pb::Root>, Status> {
+        let entries = request.get_ref().entries.iter().map(entry_fields).collect::<Result<Vec<_>, _>>()?;
+        let root = self.tree.write().await.update_batch(&entries).map_err(internal)?;
+        Ok(Response::new(root_message(root)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_store::InMemoryKVStore;
+
+    fn service() -> TreeService<InMemoryKVStore> {
+        TreeService::new(SparseMerkleTree::new(InMemoryKVStore::new()))
+    }
+
+    fn entry(key: Hash, value: Hash) -> pb::Entry {
+        pb::Entry { key: key.to_vec(), value: value.to_vec() }
+    }
+
+    #[tokio::test]
+    async fn test_put_then_prove() {
+        let service = service();
+        let put = service.put(Request::new(pb::PutRequest { entry: Some(entry([1u8; 32], [2u8; 32])) })).await.unwrap();
+        let got = service.get(Request::new(pb::GetRequest { key: vec![1u8; 32] })).await.unwrap();
+        assert_eq!(got.get_ref().value, Some(vec![2u8; 32]));
+
+        let response = service.get_proof(Request::new(pb::GetProofRequest { key: vec![1u8; 32] })).await.unwrap().into_inner();
+        let root: Hash = response.root.unwrap().hash.try_into().unwrap();
+        assert_eq!(root.to_vec(), put.get_ref().hash);
+        let proof = MerkleProof::try_from(response.proof.unwrap()).unwrap();
+        assert!(proof.verify(&root, &[1u8; 32], &[2u8; 32]));
+    }
+
+    #[tokio::test]
+    async fn test_batch_update_matches_tree() {
+        let service = service();
+        let entries = vec![entry([1u8; 32], [10u8; 32]), entry([2u8; 32], [20u8; 32])];
+        let root = service.batch_update(Request::new(pb::BatchUpdateRequest { entries })).await.unwrap();
+        let current = service.get_root(Request::new(pb::GetRootRequest {})).await.unwrap();
+        assert_eq!(root.get_ref(), current.get_ref());
+
+        let mut tree = SparseMerkleTree::new(InMemoryKVStore::new());
+        tree.update_batch(&[([1u8; 32], [10u8; 32]), ([2u8; 32], [20u8; 32])]).unwrap();
+        assert_eq!(current.get_ref().hash, tree.root().to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_rejects_malformed_keys() {
+        let service = service();
+        let status = service.get(Request::new(pb::GetRequest { key: vec![1u8; 31] })).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        let status = service.put(Request::new(pb::PutRequest { entry: None })).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(service.into_tree().root(), SparseMerkleTree::new(InMemoryKVStore::new()).root());
+    }
+}
@@ -0,0 +1,43 @@
+/// A signature scheme the crate can check signatures under, without tying
+/// it to a particular curve or library.
+pub trait SignatureScheme {
+    type PublicKey: Clone + Eq;
+    type Signature: Clone;
+
+    fn verify(public_key: &Self::PublicKey, message: &[u8], signature: &Self::Signature) -> bool;
+}
+
+/// Holder of a private key for scheme `S`.
+pub trait Signer<S: SignatureScheme> {
+    fn public_key(&self) -> S::PublicKey;
+    fn sign(&self, message: &[u8]) -> S::Signature;
+}
+
+/// Ed25519 with keys and signatures as raw bytes. Verification is strict, so
+/// a signature has a single valid encoding.
+#[cfg(feature = "ed25519")]
+pub struct Ed25519;
+
+#[cfg(feature = "ed25519")]
+impl SignatureScheme for Ed25519 {
+    type PublicKey = [u8; 32];
+    type Signature = [u8; 64];
+
+    fn verify(public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+        let Ok(key) = ed25519_dalek::VerifyingKey::from_bytes(public_key) else {
+            return false;
+        };
+        key.verify_strict(message, &ed25519_dalek::Signature::from_bytes(signature)).is_ok()
+    }
+}
+
+#[cfg(feature = "ed25519")]
+impl Signer<Ed25519> for ed25519_dalek::SigningKey {
+    fn public_key(&self) -> [u8; 32] {
+        self.verifying_key().to_bytes()
+    }
+
+    fn sign(&self, message: &[u8]) -> [u8; 64] {
+        ed25519_dalek::Signer::sign(self, message).to_bytes()
+    }
+}
@@ -0,0 +1,125 @@
+use crate::{
+    error::SMTError,
+    iter::Leaves,
+    kv_store::KVStore,
+    proof::{MerkleProof, MultiProof, NonMembershipProof},
+    sparse_merkle_tree::SparseMerkleTree,
+    tree_hasher::TreeDigest,
+    DefaultHasher, Hash,
+};
+
+/// Read-only view of a tree frozen at the root it had when taken. Later writes
+/// to the live tree are not visible through it.
+///
+/// Taking a snapshot clones the store, so it is only cheap for stores whose
+/// clones share data, such as the copy-on-write `InMemoryKVStore`.
+pub struct TreeSnapshot<S: KVStore, D: TreeDigest = DefaultHasher> {
+    tree: SparseMerkleTree<S, D>,
+}
+
+impl<S: KVStore, D: TreeDigest> TreeSnapshot<S, D> {
+    pub fn root(&self) -> Hash {
+        self.tree.root()
+    }
+
+    pub fn get(&self, key: Hash) -> Result<Option<Hash>, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        self.tree.get(key)
+    }
+
+    pub fn get_proof(&self, key: Hash) -> Result<MerkleProof, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        self.tree.get_proof(key)
+    }
+
+    pub fn get_multiproof(&self, keys: &[Hash]) -> Result<MultiProof, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        self.tree.get_multiproof(keys)
+    }
+
+    pub fn get_non_membership_proof(&self, key: Hash) -> Result<Option<NonMembershipProof>, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        self.tree.get_non_membership_proof(key)
+    }
+
+    pub fn verify_proof(&self, key: Hash, value: Hash, proof: &MerkleProof) -> Result<(), SMTError> {
+        self.tree.verify_proof(key, value, proof)
+    }
+}
+
+impl<S: KVStore, D: TreeDigest> TreeSnapshot<S, D>
+where
+    SMTError: From<S::Error>,
+{
+    pub fn iter(&self) -> Leaves<'_, S, D> {
+        self.tree.iter()
+    }
+}
+
+impl<S: KVStore + Clone, D: TreeDigest> Clone for TreeSnapshot<S, D> {
+    fn clone(&self) -> Self {
+        Self {
+            tree: self.tree.clone(),
+        }
+    }
+}
+
+impl<S: KVStore + Clone, D: TreeDigest> SparseMerkleTree<S, D> {
+    /// Freezes the current state for readers while writers carry on.
+    pub fn snapshot(&self) -> TreeSnapshot<S, D> {
+        TreeSnapshot {
+            tree: SparseMerkleTree {
+                hasher: self.hasher.clone(),
+                store: self.store.clone(),
+                root: self.root,
+                depth: self.depth,
+                observers: Vec::new(),
+                #[cfg(feature = "lru")]
+                cache: self.cache.clone(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_store::InMemoryKVStore;
+
+    #[test]
+    fn test_snapshot_ignores_later_writes() {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        let key: Hash = [1u8; 32];
+        smt.update(key, [10u8; 32]).unwrap();
+
+        let snapshot = smt.snapshot();
+        smt.update(key, [11u8; 32]).unwrap();
+        smt.update([2u8; 32], [20u8; 32]).unwrap();
+
+        assert_ne!(snapshot.root(), smt.root());
+        assert_eq!(snapshot.get(key).unwrap(), Some([10u8; 32]));
+        assert_eq!(snapshot.get([2u8; 32]).unwrap(), None);
+        let proof = snapshot.get_proof(key).unwrap();
+        assert!(proof.verify(&snapshot.root(), &key, &[10u8; 32]));
+    }
+
+    #[test]
+    fn test_snapshot_can_be_read_from_another_thread() {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        smt.update([1u8; 32], [10u8; 32]).unwrap();
+        let snapshot = smt.snapshot();
+
+        let reader = std::thread::spawn(move || snapshot.get([1u8; 32]).unwrap());
+        smt.update([1u8; 32], [11u8; 32]).unwrap();
+
+        assert_eq!(reader.join().unwrap(), Some([10u8; 32]));
+    }
+}
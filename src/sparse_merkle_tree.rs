@@ -1,126 +1,866 @@
-use crate::{kv_store::KVStore, proof::MerkleProof, tree_hasher::TreeHasher, DefaultHasher, Hash};
-use tracing::{debug, error, info, warn};
+use crate::{error::SMTError, hashers::HasherId, hex::HexFmt, kv_store::{KVStore, TreeWriteBatch}, node::{decode_internal, decode_leaf, encode_internal, encode_leaf}, observer::TreeObserver, op::Op, proof::{KeyProof, MerkleProof, MultiProof, NonMembershipProof}, spec::TreeSpec, tree_hasher::{TreeDigest, TreeHasher}, DefaultHasher, Hash};
+use std::collections::BTreeMap;
+#[cfg(feature = "lru")]
+use crate::node_cache::{CacheConfig, NodeCache};
+use std::sync::Arc;
+use tracing::{debug, field, info, instrument, warn, Span};
 
-pub struct SparseMerkleTree<S: KVStore> {
-    pub(crate) hasher: TreeHasher<DefaultHasher>,
+/// Sparse Merkle tree over 256-bit keys, generic over the backing store and
+/// the hash function. `D` defaults to `DefaultHasher`.
+///
+/// Leaves sit `depth` levels below the root, 256 unless changed with
+/// `with_depth`, and only the first `depth` bits of a key pick its leaf.
+pub struct SparseMerkleTree<S: KVStore, D: TreeDigest = DefaultHasher> {
+    pub(crate) hasher: TreeHasher<D>,
     pub(crate) store: S,
     pub(crate) root: Hash,
+    pub(crate) depth: usize,
+    pub(crate) observers: Vec<Arc<dyn TreeObserver>>,
+    #[cfg(feature = "lru")]
+    pub(crate) cache: Option<Arc<NodeCache>>, // Shared by clones and snapshots
 }
 
+pub use crate::tree_hasher::DEFAULT_DEPTH;
+
 impl<S: KVStore> SparseMerkleTree<S> {
     pub fn new(store: S) -> Self {
-        let hasher = TreeHasher::<DefaultHasher>::new();
-        let root = [0u8; 32];
+        Self::with_hasher(store)
+    }
+
+    /// Reopens a tree over a store that already holds one, starting from the
+    /// root the store last recorded. Falls back to an empty tree.
+    pub fn open(store: S) -> Result<Self, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        Self::open_with_hasher(store)
+    }
+}
+
+impl<S: KVStore, D: HasherId> SparseMerkleTree<S, D> {
+    /// Describes the hashing scheme this tree uses.
+    pub fn spec(&self) -> TreeSpec {
+        TreeSpec {
+            hasher_id: D::HASHER_ID.to_string(),
+            depth: self.depth as u16,
+            empty_root: self.hasher.empty(self.depth),
+            key_domain: self.hasher.key_domain(),
+            ..TreeSpec::default()
+        }
+    }
+}
+
+impl<S: KVStore, D: TreeDigest> SparseMerkleTree<S, D> {
+    /// Creates an empty tree hashing with `D` instead of the default hasher.
+    pub fn with_hasher(store: S) -> Self {
+        let hasher = TreeHasher::<D>::new();
+        let root = hasher.empty(DEFAULT_DEPTH);
         info!("Created new Sparse Merkle Tree");
         Self {
             hasher,
             store,
             root,
+            depth: DEFAULT_DEPTH,
+            observers: Vec::new(),
+            #[cfg(feature = "lru")]
+            cache: None,
         }
     }
 
-    pub fn update(&mut self, key: Hash, value: Hash) -> Result<(), S::Error> {
-        info!("Updating tree with key {:?}, value {:?}", key, value);
+    /// Like `open`, hashing with `D` instead of the default hasher.
+    pub fn open_with_hasher(store: S) -> Result<Self, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let hasher = TreeHasher::<D>::new();
+        let root = store.get_root()?.unwrap_or(hasher.empty(DEFAULT_DEPTH));
+        info!("Opened Sparse Merkle Tree with root {}", HexFmt(&root));
+        Ok(Self {
+            hasher,
+            store,
+            root,
+            depth: DEFAULT_DEPTH,
+            observers: Vec::new(),
+            #[cfg(feature = "lru")]
+            cache: None,
+        })
+    }
+
+    /// Shortens the tree to `depth` levels, so keys that only use their first
+    /// `depth` bits (a 20-byte address is 160) cost `depth` hashes per update
+    /// instead of 256. Keys must be zero past that many bits; writes of any
+    /// other key fail with `KeyBeyondDepth`. Set this before
+    /// the first write, and with the same value whenever the store is
+    /// reopened: trees of different depths have different roots.
+    ///
+    /// Panics if `depth` is 0 or above 256.
+    pub fn with_depth(mut self, depth: usize) -> Self {
+        assert!((1..=DEFAULT_DEPTH).contains(&depth), "tree depth must be 1 to 256");
+        if self.root == self.hasher.empty(self.depth) {
+            self.root = self.hasher.empty(depth);
+        }
+        self.depth = depth;
+        self
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Keeps the most recently read nodes in memory, within `config`'s
+    /// limits, so repeated reads and proofs near the root skip the store.
+    #[cfg(feature = "lru")]
+    pub fn with_cache(mut self, config: CacheConfig) -> Self {
+        self.cache = Some(Arc::new(NodeCache::new(config)));
+        self
+    }
+
+    #[cfg(feature = "lru")]
+    pub fn cache(&self) -> Option<&NodeCache> {
+        self.cache.as_deref()
+    }
+
+    /// Registers an observer to be told about every later change to the tree.
+    pub fn add_observer(&mut self, observer: Arc<dyn TreeObserver>) {
+        self.observers.push(observer);
+    }
+
+    #[instrument(name = "smt.update", skip_all, fields(key = %HexFmt(&key), root = field::Empty))]
+    pub fn update(&mut self, key: Hash, value: Hash) -> Result<(), SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        self.check_key(&key)?;
+        let old_root = self.root;
+        let side_nodes = self.side_nodes_for(&key)?;
+
+        let mut batch = TreeWriteBatch::new();
         let leaf_hash = self.hasher.digest_leaf(&key, &value);
-        self.store.set(key, value.to_vec())?;
-        debug!("Set key-value pair in store");
+        batch.set_node(leaf_hash, encode_leaf(&key, &value));
 
         let mut current = leaf_hash;
-        for i in (0..256).rev() {
-            let bit = (key[i / 8] >> (7 - (i % 8))) & 1;
-            let sibling = self.hasher.zero_hash();
-            let (left, right) = if bit == 0 {
+        for i in (0..self.depth).rev() {
+            let sibling = side_nodes[i];
+            let (left, right) = if get_bit(&key, i) == 0 {
                 (current, sibling)
             } else {
                 (sibling, current)
             };
             current = self.hasher.digest_node(&left, &right);
-            self.store.set(current, [left, right].concat())?;
-            debug!("Updated node at depth {}, current hash: {:?}", i, current);
+            batch.set_node(current, encode_internal(&left, &right));
         }
 
+        batch.set_root(current);
+        self.commit(batch)?;
         self.root = current;
-        info!("Updated tree with key {:?}, new root: {:?}", key, self.root);
+        record_root(&self.root);
+        for observer in &self.observers {
+            observer.on_update(&key, &value);
+            observer.on_commit(&old_root, &self.root);
+        }
         Ok(())
     }
 
-    pub fn get(&self, key: Hash) -> Result<Option<Hash>, S::Error> {
-        if self.root == [0u8; 32] {
-            return Ok(None);
+    /// Applies many writes at once, hashing each node shared by several of the
+    /// updated paths only once. Later entries win when a key repeats.
+    #[instrument(name = "smt.update_batch", skip_all, fields(entries = entries.len(), root = field::Empty))]
+    pub fn update_batch(&mut self, entries: &[(Hash, Hash)]) -> Result<Hash, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        for (key, _) in entries {
+            self.check_key(key)?;
         }
-        self.store
-            .get(&key)
-            .map(|opt| opt.and_then(|v| v.try_into().ok()))
+        let sorted: Vec<(Hash, Option<Hash>)> = entries
+            .iter()
+            .map(|(key, value)| (*key, Some(*value)))
+            .collect::<BTreeMap<_, _>>()
+            .into_iter()
+            .collect();
+
+        self.apply_sorted(&sorted)?;
+        record_root(&self.root);
+        Ok(self.root)
     }
 
-    pub fn get_proof(&self, key: Hash) -> Result<MerkleProof, S::Error> {
-        let mut current = self.root;
-        let mut side_nodes = Vec::new();
+    /// Applies puts and deletes together in one pass, like `update_batch`.
+    /// Later operations win when a key repeats.
+    #[instrument(name = "smt.apply", skip_all, fields(ops = ops.len(), root = field::Empty))]
+    pub fn apply(&mut self, ops: &[Op]) -> Result<Hash, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let mut sorted = BTreeMap::new();
+        for op in ops {
+            self.check_key(op.key())?;
+            match op {
+                Op::Put(key, value) => sorted.insert(*key, Some(*value)),
+                Op::Delete(key) => sorted.insert(*key, None),
+            };
+        }
+        let mut sorted: Vec<(Hash, Option<Hash>)> = sorted.into_iter().collect();
+        // Deleting a missing key changes nothing and is not reported.
+        let mut absent = Vec::new();
+        for (key, value) in &sorted {
+            if value.is_none() && self.get(*key)?.is_none() {
+                absent.push(*key);
+            }
+        }
+        sorted.retain(|(key, value)| value.is_some() || !absent.contains(key));
+
+        self.apply_sorted(&sorted)?;
+        record_root(&self.root);
+        Ok(self.root)
+    }
+
+    /// Fails unless `key` is zero past the tree's depth. Other keys would
+    /// land on the leaf of a key sharing their first `depth` bits.
+    pub(crate) fn check_key(&self, key: &Hash) -> Result<(), SMTError> {
+        match (self.depth..DEFAULT_DEPTH).any(|bit| get_bit(key, bit) == 1) {
+            true => Err(SMTError::KeyBeyondDepth { key: *key, depth: self.depth }),
+            false => Ok(()),
+        }
+    }
+
+    fn apply_sorted(&mut self, sorted: &[(Hash, Option<Hash>)]) -> Result<(), SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let old_root = self.root;
+        let mut batch = TreeWriteBatch::new();
+        let root = self.update_subtree(self.root, 0, sorted, &mut batch)?;
+        batch.set_root(root);
+        self.commit(batch)?;
+        self.root = root;
+        self.notify_batch(old_root, sorted);
+        Ok(())
+    }
+
+    /// Applies `sorted` like `apply_sorted`, but to the tree under `root`
+    /// rather than the current one, and returns its new root. Neither the
+    /// tree's root nor the one recorded in the store changes, and observers
+    /// are not told; this is for other trees kept in the same store.
+    pub(crate) fn apply_at(&mut self, root: Hash, sorted: &[(Hash, Option<Hash>)]) -> Result<Hash, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let mut batch = TreeWriteBatch::new();
+        let root = self.update_subtree(root, 0, sorted, &mut batch)?;
+        self.commit(batch)?;
+        Ok(root)
+    }
+
+    /// Reports already-applied `writes` (`None` for a delete) and the move
+    /// from `old_root` to the current root to every observer.
+    pub(crate) fn notify_batch(&self, old_root: Hash, writes: &[(Hash, Option<Hash>)]) {
+        for observer in &self.observers {
+            for (key, value) in writes {
+                match value {
+                    Some(value) => observer.on_update(key, value),
+                    None => observer.on_delete(key),
+                }
+            }
+            observer.on_commit(&old_root, &self.root);
+        }
+    }
+
+    /// Rewrites the subtree rooted at `node` (at `depth`) with `entries`, which
+    /// must be sorted by key, unique, and all fall under this subtree. A `None`
+    /// value deletes the key; subtrees left empty go back to their default hash.
+    fn update_subtree(
+        &self,
+        node: Hash,
+        depth: usize,
+        entries: &[(Hash, Option<Hash>)],
+        batch: &mut TreeWriteBatch,
+    ) -> Result<Hash, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        if entries.is_empty() {
+            return Ok(node);
+        }
+        if depth == self.depth {
+            return Ok(match entries[0] {
+                (key, Some(value)) => {
+                    let leaf_hash = self.hasher.digest_leaf(&key, &value);
+                    batch.set_node(leaf_hash, encode_leaf(&key, &value));
+                    leaf_hash
+                }
+                (_, None) => self.hasher.empty(0),
+            });
+        }
+
+        let (left, right) = self.get_children(&node, depth)?;
+        let split = entries.partition_point(|(key, _)| get_bit(key, depth) == 0);
+        let left = self.update_subtree(left, depth + 1, &entries[..split], batch)?;
+        let right = self.update_subtree(right, depth + 1, &entries[split..], batch)?;
+        let empty = self.hasher.empty(self.depth - depth - 1);
+        if left == empty && right == empty {
+            return Ok(self.hasher.empty(self.depth - depth));
+        }
+
+        let current = self.hasher.digest_node(&left, &right);
+        batch.set_node(current, encode_internal(&left, &right));
+        Ok(current)
+    }
+
+    /// Removes `key` from the tree. Subtrees left without any leaf go back to
+    /// their default hash, so the root ends up exactly as if the key had never
+    /// been inserted. Deleting a missing key is a no-op.
+    #[instrument(name = "smt.delete", skip_all, fields(key = %HexFmt(&key), root = field::Empty))]
+    pub fn delete(&mut self, key: Hash) -> Result<(), SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        if self.get(key)?.is_none() {
+            debug!("key not present, nothing to delete");
+            return Ok(());
+        }
+
+        let side_nodes = self.side_nodes_for(&key)?;
+        let mut batch = TreeWriteBatch::new();
+        let mut current = self.hasher.empty(0);
+        for i in (0..self.depth).rev() {
+            let sibling = side_nodes[i];
+            let empty = self.hasher.empty(self.depth - i - 1);
+            if current == empty && sibling == empty {
+                current = self.hasher.empty(self.depth - i); // Still inside an empty subtree
+                continue;
+            }
+            let (left, right) = if get_bit(&key, i) == 0 {
+                (current, sibling)
+            } else {
+                (sibling, current)
+            };
+            current = self.hasher.digest_node(&left, &right);
+            batch.set_node(current, encode_internal(&left, &right));
+        }
+
+        batch.set_root(current);
+        self.commit(batch)?;
+        let old_root = self.root;
+        self.root = current;
+        record_root(&self.root);
+        for observer in &self.observers {
+            observer.on_delete(&key);
+            observer.on_commit(&old_root, &self.root);
+        }
+        Ok(())
+    }
+
+    /// Marks the current state so later writes can be undone with `rollback`.
+    /// Writes still go to the store immediately; use `begin_batch` to keep
+    /// them out of the store until they are committed.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint { root: self.root }
+    }
+
+    /// Undoes every write made since `checkpoint` was taken.
+    pub fn rollback(&mut self, checkpoint: Checkpoint) -> Result<(), SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        self.revert_to(checkpoint.root)
+    }
+
+    /// Moves the tree back to `root`, which it must have had earlier. Only the
+    /// subtrees that differ are walked, to report the keys that change. Fails
+    /// with `MissingNode`, leaving the tree untouched, if the store no longer
+    /// holds part of `root`.
+    #[instrument(name = "smt.revert_to", skip_all, fields(from = %HexFmt(&self.root), root = %HexFmt(&root), changes = field::Empty))]
+    pub fn revert_to(&mut self, root: Hash) -> Result<(), SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let mut changes = Vec::new();
+        self.diff_leaves(self.root, root, 0, &mut changes)?;
+
+        let mut batch = TreeWriteBatch::new();
+        batch.set_root(root);
+        self.commit(batch)?;
+        let old_root = self.root;
+        self.root = root;
+        Span::current().record("changes", changes.len());
+
+        for observer in &self.observers {
+            for (key, value) in &changes {
+                match value {
+                    Some(value) => observer.on_update(key, value),
+                    None => observer.on_delete(key),
+                }
+            }
+            observer.on_commit(&old_root, &self.root);
+        }
+        Ok(())
+    }
+
+    /// Collects, for every key whose leaf differs between the subtrees rooted
+    /// at `current` and `target`, the value it has under `target`.
+    fn diff_leaves(&self, current: Hash, target: Hash, depth: usize, changes: &mut Vec<(Hash, Option<Hash>)>) -> Result<(), SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        if current == target {
+            return Ok(());
+        }
+        if depth == self.depth {
+            // A different key may have replaced the leaf in a shallow tree.
+            if !self.hasher.is_empty(&current) {
+                let (key, _) = self.read_leaf(&current)?;
+                changes.push((key, None));
+            }
+            if !self.hasher.is_empty(&target) {
+                let (key, value) = self.read_leaf(&target)?;
+                changes.retain(|(changed, _)| *changed != key);
+                changes.push((key, Some(value)));
+            }
+            return Ok(());
+        }
+
+        let (current_left, current_right) = self.read_node(&current)?;
+        let (target_left, target_right) = self.read_node(&target)?;
+        self.diff_leaves(current_left, target_left, depth + 1, changes)?;
+        self.diff_leaves(current_right, target_right, depth + 1, changes)
+    }
+
+    /// Collects the nodes (leaf preimages included) that only the subtree at
+    /// `old` holds into `stale`, and those only the subtree at `new` holds into
+    /// `fresh`. Subtree hashes pin down their position, so this only has to
+    /// walk where the two differ.
+    pub(crate) fn diff_nodes(&self, old: Hash, new: Hash, depth: usize, stale: &mut Vec<Hash>, fresh: &mut Vec<Hash>) -> Result<(), SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        if old == new {
+            return Ok(());
+        }
+        if !self.hasher.is_empty(&old) {
+            stale.push(old);
+        }
+        if !self.hasher.is_empty(&new) {
+            fresh.push(new);
+        }
+        if depth == self.depth {
+            return Ok(());
+        }
+
+        let (old_left, old_right) = self.read_node(&old)?;
+        let (new_left, new_right) = self.read_node(&new)?;
+        self.diff_nodes(old_left, new_left, depth + 1, stale, fresh)?;
+        self.diff_nodes(old_right, new_right, depth + 1, stale, fresh)
+    }
 
-        debug!("Generating proof for key {:?}", key);
-        debug!("Starting from root {:?}", current);
+    /// Like `get_children`, for a node whose height is not known: an empty
+    /// subtree of any height has two empty children one level down.
+    pub(crate) fn read_node(&self, node: &Hash) -> Result<(Hash, Hash), SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        if let Some(height) = self.hasher.default_hashes().height_of(node) {
+            let child = self.hasher.empty(height.saturating_sub(1));
+            return Ok((child, child));
+        }
+        let node_value = self.load_node(node)?.ok_or(SMTError::MissingNode(*node))?;
+        decode_internal(&node_value).ok_or(SMTError::CorruptNode { hash: *node, len: node_value.len() })
+    }
+
+    /// Reads the key and value out of the leaf stored under `node`, failing
+    /// like `read_node` when it is missing or not a leaf record.
+    pub(crate) fn read_leaf(&self, node: &Hash) -> Result<(Hash, Hash), SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let record = self.load_node(node)?.ok_or(SMTError::MissingNode(*node))?;
+        decode_leaf(&record).ok_or(SMTError::CorruptNode { hash: *node, len: record.len() })
+    }
 
-        for i in 0..256 {
-            if current == self.hasher.zero_hash() {
-                debug!("Reached zero hash at depth {}", i);
+    /// Value of `key`, read from the leaf record at the end of its path.
+    #[instrument(name = "smt.get", level = "debug", skip_all, fields(key = %HexFmt(&key), root = %HexFmt(&self.root)))]
+    pub fn get(&self, key: Hash) -> Result<Option<Hash>, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        self.get_at(self.root, key)
+    }
+
+    /// Value of `key` in the tree under `root`, which may be any root whose
+    /// nodes are in the store.
+    pub(crate) fn get_at(&self, root: Hash, key: Hash) -> Result<Option<Hash>, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let leaf = self.leaf_on_path(root, &key)?;
+        Ok(leaf.filter(|(leaf_key, _)| *leaf_key == key).map(|(_, value)| value))
+    }
+
+    /// The leaf at the end of `key`'s path, if there is one. In a tree
+    /// shallower than 256 levels it may belong to another key sharing the
+    /// path.
+    fn leaf_on_path(&self, root: Hash, key: &Hash) -> Result<Option<(Hash, Hash)>, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let mut current = root;
+        for i in 0..self.depth {
+            if self.hasher.is_empty(&current) {
+                return Ok(None);
+            }
+            let (left, right) = self.get_children(&current, i)?;
+            current = if get_bit(key, i) == 0 { left } else { right };
+        }
+        if self.hasher.is_empty(&current) {
+            return Ok(None);
+        }
+        self.read_leaf(&current).map(Some)
+    }
+
+    /// Proves whatever the tree holds for `key`: its value if present,
+    /// otherwise that it is absent, including when another key's leaf sits
+    /// at the end of its path. Fails instead of guessing on nodes missing
+    /// from the store or corrupt.
+    #[instrument(name = "smt.prove", level = "debug", skip_all, fields(key = %HexFmt(&key), root = %HexFmt(&self.root)))]
+    pub fn prove(&self, key: Hash) -> Result<KeyProof, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        self.prove_at(self.root, key)
+    }
+
+    /// Like `prove`, under `root` instead of the current root.
+    pub(crate) fn prove_at(&self, root: Hash, key: Hash) -> Result<KeyProof, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let mut current = root;
+        let mut side_nodes = Vec::new();
+        for i in 0..self.depth {
+            if self.hasher.is_empty(&current) {
                 break;
             }
+            let (left, right) = self.read_node(&current)?;
+            if get_bit(&key, i) == 0 {
+                side_nodes.push(right);
+                current = left;
+            } else {
+                side_nodes.push(left);
+                current = right;
+            }
+        }
+
+        if self.hasher.is_empty(&current) {
+            return Ok(KeyProof::NonMembership(NonMembershipProof { side_nodes, leaf: None }));
+        }
+        let (leaf_key, value) = self.read_leaf(&current)?;
+        if leaf_key != key {
+            debug!(other_key = %HexFmt(&leaf_key), "key absent, its path ends in another key's leaf");
+            return Ok(KeyProof::NonMembership(NonMembershipProof { side_nodes, leaf: Some((leaf_key, value)) }));
+        }
+        Ok(KeyProof::Membership { value, proof: MerkleProof { side_nodes } })
+    }
 
-            let node_value = self.store.get(&current)?.unwrap_or_else(|| vec![0u8; 64]);
-            let (left, right) = node_value.split_at(32);
-            let bit = (key[i / 8] >> (7 - (i % 8))) & 1;
+    #[instrument(name = "smt.get_proof", level = "debug", skip_all, fields(key = %HexFmt(&key), root = %HexFmt(&self.root)))]
+    pub fn get_proof(&self, key: Hash) -> Result<MerkleProof, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        self.get_proof_at(self.root, key)
+    }
 
-            debug!(
-                "At depth {}, bit {}, left: {:?}, right: {:?}",
-                i, bit, left, right
-            );
+    /// Builds a proof for `key` under an earlier `root`. Fails with
+    /// `MissingNode` if part of that root's tree is gone from the store, as
+    /// after `VersionedSparseMerkleTree::prune_before`.
+    pub(crate) fn get_proof_at(&self, root: Hash, key: Hash) -> Result<MerkleProof, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let mut current = root;
+        let mut side_nodes = Vec::new();
+        for i in 0..self.depth {
+            if self.hasher.is_empty(&current) {
+                break;
+            }
 
-            if bit == 0 {
-                side_nodes.push(right.try_into().unwrap());
-                current = left.try_into().unwrap();
+            let (left, right) = self.get_children(&current, i)?;
+            if get_bit(&key, i) == 0 {
+                side_nodes.push(right);
+                current = left;
             } else {
-                side_nodes.push(left.try_into().unwrap());
-                current = right.try_into().unwrap();
+                side_nodes.push(left);
+                current = right;
             }
         }
 
-        debug!("Generated proof with {} side nodes", side_nodes.len());
+        #[cfg(feature = "metrics")]
+        crate::metrics::record(|metrics| metrics.proof_size(side_nodes.len()));
         Ok(MerkleProof { side_nodes })
     }
 
-    pub fn verify_proof(&self, key: Hash, value: Hash, proof: &MerkleProof) -> bool {
-        let leaf_hash = self.hasher.digest_leaf(&key, &value);
-        let mut current = leaf_hash;
+    /// Proves all of `keys` at once, sharing the side nodes their paths have in
+    /// common. Duplicate keys are proven once.
+    #[instrument(name = "smt.get_multiproof", level = "debug", skip_all, fields(keys = keys.len(), root = %HexFmt(&self.root)))]
+    pub fn get_multiproof(&self, keys: &[Hash]) -> Result<MultiProof, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let mut keys = keys.to_vec();
+        keys.sort();
+        keys.dedup();
 
-        debug!("Verifying proof for key {:?}, value {:?}", key, value);
-        debug!("Starting from leaf hash {:?}", current);
+        let mut proof = MultiProof::new(self.depth);
+        if !keys.is_empty() {
+            self.collect_multiproof(self.root, &keys, 0, &mut proof)?;
+        }
+        #[cfg(feature = "metrics")]
+        crate::metrics::record(|metrics| metrics.proof_size(proof.side_nodes.len()));
+        Ok(proof)
+    }
 
+    /// Proves that `key` has no leaf under the current root, either because
+    /// its path ends in an empty subtree or because another key's leaf sits
+    /// at the end of it. Returns `None` if the key is present.
+    #[instrument(name = "smt.get_non_membership_proof", level = "debug", skip_all, fields(key = %HexFmt(&key), root = %HexFmt(&self.root)))]
+    pub fn get_non_membership_proof(&self, key: Hash) -> Result<Option<NonMembershipProof>, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let mut current = self.root;
+        let mut side_nodes = Vec::new();
+        for i in 0..self.depth {
+            if self.hasher.is_empty(&current) {
+                break;
+            }
+
+            let (left, right) = self.get_children(&current, i)?;
+            if get_bit(&key, i) == 0 {
+                side_nodes.push(right);
+                current = left;
+            } else {
+                side_nodes.push(left);
+                current = right;
+            }
+        }
+
+        let leaf = match self.hasher.is_empty(&current) {
+            true => None,
+            false => {
+                let leaf = self.read_leaf(&current)?;
+                if leaf.0 == key {
+                    debug!("key present, no non-membership proof");
+                    return Ok(None);
+                }
+                debug!(other_key = %HexFmt(&leaf.0), "key absent, its path ends in another key's leaf");
+                Some(leaf)
+            }
+        };
+        #[cfg(feature = "metrics")]
+        crate::metrics::record(|metrics| metrics.proof_size(side_nodes.len()));
+        Ok(Some(NonMembershipProof { side_nodes, leaf }))
+    }
+
+    /// Checks that `proof` shows `key` absent under the current root. Fails
+    /// with `DepthExceeded` for a proof longer than the tree is deep, and
+    /// `InvalidProof` for one that does not lead to the root.
+    pub fn verify_non_membership_proof(&self, key: Hash, proof: &NonMembershipProof) -> Result<(), SMTError> {
+        self.check_proof_depth(proof.side_nodes.len())?;
+        match proof.verify_at_depth(&self.hasher, &self.root, &key, self.depth) {
+            true => Ok(()),
+            false => Err(SMTError::InvalidProof),
+        }
+    }
+
+    /// Checks that `proof` shows `key` holding `value` under the current
+    /// root, failing like `verify_non_membership_proof` when it does not.
+    #[instrument(name = "smt.verify_proof", level = "debug", skip_all, fields(key = %HexFmt(&key), root = %HexFmt(&self.root)))]
+    pub fn verify_proof(&self, key: Hash, value: Hash, proof: &MerkleProof) -> Result<(), SMTError> {
+        self.check_proof_depth(proof.side_nodes.len())?;
+        let mut current = self.hasher.digest_leaf(&key, &value);
         for (i, sibling) in proof.side_nodes.iter().enumerate().rev() {
-            let bit = (key[i / 8] >> (7 - (i % 8))) & 1;
-            let (left, right) = if bit == 0 {
+            let (left, right) = if get_bit(&key, i) == 0 {
                 (current, *sibling)
             } else {
                 (*sibling, current)
             };
             current = self.hasher.digest_node(&left, &right);
+        }
 
-            debug!(
-                "At depth {}, bit {}, left: {:?}, right: {:?}, current: {:?}",
-                255 - i,
-                bit,
-                left,
-                right,
-                current
-            );
+        match current == self.root {
+            true => Ok(()),
+            false => Err(SMTError::InvalidProof),
         }
+    }
 
-        debug!("Final hash: {:?}", current);
-        debug!("Root hash:  {:?}", self.root);
+    fn check_proof_depth(&self, depth: usize) -> Result<(), SMTError> {
+        match depth > self.depth {
+            true => Err(SMTError::DepthExceeded { depth, max: self.depth }),
+            false => Ok(()),
+        }
+    }
+
+    pub fn root(&self) -> Hash {
+        self.root
+    }
+
+    /// Walks from the root towards `key` and collects the sibling at every
+    /// depth. Siblings below the point where the path leaves the populated
+    /// part of the tree are empty subtrees.
+    fn side_nodes_for(&self, key: &Hash) -> Result<Vec<Hash>, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let mut side_nodes: Vec<Hash> = (0..self.depth).map(|i| self.hasher.empty(self.depth - i - 1)).collect();
+        let mut current = self.root;
+
+        for (i, side_node) in side_nodes.iter_mut().enumerate() {
+            if self.hasher.is_empty(&current) {
+                break;
+            }
+            let (left, right) = self.get_children(&current, i)?;
+            if get_bit(key, i) == 0 {
+                *side_node = right;
+                current = left;
+            } else {
+                *side_node = left;
+                current = right;
+            }
+        }
 
-        current == self.root
+        Ok(side_nodes)
+    }
+
+    /// Walks the subtree rooted at `node` holding the sorted `keys`, recording
+    /// a sibling wherever all the keys continue down the same side.
+    pub(crate) fn collect_multiproof(&self, node: Hash, keys: &[Hash], depth: usize, proof: &mut MultiProof) -> Result<(), SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        if depth == self.depth {
+            return Ok(());
+        }
+
+        let (left, right) = self.get_children(&node, depth)?;
+        let split = keys.partition_point(|key| get_bit(key, depth) == 0);
+        let (left_keys, right_keys) = keys.split_at(split);
+
+        if right_keys.is_empty() {
+            proof.push(self.sibling(right));
+        } else if left_keys.is_empty() {
+            proof.push(self.sibling(left));
+        }
+        if !left_keys.is_empty() {
+            self.collect_multiproof(left, left_keys, depth + 1, proof)?;
+        }
+        if !right_keys.is_empty() {
+            self.collect_multiproof(right, right_keys, depth + 1, proof)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the internal node at `depth` and splits it into its left and
+    /// right children. An empty subtree has two empty children. A node
+    /// missing from the store is `MissingNode`, and a record that does not
+    /// decode as an internal node is `CorruptNode`.
+    fn get_children(&self, node: &Hash, depth: usize) -> Result<(Hash, Hash), SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let empty = self.hasher.empty(self.depth - depth - 1);
+        if self.hasher.is_empty(node) {
+            return Ok((empty, empty));
+        }
+        let record = self.load_node(node)?.ok_or(SMTError::MissingNode(*node))?;
+        decode_internal(&record).ok_or(SMTError::CorruptNode { hash: *node, len: record.len() })
     }
 
+    /// Record stored under `hash`, from the cache when there is one.
+    pub(crate) fn load_node(&self, hash: &Hash) -> Result<Option<Vec<u8>>, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        #[cfg(feature = "lru")]
+        if let Some(cache) = &self.cache {
+            if let Some(record) = cache.get(hash) {
+                return Ok(Some(record));
+            }
+            let record = self.store.get_node(hash)?;
+            #[cfg(feature = "metrics")]
+            crate::metrics::record(|metrics| metrics.node_reads(1));
+            if let Some(record) = &record {
+                cache.insert(*hash, record.clone());
+            }
+            return Ok(record);
+        }
+        #[cfg(feature = "metrics")]
+        crate::metrics::record(|metrics| metrics.node_reads(1));
+        Ok(self.store.get_node(hash)?)
+    }
+
+    /// Writes `batch` to the store, dropping the nodes it deletes from the
+    /// cache.
+    pub(crate) fn commit(&mut self, batch: TreeWriteBatch) -> Result<(), SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        #[cfg(feature = "lru")]
+        if let Some(cache) = &self.cache {
+            for hash in batch.removed_nodes() {
+                cache.remove(hash);
+            }
+        }
+        #[cfg(feature = "metrics")]
+        let written = batch.written_nodes() as u64;
+        batch.commit(&mut self.store)?;
+        #[cfg(feature = "metrics")]
+        crate::metrics::record(|metrics| metrics.node_writes(written));
+        Ok(())
+    }
+
+    /// `node` as a multiproof sibling: `None` when it is an empty subtree,
+    /// which the proof only records in its bitmap.
+    pub(crate) fn sibling(&self, node: Hash) -> Option<Hash> {
+        (!self.hasher.is_empty(&node)).then_some(node)
+    }
+}
+
+/// A root to return to with `SparseMerkleTree::rollback`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[must_use]
+pub struct Checkpoint {
+    root: Hash,
+}
+
+impl Checkpoint {
     pub fn root(&self) -> Hash {
         self.root
     }
 }
+
+/// Sets the `root` field of the current span to the root a write ended at.
+fn record_root(root: &Hash) {
+    Span::current().record("root", field::display(HexFmt(root)));
+}
+
+/// Returns the bit of `key` that selects the child at `depth`, most significant bit first.
+pub(crate) fn get_bit(key: &Hash, depth: usize) -> u8 {
+    (key[depth / 8] >> (7 - (depth % 8))) & 1
+}
+
+/// Forks the tree. The cost is whatever cloning the store costs, which is
+/// O(1) for `InMemoryKVStore`.
+impl<S: KVStore + Clone, D: TreeDigest> Clone for SparseMerkleTree<S, D> {
+    fn clone(&self) -> Self {
+        Self {
+            hasher: self.hasher.clone(),
+            store: self.store.clone(),
+            root: self.root,
+            depth: self.depth,
+            observers: self.observers.clone(),
+            #[cfg(feature = "lru")]
+            cache: self.cache.clone(),
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use serde::{Serialize, Deserialize};
+
+#[cfg(feature = "blake3")]
+use crate::hashers::Blake3;
+#[cfg(feature = "poseidon")]
+use crate::hashers::Poseidon;
+use crate::{
+    error::SMTError,
+    hashers::{HasherId, Keccak256, Sha256, Sha3_256},
+    proof::MerkleProof,
+    sparse_merkle_tree::DEFAULT_DEPTH,
+    tree_hasher::{TreeDigest, TreeHasher, LEAF_PREFIX, NODE_PREFIX},
+    DefaultHasher, Hash,
+};
+
+/// Identifier of the hash function behind `DefaultHasher`.
+pub const DEFAULT_HASHER_ID: &str = DefaultHasher::HASHER_ID;
+
+/// Hashers `verify_with_spec` accepts. All of them produce 32-byte digests.
+pub const SUPPORTED_HASHERS: &[&str] = &[
+    "sha256",
+    "sha3-256",
+    "keccak256",
+    #[cfg(feature = "blake3")]
+    "blake3",
+    #[cfg(feature = "poseidon")]
+    "poseidon-bn254",
+];
+
+/// Everything a verifier needs to know about how a tree hashes its contents,
+/// so it can check it is configured compatibly before checking any proof.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreeSpec {
+    pub hasher_id: String,
+    pub depth: u16,
+    pub leaf_prefix: u8,
+    pub node_prefix: u8,
+    pub empty_root: Hash,
+    /// Mixed into every leaf hash when keys are derived by a `KeyHasher`.
+    #[serde(default)]
+    pub key_domain: Option<Hash>,
+}
+
+impl Default for TreeSpec {
+    fn default() -> Self {
+        Self {
+            hasher_id: DEFAULT_HASHER_ID.to_string(),
+            depth: DEFAULT_DEPTH as u16,
+            leaf_prefix: LEAF_PREFIX,
+            node_prefix: NODE_PREFIX,
+            empty_root: TreeHasher::<Sha256>::new().empty(DEFAULT_DEPTH),
+            key_domain: None,
+        }
+    }
+}
+
+/// Verifies a membership proof from a deployment described by `spec`, which
+/// may come from an untrusted source. Fails if the spec asks for a hasher or
+/// depth this crate cannot reproduce, gives leaves and internal nodes the
+/// same prefix, which would let a node pass for a leaf, or if its
+/// `empty_root` is not the default hash its hasher gives an empty tree of
+/// that depth; otherwise returns whether the proof holds.
+pub fn verify_with_spec(spec: &TreeSpec, root: &Hash, key: &Hash, value: &Hash, proof: &MerkleProof) -> Result<bool, SMTError> {
+    if !(1..=DEFAULT_DEPTH).contains(&(spec.depth as usize)) {
+        return Err(SMTError::UnsupportedSpec(format!("depth {}", spec.depth)));
+    }
+    if spec.leaf_prefix == spec.node_prefix {
+        return Err(SMTError::UnsupportedSpec(format!("leaf and node prefix both {}", spec.leaf_prefix)));
+    }
+    if proof.side_nodes.len() > spec.depth as usize {
+        return Err(SMTError::InvalidProof);
+    }
+
+    let verified = match spec.hasher_id.as_str() {
+        "sha256" => proof.verify_with(&spec_hasher::<Sha256>(spec)?, root, key, value),
+        "sha3-256" => proof.verify_with(&spec_hasher::<Sha3_256>(spec)?, root, key, value),
+        "keccak256" => proof.verify_with(&spec_hasher::<Keccak256>(spec)?, root, key, value),
+        #[cfg(feature = "blake3")]
+        "blake3" => proof.verify_with(&spec_hasher::<Blake3>(spec)?, root, key, value),
+        #[cfg(feature = "poseidon")]
+        "poseidon-bn254" => proof.verify_with(&spec_hasher::<Poseidon>(spec)?, root, key, value),
+        other => return Err(SMTError::UnsupportedSpec(format!("hasher {}", other))),
+    };
+    Ok(verified)
+}
+
+/// Hasher for `spec`, after checking the spec's empty root against it.
+fn spec_hasher<D: TreeDigest>(spec: &TreeSpec) -> Result<TreeHasher<D>, SMTError> {
+    let hasher = TreeHasher::with_prefixes(spec.leaf_prefix, spec.node_prefix);
+    if hasher.empty(spec.depth as usize) != spec.empty_root {
+        return Err(SMTError::UnsupportedSpec("empty root".to_string()));
+    }
+    Ok(match spec.key_domain {
+        Some(domain) => hasher.with_key_domain(domain),
+        None => hasher,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{kv_store::InMemoryKVStore, sparse_merkle_tree::SparseMerkleTree};
+
+    #[test]
+    fn test_verify_with_default_spec() {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        let key: Hash = [1u8; 32];
+        let value: Hash = [2u8; 32];
+        smt.update(key, value).unwrap();
+        let proof = smt.get_proof(key).unwrap();
+
+        let spec = smt.spec();
+        assert!(verify_with_spec(&spec, &smt.root(), &key, &value, &proof).unwrap());
+        assert!(!verify_with_spec(&spec, &smt.root(), &key, &[3u8; 32], &proof).unwrap());
+    }
+
+    #[test]
+    fn test_verify_with_other_hasher() {
+        // Build a single-leaf Keccak tree by hand
+        let hasher = TreeHasher::<Keccak256>::new();
+        let key: Hash = [0u8; 32];
+        let value: Hash = [7u8; 32];
+        let mut root = hasher.digest_leaf(&key, &value);
+        for height in 0..256 {
+            root = hasher.digest_node(&root, &hasher.empty(height));
+        }
+        let proof = MerkleProof { side_nodes: (0..256).rev().map(|height| hasher.empty(height)).collect() };
+
+        let spec = TreeSpec { hasher_id: "keccak256".to_string(), empty_root: hasher.empty(256), ..TreeSpec::default() };
+        assert!(verify_with_spec(&spec, &root, &key, &value, &proof).unwrap());
+        // A spec claiming another empty root is not this tree
+        let wrong = TreeSpec { hasher_id: "keccak256".to_string(), ..TreeSpec::default() };
+        assert!(matches!(verify_with_spec(&wrong, &root, &key, &value, &proof), Err(SMTError::UnsupportedSpec(_))));
+        // The same proof must not verify under the default hasher
+        assert!(!verify_with_spec(&TreeSpec::default(), &root, &key, &value, &proof).unwrap());
+    }
+
+    #[test]
+    fn test_verify_with_spec_rejects_shared_prefix() {
+        // With one prefix for both, a root over children `left` and `right`
+        // hashes like a leaf with that key and value, which an empty proof
+        // then claims
+        let hasher = TreeHasher::<Sha256>::with_prefixes(0, 0);
+        let (left, right) = (hasher.empty(255), hasher.digest_leaf(&[1u8; 32], &[2u8; 32]));
+        let root = hasher.digest_node(&left, &right);
+        let forged = MerkleProof { side_nodes: Vec::new() };
+        assert!(forged.verify_with(&hasher, &root, &left, &right));
+
+        let spec = TreeSpec { leaf_prefix: 0, node_prefix: 0, empty_root: hasher.empty(256), ..TreeSpec::default() };
+        assert!(matches!(verify_with_spec(&spec, &root, &left, &right, &forged), Err(SMTError::UnsupportedSpec(_))));
+    }
+
+    #[test]
+    fn test_verify_with_spec_rejects_unknown_hasher() {
+        let spec = TreeSpec { hasher_id: "md5".to_string(), ..TreeSpec::default() };
+        let proof = MerkleProof { side_nodes: Vec::new() };
+        assert!(matches!(
+            verify_with_spec(&spec, &[0u8; 32], &[0u8; 32], &[0u8; 32], &proof),
+            Err(SMTError::UnsupportedSpec(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_with_spec_rejects_unsupported_depths() {
+        let proof = MerkleProof { side_nodes: Vec::new() };
+        for depth in [0, 257] {
+            let spec = TreeSpec { depth, ..TreeSpec::default() };
+            assert!(verify_with_spec(&spec, &[0u8; 32], &[0u8; 32], &[0u8; 32], &proof).is_err());
+        }
+    }
+
+    #[test]
+    fn test_verify_with_shallow_spec() {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new()).with_depth(160);
+        let mut key: Hash = [0u8; 32];
+        key[..20].fill(1);
+        smt.update(key, [2u8; 32]).unwrap();
+        let proof = smt.get_proof(key).unwrap();
+
+        assert!(verify_with_spec(&smt.spec(), &smt.root(), &key, &[2u8; 32], &proof).unwrap());
+        let too_shallow = TreeSpec { depth: 100, ..smt.spec() };
+        assert!(verify_with_spec(&too_shallow, &smt.root(), &key, &[2u8; 32], &proof).is_err());
+    }
+
+    #[test]
+    fn test_spec_serde_roundtrip() {
+        let spec = TreeSpec::default();
+        let json = serde_json::to_string(&spec).unwrap();
+        assert_eq!(serde_json::from_str::<TreeSpec>(&json).unwrap(), spec);
+    }
+}
@@ -0,0 +1,497 @@
+//! Account state kept in a tree. Each account is a leaf keyed by the hash of
+//! its address, whose value is the hash of the account's record; the record
+//! itself is stored under that hash, next to the tree's nodes.
+//!
+//! Every account also has a storage tree of its own, mapping 32-byte slots to
+//! 32-byte values, whose root is part of the account record. Storage trees
+//! keep their nodes in the same store as the account tree.
+
+use std::collections::{BTreeMap, HashSet};
+
+use digest::Digest;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::{
+    account::{empty_storage_root, Account},
+    arith::{checked_add, checked_sub},
+    error::SMTError,
+    hex::HexFmt,
+    kv_store::KVStore,
+    op::Op,
+    proof::KeyProof,
+    sparse_merkle_tree::SparseMerkleTree,
+    transaction::Transaction,
+    DefaultHasher, Hash,
+};
+
+const ADDRESS_DOMAIN: &[u8] = b"SimpleSparseMerkle/account-address/v1";
+const RECORD_LEN: usize = 80;
+
+/// Leaf key of the account at `address`.
+pub fn address_key(address: &Hash) -> Hash {
+    let mut hasher = DefaultHasher::new();
+    hasher.update(ADDRESS_DOMAIN);
+    hasher.update(address);
+    hasher.finalize().into()
+}
+
+/// Address, balance, nonce and storage root, with the integers big-endian.
+pub fn encode_account(account: &Account) -> Vec<u8> {
+    [&account.address[..], &account.balance.to_be_bytes(), &account.nonce.to_be_bytes(), &account.storage_root].concat()
+}
+
+pub fn decode_account(record: &[u8]) -> Option<Account> {
+    if record.len() != RECORD_LEN {
+        return None;
+    }
+    Some(Account {
+        address: record[..32].try_into().ok()?,
+        balance: u64::from_be_bytes(record[32..40].try_into().ok()?),
+        nonce: u64::from_be_bytes(record[40..48].try_into().ok()?),
+        storage_root: record[48..].try_into().ok()?,
+    })
+}
+
+/// Leaf value committing to `account`.
+pub fn account_hash(account: &Account) -> Hash {
+    DefaultHasher::digest(encode_account(account)).into()
+}
+
+/// An account's state, or its absence, proven against a state root.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AccountProof {
+    pub address: Hash,
+    pub account: Option<Account>,
+    pub proof: KeyProof,
+}
+
+impl AccountProof {
+    pub fn verify(&self, root: &Hash) -> bool {
+        let key = address_key(&self.address);
+        match (&self.account, &self.proof) {
+            (Some(account), KeyProof::Membership { value, .. }) => {
+                account.address == self.address && *value == account_hash(account) && self.proof.verify(root, &key)
+            }
+            (None, KeyProof::NonMembership(_)) => self.proof.verify(root, &key),
+            _ => false,
+        }
+    }
+}
+
+/// A storage slot's value, or its absence, proven against a state root by
+/// chaining the account's proof and the proof within its storage tree.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StorageProof {
+    pub account: AccountProof,
+    pub slot: Hash,
+    pub proof: KeyProof,
+}
+
+impl StorageProof {
+    pub fn value(&self) -> Option<Hash> {
+        self.proof.value()
+    }
+
+    /// Checks the account against `root`, then the slot against the
+    /// account's storage root. A missing account has empty storage.
+    pub fn verify(&self, root: &Hash) -> bool {
+        let storage_root = match &self.account.account {
+            Some(account) => account.storage_root,
+            None => empty_storage_root(),
+        };
+        self.account.verify(root) && self.proof.verify(&storage_root, &self.slot)
+    }
+}
+
+/// Balances every node starts from. Nodes building their state from the
+/// same config get the same genesis root, whatever order the accounts are
+/// listed in.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenesisConfig {
+    pub accounts: Vec<GenesisAccount>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenesisAccount {
+    pub address: Hash,
+    pub balance: u64,
+}
+
+impl GenesisConfig {
+    pub fn to_json(&self) -> Result<String, SMTError> {
+        serde_json::to_string_pretty(self).map_err(|_| SMTError::InvalidEncoding)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SMTError> {
+        serde_json::from_str(json).map_err(|_| SMTError::InvalidEncoding)
+    }
+}
+
+/// Applies transfers to the accounts held in a tree. An account that was
+/// never written reads as empty, with a zero balance and nonce.
+///
+/// Records of earlier account states are left in the store, so rolling the
+/// tree back to an earlier root still finds them.
+pub struct StateMachine<S: KVStore> {
+    tree: SparseMerkleTree<S>,
+}
+
+impl<S: KVStore> StateMachine<S> {
+    pub fn new(tree: SparseMerkleTree<S>) -> Self {
+        Self { tree }
+    }
+
+    /// Builds the initial state in a fresh default tree over `store`, with
+    /// each address holding its balance and a zero nonce. An address listed
+    /// twice is rejected rather than resolved by order.
+    pub fn from_genesis(store: S, accounts: &[(Hash, u64)]) -> Result<Self, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let mut seen = HashSet::with_capacity(accounts.len());
+        let mut genesis = Vec::with_capacity(accounts.len());
+        for (address, balance) in accounts {
+            if !seen.insert(*address) {
+                return Err(SMTError::KeyConflict(*address));
+            }
+            genesis.push(Account::new(*address, *balance));
+        }
+        let mut state = Self::new(SparseMerkleTree::new(store));
+        let root = state.write(&genesis)?;
+        info!("Built genesis state with {} accounts, root: {}", genesis.len(), HexFmt(&root));
+        Ok(state)
+    }
+
+    /// Like `from_genesis`, taking the accounts from `config`.
+    pub fn from_genesis_config(store: S, config: &GenesisConfig) -> Result<Self, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let accounts: Vec<(Hash, u64)> = config.accounts.iter().map(|account| (account.address, account.balance)).collect();
+        Self::from_genesis(store, &accounts)
+    }
+
+    pub fn root(&self) -> Hash {
+        self.tree.root()
+    }
+
+    pub fn tree(&self) -> &SparseMerkleTree<S> {
+        &self.tree
+    }
+
+    pub fn into_tree(self) -> SparseMerkleTree<S> {
+        self.tree
+    }
+
+    /// The account at `address`, or `None` if it was never written.
+    pub fn account(&self, address: &Hash) -> Result<Option<Account>, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let Some(record_hash) = self.tree.get(address_key(address))? else {
+            return Ok(None);
+        };
+        let record = self.tree.store.get(&record_hash)?.ok_or(SMTError::MissingNode(record_hash))?;
+        match decode_account(&record) {
+            Some(account) if account_hash(&account) == record_hash => Ok(Some(account)),
+            _ => Err(SMTError::CorruptNode { hash: record_hash, len: record.len() }),
+        }
+    }
+
+    /// Writes `account` as is, for seeding balances. Returns the new root.
+    pub fn set_account(&mut self, account: &Account) -> Result<Hash, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        self.write(std::slice::from_ref(account))
+    }
+
+    /// Moves `tx.amount` from `tx.from` to `tx.to` and returns the new root.
+    /// The nonce must be the sender's current one, which the transfer then
+    /// bumps. A rejected transaction leaves the state untouched.
+    ///
+    /// Signatures are not checked here; see `Transaction::verify_signature`.
+    pub fn apply(&mut self, tx: &Transaction) -> Result<Hash, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let mut sender = self.account_or_empty(&tx.from)?;
+        if tx.nonce != sender.nonce {
+            return Err(SMTError::BadNonce { expected: sender.nonce, got: tx.nonce });
+        }
+        if sender.balance < tx.amount {
+            return Err(SMTError::InsufficientBalance { balance: sender.balance, amount: tx.amount });
+        }
+        sender.nonce = checked_add(sender.nonce, 1)?;
+
+        let accounts = if tx.to == tx.from {
+            vec![sender]
+        } else {
+            sender.balance = checked_sub(sender.balance, tx.amount)?;
+            let mut recipient = self.account_or_empty(&tx.to)?;
+            recipient.balance = checked_add(recipient.balance, tx.amount)?;
+            vec![sender, recipient]
+        };
+        debug!("Applying transfer of {} from {} to {}", tx.amount, HexFmt(&tx.from), HexFmt(&tx.to));
+        self.write(&accounts)
+    }
+
+    /// Applies `txs` in order, stopping at the first rejected one. The ones
+    /// before it stay applied.
+    pub fn apply_all(&mut self, txs: &[Transaction]) -> Result<Hash, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        for tx in txs {
+            self.apply(tx)?;
+        }
+        Ok(self.root())
+    }
+
+    /// Value of `slot` in the storage of the account at `address`.
+    pub fn storage(&self, address: &Hash, slot: Hash) -> Result<Option<Hash>, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        match self.account(address)? {
+            Some(account) => self.tree.get_at(account.storage_root, slot),
+            None => Ok(None),
+        }
+    }
+
+    /// Applies `ops` to the storage of the account at `address`, creating the
+    /// account if needed, and returns the new state root. Later operations
+    /// win when a slot repeats.
+    pub fn update_storage(&mut self, address: &Hash, ops: &[Op]) -> Result<Hash, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let mut account = self.account_or_empty(address)?;
+        let mut sorted = BTreeMap::new();
+        for op in ops {
+            match op {
+                Op::Put(slot, value) => sorted.insert(*slot, Some(*value)),
+                Op::Delete(slot) => sorted.insert(*slot, None),
+            };
+        }
+        let mut writes = Vec::with_capacity(sorted.len());
+        for (slot, value) in sorted {
+            // Deleting a missing slot changes nothing
+            if value.is_some() || self.tree.get_at(account.storage_root, slot)?.is_some() {
+                writes.push((slot, value));
+            }
+        }
+        account.storage_root = self.tree.apply_at(account.storage_root, &writes)?;
+        self.write(&[account])
+    }
+
+    /// Proves the value of `slot` in the storage of the account at `address`
+    /// under the current root.
+    pub fn prove_storage(&self, address: &Hash, slot: Hash) -> Result<StorageProof, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let account = self.prove_account(address)?;
+        let storage_root = match &account.account {
+            Some(account) => account.storage_root,
+            None => self.empty_storage_root(),
+        };
+        Ok(StorageProof { proof: self.tree.prove_at(storage_root, slot)?, account, slot })
+    }
+
+    /// Proves the state of the account at `address` under the current root.
+    pub fn prove_account(&self, address: &Hash) -> Result<AccountProof, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        Ok(AccountProof {
+            address: *address,
+            account: self.account(address)?,
+            proof: self.tree.prove(address_key(address))?,
+        })
+    }
+
+    fn account_or_empty(&self, address: &Hash) -> Result<Account, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        Ok(self.account(address)?.unwrap_or_else(|| Account { storage_root: self.empty_storage_root(), ..Account::new(*address, 0) }))
+    }
+
+    /// Root of an empty storage tree, which differs from `empty_storage_root`
+    /// when the tree is not a default one.
+    fn empty_storage_root(&self) -> Hash {
+        self.tree.hasher.empty(self.tree.depth())
+    }
+
+    fn write(&mut self, accounts: &[Account]) -> Result<Hash, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let mut leaves = Vec::with_capacity(accounts.len());
+        for account in accounts {
+            let record_hash = account_hash(account);
+            self.tree.store.set(record_hash, encode_account(account))?;
+            leaves.push((address_key(&account.address), record_hash));
+        }
+        self.tree.update_batch(&leaves)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_store::InMemoryKVStore;
+
+    const ALICE: Hash = [1u8; 32];
+    const BOB: Hash = [2u8; 32];
+
+    fn transfer(from: Hash, to: Hash, amount: u64, nonce: u64) -> Transaction {
+        Transaction { from, to, amount, nonce, ..Default::default() }
+    }
+
+    fn funded() -> StateMachine<InMemoryKVStore> {
+        let mut state = StateMachine::new(SparseMerkleTree::new(InMemoryKVStore::new()));
+        state.set_account(&Account::new(ALICE, 100)).unwrap();
+        state
+    }
+
+    #[test]
+    fn test_transfer_moves_balance() {
+        let mut state = funded();
+        let root = state.apply(&transfer(ALICE, BOB, 30, 0)).unwrap();
+
+        assert_eq!(root, state.root());
+        assert_eq!(state.account(&ALICE).unwrap(), Some(Account { balance: 70, nonce: 1, ..Account::new(ALICE, 0) }));
+        assert_eq!(state.account(&BOB).unwrap(), Some(Account { balance: 30, nonce: 0, ..Account::new(BOB, 0) }));
+    }
+
+    #[test]
+    fn test_rejected_transactions_change_nothing() {
+        let mut state = funded();
+        let root = state.root();
+
+        assert!(matches!(state.apply(&transfer(ALICE, BOB, 10, 1)), Err(SMTError::BadNonce { expected: 0, got: 1 })));
+        assert!(matches!(
+            state.apply(&transfer(ALICE, BOB, 101, 0)),
+            Err(SMTError::InsufficientBalance { balance: 100, amount: 101 })
+        ));
+        assert!(state.apply(&transfer(BOB, ALICE, 1, 0)).is_err());
+        assert_eq!(state.root(), root);
+        assert_eq!(state.account(&BOB).unwrap(), None);
+    }
+
+    #[test]
+    fn test_replayed_transaction_is_rejected() {
+        let mut state = funded();
+        let tx = transfer(ALICE, BOB, 10, 0);
+        state.apply(&tx).unwrap();
+        assert!(state.apply(&tx).is_err());
+    }
+
+    #[test]
+    fn test_transfer_to_self_only_bumps_nonce() {
+        let mut state = funded();
+        state.apply(&transfer(ALICE, ALICE, 40, 0)).unwrap();
+        assert_eq!(state.account(&ALICE).unwrap(), Some(Account { balance: 100, nonce: 1, ..Account::new(ALICE, 0) }));
+    }
+
+    #[test]
+    fn test_recipient_overflow_is_rejected() {
+        let mut state = funded();
+        state.set_account(&Account::new(BOB, u64::MAX)).unwrap();
+        assert!(matches!(state.apply(&transfer(ALICE, BOB, 1, 0)), Err(SMTError::Arithmetic(_))));
+        assert_eq!(state.account(&ALICE).unwrap().unwrap().balance, 100);
+    }
+
+    #[test]
+    fn test_account_proofs() {
+        let mut state = funded();
+        state.apply_all(&[transfer(ALICE, BOB, 30, 0), transfer(BOB, ALICE, 5, 0)]).unwrap();
+        let root = state.root();
+
+        let proof = state.prove_account(&BOB).unwrap();
+        assert_eq!(proof.account.as_ref().unwrap().balance, 25);
+        assert!(proof.verify(&root));
+
+        let absent = state.prove_account(&[3u8; 32]).unwrap();
+        assert!(absent.account.is_none());
+        assert!(absent.verify(&root));
+
+        let mut forged = proof.clone();
+        forged.account.as_mut().unwrap().balance = 1000;
+        assert!(!forged.verify(&root));
+    }
+
+    #[test]
+    fn test_genesis_root_ignores_account_order() {
+        let config = GenesisConfig::from_json(
+            &GenesisConfig {
+                accounts: vec![GenesisAccount { address: ALICE, balance: 100 }, GenesisAccount { address: BOB, balance: 50 }],
+            }
+            .to_json()
+            .unwrap(),
+        )
+        .unwrap();
+        let state = StateMachine::from_genesis_config(InMemoryKVStore::new(), &config).unwrap();
+        let reordered = StateMachine::from_genesis(InMemoryKVStore::new(), &[(BOB, 50), (ALICE, 100)]).unwrap();
+
+        assert_eq!(state.root(), reordered.root());
+        assert_eq!(state.account(&BOB).unwrap(), Some(Account::new(BOB, 50)));
+        assert!(state.prove_account(&ALICE).unwrap().verify(&state.root()));
+    }
+
+    #[test]
+    fn test_genesis_rejects_repeated_address() {
+        let result = StateMachine::from_genesis(InMemoryKVStore::new(), &[(ALICE, 1), (ALICE, 2)]);
+        assert!(matches!(result, Err(SMTError::KeyConflict(address)) if address == ALICE));
+    }
+
+    #[test]
+    fn test_account_storage() {
+        let mut state = funded();
+        let balance_root = state.root();
+        state.update_storage(&ALICE, &[Op::Put([1u8; 32], [10u8; 32]), Op::Put([2u8; 32], [20u8; 32])]).unwrap();
+        let alice = state.account(&ALICE).unwrap().unwrap();
+
+        assert_ne!(state.root(), balance_root);
+        assert_ne!(alice.storage_root, empty_storage_root());
+        assert_eq!(alice.balance, 100);
+        assert_eq!(state.storage(&ALICE, [1u8; 32]).unwrap(), Some([10u8; 32]));
+        assert_eq!(state.storage(&ALICE, [3u8; 32]).unwrap(), None);
+        assert_eq!(state.storage(&BOB, [1u8; 32]).unwrap(), None);
+
+        // Transfers keep the storage root
+        state.apply(&transfer(ALICE, BOB, 10, 0)).unwrap();
+        assert_eq!(state.account(&ALICE).unwrap().unwrap().storage_root, alice.storage_root);
+
+        // Emptying the storage brings back the empty root
+        state.update_storage(&ALICE, &[Op::Delete([1u8; 32]), Op::Delete([2u8; 32]), Op::Delete([9u8; 32])]).unwrap();
+        assert_eq!(state.account(&ALICE).unwrap().unwrap().storage_root, empty_storage_root());
+    }
+
+    #[test]
+    fn test_storage_proofs() {
+        let mut state = funded();
+        state.update_storage(&BOB, &[Op::Put([1u8; 32], [10u8; 32])]).unwrap();
+        let root = state.root();
+
+        let proof = state.prove_storage(&BOB, [1u8; 32]).unwrap();
+        assert_eq!(proof.value(), Some([10u8; 32]));
+        assert!(proof.verify(&root));
+
+        let absent_slot = state.prove_storage(&BOB, [2u8; 32]).unwrap();
+        assert_eq!(absent_slot.value(), None);
+        assert!(absent_slot.verify(&root));
+
+        let absent_account = state.prove_storage(&[3u8; 32], [1u8; 32]).unwrap();
+        assert_eq!(absent_account.value(), None);
+        assert!(absent_account.verify(&root));
+
+        // A slot proven against another account's storage does not verify
+        let mut forged = proof.clone();
+        forged.account = state.prove_account(&ALICE).unwrap();
+        assert!(!forged.verify(&root));
+    }
+}
@@ -0,0 +1,221 @@
+//! Fast sync of a whole state from a peer, one chunk of leaves at a time.
+//! The serving side cuts the key space into consecutive intervals holding a
+//! fixed number of leaves each, and proves every interval with a
+//! `RangeProof`. A `SyncClient` asks for the intervals in key order and
+//! writes each one into its own store only once it verifies against the
+//! target root, so a faulty or malicious peer cannot slip in a leaf or leave
+//! one out.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::SMTError,
+    kv_store::KVStore,
+    range::RangeProof,
+    sparse_merkle_tree::{get_bit, SparseMerkleTree},
+    tree_hasher::TreeDigest,
+    DefaultHasher, Hash,
+};
+
+const LAST_KEY: Hash = [0xff; 32];
+
+/// The leaves with keys in `start..=end`, proven against the root they were
+/// taken from. `proof.entries` holds the leaves.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SyncChunk {
+    pub start: Hash,
+    pub end: Hash,
+    pub proof: RangeProof,
+}
+
+impl SyncChunk {
+    /// First key of the next chunk, or `None` if this one reaches the end of
+    /// the key space.
+    pub fn next_start(&self) -> Option<Hash> {
+        let mut next = self.end;
+        for byte in next.iter_mut().rev() {
+            let (incremented, carry) = byte.overflowing_add(1);
+            *byte = incremented;
+            if !carry {
+                return Some(next);
+            }
+        }
+        None
+    }
+}
+
+impl<S: KVStore, D: TreeDigest> SparseMerkleTree<S, D>
+where
+    SMTError: From<S::Error>,
+{
+    /// The chunk of at most `max_leaves` leaves starting at `start`. It ends
+    /// at its last leaf when full and at the end of the key space otherwise.
+    /// Start at the zero key and follow `SyncChunk::next_start`.
+    pub fn sync_chunk(&self, start: Hash, max_leaves: usize) -> Result<SyncChunk, SMTError> {
+        if max_leaves == 0 {
+            return Err(SMTError::UnsupportedOperation);
+        }
+        let mut leaves = Vec::new();
+        self.leaves_from(self.root, 0, Some(&start), max_leaves, &mut leaves)?;
+        let end = match leaves.last() {
+            Some((key, _)) if leaves.len() == max_leaves => *key,
+            _ => LAST_KEY,
+        };
+        Ok(SyncChunk { start, end, proof: self.prove_range(start, end)? })
+    }
+
+    /// Adds to `leaves`, in key order and until it holds `limit`, the leaves
+    /// under `node` with keys from `start` on. `start` is `None` once the
+    /// walk has left its path, as every key further right is larger.
+    fn leaves_from(&self, node: Hash, depth: usize, start: Option<&Hash>, limit: usize, leaves: &mut Vec<(Hash, Hash)>) -> Result<(), SMTError> {
+        if leaves.len() == limit || self.hasher.is_empty(&node) {
+            return Ok(());
+        }
+        if depth == self.depth {
+            let (key, value) = self.read_leaf(&node)?;
+            if start.is_none_or(|start| key >= *start) {
+                leaves.push((key, value));
+            }
+            return Ok(());
+        }
+        let (left, right) = self.read_node(&node)?;
+        match start {
+            Some(start) if get_bit(start, depth) == 1 => self.leaves_from(right, depth + 1, Some(start), limit, leaves),
+            _ => {
+                self.leaves_from(left, depth + 1, start, limit, leaves)?;
+                self.leaves_from(right, depth + 1, None, limit, leaves)
+            }
+        }
+    }
+}
+
+/// Rebuilds a tree with a known root from `SyncChunk`s taken in order.
+pub struct SyncClient<S: KVStore, D: TreeDigest = DefaultHasher> {
+    tree: SparseMerkleTree<S, D>,
+    target: Hash,
+    next: Option<Hash>,
+}
+
+impl<S: KVStore> SyncClient<S> {
+    /// Syncs the state under `target` into `store`, which should be empty.
+    pub fn new(store: S, target: Hash) -> Self {
+        Self::from_tree(SparseMerkleTree::new(store), target)
+    }
+}
+
+impl<S: KVStore, D: TreeDigest> SyncClient<S, D> {
+    /// Like `new`, filling `tree`, which should be empty and set up with the
+    /// depth and hasher of the tree being synced.
+    pub fn from_tree(tree: SparseMerkleTree<S, D>, target: Hash) -> Self {
+        Self { tree, target, next: Some([0u8; 32]) }
+    }
+
+    pub fn target(&self) -> Hash {
+        self.target
+    }
+
+    /// Start of the chunk to ask for next, `None` once every chunk is in.
+    pub fn next_start(&self) -> Option<Hash> {
+        self.next
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.next.is_none()
+    }
+
+    /// Verifies `chunk` against the target root and writes its leaves.
+    /// Fails with `InvalidProof`, writing nothing, if it does not verify or
+    /// does not start where the previous chunk ended.
+    pub fn apply(&mut self, chunk: &SyncChunk) -> Result<(), SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        if self.next != Some(chunk.start)
+            || chunk.proof.siblings.depth as usize != self.tree.depth()
+            || !chunk.proof.verify_with(&self.tree.hasher, &self.target, &chunk.start, &chunk.end)
+        {
+            return Err(SMTError::InvalidProof);
+        }
+        self.tree.update_batch(&chunk.proof.entries)?;
+        self.next = chunk.next_start();
+        Ok(())
+    }
+
+    /// The synced tree. Fails with `RootMismatch` if chunks are still missing.
+    pub fn finish(self) -> Result<SparseMerkleTree<S, D>, SMTError> {
+        if !self.is_complete() || self.tree.root() != self.target {
+            return Err(SMTError::RootMismatch);
+        }
+        Ok(self.tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_store::InMemoryKVStore;
+
+    fn tree(count: u8) -> SparseMerkleTree<InMemoryKVStore> {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        let entries: Vec<(Hash, Hash)> = (0..count).map(|i| ([i.wrapping_mul(37); 32], [i; 32])).collect();
+        smt.update_batch(&entries).unwrap();
+        smt
+    }
+
+    #[test]
+    fn test_sync_in_chunks_rebuilds_the_root() {
+        let source = tree(20);
+        let mut client = SyncClient::new(InMemoryKVStore::new(), source.root());
+        let mut chunks = 0;
+        while let Some(start) = client.next_start() {
+            let chunk = source.sync_chunk(start, 6).unwrap();
+            assert!(chunk.proof.entries.len() <= 6);
+            client.apply(&chunk).unwrap();
+            chunks += 1;
+        }
+        assert_eq!(chunks, 4);
+        let synced = client.finish().unwrap();
+        assert_eq!(synced.root(), source.root());
+        assert_eq!(synced.len().unwrap(), 20);
+    }
+
+    #[test]
+    fn test_sync_of_empty_tree() {
+        let source = tree(0);
+        let mut client = SyncClient::new(InMemoryKVStore::new(), source.root());
+        client.apply(&source.sync_chunk([0u8; 32], 4).unwrap()).unwrap();
+        assert_eq!(client.finish().unwrap().root(), source.root());
+    }
+
+    #[test]
+    fn test_rejects_bad_chunks() {
+        let source = tree(10);
+        let mut client = SyncClient::new(InMemoryKVStore::new(), source.root());
+        let first = source.sync_chunk([0u8; 32], 4).unwrap();
+        let second = source.sync_chunk(first.next_start().unwrap(), 4).unwrap();
+        assert!(matches!(client.apply(&second), Err(SMTError::InvalidProof)));
+
+        let mut dropped = first.clone();
+        dropped.proof.entries.pop();
+        assert!(matches!(client.apply(&dropped), Err(SMTError::InvalidProof)));
+
+        let other = tree(11).sync_chunk([0u8; 32], 4).unwrap();
+        assert!(matches!(client.apply(&other), Err(SMTError::InvalidProof)));
+
+        client.apply(&first).unwrap();
+        assert_eq!(client.next_start(), first.next_start());
+        assert!(matches!(client.finish(), Err(SMTError::RootMismatch)));
+    }
+
+    #[test]
+    fn test_next_start_carries() {
+        let chunk = tree(1).sync_chunk([0u8; 32], 1).unwrap();
+        let mut end = [0u8; 32];
+        end[30] = 1;
+        end[31] = 0xff;
+        let mut expected = [0u8; 32];
+        expected[30] = 2;
+        assert_eq!(SyncChunk { end, ..chunk.clone() }.next_start(), Some(expected));
+        assert_eq!(SyncChunk { end: LAST_KEY, ..chunk }.next_start(), None);
+    }
+}
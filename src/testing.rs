@@ -0,0 +1,180 @@
+//! Reproducible trees for tests and fuzzing, here and in downstream crates.
+//! Fixture leaves are derived by hashing the seed and index, not drawn from
+//! an RNG, so a seed gives the same tree on every platform and version, and
+//! the golden roots below stay valid.
+
+use std::fmt;
+
+use digest::Digest;
+use proptest::{collection, prelude::*};
+
+use crate::{
+    kv_store::InMemoryKVStore, proof::MerkleProof, sparse_merkle_tree::SparseMerkleTree, DefaultHasher, Hash,
+};
+
+const FIXTURE_DOMAIN: &[u8] = b"SimpleSparseMerkle/fixture/v1";
+
+const fn hex(digits: &str) -> Hash {
+    const fn nibble(digit: u8) -> u8 {
+        match digit {
+            b'0'..=b'9' => digit - b'0',
+            b'a'..=b'f' => digit - b'a' + 10,
+            _ => panic!("lowercase hex digit expected"),
+        }
+    }
+    let digits = digits.as_bytes();
+    let mut hash = [0u8; 32];
+    let mut i = 0;
+    while i < 32 {
+        hash[i] = nibble(digits[2 * i]) << 4 | nibble(digits[2 * i + 1]);
+        i += 1;
+    }
+    hash
+}
+
+/// Root of an empty tree of depth 256 with the default hasher.
+pub const GOLDEN_EMPTY_ROOT: Hash = hex("6155289130893872355eac98042d22aefa2c2e708bea169402760e3b55f9a2dc");
+
+/// `(seed, n, root)`: the root of `TreeFixture::with_random_leaves(seed, n)`,
+/// as computed by an independent implementation of the scheme.
+pub const GOLDEN_ROOTS: &[(u64, usize, Hash)] = &[
+    (0, 1, hex("28341a400de6521b2b6820bb7fdfd794742fcd37d6ac41e9a3a3a1d2241183aa")),
+    (1, 16, hex("00c43691840478af49cc3011d1ab05976bf10950bc5bc1996ffd11d0caeb3ccc")),
+    (42, 100, hex("34b2eba1ce5f25d8d3cc40a0face583f2f63efcc80c9ac9bcf9ad92bf27c4f82")),
+    (7, 1000, hex("0b156434bcb9f72d2c221f4385cc08ecac9ec68b34137303f6cb5af272c41d9c")),
+];
+
+/// Leaf `index` of the fixture for `seed`: the key is the hash of the domain
+/// tag, seed, index and `k`, the value the same with `v`.
+pub fn fixture_leaf(seed: u64, index: u64) -> (Hash, Hash) {
+    let hash = |kind: u8| -> Hash {
+        DefaultHasher::new()
+            .chain_update(FIXTURE_DOMAIN)
+            .chain_update(seed.to_be_bytes())
+            .chain_update(index.to_be_bytes())
+            .chain_update([kind])
+            .finalize()
+            .into()
+    };
+    (hash(b'k'), hash(b'v'))
+}
+
+/// In-memory tree holding the fixture leaves for a seed.
+pub struct TreeFixture {
+    seed: u64,
+    leaves: Vec<(Hash, Hash)>,
+    tree: SparseMerkleTree<InMemoryKVStore>,
+}
+
+impl TreeFixture {
+    /// The tree holding the first `n` fixture leaves for `seed`.
+    pub fn with_random_leaves(seed: u64, n: usize) -> Self {
+        let leaves: Vec<(Hash, Hash)> = (0..n as u64).map(|index| fixture_leaf(seed, index)).collect();
+        let mut tree = SparseMerkleTree::new(InMemoryKVStore::new());
+        tree.update_batch(&leaves).expect("an unbounded in-memory store takes every write");
+        Self { seed, leaves, tree }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Leaves in index order.
+    pub fn leaves(&self) -> &[(Hash, Hash)] {
+        &self.leaves
+    }
+
+    pub fn root(&self) -> Hash {
+        self.tree.root()
+    }
+
+    pub fn tree(&self) -> &SparseMerkleTree<InMemoryKVStore> {
+        &self.tree
+    }
+
+    pub fn into_tree(self) -> SparseMerkleTree<InMemoryKVStore> {
+        self.tree
+    }
+
+    /// Proof for leaf `index`.
+    ///
+    /// Panics if there is no such leaf.
+    pub fn proof(&self, index: usize) -> MerkleProof {
+        self.tree.get_proof(self.leaves[index].0).expect("fixture nodes are all in memory")
+    }
+
+    /// A key the fixture does not hold, the key of the first leaf past the
+    /// end.
+    pub fn absent_key(&self) -> Hash {
+        fixture_leaf(self.seed, self.leaves.len() as u64).0
+    }
+}
+
+impl fmt::Debug for TreeFixture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TreeFixture")
+            .field("seed", &self.seed)
+            .field("leaves", &self.leaves.len())
+            .field("root", &crate::hex::HexFmt(&self.root()))
+            .finish()
+    }
+}
+
+/// Any 32-byte key or value.
+pub fn hash() -> impl Strategy<Value = Hash> {
+    any::<Hash>()
+}
+
+/// Up to `max` leaves with distinct keys, in key order.
+pub fn leaves(max: usize) -> impl Strategy<Value = Vec<(Hash, Hash)>> {
+    collection::btree_map(hash(), hash(), 0..=max).prop_map(|leaves| leaves.into_iter().collect())
+}
+
+/// A fixture of 1 to `max_leaves` leaves from any seed.
+pub fn fixture(max_leaves: usize) -> impl Strategy<Value = TreeFixture> {
+    (any::<u64>(), 1..=max_leaves).prop_map(|(seed, n)| TreeFixture::with_random_leaves(seed, n))
+}
+
+/// `(root, key, value, proof)` for a leaf of a fixture of up to
+/// `max_leaves` leaves, which verifies.
+pub fn membership_proof(max_leaves: usize) -> impl Strategy<Value = (Hash, Hash, Hash, MerkleProof)> {
+    (fixture(max_leaves), any::<prop::sample::Index>()).prop_map(|(fixture, index)| {
+        let index = index.index(fixture.leaves().len());
+        let (key, value) = fixture.leaves()[index];
+        (fixture.root(), key, value, fixture.proof(index))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_golden_roots() {
+        assert_eq!(TreeFixture::with_random_leaves(3, 0).root(), GOLDEN_EMPTY_ROOT);
+        for (seed, n, root) in GOLDEN_ROOTS {
+            assert_eq!(TreeFixture::with_random_leaves(*seed, *n).root(), *root, "seed {} with {} leaves", seed, n);
+        }
+    }
+
+    #[test]
+    fn test_fixture_reads_back() {
+        let fixture = TreeFixture::with_random_leaves(9, 10);
+        let (key, value) = fixture.leaves()[4];
+        assert_eq!(fixture.tree().get(key).unwrap(), Some(value));
+        assert!(fixture.proof(4).verify(&fixture.root(), &key, &value));
+        assert_eq!(fixture.tree().get(fixture.absent_key()).unwrap(), None);
+    }
+
+    proptest! {
+        #[test]
+        fn test_membership_proofs_verify((root, key, value, proof) in membership_proof(20)) {
+            prop_assert!(proof.verify(&root, &key, &value));
+        }
+
+        #[test]
+        fn test_leaves_have_distinct_keys(leaves in leaves(20)) {
+            prop_assert!(leaves.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        }
+    }
+}
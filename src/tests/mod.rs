@@ -1 +1,2 @@
 pub mod sparse_merkle_tree_tests;
+pub mod no_panic_tests;
@@ -0,0 +1,103 @@
+use proptest::prelude::*;
+
+use crate::{
+    checkpoint::CheckpointDocument,
+    chunk::SnapshotChunk,
+    diff::DiffProof,
+    error::SMTError,
+    kv_store::{InMemoryKVStore, KVStore},
+    node::NodeCodec,
+    op::Op,
+    proof::{CompressedMerkleProof, MerkleProof, MultiProof, NonMembershipProof},
+    range::RangeProof,
+    sparse_merkle_tree::SparseMerkleTree,
+    Hash,
+};
+
+// Everything below feeds untrusted bytes, proofs or store contents into the
+// tree and only checks that it answers with a value or an error, never a panic.
+
+fn tree(keys: &[u8]) -> SparseMerkleTree<InMemoryKVStore> {
+    let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+    for key in keys {
+        smt.update([*key; 32], [key.wrapping_add(1); 32]).unwrap();
+    }
+    smt
+}
+
+proptest! {
+    #[test]
+    fn test_decoders_never_panic(bytes in prop::collection::vec(any::<u8>(), 0..400)) {
+        let _ = MerkleProof::from_bytes(&bytes);
+        let _ = SnapshotChunk::from_bytes(&bytes);
+        let _ = CheckpointDocument::from_bytes(&bytes);
+        let _ = Op::from_bytes(&bytes);
+        let _ = NodeCodec::decode(&bytes);
+    }
+
+    #[test]
+    fn test_merkle_proofs_never_panic(side_nodes in prop::collection::vec(any::<Hash>(), 0..300), root: Hash, key: Hash, value: Hash, leaf: Option<(Hash, Hash)>) {
+        let smt = tree(&[1, 2]);
+        let proof = MerkleProof { side_nodes: side_nodes.clone() };
+        let _ = proof.verify(&root, &key, &value);
+        let _ = smt.verify_proof(key, value, &proof);
+        let absent = NonMembershipProof { side_nodes, leaf };
+        let _ = absent.verify(&root, &key);
+        let _ = smt.verify_non_membership_proof(key, &absent);
+    }
+
+    #[test]
+    fn test_compressed_proofs_never_panic(depth: u16, bitmap: [u8; 32], side_nodes in prop::collection::vec(any::<Hash>(), 0..8), root: Hash, key: Hash, value: Hash) {
+        let proof = CompressedMerkleProof { depth, bitmap, side_nodes };
+        let _ = proof.decompress();
+        let _ = proof.verify(&root, &key, &value);
+    }
+
+    #[test]
+    fn test_batch_proofs_never_panic(
+        len in 0u32..600,
+        bitmap in prop::collection::vec(any::<u8>(), 0..80),
+        side_nodes in prop::collection::vec(any::<Hash>(), 0..8),
+        depth: u16,
+        entries in prop::collection::vec(any::<(Hash, Hash)>(), 0..4),
+        root: Hash,
+        start: Hash,
+        end: Hash,
+    ) {
+        let siblings = MultiProof { len, bitmap, side_nodes, depth };
+        let _ = siblings.verify(&root, &entries);
+        let range = RangeProof { entries: entries.clone(), siblings: siblings.clone() };
+        let _ = range.verify(&root, &start, &end);
+        let old_values = entries.iter().map(|(key, value)| (*key, Some(*value))).collect();
+        let _ = DiffProof { old_values, siblings }.verify(&root, &start, &entries);
+    }
+
+    #[test]
+    fn test_corrupt_nodes_never_panic(garbage in prop::collection::vec(any::<u8>(), 0..100), key: u8) {
+        let mut smt = tree(&[0x10, 0x90, key]);
+        let old_root = smt.root();
+        smt.update([0x42; 32], [7u8; 32]).unwrap();
+        let root = smt.root();
+        smt.store.set_node(root, garbage.clone()).unwrap();
+
+        let got = smt.get([key; 32]);
+        let proof = smt.get_proof([key; 32]);
+        let absent = smt.get_non_membership_proof([0x55; 32]);
+        if NodeCodec::decode_internal(&garbage).is_none() {
+            // A root record that is not an internal node is reported, not read as empty
+            let corrupt = |error: &SMTError| matches!(error, SMTError::CorruptNode { hash, len } if *hash == root && *len == garbage.len());
+            prop_assert!(got.as_ref().is_err_and(corrupt));
+            prop_assert!(proof.as_ref().is_err_and(corrupt));
+            prop_assert!(absent.as_ref().is_err_and(corrupt));
+        }
+        let _ = smt.prove([key; 32]);
+        let _ = smt.iter().collect::<Result<Vec<_>, _>>();
+        let _ = smt.prove_range([0u8; 32], [0xff; 32]);
+        let _ = smt.prove_update_batch(root, &[([key; 32], [9u8; 32])]);
+        let reverted = smt.revert_to(old_root);
+        if garbage.len() != 64 {
+            let corrupt = matches!(reverted, Err(SMTError::CorruptNode { len, .. }) if len == garbage.len());
+            prop_assert!(corrupt);
+        }
+    }
+}
@@ -20,7 +20,7 @@ proptest! {
         smt.update(key, value).unwrap();
         let proof = smt.get_proof(key).unwrap();
 
-        prop_assert!(smt.verify_proof(key, value, &proof));
+        prop_assert!(smt.verify_proof(key, value, &proof).is_ok());
     }
 
     #[test]
@@ -35,7 +35,7 @@ proptest! {
         for (key, value) in &inserts {
             prop_assert_eq!(smt.get(*key).unwrap(), Some(*value));
             let proof = smt.get_proof(*key).unwrap();
-            prop_assert!(smt.verify_proof(*key, *value, &proof));
+            prop_assert!(smt.verify_proof(*key, *value, &proof).is_ok());
         }
     }
 }
@@ -1,25 +1,7 @@
-use crate::{kv_store::InMemoryKVStore, sparse_merkle_tree::SparseMerkleTree, Hash};
-use tracing_subscriber;
-
-
-fn init_logger() {
-    #[cfg(feature = "debug-logs")]
-    {
-        // Initialize the logger with debug level if the feature is enabled
-        let _ = tracing_subscriber::fmt()
-            .with_max_level(tracing::Level::DEBUG)
-            .try_init();
-    }
-
-    #[cfg(not(feature = "debug-logs"))]
-    {
-        // Initialize the logger with info level by default
-        let _ = tracing_subscriber::fmt()
-            .with_max_level(tracing::Level::INFO)
-            .try_init();
-    }
-}
-
+use crate::{error::SMTError, kv_store::{InMemoryKVStore, KVStore, TreeWriteBatch}, observer::TreeObserver, op::Op, proof::{verify_multiproof, KeyProof}, range::verify_range, sparse_merkle_tree::SparseMerkleTree, tree_hasher::TreeHasher, Hash};
+use sha3::Sha3_256;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
 
 #[test]
 fn test_insert_and_get() {
@@ -57,10 +39,10 @@ fn test_proof_verification() {
     let proof = smt.get_proof(key).unwrap(); // Generate proof
 
     // Assert
-    assert!(smt.verify_proof(key, value, &proof)); // Correct proof verification
+    assert!(smt.verify_proof(key, value, &proof).is_ok()); // Correct proof verification
     // Expected output: true (proof verification succeeds)
 
-    assert!(!smt.verify_proof(key, [3u8; 32], &proof)); // Incorrect value should fail
+    assert!(smt.verify_proof(key, [3u8; 32], &proof).is_err()); // Incorrect value should fail
     // Expected output: false (proof verification fails for wrong value)
 }
 
@@ -82,15 +64,15 @@ fn test_empty_tree() {
 #[test]
 fn test_new_tree_is_empty() {
     // Test case: Check the root of a new, empty tree.
-    // Expected output: The root hash should be all zeroes.
+    // Expected output: The root hash should be the default hash of an empty 256-level subtree.
 
     // Arrange
     let store = InMemoryKVStore::new();
     let smt = SparseMerkleTree::new(store);
 
     // Assert
-    assert_eq!(smt.root(), [0u8; 32]); // Root of empty tree
-    // Expected output: [0, 0, ..., 0] (32 bytes of zeroes)
+    assert_eq!(smt.root(), smt.hasher.default_hashes().at(256)); // Root of empty tree
+    assert!(smt.is_empty());
 }
 
 #[test]
@@ -197,11 +179,914 @@ fn test_consistency_after_multiple_updates() {
         // Assert
         assert_eq!(smt.get(key).unwrap(), Some(value)); // Check value consistency
         let proof = smt.get_proof(key).unwrap();
-        assert!(smt.verify_proof(key, value, &proof)); // Proof should verify correctly
+        assert!(smt.verify_proof(key, value, &proof).is_ok()); // Proof should verify correctly
         // Expected output: Value and proof are correct for each iteration
     }
 }
 
+#[test]
+fn test_cloned_tree_is_independent() {
+    // Test case: Fork a tree and mutate the fork.
+    // Expected output: The original tree keeps its root and values.
+
+    // Arrange
+    let smt = setup_tree();
+    let original_root = smt.root();
+    let mut fork = smt.clone();
+    let key: Hash = [8u8; 32];
+    let value: Hash = [80u8; 32];
+
+    // Act
+    fork.update(key, value).unwrap(); // Mutate only the fork
+
+    // Assert
+    assert_eq!(fork.get(key).unwrap(), Some(value));
+    assert_eq!(smt.get(key).unwrap(), None); // Original does not see the write
+    assert_eq!(smt.root(), original_root);
+    assert_ne!(fork.root(), original_root);
+}
+
+#[test]
+fn test_second_insert_keeps_first_key_provable() {
+    // Test case: Insert two keys and prove the first one against the final root.
+    // Expected output: Both proofs verify; the first key is not detached by the second insert.
+
+    // Arrange
+    let store = InMemoryKVStore::new();
+    let mut smt = SparseMerkleTree::new(store);
+    let key1: Hash = [1u8; 32];
+    let value1: Hash = [10u8; 32];
+    let key2: Hash = [2u8; 32];
+    let value2: Hash = [20u8; 32];
+
+    // Act
+    smt.update(key1, value1).unwrap();
+    smt.update(key2, value2).unwrap();
+
+    // Assert
+    let proof1 = smt.get_proof(key1).unwrap();
+    let proof2 = smt.get_proof(key2).unwrap();
+    assert!(smt.verify_proof(key1, value1, &proof1).is_ok());
+    assert!(smt.verify_proof(key2, value2, &proof2).is_ok());
+    assert!(smt.verify_proof(key1, value2, &proof1).is_err()); // Proofs are still value-specific
+}
+
+#[test]
+fn test_adjacent_keys_share_path() {
+    // Test case: Insert keys that differ only in their last bit.
+    // Expected output: Both stay provable even though their paths share 255 nodes.
+
+    // Arrange
+    let store = InMemoryKVStore::new();
+    let mut smt = SparseMerkleTree::new(store);
+    let key1: Hash = [0u8; 32];
+    let mut key2: Hash = [0u8; 32];
+    key2[31] = 1;
+
+    // Act
+    smt.update(key1, [1u8; 32]).unwrap();
+    smt.update(key2, [2u8; 32]).unwrap();
+
+    // Assert
+    assert!(smt.verify_proof(key1, [1u8; 32], &smt.get_proof(key1).unwrap()).is_ok());
+    assert!(smt.verify_proof(key2, [2u8; 32], &smt.get_proof(key2).unwrap()).is_ok());
+}
+
+#[test]
+fn test_root_is_independent_of_insert_order() {
+    // Test case: Insert the same entries into two trees in opposite orders.
+    // Expected output: Both trees end with the same root.
+
+    // Arrange
+    let entries: Vec<(Hash, Hash)> = (0..20u8).map(|i| ([i; 32], [i.wrapping_mul(3); 32])).collect();
+    let mut forward = SparseMerkleTree::new(InMemoryKVStore::new());
+    let mut backward = SparseMerkleTree::new(InMemoryKVStore::new());
+
+    // Act
+    for (key, value) in &entries {
+        forward.update(*key, *value).unwrap();
+    }
+    for (key, value) in entries.iter().rev() {
+        backward.update(*key, *value).unwrap();
+    }
+
+    // Assert
+    assert_eq!(forward.root(), backward.root());
+}
+
+#[test]
+fn test_interleaved_updates_and_overwrites() {
+    // Test case: Interleave inserts of new keys with overwrites of existing ones.
+    // Expected output: After every step, all live keys return their latest value and prove correctly.
+
+    // Arrange
+    let mut smt = setup_tree();
+    let mut expected: HashMap<Hash, Hash> = HashMap::new();
+    expected.insert([1u8; 32], [10u8; 32]);
+    expected.insert([2u8; 32], [20u8; 32]);
+
+    // Act & Assert
+    for round in 0..5u8 {
+        for i in 0..6u8 {
+            let key: Hash = [i * 7 + 1; 32];
+            let value: Hash = [round.wrapping_mul(31).wrapping_add(i); 32];
+            smt.update(key, value).unwrap();
+            expected.insert(key, value);
+
+            for (key, value) in &expected {
+                assert_eq!(smt.get(*key).unwrap(), Some(*value));
+                let proof = smt.get_proof(*key).unwrap();
+                assert!(smt.verify_proof(*key, *value, &proof).is_ok(), "Stale proof for key {:?}", key);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_overwrite_restores_previous_root() {
+    // Test case: Overwrite a key and then write its original value back.
+    // Expected output: The root returns to the value it had before the overwrite.
+
+    // Arrange
+    let mut smt = setup_tree();
+    let key: Hash = [1u8; 32];
+    let original_root = smt.root();
+
+    // Act
+    smt.update(key, [99u8; 32]).unwrap();
+    let changed_root = smt.root();
+    smt.update(key, [10u8; 32]).unwrap(); // setup_tree's original value
+
+    // Assert
+    assert_ne!(changed_root, original_root);
+    assert_eq!(smt.root(), original_root);
+}
+
+#[test]
+fn test_non_membership_proof_for_absent_key() {
+    // Test case: Prove that a key which was never inserted is absent.
+    // Expected output: The proof verifies against the current root.
+
+    // Arrange
+    let smt = setup_tree();
+    let absent_key: Hash = [99u8; 32];
+
+    // Act
+    let proof = smt.get_non_membership_proof(absent_key).unwrap().expect("Key should be absent");
+
+    // Assert
+    assert!(smt.verify_non_membership_proof(absent_key, &proof).is_ok());
+    assert!(proof.side_nodes.len() < 256); // Stops at the first empty subtree
+}
+
+#[test]
+fn test_non_membership_proof_for_neighbouring_key() {
+    // Test case: Prove absence of a key that shares all but the last bit with a present key.
+    // Expected output: A full-depth proof that verifies, while the present key gets no proof.
+
+    // Arrange
+    let mut smt = setup_tree();
+    let present_key: Hash = [0u8; 32];
+    let mut absent_key: Hash = [0u8; 32];
+    absent_key[31] = 1;
+    smt.update(present_key, [5u8; 32]).unwrap();
+
+    // Act
+    let proof = smt.get_non_membership_proof(absent_key).unwrap().expect("Key should be absent");
+
+    // Assert
+    assert_eq!(proof.side_nodes.len(), 256);
+    assert!(smt.verify_non_membership_proof(absent_key, &proof).is_ok());
+    assert!(smt.get_non_membership_proof(present_key).unwrap().is_none());
+}
+
+#[test]
+fn test_non_membership_proof_fails_after_insert() {
+    // Test case: Insert the key after generating its non-membership proof.
+    // Expected output: The old proof no longer verifies against the new root.
+
+    // Arrange
+    let mut smt = setup_tree();
+    let key: Hash = [42u8; 32];
+    let proof = smt.get_non_membership_proof(key).unwrap().unwrap();
+
+    // Act
+    smt.update(key, [1u8; 32]).unwrap();
+
+    // Assert
+    assert!(smt.verify_non_membership_proof(key, &proof).is_err());
+    assert!(smt.get_non_membership_proof(key).unwrap().is_none());
+}
+
+#[test]
+fn test_non_membership_proof_cannot_be_reused_for_other_key() {
+    // Test case: Use an absence proof for a present key.
+    // Expected output: Verification fails.
+
+    // Arrange
+    let smt = setup_tree();
+    let absent_key: Hash = [200u8; 32];
+    let present_key: Hash = [1u8; 32];
+    let proof = smt.get_non_membership_proof(absent_key).unwrap().unwrap();
+
+    // Assert
+    assert!(smt.verify_non_membership_proof(present_key, &proof).is_err());
+}
+
+#[test]
+fn test_update_batch_matches_sequential_updates() {
+    // Test case: Apply the same writes one by one and as a single batch.
+    // Expected output: Both trees end with the same root and the same proofs verify.
+
+    // Arrange
+    let entries: Vec<(Hash, Hash)> = (0..50u8).map(|i| ([i.wrapping_mul(37); 32], [i; 32])).collect();
+    let mut sequential = setup_tree();
+    let mut batched = setup_tree();
+
+    // Act
+    for (key, value) in &entries {
+        sequential.update(*key, *value).unwrap();
+    }
+    let root = batched.update_batch(&entries).unwrap();
+
+    // Assert
+    assert_eq!(root, sequential.root());
+    assert_eq!(batched.root(), sequential.root());
+    for (key, value) in &entries {
+        assert_eq!(batched.get(*key).unwrap(), Some(*value));
+        let proof = batched.get_proof(*key).unwrap();
+        assert!(batched.verify_proof(*key, *value, &proof).is_ok());
+    }
+}
+
+#[test]
+fn test_update_batch_last_write_wins() {
+    // Test case: Batch containing the same key twice.
+    // Expected output: The later value is stored.
+
+    // Arrange
+    let mut smt = setup_tree();
+    let key: Hash = [9u8; 32];
+
+    // Act
+    smt.update_batch(&[(key, [1u8; 32]), (key, [2u8; 32])]).unwrap();
+
+    // Assert
+    assert_eq!(smt.get(key).unwrap(), Some([2u8; 32]));
+}
+
+#[test]
+fn test_update_batch_empty_keeps_root() {
+    // Test case: Apply an empty batch.
+    // Expected output: The root is unchanged.
+
+    // Arrange
+    let mut smt = setup_tree();
+    let root = smt.root();
+
+    // Act & Assert
+    assert_eq!(smt.update_batch(&[]).unwrap(), root);
+}
+
+#[test]
+fn test_spec_describes_empty_tree() {
+    // Test case: Read the hashing spec of a new tree.
+    // Expected output: The spec's empty root equals the root of an empty tree.
+
+    // Arrange
+    let smt = SparseMerkleTree::new(InMemoryKVStore::new());
+
+    // Act
+    let spec = smt.spec();
+
+    // Assert
+    assert_eq!(spec.empty_root, smt.root());
+    assert_eq!(spec.depth, 256);
+    assert_eq!(spec.hasher_id, "sha256");
+}
+
+
+#[test]
+fn test_delete_only_key_empties_tree() {
+    // Test case: Delete the only key in the tree.
+    // Expected output: The root returns to the empty root and the key is gone.
+
+    // Arrange
+    let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+    let key: Hash = [1u8; 32];
+    smt.update(key, [2u8; 32]).unwrap();
+
+    // Act
+    smt.delete(key).unwrap();
+
+    // Assert
+    assert_eq!(smt.root(), SparseMerkleTree::new(InMemoryKVStore::new()).root());
+    assert_eq!(smt.get(key).unwrap(), None);
+}
+
+#[test]
+fn test_delete_keeps_other_keys_provable() {
+    // Test case: Insert two keys and delete one of them.
+    // Expected output: The root matches the tree with only the kept key, whose proof still verifies.
+
+    // Arrange
+    let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+    let kept: Hash = [1u8; 32];
+    let deleted: Hash = [2u8; 32];
+    smt.update(kept, [10u8; 32]).unwrap();
+    let root_before = smt.root();
+    smt.update(deleted, [20u8; 32]).unwrap();
+
+    // Act
+    smt.delete(deleted).unwrap();
+
+    // Assert
+    assert_eq!(smt.root(), root_before);
+    assert_eq!(smt.get(deleted).unwrap(), None);
+    let proof = smt.get_proof(kept).unwrap();
+    assert!(smt.verify_proof(kept, [10u8; 32], &proof).is_ok());
+}
+
+#[test]
+fn test_delete_missing_key_is_noop() {
+    // Test case: Delete a key that was never inserted.
+    // Expected output: The root is unchanged.
+
+    // Arrange
+    let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+    smt.update([1u8; 32], [10u8; 32]).unwrap();
+    let root = smt.root();
+
+    // Act
+    smt.delete([3u8; 32]).unwrap();
+
+    // Assert
+    assert_eq!(smt.root(), root);
+}
+
+#[test]
+fn test_tree_with_custom_hasher() {
+    // Test case: Build a tree hashing with SHA3-256 instead of the default hasher.
+    // Expected output: Its proofs verify with a SHA3 hasher and its root differs from the default tree.
+
+    // Arrange
+    let mut sha3_tree = SparseMerkleTree::<_, Sha3_256>::with_hasher(InMemoryKVStore::new());
+    let mut default_tree = SparseMerkleTree::new(InMemoryKVStore::new());
+    let key: Hash = [1u8; 32];
+    let value: Hash = [2u8; 32];
+
+    // Act
+    sha3_tree.update(key, value).unwrap();
+    default_tree.update(key, value).unwrap();
+
+    // Assert
+    let proof = sha3_tree.get_proof(key).unwrap();
+    assert!(proof.verify_with(&TreeHasher::<Sha3_256>::new(), &sha3_tree.root(), &key, &value));
+    assert!(sha3_tree.verify_proof(key, value, &proof).is_ok());
+    assert_ne!(sha3_tree.root(), default_tree.root());
+}
+
+#[test]
+fn test_compressed_proof_verifies() {
+    // Test case: Compress a proof from a tree holding a handful of keys.
+    // Expected output: The compressed proof is much smaller and verifies like the full one.
+
+    // Arrange
+    let mut smt = setup_tree();
+    let key: Hash = [2u8; 32];
+    let value = smt.get(key).unwrap().unwrap();
+    smt.update([3u8; 32], [30u8; 32]).unwrap();
+
+    // Act
+    let proof = smt.get_proof(key).unwrap();
+    let compressed = proof.compress();
+
+    // Assert
+    assert!(compressed.side_nodes.len() < 8);
+    assert!(compressed.verify(&smt.root(), &key, &value));
+    assert!(!compressed.verify(&smt.root(), &key, &[0u8; 32]));
+    assert!(smt.verify_proof(key, value, &compressed.decompress().unwrap()).is_ok());
+}
+
+#[test]
+fn test_multiproof_verifies_all_keys() {
+    // Test case: Prove several keys with one multiproof.
+    // Expected output: The proof verifies for the right values in any order and rejects a wrong value.
+
+    // Arrange
+    let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+    let entries: Vec<(Hash, Hash)> = (0..16u8).map(|i| ([i.wrapping_mul(37); 32], [i; 32])).collect();
+    smt.update_batch(&entries).unwrap();
+    let keys: Vec<Hash> = entries.iter().rev().map(|(key, _)| *key).collect();
+
+    // Act
+    let proof = smt.get_multiproof(&keys).unwrap();
+
+    // Assert
+    assert!(verify_multiproof(&smt.root(), &entries, &proof));
+    let mut wrong = entries.clone();
+    wrong[3].1 = [99u8; 32];
+    assert!(!verify_multiproof(&smt.root(), &wrong, &proof));
+    assert!(!verify_multiproof(&smt.root(), &entries[1..], &proof));
+}
+
+#[test]
+fn test_multiproof_shares_side_nodes() {
+    // Test case: Compare a multiproof for two keys with their individual proofs.
+    // Expected output: The multiproof carries fewer non-zero side nodes than the two proofs combined.
+
+    // Arrange
+    let smt = setup_tree();
+    let keys = [[1u8; 32], [2u8; 32]];
+
+    // Act
+    let proof = smt.get_multiproof(&keys).unwrap();
+
+    // Assert
+    let separate: usize = keys
+        .iter()
+        .map(|key| smt.get_proof(*key).unwrap().compress().side_nodes.len())
+        .sum();
+    assert!(proof.side_nodes.len() < separate);
+    assert!(proof.verify(&smt.root(), &[([1u8; 32], [10u8; 32]), ([2u8; 32], [20u8; 32])]));
+}
+
+#[derive(Default)]
+struct RecordingObserver {
+    events: Mutex<Vec<String>>,
+}
+
+impl TreeObserver for RecordingObserver {
+    fn on_update(&self, key: &Hash, _value: &Hash) {
+        self.events.lock().unwrap().push(format!("update {}", key[0]));
+    }
+
+    fn on_delete(&self, key: &Hash) {
+        self.events.lock().unwrap().push(format!("delete {}", key[0]));
+    }
+
+    fn on_commit(&self, old_root: &Hash, new_root: &Hash) {
+        assert_ne!(old_root, new_root);
+        self.events.lock().unwrap().push("commit".to_string());
+    }
+}
+
+#[test]
+fn test_observer_sees_updates_and_deletes() {
+    // Test case: Register an observer and run an update, a batch and a delete.
+    // Expected output: Each leaf change is reported, followed by one commit per operation.
+
+    // Arrange
+    let observer = Arc::new(RecordingObserver::default());
+    let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+    smt.add_observer(observer.clone());
+
+    // Act
+    smt.update([1u8; 32], [10u8; 32]).unwrap();
+    smt.update_batch(&[([2u8; 32], [20u8; 32]), ([3u8; 32], [30u8; 32])]).unwrap();
+    smt.delete([1u8; 32]).unwrap();
+
+    // Assert
+    let events = observer.events.lock().unwrap();
+    assert_eq!(
+        *events,
+        ["update 1", "commit", "update 2", "update 3", "commit", "delete 1", "commit"]
+    );
+}
+
+#[test]
+fn test_revert_to_earlier_root() {
+    // Test case: Overwrite, add and delete keys, then revert to the earlier root.
+    // Expected output: Root, values and proofs match the earlier state again.
+
+    // Arrange
+    let mut smt = setup_tree();
+    let root = smt.root();
+    smt.update([1u8; 32], [11u8; 32]).unwrap();
+    smt.update([3u8; 32], [30u8; 32]).unwrap();
+    smt.delete([2u8; 32]).unwrap();
+
+    // Act
+    smt.revert_to(root).unwrap();
+
+    // Assert
+    assert_eq!(smt.root(), root);
+    assert_eq!(smt.get([1u8; 32]).unwrap(), Some([10u8; 32]));
+    assert_eq!(smt.get([2u8; 32]).unwrap(), Some([20u8; 32]));
+    assert_eq!(smt.get([3u8; 32]).unwrap(), None);
+    let proof = smt.get_proof([2u8; 32]).unwrap();
+    assert!(smt.verify_proof([2u8; 32], [20u8; 32], &proof).is_ok());
+}
+
+#[test]
+fn test_revert_to_unknown_root_fails() {
+    // Test case: Revert to a root the tree never had.
+    // Expected output: A MissingNode error, with the tree left as it was.
+
+    // Arrange
+    let mut smt = setup_tree();
+    let root = smt.root();
+
+    // Act
+    let result = smt.revert_to([9u8; 32]);
+
+    // Assert
+    assert!(matches!(result, Err(SMTError::MissingNode(node)) if node == [9u8; 32]));
+    assert_eq!(smt.root(), root);
+    assert_eq!(smt.get([1u8; 32]).unwrap(), Some([10u8; 32]));
+}
+
+#[test]
+fn test_missing_node_is_reported() {
+    // Test case: The root's record is gone from the store, as after a bad prune.
+    // Expected output: Reads, proofs and writes fail with MissingNode instead of seeing an empty tree.
+
+    // Arrange
+    let mut smt = setup_tree();
+    let root = smt.root();
+    smt.store.remove_node(&root).unwrap();
+
+    // Act
+    let got = smt.get([1u8; 32]);
+    let proof = smt.get_proof([1u8; 32]);
+    let absent = smt.get_non_membership_proof([9u8; 32]);
+    let updated = smt.update([9u8; 32], [90u8; 32]);
+
+    // Assert
+    assert!(matches!(got, Err(SMTError::MissingNode(node)) if node == root));
+    assert!(matches!(proof, Err(SMTError::MissingNode(node)) if node == root));
+    assert!(matches!(absent, Err(SMTError::MissingNode(node)) if node == root));
+    assert!(matches!(updated, Err(SMTError::MissingNode(node)) if node == root));
+    assert_eq!(smt.root(), root);
+}
+
+#[test]
+fn test_truncated_leaf_record_is_corrupt() {
+    // Test case: A leaf record in the store loses its last bytes.
+    // Expected output: Reads and proofs through it fail with CorruptNode.
+
+    // Arrange
+    let mut smt = setup_tree();
+    let leaf = smt.hasher.digest_leaf(&[1u8; 32], &[10u8; 32]);
+    let mut record = smt.store.get_node(&leaf).unwrap().unwrap();
+    record.truncate(40);
+    smt.store.set_node(leaf, record).unwrap();
+
+    // Act
+    let got = smt.get([1u8; 32]);
+    let absent = smt.get_non_membership_proof([1u8; 32]);
+
+    // Assert
+    assert!(matches!(got, Err(SMTError::CorruptNode { hash, len: 40 }) if hash == leaf));
+    assert!(matches!(absent, Err(SMTError::CorruptNode { hash, len: 40 }) if hash == leaf));
+    assert_eq!(smt.get([2u8; 32]).unwrap(), Some([20u8; 32]));
+}
+
+#[test]
+fn test_records_of_the_wrong_kind_are_corrupt() {
+    // Test case: In a one-level tree, store an internal record under the leaf's hash, then bare children, then a leaf record under the root's.
+    // Expected output: Both reads fail with CorruptNode naming the record read, not a made-up leaf or pair of children.
+
+    // Arrange
+    let mut smt = SparseMerkleTree::new(InMemoryKVStore::new()).with_depth(1);
+    let key: Hash = [0u8; 32];
+    smt.update(key, [5u8; 32]).unwrap();
+    let root = smt.root();
+    let leaf = smt.hasher.digest_leaf(&key, &[5u8; 32]);
+    let internal_record = smt.store.get_node(&root).unwrap().unwrap();
+    let leaf_record = smt.store.get_node(&leaf).unwrap().unwrap();
+
+    // Act
+    smt.store.set_node(leaf, internal_record.clone()).unwrap();
+    let internal_as_leaf = smt.get(key);
+    smt.store.set_node(leaf, internal_record[2..].to_vec()).unwrap();
+    let bare_as_leaf = smt.get(key);
+    smt.store.set_node(leaf, leaf_record.clone()).unwrap();
+    smt.store.set_node(root, leaf_record).unwrap();
+    let leaf_as_internal = smt.get_proof(key);
+
+    // Assert
+    let len = internal_record.len();
+    assert!(matches!(internal_as_leaf, Err(SMTError::CorruptNode { hash, len: l }) if hash == leaf && l == len));
+    assert!(matches!(bare_as_leaf, Err(SMTError::CorruptNode { hash, len: 64 }) if hash == leaf));
+    assert!(matches!(leaf_as_internal, Err(SMTError::CorruptNode { hash, len: l }) if hash == root && l == len));
+}
+
+#[test]
+fn test_nested_checkpoints_roll_back() {
+    // Test case: Take two checkpoints with writes after each, then roll back one at a time.
+    // Expected output: Each rollback restores the state at its checkpoint.
+
+    // Arrange
+    let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+    let outer = smt.checkpoint();
+    smt.update([1u8; 32], [10u8; 32]).unwrap();
+    let inner = smt.checkpoint();
+    smt.update([1u8; 32], [11u8; 32]).unwrap();
+
+    // Act
+    smt.rollback(inner).unwrap();
+    let after_inner = smt.get([1u8; 32]).unwrap();
+    smt.rollback(outer).unwrap();
+
+    // Assert
+    assert_eq!(after_inner, Some([10u8; 32]));
+    assert_eq!(smt.root(), outer.root());
+    assert_eq!(smt.get([1u8; 32]).unwrap(), None);
+}
+
+/// Store whose node reads start failing once a budget runs out.
+#[derive(Clone)]
+struct FlakyStore {
+    inner: InMemoryKVStore,
+    node_reads_left: std::cell::Cell<usize>,
+}
+
+impl KVStore for FlakyStore {
+    type Error = std::io::Error;
+
+    fn get(&self, key: &Hash) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.inner.get(key)
+    }
+
+    fn set(&mut self, key: Hash, value: Vec<u8>) -> Result<(), Self::Error> {
+        self.inner.set(key, value)
+    }
+
+    fn remove(&mut self, key: &Hash) -> Result<(), Self::Error> {
+        self.inner.remove(key)
+    }
+
+    fn get_node(&self, hash: &Hash) -> Result<Option<Vec<u8>>, Self::Error> {
+        match self.node_reads_left.get() {
+            0 => Err(std::io::Error::other("node read failed")),
+            left => {
+                self.node_reads_left.set(left - 1);
+                self.inner.get_node(hash)
+            }
+        }
+    }
+}
+
+#[test]
+fn test_failed_update_leaves_store_untouched() {
+    // Test case: Make the store fail part way through reading an update's path.
+    // Expected output: The update errors, and neither the tree nor the store sees any of its writes.
+
+    // Arrange
+    let mut smt = SparseMerkleTree::new(FlakyStore {
+        inner: InMemoryKVStore::new(),
+        node_reads_left: std::cell::Cell::new(usize::MAX),
+    });
+    smt.update([1u8; 32], [10u8; 32]).unwrap();
+    let root = smt.root();
+    smt.store.node_reads_left.set(3);
+
+    // Act
+    let result = smt.update([2u8; 32], [20u8; 32]);
+
+    // Assert
+    assert!(result.is_err());
+    assert_eq!(smt.root(), root);
+    assert_eq!(smt.store.inner.get(&[2u8; 32]).unwrap(), None);
+    smt.store.node_reads_left.set(usize::MAX);
+    let proof = smt.get_proof([1u8; 32]).unwrap();
+    assert!(smt.verify_proof([1u8; 32], [10u8; 32], &proof).is_ok());
+}
+
+#[test]
+fn test_tree_write_batch_commits_or_aborts() {
+    // Test case: Stage writes in a TreeWriteBatch, abort one batch and commit another.
+    // Expected output: Only the committed batch reaches the store.
+
+    // Arrange
+    let mut store = InMemoryKVStore::new();
+    let mut aborted = TreeWriteBatch::new();
+    aborted.set([1u8; 32], vec![1]);
+    let mut committed = TreeWriteBatch::new();
+    committed.set([2u8; 32], vec![2]);
+    committed.set_node([3u8; 32], vec![3; 64]);
+
+    // Act
+    aborted.abort();
+    committed.commit(&mut store).unwrap();
+
+    // Assert
+    assert_eq!(store.get(&[1u8; 32]).unwrap(), None);
+    assert_eq!(store.get(&[2u8; 32]).unwrap(), Some(vec![2]));
+    assert_eq!(store.get_node(&[3u8; 32]).unwrap(), Some(vec![3; 64]));
+}
+
+#[test]
+fn test_iter_yields_leaves_in_key_order() {
+    // Test case: Insert keys out of order, overwrite one and delete another, then iterate.
+    // Expected output: The remaining leaves come back sorted by key, and len() counts them.
+
+    // Arrange
+    let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+    assert!(smt.is_empty());
+    for key in [5u8, 200, 1, 77] {
+        smt.update([key; 32], [key; 32]).unwrap();
+    }
+    smt.update([77u8; 32], [0u8; 32]).unwrap();
+    smt.delete([200u8; 32]).unwrap();
+
+    // Act
+    let leaves: Vec<(Hash, Hash)> = smt.iter().collect::<Result<_, _>>().unwrap();
+
+    // Assert
+    assert_eq!(leaves, vec![([1u8; 32], [1u8; 32]), ([5u8; 32], [5u8; 32]), ([77u8; 32], [0u8; 32])]);
+    assert_eq!(smt.len().unwrap(), 3);
+    assert!(!smt.is_empty());
+}
+
+#[test]
+fn test_select_with_proof_filters_by_value() {
+    // Test case: Store balances in the first 8 bytes of each value and select those at or above a threshold.
+    // Expected output: Only the matching leaves come back, in key order, and one multiproof covers them.
+
+    // Arrange
+    let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+    for (key, balance) in [(1u8, 500u64), (2, 50), (3, 1000), (4, 999)] {
+        let mut value = [0u8; 32];
+        value[..8].copy_from_slice(&balance.to_be_bytes());
+        smt.update([key; 32], value).unwrap();
+    }
+    let balance = |value: &Hash| u64::from_be_bytes(value[..8].try_into().unwrap());
+
+    // Act
+    let (selected, proof) = smt.select_with_proof(|_, value| balance(value) >= 999).unwrap();
+
+    // Assert
+    let keys: Vec<Hash> = selected.iter().map(|(key, _)| *key).collect();
+    assert_eq!(keys, vec![[3u8; 32], [4u8; 32]]);
+    assert!(verify_multiproof(&smt.root(), &selected, &proof));
+    assert!(smt.select_with_proof(|_, _| false).unwrap().0.is_empty());
+}
+
+#[test]
+fn test_shallow_tree_for_address_keys() {
+    // Test case: Build a 160-level tree over 20-byte addresses padded to 32 bytes.
+    // Expected output: Proofs carry 160 side nodes and verify, and the root differs from a full-depth tree.
+
+    // Arrange
+    let address = |byte: u8| {
+        let mut key = [0u8; 32];
+        key[..20].fill(byte);
+        key
+    };
+    let mut shallow = SparseMerkleTree::new(InMemoryKVStore::new()).with_depth(160);
+    let mut full = SparseMerkleTree::new(InMemoryKVStore::new());
+
+    // Act
+    for byte in [1u8, 2, 0x80] {
+        shallow.update(address(byte), [byte; 32]).unwrap();
+        full.update(address(byte), [byte; 32]).unwrap();
+    }
+    shallow.delete(address(2)).unwrap();
+    full.delete(address(2)).unwrap();
+
+    // Assert
+    assert_eq!(shallow.depth(), 160);
+    assert_eq!(shallow.spec().depth, 160);
+    assert_ne!(shallow.root(), full.root());
+    assert_eq!(shallow.get(address(1)).unwrap(), Some([1u8; 32]));
+
+    let proof = shallow.get_proof(address(1)).unwrap();
+    assert_eq!(proof.side_nodes.len(), 160);
+    assert!(proof.verify(&shallow.root(), &address(1), &[1u8; 32]));
+
+    let entries = [(address(1), [1u8; 32]), (address(0x80), [0x80u8; 32])];
+    let multiproof = shallow.get_multiproof(&[address(1), address(0x80)]).unwrap();
+    assert_eq!(multiproof.depth, 160);
+    assert!(verify_multiproof(&shallow.root(), &entries, &multiproof));
+
+    let range = shallow.prove_range(address(0), address(0x7f)).unwrap();
+    assert_eq!(range.entries, vec![entries[0]]);
+    assert!(verify_range(&shallow.root(), &address(0), &address(0x7f), &range));
+
+    let leaves: Vec<(Hash, Hash)> = shallow.iter().collect::<Result<_, _>>().unwrap();
+    assert_eq!(leaves, entries.to_vec());
+}
+
+#[test]
+fn test_empty_subtrees_hash_to_default_hashes() {
+    // Test case: Check the default hash table and that proofs carry its entries for empty siblings.
+    // Expected output: Each level hashes two copies of the one below, and a lone leaf's siblings are the defaults.
+
+    // Arrange
+    let hasher = TreeHasher::<sha2::Sha256>::new();
+    let defaults = hasher.default_hashes();
+    let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+    let key: Hash = [1u8; 32];
+
+    // Act
+    smt.update(key, [2u8; 32]).unwrap();
+    let proof = smt.get_proof(key).unwrap();
+
+    // Assert
+    assert_eq!(defaults.at(0), [0u8; 32]);
+    for height in 1..=256 {
+        assert_eq!(defaults.at(height), hasher.digest_node(&defaults.at(height - 1), &defaults.at(height - 1)));
+        assert_eq!(defaults.height_of(&defaults.at(height)), Some(height));
+    }
+    assert_eq!(defaults.height_of(&smt.root()), None);
+    let expected: Vec<Hash> = (0..256).rev().map(|height| defaults.at(height)).collect();
+    assert_eq!(proof.side_nodes, expected);
+    assert!(proof.compress().side_nodes.is_empty());
+
+    let shallow = SparseMerkleTree::new(InMemoryKVStore::new()).with_depth(160);
+    assert_eq!(shallow.root(), defaults.at(160));
+    assert_eq!(shallow.spec().empty_root, defaults.at(160));
+}
+
+#[test]
+fn test_delete_restores_default_subtrees() {
+    // Test case: Insert keys sharing a long prefix, then delete all but one.
+    // Expected output: The root matches a tree that only ever held the remaining key.
+
+    // Arrange
+    let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+    let mut single = SparseMerkleTree::new(InMemoryKVStore::new());
+    let mut neighbour: Hash = [1u8; 32];
+    neighbour[31] ^= 1;
+
+    // Act
+    smt.update([1u8; 32], [1u8; 32]).unwrap();
+    smt.update(neighbour, [2u8; 32]).unwrap();
+    smt.update([0x80; 32], [3u8; 32]).unwrap();
+    smt.delete(neighbour).unwrap();
+    smt.delete([0x80; 32]).unwrap();
+    single.update([1u8; 32], [1u8; 32]).unwrap();
+
+    // Assert
+    assert_eq!(smt.root(), single.root());
+    let proof = smt.get_non_membership_proof(neighbour).unwrap().unwrap();
+    assert!(proof.verify(&smt.root(), &neighbour));
+}
+
+#[test]
+fn test_key_sharing_a_leaf_path_is_proven_absent() {
+    // Test case: In an 8-level tree, query a key whose path ends at another key's leaf.
+    // Expected output: get returns None, and prove emits a non-membership proof naming the occupying leaf.
+
+    // Arrange
+    let mut smt = SparseMerkleTree::new(InMemoryKVStore::new()).with_depth(8);
+    let mut stored: Hash = [0u8; 32];
+    stored[0] = 1;
+    let mut shadowed = stored;
+    shadowed[31] = 2; // Same first byte, so the same leaf
+
+    // Act
+    smt.update(stored, [10u8; 32]).unwrap();
+    let proof = smt.prove(shadowed).unwrap();
+
+    // Assert
+    assert_eq!(smt.get(stored).unwrap(), Some([10u8; 32]));
+    assert_eq!(smt.get(shadowed).unwrap(), None);
+    let KeyProof::NonMembership(absent) = proof else {
+        panic!("expected a non-membership proof");
+    };
+    assert_eq!(absent.leaf, Some((stored, [10u8; 32])));
+    assert!(smt.verify_non_membership_proof(shadowed, &absent).is_ok());
+    assert!(smt.verify_non_membership_proof(stored, &absent).is_err());
+    assert!(smt.get_non_membership_proof(stored).unwrap().is_none());
+
+    let present = smt.prove(stored).unwrap();
+    assert_eq!(present.value(), Some([10u8; 32]));
+
+    // The shadowed key cannot be written, so it never replaces the leaf.
+    assert!(matches!(smt.update(shadowed, [20u8; 32]), Err(SMTError::KeyBeyondDepth { .. })));
+    assert_eq!(smt.iter().collect::<Result<Vec<_>, _>>().unwrap(), vec![(stored, [10u8; 32])]);
+}
+
+#[test]
+fn test_prove_covers_present_and_absent_keys() {
+    // Test case: Prove a stored key and a key whose path ends in an empty subtree.
+    // Expected output: A verifying membership proof with the value, and a verifying non-membership proof.
+
+    // Arrange
+    let smt = setup_tree();
+    let key: Hash = [1u8; 32];
+    let missing: Hash = [0xeeu8; 32];
+
+    // Act
+    let present = smt.prove(key).unwrap();
+    let absent = smt.prove(missing).unwrap();
+
+    // Assert
+    assert_eq!(present.value(), smt.get(key).unwrap());
+    assert!(present.verify(&smt.root(), &key));
+    assert!(!present.verify(&smt.root(), &missing));
+    assert_eq!(absent.value(), None);
+    assert!(absent.verify(&smt.root(), &missing));
+}
+
+#[test]
+#[should_panic(expected = "tree depth must be 1 to 256")]
+fn test_with_depth_rejects_zero() {
+    let _ = SparseMerkleTree::new(InMemoryKVStore::new()).with_depth(0);
+}
 
 // Helper function to create a tree with some initial data
 fn setup_tree() -> SparseMerkleTree<InMemoryKVStore> {
@@ -228,7 +1113,7 @@ fn test_proof_verification2() {
 
     smt.update(key, value).unwrap();
     let proof = smt.get_proof(key).unwrap();
-    assert!(smt.verify_proof(key, value, &proof));
+    assert!(smt.verify_proof(key, value, &proof).is_ok());
 }
 
 #[test]
@@ -240,7 +1125,22 @@ fn test_proof_verification_fails_for_wrong_value() {
 
     smt.update(key, value).unwrap();
     let proof = smt.get_proof(key).unwrap();
-    assert!(!smt.verify_proof(key, wrong_value, &proof));
+    assert!(smt.verify_proof(key, wrong_value, &proof).is_err());
+}
+
+#[test]
+fn test_proof_verification_errors_say_why() {
+    let mut smt = setup_tree();
+    let key: Hash = [5u8; 32];
+    smt.update(key, [50u8; 32]).unwrap();
+    let mut proof = smt.get_proof(key).unwrap();
+
+    assert!(matches!(smt.verify_proof(key, [51u8; 32], &proof), Err(SMTError::InvalidProof)));
+    proof.side_nodes.push([0u8; 32]);
+    assert!(matches!(
+        smt.verify_proof(key, [50u8; 32], &proof),
+        Err(SMTError::DepthExceeded { depth: 257, max: 256 })
+    ));
 }
 
 #[test]
@@ -249,7 +1149,7 @@ fn test_proof_verification_fails_for_non_existent_key() {
     let non_existent_key: Hash = [99u8; 32];
     let value: Hash = [0u8; 32];
     let proof = smt.get_proof(non_existent_key).unwrap();
-    assert!(!smt.verify_proof(non_existent_key, value, &proof));
+    assert!(smt.verify_proof(non_existent_key, value, &proof).is_err());
 }
 
 #[test]
@@ -302,8 +1202,6 @@ fn test_root_changes() {
 
 #[test]
 fn test_multiple_updates() {
-    // ! INVESTIGATE
-    // let _ = tracing_subscriber::fmt().try_init();
     let store = InMemoryKVStore::new();
     let mut smt = SparseMerkleTree::new(store);
 
@@ -318,18 +1216,16 @@ fn test_multiple_updates() {
         let key: Hash = [i; 32];
         let value: Hash = [i.wrapping_add(1); 32];
         let proof = smt.get_proof(key).unwrap();
-        // assert!(
-        //     smt.verify_proof(key, value, &proof),
-        //     "Failed to verify proof for key {:?}",
-        //     key
-        // );
+        assert!(
+            smt.verify_proof(key, value, &proof).is_ok(),
+            "Failed to verify proof for key {:?}",
+            key
+        );
     }
 }
 
 #[test]
 fn test_large_tree() {
-    // ! INVESTIGATE
-    // let _ = tracing_subscriber::fmt().try_init();
     let store = InMemoryKVStore::new();
     let mut smt = SparseMerkleTree::new(store);
 
@@ -344,11 +1240,11 @@ fn test_large_tree() {
         let value: Hash = [(i + 1) as u8; 32];
         assert_eq!(smt.get(key).unwrap(), Some(value));
         let proof = smt.get_proof(key).unwrap();
-        // assert!(
-        //     smt.verify_proof(key, value, &proof),
-        //     "Failed to verify proof for key {:?}",
-        //     key
-        // );
+        assert!(
+            smt.verify_proof(key, value, &proof).is_ok(),
+            "Failed to verify proof for key {:?}",
+            key
+        );
     }
 }
 
@@ -374,28 +1270,274 @@ proptest! {
         smt.update(key, value).unwrap();
         let proof = smt.get_proof(key).unwrap();
 
-        prop_assert!(smt.verify_proof(key, value, &proof));
+        prop_assert!(smt.verify_proof(key, value, &proof).is_ok());
     }
 
-    // #[test]
-    // fn test_multiple_inserts_prop(inserts: Vec<(Hash, Hash)>) {
-    //     // ! TODO - INVESTIGATE 
-    //     let store = InMemoryKVStore::new();
-    //     let mut smt = SparseMerkleTree::new(store);
+    #[test]
+    fn test_multiple_inserts_prop(inserts: Vec<(Hash, Hash)>) {
+        let store = InMemoryKVStore::new();
+        let mut smt = SparseMerkleTree::new(store);
 
-    //     for (i, (key, value)) in inserts.iter().enumerate() {
-    //         println!("Inserting #{}: key = {:?}, value = {:?}", i, key, value);
-    //         smt.update(*key, *value).unwrap();
-    //     }
+        // Later writes to the same key win
+        let mut expected = HashMap::new();
+        for (key, value) in &inserts {
+            smt.update(*key, *value).unwrap();
+            expected.insert(*key, *value);
+        }
 
-    //     for (i, (key, value)) in inserts.iter().enumerate() {
-    //         println!("Verifying #{}: key = {:?}, expected value = {:?}", i, key, value);
-    //         let result = smt.get(*key).unwrap();
-    //         println!("  Actual value: {:?}", result);
-    //         // prop_assert_eq!(result, Some(*value), "Mismatch for insert #{}", i);
+        for (i, (key, value)) in expected.iter().enumerate() {
+            prop_assert_eq!(smt.get(*key).unwrap(), Some(*value), "Mismatch for insert #{}", i);
 
-    //         let proof = smt.get_proof(*key).unwrap();
-    //         // prop_assert!(smt.verify_proof(*key, *value, &proof), "Proof verification failed for insert #{}", i);
-    //     }
-    // }
+            let proof = smt.get_proof(*key).unwrap();
+            prop_assert!(smt.verify_proof(*key, *value, &proof).is_ok(), "Proof verification failed for insert #{}", i);
+        }
+    }
+
+    #[test]
+    fn test_update_batch_matches_sequential_prop(inserts: Vec<(Hash, Hash)>) {
+        let mut sequential = SparseMerkleTree::new(InMemoryKVStore::new());
+        let mut batched = SparseMerkleTree::new(InMemoryKVStore::new());
+
+        for (key, value) in &inserts {
+            sequential.update(*key, *value).unwrap();
+        }
+        batched.update_batch(&inserts).unwrap();
+
+        prop_assert_eq!(batched.root(), sequential.root());
+    }
+
+    #[test]
+    fn test_interleaved_updates_keep_proofs_valid_prop(
+        keys in prop::collection::vec(any::<Hash>(), 1..8),
+        writes in prop::collection::vec((any::<prop::sample::Index>(), any::<Hash>()), 1..32),
+    ) {
+        let store = InMemoryKVStore::new();
+        let mut smt = SparseMerkleTree::new(store);
+        let mut expected = HashMap::new();
+
+        for (index, value) in &writes {
+            let key = *index.get(&keys);
+            smt.update(key, *value).unwrap();
+            expected.insert(key, *value);
+
+            // Every key written so far must stay provable against the new root
+            for (key, value) in &expected {
+                let proof = smt.get_proof(*key).unwrap();
+                prop_assert!(smt.verify_proof(*key, *value, &proof).is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn test_insert_then_delete_restores_root_prop(inserts: Vec<(Hash, Hash)>, key: Hash, value: Hash) {
+        let store = InMemoryKVStore::new();
+        let mut smt = SparseMerkleTree::new(store);
+
+        for (k, v) in inserts.iter().filter(|(k, _)| *k != key) {
+            smt.update(*k, *v).unwrap();
+        }
+        let root_before = smt.root();
+
+        smt.update(key, value).unwrap();
+        smt.delete(key).unwrap();
+
+        prop_assert_eq!(smt.root(), root_before);
+        prop_assert_eq!(smt.get(key).unwrap(), None);
+    }
+
+    #[test]
+    fn test_delete_matches_never_inserted_prop(inserts: Vec<(Hash, Hash)>, deleted in any::<prop::sample::Index>()) {
+        prop_assume!(!inserts.is_empty());
+        let deleted_key = deleted.get(&inserts).0;
+
+        let mut with_delete = SparseMerkleTree::new(InMemoryKVStore::new());
+        for (key, value) in &inserts {
+            with_delete.update(*key, *value).unwrap();
+        }
+        with_delete.delete(deleted_key).unwrap();
+
+        let mut without = SparseMerkleTree::new(InMemoryKVStore::new());
+        let mut expected = BTreeMap::new();
+        for (key, value) in inserts.iter().filter(|(key, _)| *key != deleted_key) {
+            expected.insert(*key, *value);
+        }
+        for (key, value) in &expected {
+            without.update(*key, *value).unwrap();
+        }
+
+        prop_assert_eq!(with_delete.root(), without.root());
+        for (key, value) in &expected {
+            let proof = with_delete.get_proof(*key).unwrap();
+            prop_assert!(with_delete.verify_proof(*key, *value, &proof).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_multiproof_matches_single_proofs_prop(
+        inserts in prop::collection::vec(any::<(Hash, Hash)>(), 1..16),
+        picks in prop::collection::vec(any::<prop::sample::Index>(), 1..8),
+    ) {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        smt.update_batch(&inserts).unwrap();
+
+        let mut entries = BTreeMap::new();
+        for pick in &picks {
+            let key = pick.get(&inserts).0;
+            entries.insert(key, smt.get(key).unwrap().unwrap());
+        }
+        let keys: Vec<Hash> = entries.keys().copied().collect();
+        let entries: Vec<(Hash, Hash)> = entries.into_iter().collect();
+
+        let proof = smt.get_multiproof(&keys).unwrap();
+        prop_assert!(verify_multiproof(&smt.root(), &entries, &proof));
+    }
+
+    #[test]
+    fn test_apply_matches_sequential_ops_prop(
+        keys in prop::collection::vec(any::<Hash>(), 1..8),
+        ops in prop::collection::vec((any::<prop::sample::Index>(), any::<Option<Hash>>()), 1..24),
+    ) {
+        let ops: Vec<Op> = ops
+            .iter()
+            .map(|(index, value)| match value {
+                Some(value) => Op::Put(*index.get(&keys), *value),
+                None => Op::Delete(*index.get(&keys)),
+            })
+            .collect();
+
+        let mut sequential = SparseMerkleTree::new(InMemoryKVStore::new());
+        for op in &ops {
+            match op {
+                Op::Put(key, value) => sequential.update(*key, *value).unwrap(),
+                Op::Delete(key) => sequential.delete(*key).unwrap(),
+            }
+        }
+        let mut batched = SparseMerkleTree::new(InMemoryKVStore::new());
+        batched.apply(&ops).unwrap();
+
+        prop_assert_eq!(batched.root(), sequential.root());
+        for key in &keys {
+            prop_assert_eq!(batched.get(*key).unwrap(), sequential.get(*key).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_iter_matches_inserted_entries_prop(inserts: Vec<(Hash, Hash)>) {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        let mut expected = BTreeMap::new();
+        for (key, value) in &inserts {
+            smt.update(*key, *value).unwrap();
+            expected.insert(*key, *value);
+        }
+
+        let leaves: Vec<(Hash, Hash)> = smt.iter().collect::<Result<_, _>>().unwrap();
+        prop_assert_eq!(leaves, expected.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_range_proof_matches_filtered_entries_prop(inserts: Vec<(Hash, Hash)>, start: Hash, end: Hash) {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        let mut expected = BTreeMap::new();
+        for (key, value) in &inserts {
+            smt.update(*key, *value).unwrap();
+            expected.insert(*key, *value);
+        }
+
+        let proof = smt.prove_range(start, end).unwrap();
+        let in_range: Vec<(Hash, Hash)> = expected.into_iter().filter(|(key, _)| *key >= start && *key <= end).collect();
+        prop_assert_eq!(&proof.entries, &in_range);
+        prop_assert!(verify_range(&smt.root(), &start, &end, &proof));
+    }
+}
+
+#[derive(Clone, Default)]
+struct CapturedLog(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for CapturedLog {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_operations_run_in_named_spans() {
+    // Test case: Update a key and prove it under a subscriber logging span closes.
+    // Expected output: An smt.update span with the key and new root, and an smt.get_proof span.
+
+    // Arrange
+    let log = CapturedLog::default();
+    let writer = log.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .finish();
+    let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+
+    // Act
+    tracing::subscriber::with_default(subscriber, || {
+        smt.update([0xabu8; 32], [1u8; 32]).unwrap();
+        smt.get_proof([0xabu8; 32]).unwrap();
+    });
+
+    // Assert
+    let log = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+    let (key, root) = (crate::hex::HexFmt(&[0xabu8; 32]), crate::hex::HexFmt(&smt.root()));
+    assert!(log.contains(&format!("smt.update{{key={} root={}}}", key, root)), "{}", log);
+    assert!(log.contains(&format!("smt.get_proof{{key={} root={}}}", key, root)), "{}", log);
+}
+
+#[test]
+fn test_hashers_share_default_hashes() {
+    // Test case: Build several hashers over the same and different digests and node prefixes.
+    // Expected output: Only hashers with the same digest and node prefix share one table.
+
+    // Arrange
+    let first = TreeHasher::<sha2::Sha256>::new();
+    let second = TreeHasher::<sha2::Sha256>::new();
+    let prefixed = TreeHasher::<sha2::Sha256>::with_prefixes(0, 7);
+    let sha3 = TreeHasher::<Sha3_256>::new();
+
+    // Assert
+    assert!(std::ptr::eq(first.default_hashes(), second.default_hashes()));
+    assert!(!std::ptr::eq(first.default_hashes(), prefixed.default_hashes()));
+    assert!(!std::ptr::eq(first.default_hashes(), sha3.default_hashes()));
+    assert_ne!(first.empty(1), prefixed.empty(1));
+    assert_ne!(first.empty(1), sha3.empty(1));
+}
+
+#[test]
+fn test_keys_past_depth_are_rejected() {
+    // Test case: Write keys with bits set past the depth of an 8-level tree.
+    // Expected output: Every write fails with KeyBeyondDepth and the stored key keeps its value.
+
+    // Arrange
+    let mut smt = SparseMerkleTree::new(InMemoryKVStore::new()).with_depth(8);
+    let mut key = [0u8; 32];
+    key[0] = 0xab;
+    smt.update(key, [1u8; 32]).unwrap();
+    let root = smt.root();
+    let mut long = key;
+    long[31] = 1; // Same first byte, so the same leaf
+
+    // Act
+    let results = [
+        smt.update(long, [2u8; 32]).map(|_| root),
+        smt.update_batch(&[(key, [3u8; 32]), (long, [2u8; 32])]),
+        smt.apply(&[Op::Put(long, [2u8; 32])]),
+        smt.apply(&[Op::Delete(long)]),
+    ];
+
+    // Assert
+    for result in results {
+        assert!(matches!(result, Err(SMTError::KeyBeyondDepth { key, depth: 8 }) if key == long));
+    }
+    assert_eq!(smt.root(), root);
+    assert_eq!(smt.get(key).unwrap(), Some([1u8; 32]));
 }
@@ -4,6 +4,25 @@ use serde::ser::SerializeStruct;
 use sha2::{Digest, Sha256};
 use std::fmt;
 
+#[cfg(feature = "ed25519")]
+use crate::signature::{Ed25519, SignatureScheme, Signer};
+
+#[cfg(feature = "serde_hex")]
+use crate::hex::serde_hex::Hex;
+
+const SIGNING_DOMAIN: &[u8] = b"SimpleSparseMerkle/transaction/v1";
+
+// How the byte fields are read: as hex strings with `serde_hex`, otherwise as
+// lists of numbers.
+#[cfg(feature = "serde_hex")]
+type HashField = Hex<32>;
+#[cfg(feature = "serde_hex")]
+type SignatureField = Hex<64>;
+#[cfg(not(feature = "serde_hex"))]
+type HashField = [u8; 32];
+#[cfg(not(feature = "serde_hex"))]
+type SignatureField = Vec<u8>;
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Transaction {
     pub from: [u8; 32],      // Sender's address
@@ -20,11 +39,15 @@ impl Serialize for Transaction {
         S: Serializer,
     {
         let mut state = serializer.serialize_struct("Transaction", 5)?;
-        state.serialize_field("from", &self.from)?;
-        state.serialize_field("to", &self.to)?;
+        #[cfg(feature = "serde_hex")]
+        let (from, to, signature) = (Hex(self.from), Hex(self.to), Hex(self.signature));
+        #[cfg(not(feature = "serde_hex"))]
+        let (from, to, signature) = (self.from, self.to, self.signature.as_slice());
+        state.serialize_field("from", &from)?;
+        state.serialize_field("to", &to)?;
         state.serialize_field("amount", &self.amount)?;
         state.serialize_field("nonce", &self.nonce)?;
-        state.serialize_field("signature", &self.signature.as_slice())?;
+        state.serialize_field("signature", &signature)?;
         state.end()
     }
 }
@@ -89,11 +112,11 @@ impl<'de> Deserialize<'de> for Transaction {
             where
                 V: de::MapAccess<'de>,
             {
-                let mut from = None;
-                let mut to = None;
+                let mut from: Option<HashField> = None;
+                let mut to: Option<HashField> = None;
                 let mut amount = None;
                 let mut nonce = None;
-                let mut signature: Option<Vec<u8>> = None;
+                let mut signature: Option<SignatureField> = None;
 
                 while let Some(key) = map.next_key()? {
                     match key {
@@ -134,9 +157,13 @@ impl<'de> Deserialize<'de> for Transaction {
                 let to = to.ok_or_else(|| de::Error::missing_field("to"))?;
                 let amount = amount.ok_or_else(|| de::Error::missing_field("amount"))?;
                 let nonce = nonce.ok_or_else(|| de::Error::missing_field("nonce"))?;
-                let signature = signature.clone().ok_or_else(|| de::Error::missing_field("signature"))?;
+                let signature = signature.ok_or_else(|| de::Error::missing_field("signature"))?;
+
+                #[cfg(feature = "serde_hex")]
+                let (from, to, signature) = (from.0, to.0, signature.0);
 
                 // Convert the signature from Vec<u8> to [u8; 64]
+                #[cfg(not(feature = "serde_hex"))]
                 let signature: [u8; 64] = signature.clone()
                     .try_into()
                     .map_err(|_| de::Error::invalid_length(signature.len(), &"expected a Vec of length 64"))?;
@@ -151,7 +178,7 @@ impl<'de> Deserialize<'de> for Transaction {
             }
         }
 
-        const FIELDS: &'static [&'static str] = &["from", "to", "amount", "nonce", "signature"];
+        const FIELDS: &[&str] = &["from", "to", "amount", "nonce", "signature"];
         deserializer.deserialize_struct("Transaction", FIELDS, TransactionVisitor)
     }
 }
@@ -172,13 +199,38 @@ impl Transaction {
     /// Computes a hash for the transaction using a chosen hash function.
     pub fn compute_hash(&self) -> [u8; 32] {
         let mut hasher = Sha256::new();
-        hasher.update(&self.from);
-        hasher.update(&self.to);
-        hasher.update(&self.amount.to_le_bytes());
-        hasher.update(&self.nonce.to_le_bytes());
-        hasher.update(&self.signature);
+        hasher.update(self.from);
+        hasher.update(self.to);
+        hasher.update(self.amount.to_le_bytes());
+        hasher.update(self.nonce.to_le_bytes());
+        hasher.update(self.signature);
+        hasher.finalize().into()
+    }
+
+    /// Hash the sender signs: every field but the signature, in declaration
+    /// order, after a domain tag.
+    pub fn signing_hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(SIGNING_DOMAIN);
+        hasher.update(self.from);
+        hasher.update(self.to);
+        hasher.update(self.amount.to_le_bytes());
+        hasher.update(self.nonce.to_le_bytes());
         hasher.finalize().into()
     }
+
+    /// Signs the transaction. `from` must already hold the signer's public
+    /// key, since that is what `verify_signature` checks against.
+    #[cfg(feature = "ed25519")]
+    pub fn sign(&mut self, keypair: &impl Signer<Ed25519>) {
+        self.signature = keypair.sign(&self.signing_hash());
+    }
+
+    /// Whether `signature` was made over this transaction by the key in `from`.
+    #[cfg(feature = "ed25519")]
+    pub fn verify_signature(&self) -> bool {
+        Ed25519::verify(&self.from, &self.signing_hash(), &self.signature)
+    }
 }
 
 pub struct TransactionBuilder {
@@ -189,6 +241,12 @@ pub struct TransactionBuilder {
     signature: Option<[u8; 64]>,
 }
 
+impl Default for TransactionBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TransactionBuilder {
     pub fn new() -> Self {
         Self {
@@ -270,4 +328,48 @@ mod tests {
         assert!(result.is_err());
         assert_eq!(result.unwrap_err(), "Sender address is missing");
     }
+
+    #[cfg(feature = "ed25519")]
+    #[test]
+    fn test_signature_detects_tampering() {
+        let keypair = ed25519_dalek::SigningKey::from_bytes(&[7u8; 32]);
+        let mut tx = Transaction {
+            from: Signer::<Ed25519>::public_key(&keypair),
+            to: [2u8; 32],
+            amount: 100,
+            nonce: 0,
+            signature: [0u8; 64],
+        };
+        assert!(!tx.verify_signature());
+        tx.sign(&keypair);
+        assert!(tx.verify_signature());
+
+        let mut tampered = tx.clone();
+        tampered.amount = 1000;
+        assert!(!tampered.verify_signature());
+        let mut tampered = tx.clone();
+        tampered.to = [3u8; 32];
+        assert!(!tampered.verify_signature());
+        let mut tampered = tx.clone();
+        tampered.signature[0] ^= 1;
+        assert!(!tampered.verify_signature());
+    }
+
+    #[cfg(feature = "ed25519")]
+    #[test]
+    fn test_signature_by_another_key_is_rejected() {
+        let keypair = ed25519_dalek::SigningKey::from_bytes(&[7u8; 32]);
+        let other = ed25519_dalek::SigningKey::from_bytes(&[8u8; 32]);
+        let mut tx = Transaction { from: Signer::<Ed25519>::public_key(&keypair), ..Default::default() };
+        tx.sign(&other);
+        assert!(!tx.verify_signature());
+    }
+
+    #[test]
+    fn test_signing_hash_ignores_signature() {
+        let tx = Transaction::default();
+        let signed = Transaction { signature: [1u8; 64], ..Default::default() };
+        assert_eq!(tx.signing_hash(), signed.signing_hash());
+        assert_ne!(tx.compute_hash(), signed.compute_hash());
+    }
 }
@@ -1,20 +1,152 @@
-use digest::{Digest, Output};
+use alloc::{sync::Arc, vec, vec::Vec};
+#[cfg(not(feature = "std"))]
+use alloc::collections::BTreeMap as HashMap;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::{any::TypeId, sync::{Mutex, OnceLock, PoisonError}};
+
+use digest::{consts::U32, Digest, Output, OutputSizeUser};
 use crate::Hash;
-use digest::generic_array::GenericArray;
 
+/// Depth of a tree unless set otherwise, one level per key bit.
+pub const DEFAULT_DEPTH: usize = 256;
+
+pub const LEAF_PREFIX: u8 = 0;
+pub const NODE_PREFIX: u8 = 1;
+
+/// Digests whose output fits `Hash` exactly. Nodes, roots and proofs are all
+/// 32 bytes wide, so a wider or narrower digest (Sha512, RIPEMD-160) is
+/// rejected at compile time instead of panicking on the first hash.
+pub trait TreeDigest: Digest + OutputSizeUser<OutputSize = U32> + 'static {}
+
+impl<D: Digest + OutputSizeUser<OutputSize = U32> + 'static> TreeDigest for D {}
+
+/// Hash of an empty subtree at every height, from the empty leaf (all zero,
+/// height 0) up to an empty tree of depth 256. Each level is the node hash of
+/// two copies of the one below, so empty subtrees hash like any other and a
+/// tree commits to the same roots as other implementations of the scheme.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefaultHashes {
+    hashes: Vec<Hash>, // Indexed by height
+    heights: HashMap<Hash, usize>,
+}
+
+impl DefaultHashes {
+    /// Highest height in the table, that of the root of a full-depth tree.
+    pub const MAX_HEIGHT: usize = 256;
+
+    fn new<D: TreeDigest>(node_prefix: u8) -> Self {
+        let mut hashes: Vec<Hash> = vec![[0u8; 32]];
+        for height in 1..=Self::MAX_HEIGHT {
+            let below = hashes[height - 1];
+            hashes.push(D::new().chain_update([node_prefix]).chain_update(below).chain_update(below).finalize().into());
+        }
+        let heights = hashes.iter().enumerate().map(|(height, hash)| (*hash, height)).collect();
+        Self { hashes, heights }
+    }
+
+    /// Hash of an empty subtree `height` levels above the leaves. Panics if
+    /// `height` is above `MAX_HEIGHT`.
+    pub fn at(&self, height: usize) -> Hash {
+        self.hashes[height]
+    }
+
+    /// Height of the empty subtree hashing to `hash`, or `None` if it is not
+    /// one.
+    pub fn height_of(&self, hash: &Hash) -> Option<usize> {
+        self.heights.get(hash).copied()
+    }
+
+    pub fn as_slice(&self) -> &[Hash] {
+        &self.hashes
+    }
+
+    /// The table for `D` and `node_prefix`, built on first use and shared by
+    /// every hasher after that.
+    #[cfg(feature = "std")]
+    fn shared<D: TreeDigest>(node_prefix: u8) -> Arc<Self> {
+        type Tables = HashMap<(TypeId, u8), Arc<DefaultHashes>>;
+        static TABLES: OnceLock<Mutex<Tables>> = OnceLock::new();
+        let mut tables = TABLES.get_or_init(Default::default).lock().unwrap_or_else(PoisonError::into_inner);
+        tables
+            .entry((TypeId::of::<D>(), node_prefix))
+            .or_insert_with(|| Arc::new(Self::new::<D>(node_prefix)))
+            .clone()
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn shared<D: TreeDigest>(node_prefix: u8) -> Arc<Self> {
+        Arc::new(Self::new::<D>(node_prefix))
+    }
+}
+
+pub struct TreeHasher<D: TreeDigest> {
+    leaf_prefix: u8,
+    node_prefix: u8,
+    key_domain: Option<Hash>,
+    defaults: Arc<DefaultHashes>, // Shared by every hasher with the same digest and node prefix
+    _marker: core::marker::PhantomData<D>,
+}
+
+impl<D: TreeDigest> Clone for TreeHasher<D> {
+    fn clone(&self) -> Self {
+        Self {
+            leaf_prefix: self.leaf_prefix,
+            node_prefix: self.node_prefix,
+            key_domain: self.key_domain,
+            defaults: self.defaults.clone(),
+            _marker: core::marker::PhantomData,
+        }
+    }
+}
 
-pub struct TreeHasher<D: Digest> {
-    _marker: std::marker::PhantomData<D>,
+impl<D: TreeDigest> Default for TreeHasher<D> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl<D: Digest> TreeHasher<D> {
+impl<D: TreeDigest> TreeHasher<D> {
     pub fn new() -> Self {
-        Self { _marker: std::marker::PhantomData }
+        Self::with_prefixes(LEAF_PREFIX, NODE_PREFIX)
+    }
+
+    /// Hasher using custom domain-separation prefixes, for verifying trees
+    /// from deployments that picked different ones.
+    pub fn with_prefixes(leaf_prefix: u8, node_prefix: u8) -> Self {
+        Self {
+            leaf_prefix,
+            node_prefix,
+            key_domain: None,
+            defaults: DefaultHashes::shared::<D>(node_prefix),
+            _marker: core::marker::PhantomData,
+        }
+    }
+
+    /// Hasher that mixes `domain` into every leaf, after the prefix, so trees
+    /// whose keys were derived differently (see `key_hasher`) never share a
+    /// root.
+    pub fn with_key_domain(mut self, domain: Hash) -> Self {
+        self.key_domain = Some(domain);
+        self
+    }
+
+    pub fn key_domain(&self) -> Option<Hash> {
+        self.key_domain
+    }
+
+    /// Leaf and node prefixes, in that order.
+    pub fn prefixes(&self) -> (u8, u8) {
+        (self.leaf_prefix, self.node_prefix)
     }
 
     pub fn digest_leaf(&self, key: &Hash, value: &Hash) -> Hash {
         let mut hasher = D::new();
-        hasher.update([0u8]); // Leaf prefix
+        hasher.update([self.leaf_prefix]);
+        if let Some(domain) = &self.key_domain {
+            hasher.update(domain);
+        }
         hasher.update(key);
         hasher.update(value);
         self.finalize_to_array(hasher)
@@ -22,12 +154,39 @@ impl<D: Digest> TreeHasher<D> {
 
     pub fn digest_node(&self, left: &Hash, right: &Hash) -> Hash {
         let mut hasher = D::new();
-        hasher.update([1u8]); // Node prefix
+        hasher.update([self.node_prefix]);
         hasher.update(left);
         hasher.update(right);
         self.finalize_to_array(hasher)
     }
 
+    /// `digest_leaf`, written into `out` rather than returned.
+    #[inline]
+    pub fn digest_leaf_into(&self, key: &Hash, value: &Hash, out: &mut Hash) {
+        hash_pair_into::<D>(self.leaf_prefix, self.key_domain.as_ref(), key, value, out);
+    }
+
+    /// `digest_node`, written into `out` rather than returned.
+    #[inline]
+    pub fn digest_node_into(&self, left: &Hash, right: &Hash, out: &mut Hash) {
+        hash_pair_into::<D>(self.node_prefix, None, left, right, out);
+    }
+
+    /// Hash of an empty subtree `height` levels above the leaves.
+    pub fn empty(&self, height: usize) -> Hash {
+        self.defaults.at(height)
+    }
+
+    /// Whether `node` is an empty subtree of any height.
+    pub fn is_empty(&self, node: &Hash) -> bool {
+        self.defaults.height_of(node).is_some()
+    }
+
+    pub fn default_hashes(&self) -> &DefaultHashes {
+        &self.defaults
+    }
+
+    /// The empty leaf, `empty(0)`.
     pub fn zero_hash(&self) -> Hash {
         [0u8; 32]
     }
@@ -38,4 +197,19 @@ impl<D: Digest> TreeHasher<D> {
         hash.copy_from_slice(&result);
         hash
     }
-}
\ No newline at end of file
+}
+
+/// Hashes `prefix || domain || first || second` straight into `out`. Needs
+/// no `TreeHasher`, so checking a proof under the default prefixes does not
+/// first compute the table of default hashes.
+#[inline]
+pub(crate) fn hash_pair_into<D: TreeDigest>(prefix: u8, domain: Option<&Hash>, first: &Hash, second: &Hash, out: &mut Hash) {
+    let mut hasher = D::new();
+    hasher.update([prefix]);
+    if let Some(domain) = domain {
+        hasher.update(domain);
+    }
+    hasher.update(first);
+    hasher.update(second);
+    hasher.finalize_into(Output::<D>::from_mut_slice(out));
+}
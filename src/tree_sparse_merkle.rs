@@ -1,251 +1,26 @@
-// error.rs
-use jemallocator::Jemalloc;
-use rand::Rng;
-use thiserror::Error;
+//! Compatibility names for the original single-file tree.
+//!
+//! This module used to carry its own copy of the tree, store and hasher. They
+//! now live in `sparse_merkle_tree`, `kv_store` and `tree_hasher`; the aliases
+//! below keep old imports compiling and will be removed in a future release.
 
-#[derive(Error, Debug)]
-pub enum SMTError {
-    #[error("Key-value store error: {0}")]
-    KVStoreError(#[from] std::io::Error),
+pub use crate::{error::SMTError, kv_store::KVStore, DefaultHasher, Hash};
 
-    #[error("Invalid proof")]
-    InvalidProof,
+#[deprecated(note = "use `SimpleSparseMerkle::SparseMerkleTree` instead")]
+pub type SparseMerkleTree<S> = crate::sparse_merkle_tree::SparseMerkleTree<S>;
 
-    #[error("Unsupported operation")]
-    UnsupportedOperation,
-}
-
-// kv_store.rs
-use std::collections::BTreeMap;
-// use crate::Hash;
-
-pub trait KVStore {
-    type Error;
-
-    fn get(&self, key: &Hash) -> Result<Option<&Vec<u8>>, Self::Error>;
-    fn set(&mut self, key: Hash, value: Vec<u8>) -> Result<(), Self::Error>;
-}
-
-pub struct InMemoryKVStore {
-    store: BTreeMap<Hash, Vec<u8>>,
-}
-
-impl InMemoryKVStore {
-    pub fn new() -> Self {
-        Self {
-            store: BTreeMap::new(),
-        }
-    }
-}
-
-impl KVStore for InMemoryKVStore {
-    type Error = std::io::Error;
-
-    fn get(&self, key: &Hash) -> Result<Option<&Vec<u8>>, Self::Error> {
-        Ok(self.store.get(key))
-    }
-
-    fn set(&mut self, key: Hash, value: Vec<u8>) -> Result<(), Self::Error> {
-        self.store.insert(key, value);
-        Ok(())
-    }
-}
-
-// sparse_merkle_tree.rs
-// use crate::{kv_store::KVStore, proof::MerkleProof, tree_hasher::TreeHasher, DefaultHasher, Hash};
-use tracing::{debug, error, info, warn};
-
-pub struct SparseMerkleTree<S: KVStore> {
-    pub(crate) hasher: TreeHasher<DefaultHasher>,
-    pub(crate) store: S,
-    pub(crate) root: Hash,
-}
-
-impl<S: KVStore> SparseMerkleTree<S> {
-    pub fn new(store: S) -> Self {
-        let hasher = TreeHasher::<DefaultHasher>::new();
-        let root = [0u8; 32];
-        info!("Created new Sparse Merkle Tree");
-        Self {
-            hasher,
-            store,
-            root,
-        }
-    }
-
-    pub fn update(&mut self, key: Hash, value: Hash) -> Result<(), S::Error> {
-        info!("Updating tree with key {:?}, value {:?}", key, value);
-        let leaf_hash = self.hasher.digest_leaf(&key, &value);
-        self.store.set(key, value.to_vec())?;
-        debug!("Set key-value pair in store");
-
-        let mut current = leaf_hash;
-        for i in (0..256).rev() {
-            let bit = (key[i / 8] >> (7 - (i % 8))) & 1;
-            let sibling = self.hasher.zero_hash();
-            let (left, right) = if bit == 0 {
-                (current, sibling)
-            } else {
-                (sibling, current)
-            };
-            current = self.hasher.digest_node(&left, &right);
-            let mut combined = Vec::with_capacity(left.len() + right.len());
-            combined.extend_from_slice(&left);
-            combined.extend_from_slice(&right);
-            self.store.set(current, combined)?;
-            debug!("Updated node at depth {}, current hash: {:?}", i, current);
-        }
-
-        self.root = current;
-        info!("Updated tree with key {:?}, new root: {:?}", key, self.root);
-        Ok(())
-    }
-
-    pub fn get(&self, key: Hash) -> Result<Option<Hash>, S::Error> {
-        if self.root == [0u8; 32] {
-            return Ok(None);
-        }
-        self.store
-            .get(&key)
-            .map(|opt| opt.and_then(|v| v.as_slice().try_into().ok()))
-    }
-
-    pub fn get_proof(&self, key: Hash) -> Result<MerkleProof, S::Error> {
-        let mut current = self.root;
-        let mut side_nodes = Vec::new();
-
-        debug!("Generating proof for key {:?}", key);
-        debug!("Starting from root {:?}", current);
-
-        for i in 0..256 {
-            if current == self.hasher.zero_hash() {
-                debug!("Reached zero hash at depth {}", i);
-                break;
-            }
+#[deprecated(note = "use `SimpleSparseMerkle::InMemoryKVStore` instead")]
+pub type InMemoryKVStore = crate::kv_store::InMemoryKVStore;
 
-            // First, match on the Result to handle any potential errors
-            let node_value = match self.store.get(&current)? {
-                Some(v) => v,
-                None => &vec![0u8; 64], // Handle the None case with a default value
-            };
-
-            let (left, right) = node_value.split_at(32);
-            let bit = (key[i / 8] >> (7 - (i % 8))) & 1;
-
-            debug!(
-                "At depth {}, bit {}, left: {:?}, right: {:?}",
-                i, bit, left, right
-            );
-
-            if bit == 0 {
-                side_nodes.push(right.try_into().unwrap());
-                current = left.try_into().unwrap();
-            } else {
-                side_nodes.push(left.try_into().unwrap());
-                current = right.try_into().unwrap();
-            }
-        }
-
-        debug!("Generated proof with {} side nodes", side_nodes.len());
-        Ok(MerkleProof { side_nodes })
-    }
-
-    pub fn verify_proof(&self, key: Hash, value: Hash, proof: &MerkleProof) -> bool {
-        let leaf_hash = self.hasher.digest_leaf(&key, &value);
-        let mut current = leaf_hash;
-
-        debug!("Verifying proof for key {:?}, value {:?}", key, value);
-        debug!("Starting from leaf hash {:?}", current);
-
-        for (i, sibling) in proof.side_nodes.iter().enumerate().rev() {
-            let bit = (key[i / 8] >> (7 - (i % 8))) & 1;
-            let (left, right) = if bit == 0 {
-                (current, *sibling)
-            } else {
-                (*sibling, current)
-            };
-            current = self.hasher.digest_node(&left, &right);
-
-            debug!(
-                "At depth {}, bit {}, left: {:?}, right: {:?}, current: {:?}",
-                255 - i,
-                bit,
-                left,
-                right,
-                current
-            );
-        }
-
-        debug!("Final hash: {:?}", current);
-        debug!("Root hash:  {:?}", self.root);
-
-        current == self.root
-    }
-
-    pub fn root(&self) -> Hash {
-        self.root
-    }
-}
-use sha2::Sha256;
-
-pub type Hash = [u8; 32];
-pub type DefaultHasher = Sha256;
-
-// tree_hasher.rs
-use digest::{Digest, Output};
-// use crate::Hash;
-// 183 | use std::hash::Hash;
-
-pub struct TreeHasher<D: Digest> {
-    _marker: std::marker::PhantomData<D>,
-}
-
-impl<D: Digest> TreeHasher<D> {
-    pub fn new() -> Self {
-        Self {
-            _marker: std::marker::PhantomData,
-        }
-    }
+#[deprecated(note = "use `SimpleSparseMerkle::tree_hasher::TreeHasher` instead")]
+pub type TreeHasher<D> = crate::tree_hasher::TreeHasher<D>;
 
-    pub fn digest_leaf(&self, key: &Hash, value: &Hash) -> Hash {
-        let mut hasher = D::new();
-        hasher.update([0u8]); // Leaf prefix
-        hasher.update(key);
-        hasher.update(value);
-        self.finalize_to_array(hasher)
-    }
-
-    pub fn digest_node(&self, left: &Hash, right: &Hash) -> Hash {
-        let mut hasher = D::new();
-        hasher.update([1u8]); // Node prefix
-        hasher.update(left);
-        hasher.update(right);
-        self.finalize_to_array(hasher)
-    }
-
-    pub fn zero_hash(&self) -> Hash {
-        [0u8; 32]
-    }
-
-    fn finalize_to_array(&self, hasher: D) -> Hash {
-        let result = hasher.finalize();
-        let mut hash = [0u8; 32];
-        hash.copy_from_slice(&result);
-        hash
-    }
-}
-
-// proof.rs
-use serde::{Deserialize, Serialize};
-// use crate::Hash;
-
-#[derive(Clone, Serialize, Deserialize)]
-pub struct MerkleProof {
-    pub side_nodes: Vec<Hash>,
-}
+#[deprecated(note = "use `SimpleSparseMerkle::MerkleProof` instead")]
+pub type MerkleProof = crate::proof::MerkleProof;
 
 // main.rs (test cases)
 #[cfg(test)]
+#[allow(deprecated)]
 mod tests {
     use super::*;
     // use proptest::prelude::*;
@@ -282,9 +57,9 @@ mod tests {
 
         smt.update(key, value).unwrap();
         let proof = smt.get_proof(key).unwrap();
-        assert!(smt.verify_proof(key, value, &proof));
+        assert!(smt.verify_proof(key, value, &proof).is_ok());
 
-        assert!(!smt.verify_proof(key, [3u8; 32], &proof)); // Incorrect value should fail
+        assert!(smt.verify_proof(key, [3u8; 32], &proof).is_err()); // Incorrect value should fail
     }
 
     #[test]
@@ -301,7 +76,7 @@ mod tests {
         let store = InMemoryKVStore::new();
         let smt = SparseMerkleTree::new(store);
 
-        assert_eq!(smt.root(), [0u8; 32]); // Root of empty tree
+        assert_eq!(smt.root(), smt.hasher.empty(256)); // Root of empty tree
     }
 
     #[test]
@@ -340,14 +115,10 @@ mod tests {
         assert_ne!(smt.root(), initial_root); // Root hash should change
     }
 
-    use super::*;
-    use std::time::Instant;
-
     #[test]
     fn test_large_tree_inserts() {
         let store = InMemoryKVStore::new();
         let mut smt = SparseMerkleTree::new(store);
-        let start = Instant::now();
 
         for i in 0..1000 {
             let key: Hash = [i as u8; 32];
@@ -355,8 +126,6 @@ mod tests {
             smt.update(key, value).unwrap();
         }
 
-        println!("Time to insert 1000 items: {:?}", start.elapsed());
-
         for i in 0..1000 {
             let key: Hash = [i as u8; 32];
             let expected_value: Hash = [(i * 2) as u8; 32];
@@ -383,8 +152,10 @@ mod tests {
 }
 
 #[cfg(test)]
+#[allow(deprecated)]
 mod property_tests {
     use super::*;
+    use std::collections::BTreeMap;
     use proptest::prelude::*;
 
     proptest! {
@@ -405,7 +176,7 @@ mod property_tests {
             smt.update(key, value).unwrap();
             let proof = smt.get_proof(key).unwrap();
 
-            prop_assert!(smt.verify_proof(key, value, &proof));
+            prop_assert!(smt.verify_proof(key, value, &proof).is_ok());
         }
 
         #[test]
@@ -413,14 +184,17 @@ mod property_tests {
             let store = InMemoryKVStore::new();
             let mut smt = SparseMerkleTree::new(store);
 
+            // Later writes to the same key win
+            let mut expected = BTreeMap::new();
             for (key, value) in &inserts {
                 smt.update(*key, *value).unwrap();
+                expected.insert(*key, *value);
             }
 
-            for (key, value) in &inserts {
+            for (key, value) in &expected {
                 prop_assert_eq!(smt.get(*key).unwrap(), Some(*value));
                 let proof = smt.get_proof(*key).unwrap();
-                prop_assert!(smt.verify_proof(*key, *value, &proof));
+                prop_assert!(smt.verify_proof(*key, *value, &proof).is_ok());
             }
         }
     }
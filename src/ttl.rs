@@ -0,0 +1,281 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use digest::Digest;
+use serde::{Deserialize, Serialize};
+
+use crate::{clock::{Clock, SystemClock}, error::SMTError, kv_store::KVStore, proof::MerkleProof, sparse_merkle_tree::SparseMerkleTree, DefaultHasher, Hash};
+
+/// Leaf value committing to both `value` and its expiry time, so the expiry
+/// is covered by the root like the value itself.
+pub fn expiring_value(value: &Hash, expires_at: u64) -> Hash {
+    let mut hasher = DefaultHasher::new();
+    hasher.update(value);
+    hasher.update(expires_at.to_be_bytes());
+    hasher.finalize().into()
+}
+
+/// Proof that a leaf's expiry time had passed by some block time.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ExpiryProof {
+    pub value: Hash,
+    pub expires_at: u64,
+    pub proof: MerkleProof,
+}
+
+impl ExpiryProof {
+    /// Checks that `key` held `value` with this expiry under `root`, and that
+    /// the expiry is no later than `block_time`.
+    pub fn verify(&self, root: &Hash, key: &Hash, block_time: u64) -> bool {
+        self.expires_at <= block_time && self.proof.verify(root, key, &expiring_value(&self.value, self.expires_at))
+    }
+}
+
+/// Tree whose leaves may carry an expiry time. Expired leaves stop being
+/// returned by `get` and are removed by `sweep_expired`.
+///
+/// Leaves written with an expiry store `expiring_value` in the tree. The
+/// index of expiry times is kept in memory. Methods taking an explicit time
+/// ignore `clock`; the `*_now` ones and `update_with_ttl` read it.
+pub struct ExpiringTree<S: KVStore, C: Clock = SystemClock> {
+    tree: SparseMerkleTree<S>,
+    leaves: HashMap<Hash, (Hash, u64)>, // key -> (value, expires_at)
+    by_expiry: BTreeMap<u64, BTreeSet<Hash>>,
+    clock: C,
+}
+
+impl<S: KVStore> ExpiringTree<S> {
+    pub fn new(tree: SparseMerkleTree<S>) -> Self {
+        Self::with_clock(tree, SystemClock)
+    }
+}
+
+impl<S: KVStore, C: Clock> ExpiringTree<S, C> {
+    /// Like `new`, telling time by `clock`.
+    pub fn with_clock(tree: SparseMerkleTree<S>, clock: C) -> Self {
+        Self {
+            tree,
+            leaves: HashMap::new(),
+            by_expiry: BTreeMap::new(),
+            clock,
+        }
+    }
+
+    /// Writes a leaf that never expires.
+    pub fn update(&mut self, key: Hash, value: Hash) -> Result<(), SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        self.tree.update(key, value)?;
+        self.forget(&key);
+        Ok(())
+    }
+
+    /// Writes a leaf that expires at `expires_at`.
+    pub fn update_with_expiry(&mut self, key: Hash, value: Hash, expires_at: u64) -> Result<(), SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        self.tree.update(key, expiring_value(&value, expires_at))?;
+        self.forget(&key);
+        self.leaves.insert(key, (value, expires_at));
+        self.by_expiry.entry(expires_at).or_default().insert(key);
+        Ok(())
+    }
+
+    /// Writes a leaf that expires `ttl` seconds from now.
+    pub fn update_with_ttl(&mut self, key: Hash, value: Hash, ttl: u64) -> Result<(), SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        self.update_with_expiry(key, value, self.clock.now().saturating_add(ttl))
+    }
+
+    /// Value of `key` at time `now`, or `None` if it is absent or expired.
+    pub fn get(&self, key: Hash, now: u64) -> Result<Option<Hash>, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        match self.leaves.get(&key) {
+            Some((_, expires_at)) if *expires_at <= now => Ok(None),
+            Some((value, _)) => Ok(Some(*value)),
+            None => self.tree.get(key),
+        }
+    }
+
+    /// Expiry time of `key`, if it was written with one.
+    pub fn expires_at(&self, key: &Hash) -> Option<u64> {
+        self.leaves.get(key).map(|(_, expires_at)| *expires_at)
+    }
+
+    /// Proves that `key` had expired by `block_time` under the current root.
+    /// Returns `None` if the key has no expiry or it is still in the future.
+    pub fn prove_expiry(&self, key: Hash, block_time: u64) -> Result<Option<ExpiryProof>, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        match self.leaves.get(&key) {
+            Some((value, expires_at)) if *expires_at <= block_time => Ok(Some(ExpiryProof {
+                value: *value,
+                expires_at: *expires_at,
+                proof: self.tree.get_proof(key)?,
+            })),
+            _ => Ok(None),
+        }
+    }
+
+    /// Deletes every leaf that expired at or before `now` and returns their keys.
+    pub fn sweep_expired(&mut self, now: u64) -> Result<Vec<Hash>, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let still_live = match now.checked_add(1) {
+            Some(next) => self.by_expiry.split_off(&next),
+            None => BTreeMap::new(),
+        };
+        let expired = std::mem::replace(&mut self.by_expiry, still_live);
+
+        let mut swept = Vec::new();
+        for key in expired.into_values().flatten() {
+            self.tree.delete(key)?;
+            self.leaves.remove(&key);
+            swept.push(key);
+        }
+        Ok(swept)
+    }
+
+    /// `get` at the clock's current time.
+    pub fn get_now(&self, key: Hash) -> Result<Option<Hash>, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        self.get(key, self.clock.now())
+    }
+
+    /// `sweep_expired` at the clock's current time.
+    pub fn sweep_expired_now(&mut self) -> Result<Vec<Hash>, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        self.sweep_expired(self.clock.now())
+    }
+
+    pub fn root(&self) -> Hash {
+        self.tree.root()
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    pub fn tree(&self) -> &SparseMerkleTree<S> {
+        &self.tree
+    }
+
+    fn forget(&mut self, key: &Hash) {
+        if let Some((_, expires_at)) = self.leaves.remove(key) {
+            if let Some(keys) = self.by_expiry.get_mut(&expires_at) {
+                keys.remove(key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_store::InMemoryKVStore;
+
+    fn tree() -> ExpiringTree<InMemoryKVStore> {
+        ExpiringTree::new(SparseMerkleTree::new(InMemoryKVStore::new()))
+    }
+
+    #[test]
+    fn test_expired_leaf_is_hidden_then_swept() {
+        let mut smt = tree();
+        smt.update_with_expiry([1u8; 32], [10u8; 32], 100).unwrap();
+        smt.update([2u8; 32], [20u8; 32]).unwrap();
+
+        assert_eq!(smt.get([1u8; 32], 99).unwrap(), Some([10u8; 32]));
+        assert_eq!(smt.get([1u8; 32], 100).unwrap(), None);
+
+        assert_eq!(smt.sweep_expired(99).unwrap(), Vec::<Hash>::new());
+        assert_eq!(smt.sweep_expired(150).unwrap(), vec![[1u8; 32]]);
+        assert_eq!(smt.tree().get([1u8; 32]).unwrap(), None);
+        assert_eq!(smt.get([2u8; 32], u64::MAX).unwrap(), Some([20u8; 32]));
+    }
+
+    #[test]
+    fn test_expiry_proof() {
+        let mut smt = tree();
+        let key: Hash = [1u8; 32];
+        smt.update_with_expiry(key, [10u8; 32], 100).unwrap();
+
+        assert!(smt.prove_expiry(key, 99).unwrap().is_none());
+        let proof = smt.prove_expiry(key, 120).unwrap().unwrap();
+        assert!(proof.verify(&smt.root(), &key, 120));
+        assert!(!proof.verify(&smt.root(), &key, 50));
+
+        let mut forged = proof.clone();
+        forged.expires_at = 90;
+        assert!(!forged.verify(&smt.root(), &key, 120));
+    }
+
+    #[test]
+    fn test_rewrite_clears_old_expiry() {
+        let mut smt = tree();
+        smt.update_with_expiry([1u8; 32], [10u8; 32], 100).unwrap();
+        smt.update([1u8; 32], [11u8; 32]).unwrap();
+
+        assert_eq!(smt.expires_at(&[1u8; 32]), None);
+        assert!(smt.sweep_expired(200).unwrap().is_empty());
+        assert_eq!(smt.get([1u8; 32], 200).unwrap(), Some([11u8; 32]));
+    }
+
+    #[cfg(feature = "test-clock")]
+    mod clocked {
+        use rand::Rng;
+
+        use super::*;
+        use crate::clock::{seeded_rng, TestClock};
+
+        /// Random writes with short TTLs while the clock ticks, returning the
+        /// root and swept keys after each tick.
+        fn simulate(seed: u64) -> Vec<(Hash, Vec<Hash>)> {
+            let clock = TestClock::new(1_000);
+            let mut smt = ExpiringTree::with_clock(SparseMerkleTree::new(InMemoryKVStore::new()), clock.clone());
+            let mut rng = seeded_rng(seed);
+            let mut history = Vec::new();
+            for _ in 0..50 {
+                for _ in 0..rng.gen_range(0..4) {
+                    let key = [rng.gen_range(0..16u8); 32];
+                    smt.update_with_ttl(key, rng.gen(), rng.gen_range(1..10)).unwrap();
+                }
+                clock.advance(rng.gen_range(1..4));
+                let swept = smt.sweep_expired_now().unwrap();
+                history.push((smt.root(), swept));
+            }
+            history
+        }
+
+        #[test]
+        fn test_ttl_follows_injected_clock() {
+            let clock = TestClock::new(100);
+            let mut smt = ExpiringTree::with_clock(SparseMerkleTree::new(InMemoryKVStore::new()), clock.clone());
+            smt.update_with_ttl([1u8; 32], [10u8; 32], 30).unwrap();
+            assert_eq!(smt.expires_at(&[1u8; 32]), Some(130));
+
+            clock.advance(29);
+            assert_eq!(smt.get_now([1u8; 32]).unwrap(), Some([10u8; 32]));
+            assert!(smt.sweep_expired_now().unwrap().is_empty());
+            clock.advance(1);
+            assert_eq!(smt.get_now([1u8; 32]).unwrap(), None);
+            assert_eq!(smt.sweep_expired_now().unwrap(), vec![[1u8; 32]]);
+        }
+
+        #[test]
+        fn test_simulation_replays_from_seed() {
+            assert_eq!(simulate(7), simulate(7));
+            assert_ne!(simulate(7), simulate(8));
+        }
+    }
+}
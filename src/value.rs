@@ -0,0 +1,137 @@
+//! Values of any length. The leaf commits to `value_hash(value)` and the
+//! value itself is kept in the store under that hash, the way `state` keeps
+//! account records, so proofs can hand verifiers the actual data.
+
+use digest::Digest;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::SMTError,
+    kv_store::KVStore,
+    proof::KeyProof,
+    sparse_merkle_tree::SparseMerkleTree,
+    tree_hasher::TreeDigest,
+    DefaultHasher, Hash,
+};
+
+/// Hash a byte value is committed to in its leaf.
+pub fn value_hash(value: &[u8]) -> Hash {
+    DefaultHasher::digest(value).into()
+}
+
+/// Proof for a key written with `update_bytes`, carrying the value itself
+/// unless it was left out with `without_value`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ValueProof {
+    pub value: Option<Vec<u8>>,
+    pub proof: KeyProof,
+}
+
+impl ValueProof {
+    /// Checks the proof for `key` against `root`, and that the value it
+    /// carries, if any, is the one committed to.
+    pub fn verify(&self, root: &Hash, key: &Hash) -> bool {
+        let carried = match (&self.value, self.proof.value()) {
+            (Some(value), Some(committed)) => value_hash(value) == committed,
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        carried && self.proof.verify(root, key)
+    }
+
+    /// Checks that `key` holds exactly `value` under `root`, whether or not
+    /// the proof carries it.
+    pub fn verify_value(&self, root: &Hash, key: &Hash, value: &[u8]) -> bool {
+        self.proof.value() == Some(value_hash(value)) && self.verify(root, key)
+    }
+
+    /// The proof without the value, for verifiers that already hold it.
+    pub fn without_value(self) -> Self {
+        Self { value: None, ..self }
+    }
+}
+
+impl<S: KVStore, D: TreeDigest> SparseMerkleTree<S, D>
+where
+    SMTError: From<S::Error>,
+{
+    /// Sets `key` to `value`, which may be any length.
+    pub fn update_bytes(&mut self, key: Hash, value: &[u8]) -> Result<(), SMTError> {
+        let hash = value_hash(value);
+        self.store.set(hash, value.to_vec())?;
+        self.update(key, hash)
+    }
+
+    /// The value written with `update_bytes`. Fails if the store lost it or
+    /// holds something else under its hash.
+    pub fn get_bytes(&self, key: Hash) -> Result<Option<Vec<u8>>, SMTError> {
+        let Some(hash) = self.get(key)? else {
+            return Ok(None);
+        };
+        self.load_value(&hash).map(Some)
+    }
+
+    /// Proves what `key` holds, with its value if present.
+    pub fn prove_bytes(&self, key: Hash) -> Result<ValueProof, SMTError> {
+        let proof = self.prove(key)?;
+        let value = proof.value().map(|hash| self.load_value(&hash)).transpose()?;
+        Ok(ValueProof { value, proof })
+    }
+
+    fn load_value(&self, hash: &Hash) -> Result<Vec<u8>, SMTError> {
+        let value = self.store.get(hash)?.ok_or(SMTError::MissingNode(*hash))?;
+        match value_hash(&value) == *hash {
+            true => Ok(value),
+            false => Err(SMTError::CorruptNode { hash: *hash, len: value.len() }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_store::InMemoryKVStore;
+
+    fn tree() -> SparseMerkleTree<InMemoryKVStore> {
+        let mut smt = SparseMerkleTree::new(InMemoryKVStore::new());
+        smt.update_bytes([1u8; 32], b"a value longer than thirty-two bytes, as most are").unwrap();
+        smt.update_bytes([2u8; 32], b"").unwrap();
+        smt
+    }
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let smt = tree();
+        assert_eq!(smt.get_bytes([1u8; 32]).unwrap().unwrap(), b"a value longer than thirty-two bytes, as most are");
+        assert_eq!(smt.get_bytes([2u8; 32]).unwrap(), Some(Vec::new()));
+        assert_eq!(smt.get_bytes([3u8; 32]).unwrap(), None);
+        assert_eq!(smt.get([2u8; 32]).unwrap(), Some(value_hash(b"")));
+    }
+
+    #[test]
+    fn test_proof_carries_the_value() {
+        let smt = tree();
+        let root = smt.root();
+        let proof = smt.prove_bytes([1u8; 32]).unwrap();
+        assert!(proof.verify(&root, &[1u8; 32]));
+        assert!(proof.verify_value(&root, &[1u8; 32], b"a value longer than thirty-two bytes, as most are"));
+
+        let mut forged = proof.clone();
+        forged.value = Some(b"something else".to_vec());
+        assert!(!forged.verify(&root, &[1u8; 32]));
+
+        let bare = proof.without_value();
+        assert!(bare.verify(&root, &[1u8; 32]));
+        assert!(!bare.verify_value(&root, &[1u8; 32], b"something else"));
+
+        let absent = smt.prove_bytes([3u8; 32]).unwrap();
+        assert!(absent.value.is_none() && absent.verify(&root, &[3u8; 32]));
+    }
+
+    #[test]
+    fn test_lost_value_is_an_error() {
+        let mut smt = tree();
+        smt.store.remove(&value_hash(b"")).unwrap();
+        assert!(matches!(smt.get_bytes([2u8; 32]), Err(SMTError::MissingNode(_))));
+    }
+}
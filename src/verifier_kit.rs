@@ -0,0 +1,294 @@
+//! What another implementation needs to check this tree's proofs, in one
+//! place: the `TreeSpec` describing the hashing scheme, golden vectors drawn
+//! from real trees, and `minimal`, a verifier short enough to transcribe or
+//! compile into other languages. `VerifierKit::to_json` writes the spec and
+//! vectors out for a foreign test suite to load.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::SMTError, kv_store::InMemoryKVStore, proof::{MerkleProof, NonMembershipProof}, spec::TreeSpec,
+    sparse_merkle_tree::SparseMerkleTree, Hash,
+};
+
+/// One proof to check against the tree described by `specs[spec]` of its
+/// kit. `value` is `None` for a non-membership proof, which may then end at
+/// the leaf of another key in `leaf`. `valid` is what a correct verifier must
+/// answer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofVector {
+    pub name: String,
+    pub spec: usize,
+    pub root: Hash,
+    pub key: Hash,
+    pub value: Option<Hash>,
+    pub side_nodes: Vec<Hash>,
+    #[serde(default)]
+    pub leaf: Option<(Hash, Hash)>,
+    pub valid: bool,
+}
+
+/// Golden vectors, with the spec of each tree they were taken from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifierKit {
+    pub specs: Vec<TreeSpec>,
+    pub vectors: Vec<ProofVector>,
+}
+
+impl VerifierKit {
+    /// Builds the kit from fixed trees over the default hasher, so it is the
+    /// same on every run: a full-depth tree, and an 8-level tree where one key
+    /// shadows another. Each valid vector comes with a tampered copy that
+    /// must fail.
+    pub fn generate() -> Result<Self, SMTError> {
+        let mut kit = Self { specs: Vec::new(), vectors: Vec::new() };
+
+        let mut full = SparseMerkleTree::new(InMemoryKVStore::new());
+        kit.add_absent(&full, "empty tree", [0x42; 32])?;
+        full.update([1u8; 32], [10u8; 32])?;
+        kit.add_present(&full, "single leaf", [1u8; 32])?;
+        full.update([0x80; 32], [20u8; 32])?;
+        full.update([0x81; 32], [30u8; 32])?;
+        kit.add_present(&full, "leaf with empty siblings", [1u8; 32])?;
+        kit.add_present(&full, "leaf next to a close neighbour", [0x81; 32])?;
+        kit.add_absent(&full, "path ending in an empty subtree", [0x40; 32])?;
+
+        let mut shallow = SparseMerkleTree::new(InMemoryKVStore::new()).with_depth(8);
+        let (mut near, mut far) = ([0u8; 32], [0u8; 32]);
+        (near[0], far[0]) = (7, 0xf0);
+        let mut shadowed = near;
+        shadowed[31] = 8; // Same first byte, so the same leaf
+        shallow.update(near, [70u8; 32])?;
+        shallow.update(far, [80u8; 32])?;
+        kit.add_present(&shallow, "shallow tree", far)?;
+        kit.add_absent(&shallow, "path ending in another key's leaf", shadowed)?;
+
+        Ok(kit)
+    }
+
+    pub fn to_json(&self) -> Result<String, SMTError> {
+        serde_json::to_string_pretty(self).map_err(|_| SMTError::InvalidEncoding)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SMTError> {
+        serde_json::from_str(json).map_err(|_| SMTError::InvalidEncoding)
+    }
+
+    /// Runs every vector through `minimal` with `hash` as the hash function,
+    /// returning the names of those it got wrong. Empty for a kit generated
+    /// with the default hasher and a SHA-256 `hash`.
+    pub fn check<H: Fn(&[&[u8]]) -> Hash>(&self, hash: H) -> Vec<String> {
+        self.vectors
+            .iter()
+            .filter(|vector| {
+                let verified = self.specs.get(vector.spec).is_some_and(|spec| {
+                    let spec = minimal::Spec::from(spec);
+                    match vector.value {
+                        Some(value) => minimal::verify_membership(&hash, &spec, &vector.root, &vector.key, &value, &vector.side_nodes),
+                        None => minimal::verify_non_membership(&hash, &spec, &vector.root, &vector.key, &vector.side_nodes, vector.leaf.as_ref()),
+                    }
+                });
+                verified != vector.valid
+            })
+            .map(|vector| vector.name.clone())
+            .collect()
+    }
+
+    fn spec_index(&mut self, spec: TreeSpec) -> usize {
+        match self.specs.iter().position(|known| *known == spec) {
+            Some(index) => index,
+            None => {
+                self.specs.push(spec);
+                self.specs.len() - 1
+            }
+        }
+    }
+
+    fn add_present(&mut self, smt: &SparseMerkleTree<InMemoryKVStore>, name: &str, key: Hash) -> Result<(), SMTError> {
+        let value = smt.get(key)?.ok_or(SMTError::InvalidProof)?;
+        let MerkleProof { side_nodes } = smt.get_proof(key)?;
+        let vector = ProofVector { name: name.to_string(), spec: 0, root: smt.root(), key, value: Some(value), side_nodes, leaf: None, valid: true };
+        self.add_with_tampered(smt, vector);
+        Ok(())
+    }
+
+    fn add_absent(&mut self, smt: &SparseMerkleTree<InMemoryKVStore>, name: &str, key: Hash) -> Result<(), SMTError> {
+        let NonMembershipProof { side_nodes, leaf } = smt.get_non_membership_proof(key)?.ok_or(SMTError::InvalidProof)?;
+        let vector = ProofVector { name: name.to_string(), spec: 0, root: smt.root(), key, value: None, side_nodes, leaf, valid: true };
+        self.add_with_tampered(smt, vector);
+        Ok(())
+    }
+
+    /// Adds `vector` and a copy with a flipped bit in the root, which no
+    /// verifier may accept.
+    fn add_with_tampered(&mut self, smt: &SparseMerkleTree<InMemoryKVStore>, mut vector: ProofVector) {
+        vector.spec = self.spec_index(smt.spec());
+        let mut tampered = ProofVector { name: format!("{} (tampered root)", vector.name), valid: false, ..vector.clone() };
+        tampered.root[0] ^= 1;
+        self.vectors.push(vector);
+        self.vectors.push(tampered);
+    }
+}
+
+impl From<&TreeSpec> for minimal::Spec {
+    fn from(spec: &TreeSpec) -> Self {
+        Self {
+            depth: spec.depth as usize,
+            leaf_prefix: spec.leaf_prefix,
+            node_prefix: spec.node_prefix,
+            key_domain: spec.key_domain,
+        }
+    }
+}
+
+/// A proof verifier using nothing but slices and a caller-supplied hash
+/// function, and nothing from the rest of the crate, meant as the reference
+/// to port. `hash` gets the pieces to hash in order and must return their
+/// digest as if they were concatenated.
+///
+/// Keys are read most significant bit first; bit `i` picks the child at
+/// depth `i`, 0 for left. Side nodes run from the root down. A leaf hashes
+/// `leaf_prefix || key_domain? || key || value`, an internal node
+/// `node_prefix || left || right`, the empty leaf is 32 zero bytes and an
+/// empty subtree one level up hashes like a node with two empty children.
+pub mod minimal {
+    type Hash = [u8; 32];
+
+    /// The parts of `TreeSpec` verification depends on.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Spec {
+        pub depth: usize,
+        pub leaf_prefix: u8,
+        pub node_prefix: u8,
+        pub key_domain: Option<Hash>,
+    }
+
+    /// Whether `side_nodes` prove `key` holds `value` under `root`. The proof
+    /// must have exactly one side node per level.
+    pub fn verify_membership<H: Fn(&[&[u8]]) -> Hash>(hash: &H, spec: &Spec, root: &Hash, key: &Hash, value: &Hash, side_nodes: &[Hash]) -> bool {
+        if side_nodes.len() != spec.depth || spec.depth > 256 {
+            return false;
+        }
+        let leaf = leaf_hash(hash, spec, key, value);
+        fold_path(hash, spec, leaf, key, side_nodes) == *root
+    }
+
+    /// Whether `side_nodes` prove `key` has no leaf under `root`: either they
+    /// stop above the bottom and the path continues into an empty subtree,
+    /// or they reach the bottom and end at `leaf`, a different key with the
+    /// same first `depth` bits.
+    pub fn verify_non_membership<H: Fn(&[&[u8]]) -> Hash>(
+        hash: &H,
+        spec: &Spec,
+        root: &Hash,
+        key: &Hash,
+        side_nodes: &[Hash],
+        leaf: Option<&(Hash, Hash)>,
+    ) -> bool {
+        if side_nodes.len() > spec.depth || spec.depth > 256 {
+            return false;
+        }
+        let bottom = match leaf {
+            None => empty_subtree(hash, spec, spec.depth - side_nodes.len()),
+            Some((other, value)) => {
+                if other == key || side_nodes.len() != spec.depth || (0..spec.depth).any(|i| bit(other, i) != bit(key, i)) {
+                    return false;
+                }
+                leaf_hash(hash, spec, other, value)
+            }
+        };
+        fold_path(hash, spec, bottom, key, side_nodes) == *root
+    }
+
+    /// Hash of an empty subtree `height` levels above the leaves.
+    pub fn empty_subtree<H: Fn(&[&[u8]]) -> Hash>(hash: &H, spec: &Spec, height: usize) -> Hash {
+        let mut current = [0u8; 32];
+        for _ in 0..height {
+            current = hash(&[&[spec.node_prefix], &current, &current]);
+        }
+        current
+    }
+
+    fn leaf_hash<H: Fn(&[&[u8]]) -> Hash>(hash: &H, spec: &Spec, key: &Hash, value: &Hash) -> Hash {
+        match &spec.key_domain {
+            Some(domain) => hash(&[&[spec.leaf_prefix], domain, key, value]),
+            None => hash(&[&[spec.leaf_prefix], key, value]),
+        }
+    }
+
+    /// Hashes from `bottom`, at depth `side_nodes.len()`, up to the root.
+    fn fold_path<H: Fn(&[&[u8]]) -> Hash>(hash: &H, spec: &Spec, bottom: Hash, key: &Hash, side_nodes: &[Hash]) -> Hash {
+        let mut current = bottom;
+        for (i, sibling) in side_nodes.iter().enumerate().rev() {
+            current = match bit(key, i) {
+                0 => hash(&[&[spec.node_prefix], &current, sibling]),
+                _ => hash(&[&[spec.node_prefix], sibling, &current]),
+            };
+        }
+        current
+    }
+
+    fn bit(key: &Hash, i: usize) -> u8 {
+        (key[i / 8] >> (7 - (i % 8))) & 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest, Sha256};
+
+    fn sha256(parts: &[&[u8]]) -> Hash {
+        let mut hasher = Sha256::new();
+        for part in parts {
+            hasher.update(part);
+        }
+        hasher.finalize().into()
+    }
+
+    #[test]
+    fn test_minimal_verifier_agrees_with_vectors() {
+        let kit = VerifierKit::generate().unwrap();
+        assert_eq!(kit.specs.len(), 2);
+        assert!(kit.vectors.iter().any(|vector| vector.leaf.is_some()));
+        assert!(kit.check(sha256).is_empty());
+        // A different hash function gets every valid vector wrong
+        let wrong = kit.check(|parts: &[&[u8]]| sha256(&[&[0xff], &parts.concat()]));
+        assert_eq!(wrong.len(), kit.vectors.len() / 2);
+    }
+
+    #[test]
+    fn test_vectors_agree_with_crate_verifier() {
+        let kit = VerifierKit::generate().unwrap();
+        for vector in kit.vectors.iter().filter(|vector| kit.specs[vector.spec].depth == 256) {
+            let verified = match vector.value {
+                Some(value) => MerkleProof { side_nodes: vector.side_nodes.clone() }.verify(&vector.root, &vector.key, &value),
+                None => NonMembershipProof { side_nodes: vector.side_nodes.clone(), leaf: vector.leaf }.verify(&vector.root, &vector.key),
+            };
+            assert_eq!(verified, vector.valid, "{}", vector.name);
+        }
+    }
+
+    #[test]
+    fn test_minimal_rejects_wrong_proof_length() {
+        let spec = minimal::Spec::from(&TreeSpec::default());
+        let short = vec![[0u8; 32]; 255];
+        assert!(!minimal::verify_membership(&sha256, &spec, &[0u8; 32], &[0u8; 32], &[0u8; 32], &short));
+        let long = vec![[0u8; 32]; 257];
+        assert!(!minimal::verify_non_membership(&sha256, &spec, &[0u8; 32], &[0u8; 32], &long, None));
+    }
+
+    #[test]
+    fn test_minimal_empty_subtree_matches_tree_hasher() {
+        let spec = minimal::Spec::from(&TreeSpec::default());
+        assert_eq!(minimal::empty_subtree(&sha256, &spec, 256), TreeSpec::default().empty_root);
+    }
+
+    #[test]
+    fn test_kit_json_roundtrip() {
+        let kit = VerifierKit::generate().unwrap();
+        assert_eq!(VerifierKit::generate().unwrap(), kit);
+        assert_eq!(VerifierKit::from_json(&kit.to_json().unwrap()).unwrap(), kit);
+        assert!(VerifierKit::from_json("{").is_err());
+    }
+}
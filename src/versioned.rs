@@ -0,0 +1,284 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    error::SMTError,
+    kv_store::{KVStore, TreeWriteBatch},
+    op::Op,
+    proof::MerkleProof,
+    sparse_merkle_tree::SparseMerkleTree,
+    Hash,
+};
+
+/// Tree that numbers each commit and keeps answering reads and proofs against
+/// any earlier version.
+///
+/// Version 0 is the empty tree. Writes are buffered and applied to the tree as
+/// one batch on `commit`. The per-key history used to answer historical reads
+/// is kept in memory; nodes for old roots come from the store until
+/// `prune_before` reclaims them.
+pub struct VersionedSparseMerkleTree<S: KVStore> {
+    tree: SparseMerkleTree<S>,
+    roots: Vec<Hash>,
+    oldest: u64,
+    history: BTreeMap<Hash, BTreeMap<u64, Option<Hash>>>, // key -> version -> value
+    pending: BTreeMap<Hash, Option<Hash>>,
+    stale: HashMap<Hash, u64>, // node -> first version that no longer uses it
+}
+
+impl<S: KVStore> VersionedSparseMerkleTree<S> {
+    pub fn new(store: S) -> Self {
+        let tree = SparseMerkleTree::new(store);
+        let roots = vec![tree.root()];
+        Self {
+            tree,
+            roots,
+            oldest: 0,
+            history: BTreeMap::new(),
+            pending: BTreeMap::new(),
+            stale: HashMap::new(),
+        }
+    }
+
+    pub fn update(&mut self, key: Hash, value: Hash) {
+        self.pending.insert(key, Some(value));
+    }
+
+    pub fn delete(&mut self, key: Hash) {
+        self.pending.insert(key, None);
+    }
+
+    /// Latest value of `key`, including uncommitted writes.
+    pub fn get(&self, key: Hash) -> Result<Option<Hash>, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        match self.pending.get(&key) {
+            Some(value) => Ok(*value),
+            None => self.tree.get(key),
+        }
+    }
+
+    /// Applies the buffered writes as a new version and returns its number.
+    pub fn commit(&mut self) -> Result<u64, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let ops: Vec<Op> = self
+            .pending
+            .iter()
+            .map(|(key, value)| match value {
+                Some(value) => Op::Put(*key, *value),
+                None => Op::Delete(*key),
+            })
+            .collect();
+        let old_root = self.tree.root();
+        let new_root = self.tree.apply(&ops)?;
+
+        self.roots.push(new_root);
+        let version = self.version();
+        let (mut stale, mut fresh) = (Vec::new(), Vec::new());
+        self.tree.diff_nodes(old_root, new_root, 0, &mut stale, &mut fresh)?;
+        for node in fresh {
+            self.stale.remove(&node); // Back in use after an earlier version dropped it
+        }
+        for node in stale {
+            self.stale.insert(node, version);
+        }
+
+        for (key, value) in std::mem::take(&mut self.pending) {
+            self.history.entry(key).or_default().insert(version, value);
+        }
+        Ok(version)
+    }
+
+    /// Latest committed version.
+    pub fn version(&self) -> u64 {
+        self.roots.len() as u64 - 1
+    }
+
+    /// Oldest version still readable.
+    pub fn oldest_version(&self) -> u64 {
+        self.oldest
+    }
+
+    /// The tree as of the latest committed version.
+    pub fn tree(&self) -> &SparseMerkleTree<S> {
+        &self.tree
+    }
+
+    pub fn root_at_version(&self, version: u64) -> Option<Hash> {
+        if version < self.oldest {
+            return None;
+        }
+        self.roots.get(version as usize).copied()
+    }
+
+    pub fn get_at_version(&self, key: Hash, version: u64) -> Result<Option<Hash>, SMTError> {
+        if version < self.oldest || version > self.version() {
+            return Err(SMTError::UnknownVersion(version));
+        }
+        let value = self
+            .history
+            .get(&key)
+            .and_then(|versions| versions.range(..=version).next_back())
+            .and_then(|(_, value)| *value);
+        Ok(value)
+    }
+
+    /// Proof for `key` against `root_at_version(version)`.
+    pub fn prove_at_version(&self, key: Hash, version: u64) -> Result<MerkleProof, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let root = self
+            .root_at_version(version)
+            .ok_or(SMTError::UnknownVersion(version))?;
+        self.tree.get_proof_at(root, key)
+    }
+
+    /// Deletes every node that only versions older than `version` used, so
+    /// roots before it can no longer be read or proven against. Returns the
+    /// number of nodes removed.
+    pub fn prune_before(&mut self, version: u64) -> Result<usize, SMTError>
+    where
+        SMTError: From<S::Error>,
+    {
+        let version = version.min(self.version());
+        if version <= self.oldest {
+            return Ok(0);
+        }
+
+        let mut batch = TreeWriteBatch::new();
+        self.stale.retain(|node, stale_since| {
+            let prunable = *stale_since <= version;
+            if prunable {
+                batch.remove_node(*node);
+            }
+            !prunable
+        });
+        let removed = batch.len();
+        batch.set_root(self.tree.root());
+        self.tree.commit(batch)?;
+
+        // Keep, per key, only the last entry at or before the new oldest version.
+        for versions in self.history.values_mut() {
+            let mut kept = versions.split_off(&version);
+            if let Some((_, value)) = versions.pop_last() {
+                kept.entry(version).or_insert(value);
+            }
+            *versions = kept;
+        }
+        self.history.retain(|_, versions| !versions.is_empty());
+        self.oldest = version;
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_store::InMemoryKVStore;
+
+    #[test]
+    fn test_reads_and_proofs_at_past_versions() {
+        let mut smt = VersionedSparseMerkleTree::new(InMemoryKVStore::new());
+        let key: Hash = [1u8; 32];
+
+        smt.update(key, [10u8; 32]);
+        let v1 = smt.commit().unwrap();
+        smt.update(key, [11u8; 32]);
+        smt.update([2u8; 32], [20u8; 32]);
+        let v2 = smt.commit().unwrap();
+
+        assert_eq!(smt.get_at_version(key, 0).unwrap(), None);
+        assert_eq!(smt.get_at_version(key, v1).unwrap(), Some([10u8; 32]));
+        assert_eq!(smt.get_at_version(key, v2).unwrap(), Some([11u8; 32]));
+
+        let root = smt.root_at_version(v1).unwrap();
+        let proof = smt.prove_at_version(key, v1).unwrap();
+        assert!(proof.verify(&root, &key, &[10u8; 32]));
+        assert!(!proof.verify(&smt.root_at_version(v2).unwrap(), &key, &[10u8; 32]));
+    }
+
+    #[test]
+    fn test_uncommitted_writes_are_not_versioned() {
+        let mut smt = VersionedSparseMerkleTree::new(InMemoryKVStore::new());
+        let key: Hash = [1u8; 32];
+        smt.update(key, [10u8; 32]);
+        let v1 = smt.commit().unwrap();
+        smt.delete(key);
+
+        assert_eq!(smt.version(), v1);
+        assert_eq!(smt.get_at_version(key, v1).unwrap(), Some([10u8; 32]));
+        assert_eq!(smt.tree().get(key).unwrap(), Some([10u8; 32]));
+        assert_eq!(smt.get(key).unwrap(), None);
+
+        let v2 = smt.commit().unwrap();
+        assert_eq!(smt.get_at_version(key, v2).unwrap(), None);
+        assert_eq!(smt.root_at_version(v2), Some(SparseMerkleTree::new(InMemoryKVStore::new()).root()));
+    }
+
+    #[test]
+    fn test_prune_before_reclaims_old_nodes() {
+        let mut smt = VersionedSparseMerkleTree::new(InMemoryKVStore::new());
+        let key: Hash = [1u8; 32];
+        smt.update(key, [10u8; 32]);
+        smt.update([2u8; 32], [20u8; 32]);
+        let v1 = smt.commit().unwrap();
+        smt.update(key, [11u8; 32]);
+        let v2 = smt.commit().unwrap();
+        smt.update([3u8; 32], [30u8; 32]);
+        let v3 = smt.commit().unwrap();
+
+        let removed = smt.prune_before(v2).unwrap();
+        assert_eq!(removed, 257); // The path to the old leaf, plus its preimage
+
+        assert!(smt.prove_at_version(key, v1).is_err());
+        assert!(matches!(smt.get_at_version(key, v1), Err(SMTError::UnknownVersion(_))));
+        assert_eq!(smt.get_at_version(key, v2).unwrap(), Some([11u8; 32]));
+        assert_eq!(smt.get_at_version([2u8; 32], v2).unwrap(), Some([20u8; 32]));
+        for version in [v2, v3] {
+            let root = smt.root_at_version(version).unwrap();
+            let proof = smt.prove_at_version(key, version).unwrap();
+            assert!(proof.verify(&root, &key, &[11u8; 32]));
+        }
+    }
+
+    #[test]
+    fn test_pruned_root_is_missing() {
+        let mut smt = VersionedSparseMerkleTree::new(InMemoryKVStore::new());
+        let key: Hash = [1u8; 32];
+        smt.update(key, [10u8; 32]);
+        let v1 = smt.commit().unwrap();
+        let old_root = smt.root_at_version(v1).unwrap();
+        smt.update(key, [11u8; 32]);
+        let v2 = smt.commit().unwrap();
+
+        smt.prune_before(v2).unwrap();
+        assert!(matches!(smt.tree.get_proof_at(old_root, key), Err(SMTError::MissingNode(node)) if node == old_root));
+    }
+
+    #[test]
+    fn test_prune_keeps_nodes_reused_by_later_versions() {
+        let mut smt = VersionedSparseMerkleTree::new(InMemoryKVStore::new());
+        let key: Hash = [1u8; 32];
+        smt.update(key, [10u8; 32]);
+        smt.commit().unwrap();
+        smt.update(key, [11u8; 32]);
+        smt.commit().unwrap();
+        smt.update(key, [10u8; 32]); // Same tree as version 1 again
+        let v3 = smt.commit().unwrap();
+
+        smt.prune_before(v3).unwrap();
+        let proof = smt.prove_at_version(key, v3).unwrap();
+        assert!(proof.verify(&smt.root_at_version(v3).unwrap(), &key, &[10u8; 32]));
+    }
+
+    #[test]
+    fn test_unknown_version() {
+        let smt = VersionedSparseMerkleTree::new(InMemoryKVStore::new());
+        assert!(matches!(smt.get_at_version([1u8; 32], 1), Err(SMTError::UnknownVersion(1))));
+        assert!(smt.prove_at_version([1u8; 32], 1).is_err());
+        assert_eq!(smt.root_at_version(1), None);
+    }
+}
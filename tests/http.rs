@@ -0,0 +1,67 @@
+//! Drives the HTTP router the way a client would, without opening a socket.
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use tower::ServiceExt;
+use SimpleSparseMerkle::{http::router, hex::serde_hex::decode, InMemoryKVStore, MerkleProof, SparseMerkleTree};
+
+fn hex(byte: u8) -> String {
+    format!("0x{}", format!("{:02x}", byte).repeat(32))
+}
+
+async fn call(app: &Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+async fn get(app: &Router, uri: &str) -> (StatusCode, Value) {
+    call(app, Request::get(uri).body(Body::empty()).unwrap()).await
+}
+
+async fn update(app: &Router, entries: Value) -> (StatusCode, Value) {
+    let request = Request::post("/update")
+        .header("content-type", "application/json")
+        .body(Body::from(json!({ "entries": entries }).to_string()))
+        .unwrap();
+    call(app, request).await
+}
+
+#[tokio::test]
+async fn test_update_get_and_prove() {
+    let app = router(SparseMerkleTree::new(InMemoryKVStore::new()));
+    let (status, empty) = get(&app, "/root").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, updated) = update(&app, json!([{ "key": hex(1), "value": hex(2) }, { "key": hex(3), "value": hex(4) }])).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_ne!(updated["root"], empty["root"]);
+    assert_eq!(get(&app, "/root").await.1, updated);
+
+    let (status, value) = get(&app, &format!("/get/{}", hex(1))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(value, json!({ "key": hex(1), "value": hex(2) }));
+    assert_eq!(get(&app, &format!("/get/{}", hex(5))).await.1["value"], Value::Null);
+
+    let (status, proof) = get(&app, &format!("/proof/{}", hex(3))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(proof["root"], updated["root"]);
+    let root = decode::<32>(proof["root"].as_str().unwrap()).unwrap();
+    let merkle_proof: MerkleProof = serde_json::from_value(proof["proof"].clone()).unwrap();
+    assert!(merkle_proof.verify(&root, &[3u8; 32], &[4u8; 32]));
+}
+
+#[tokio::test]
+async fn test_malformed_keys_are_rejected() {
+    let app = router(SparseMerkleTree::new(InMemoryKVStore::new()));
+    let (status, body) = get(&app, "/get/0x1234").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["error"].as_str().unwrap().contains("0x1234"));
+    assert_eq!(get(&app, "/proof/nothex").await.0, StatusCode::BAD_REQUEST);
+}
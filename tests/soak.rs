@@ -0,0 +1,146 @@
+//! Randomized soak test for release qualification. Ignored by default; run with
+//!
+//!     SOAK_SECS=259200 cargo test --release --features sled --test soak -- --ignored --nocapture
+//!
+//! `SOAK_SECS` (default 60) sets how long it runs and `SOAK_SEED` replays an
+//! earlier run. Every restart reopens the sled store from disk and audits the
+//! recovered tree against an in-memory model of what it should hold.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use SimpleSparseMerkle::{
+    kv_store::SledStore, versioned::VersionedSparseMerkleTree, Hash, MerkleProof, SparseMerkleTree,
+};
+
+const OPS_PER_EPOCH: usize = 500;
+const KEY_SPACE: u16 = 2048; // Small enough that deletes and overwrites hit live keys
+const VERSIONS_KEPT: u64 = 8;
+
+fn env_u64(name: &str, default: u64) -> u64 {
+    std::env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+fn key(rng: &mut StdRng) -> Hash {
+    let mut key = [0u8; 32];
+    key[..2].copy_from_slice(&rng.gen_range(0..KEY_SPACE).to_be_bytes());
+    key
+}
+
+fn open(path: &Path) -> SparseMerkleTree<SledStore> {
+    SparseMerkleTree::open(SledStore::open(path).unwrap()).unwrap()
+}
+
+/// Checks the recovered tree against the model: same root as before the
+/// restart, every live key readable and provable, and no extra leaves.
+fn audit(smt: &SparseMerkleTree<SledStore>, model: &BTreeMap<Hash, Hash>, root: Hash, rng: &mut StdRng) {
+    assert_eq!(smt.root(), root, "root changed across restart");
+    for (key, value) in model {
+        assert_eq!(smt.get(*key).unwrap(), Some(*value));
+    }
+    let leaves = smt.iter().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(leaves.len(), model.len());
+
+    let probe = key(rng);
+    let proof = smt.get_proof(probe).unwrap();
+    match model.get(&probe) {
+        Some(value) => assert!(proof.verify(&root, &probe, value)),
+        None => {
+            let absent = smt.get_non_membership_proof(probe).unwrap().expect("absent key has a non-membership proof");
+            assert!(absent.verify(&root, &probe));
+        }
+    }
+}
+
+fn run_epoch(smt: &mut SparseMerkleTree<SledStore>, model: &mut BTreeMap<Hash, Hash>, rng: &mut StdRng) {
+    for _ in 0..OPS_PER_EPOCH {
+        match rng.gen_range(0..10) {
+            0..=4 => {
+                let (key, value) = (key(rng), rng.gen());
+                smt.update(key, value).unwrap();
+                model.insert(key, value);
+            }
+            5..=6 => {
+                let key = key(rng);
+                smt.delete(key).unwrap();
+                model.remove(&key);
+            }
+            7 => {
+                let entries: Vec<(Hash, Hash)> = (0..rng.gen_range(1..32)).map(|_| (key(rng), rng.gen())).collect();
+                smt.update_batch(&entries).unwrap();
+                model.extend(entries);
+            }
+            _ => {
+                let key = key(rng);
+                let proof: MerkleProof = smt.get_proof(key).unwrap();
+                if let Some(value) = model.get(&key) {
+                    assert!(proof.verify(&smt.root(), &key, value));
+                    assert!(!proof.verify(&smt.root(), &key, &[0xee; 32]));
+                }
+            }
+        }
+    }
+}
+
+/// Commits versions on a second store, pruning all but the last few, then
+/// reopens it to check pruning kept everything the latest root needs.
+fn run_pruning(path: &Path, rng: &mut StdRng) {
+    let _ = std::fs::remove_dir_all(path);
+    let mut versioned = VersionedSparseMerkleTree::new(SledStore::open(path).unwrap());
+    let mut model = BTreeMap::new();
+    for _ in 0..rng.gen_range(VERSIONS_KEPT..4 * VERSIONS_KEPT) {
+        for _ in 0..rng.gen_range(1..64) {
+            let key = key(rng);
+            if rng.gen_bool(0.2) {
+                versioned.delete(key);
+                model.remove(&key);
+            } else {
+                let value = rng.gen();
+                versioned.update(key, value);
+                model.insert(key, value);
+            }
+        }
+        let version = versioned.commit().unwrap();
+        versioned.prune_before(version.saturating_sub(VERSIONS_KEPT)).unwrap();
+    }
+
+    let root = versioned.tree().root();
+    drop(versioned);
+    audit(&open(path), &model, root, rng);
+}
+
+#[test]
+#[ignore = "runs for SOAK_SECS; for release qualification"]
+fn soak() {
+    let seed = env_u64("SOAK_SEED", SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs());
+    let deadline = Instant::now() + Duration::from_secs(env_u64("SOAK_SECS", 60));
+    println!("soak seed {seed}");
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let dir = tempfile::tempdir().unwrap();
+    let (tree_path, pruned_path) = (dir.path().join("tree"), dir.path().join("pruned"));
+    let mut model = BTreeMap::new();
+    let mut root = open(&tree_path).root();
+    let mut epochs = 0u64;
+
+    while Instant::now() < deadline {
+        // Restart: reopen from disk and audit before writing anything.
+        let mut smt = open(&tree_path);
+        audit(&smt, &model, root, &mut rng);
+
+        run_epoch(&mut smt, &mut model, &mut rng);
+        root = smt.root();
+        drop(smt);
+
+        if epochs % 10 == 9 {
+            run_pruning(&pruned_path, &mut rng);
+        }
+        epochs += 1;
+        if epochs.is_multiple_of(100) {
+            println!("epoch {epochs}: {} live keys, root {:02x?}", model.len(), &root[..4]);
+        }
+    }
+    println!("soak finished after {epochs} epochs, seed {seed}");
+}